@@ -0,0 +1,183 @@
+use crate::github::MergeMethod;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS merge_attempts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    repo TEXT NOT NULL,
+    title TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    method TEXT,
+    approvers TEXT NOT NULL,
+    error TEXT,
+    created_at TEXT NOT NULL
+)";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditOutcome {
+    Merged,
+
+    /// Someone else merged the pull request
+    AlreadyMerged,
+
+    /// Merging conflicted because the pull request's head changed
+    Conflict,
+
+    Closed,
+    Failed(String),
+}
+
+impl AuditOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Merged => "merged",
+            Self::AlreadyMerged => "already_merged",
+            Self::Conflict => "conflict",
+            Self::Closed => "closed",
+            Self::Failed(_) => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub url: String,
+
+    /// The repo the pull request belongs to, as `owner/repo`
+    pub repo: String,
+
+    pub title: String,
+    pub outcome: AuditOutcome,
+    pub method: Option<MergeMethod>,
+    pub approvers: Vec<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Keeps a history of merge attempts in a SQLite database, in the `merge_attempts` table
+pub struct AuditLog {
+    connection: Mutex<Connection>,
+}
+
+impl AuditLog {
+    /// Opens the database at `path`, creating it and its table if they don't exist
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute(CREATE_TABLE, [])?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn record(&self, record: &AuditRecord) -> rusqlite::Result<()> {
+        let method = record.method.as_ref().map(|method| match method {
+            MergeMethod::Merge => "merge",
+            MergeMethod::Squash => "squash",
+            MergeMethod::Rebase => "rebase",
+        });
+        let error = match &record.outcome {
+            AuditOutcome::Failed(error) => Some(error.as_str()),
+            _ => None,
+        };
+        // A panic while holding the lock can't leave the connection in a broken state
+        let connection = self
+            .connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        connection.execute(
+            "INSERT INTO merge_attempts (url, repo, title, outcome, method, approvers, error, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                record.url,
+                record.repo,
+                record.title,
+                record.outcome.as_str(),
+                method,
+                record.approvers.join(","),
+                error,
+                record.timestamp.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// The outcome, method, approvers, error and timestamp of a record
+    type Row = (String, Option<String>, String, Option<String>, String);
+
+    #[test]
+    fn test_record() {
+        let audit_log = AuditLog::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let record = AuditRecord {
+            url: "https://github.com/potato/smasher/pull/1337".into(),
+            repo: "potato/smasher".into(),
+            title: "Add potatoes".into(),
+            outcome: AuditOutcome::Merged,
+            method: Some(MergeMethod::Squash),
+            approvers: vec!["alice".into(), "bob".into()],
+            timestamp: Utc.timestamp(1_600_000_000, 0),
+        };
+        audit_log.record(&record).unwrap();
+        audit_log
+            .record(&AuditRecord {
+                outcome: AuditOutcome::Failed("not enough approvals".into()),
+                method: None,
+                ..record
+            })
+            .unwrap();
+
+        let connection = audit_log.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT outcome, method, approvers, error, created_at FROM merge_attempts ORDER BY id")
+            .unwrap();
+        let rows: Vec<Row> = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "merged".into(),
+                    Some("squash".into()),
+                    "alice,bob".into(),
+                    None,
+                    "2020-09-13T12:26:40+00:00".into()
+                ),
+                (
+                    "failed".into(),
+                    None,
+                    "alice,bob".into(),
+                    Some("not enough approvals".into()),
+                    "2020-09-13T12:26:40+00:00".into()
+                ),
+            ]
+        );
+    }
+}
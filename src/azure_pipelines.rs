@@ -0,0 +1,280 @@
+use crate::client::{ApiClient, Result};
+use crate::github::PullRequest;
+use crate::processing::Error;
+use crate::processing::{WorkflowRunner, WorkflowStatus};
+use async_trait::async_trait;
+use log::info;
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Build {
+    pub id: u64,
+    pub status: BuildStatus,
+    pub result: Option<BuildResult>,
+    pub source_version: String,
+    pub definition: Definition,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BuildStatus {
+    NotStarted,
+    InProgress,
+    Postponed,
+    Cancelling,
+    Completed,
+
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BuildResult {
+    Succeeded,
+
+    /// Some tasks failed but they were allowed to, e.g. via `continueOnError`
+    PartiallySucceeded,
+
+    Failed,
+    Canceled,
+
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BuildList {
+    pub value: Vec<Build>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NoBody {}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait AzurePipelinesClient {
+    /// The builds for a branch, most recently queued first
+    async fn builds(&self, branch: &str) -> Result<Vec<Build>>;
+    async fn retry_build(&self, build_id: u64) -> Result<NoBody>;
+}
+
+pub struct DefaultAzurePipelinesClient {
+    client: ApiClient,
+    base_url: String,
+}
+
+impl DefaultAzurePipelinesClient {
+    const API_VERSION: &'static str = "6.0";
+
+    pub fn new(organization: &str, project: &str, pat: String) -> Self {
+        Self {
+            // Azure expects personal access tokens as the password with an empty username
+            client: ApiClient::from_credentials("", pat),
+            base_url: format!(
+                "https://dev.azure.com/{}/{}/_apis/build/builds",
+                organization, project
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl AzurePipelinesClient for DefaultAzurePipelinesClient {
+    async fn builds(&self, branch: &str) -> Result<Vec<Build>> {
+        let mut url = Url::parse(&self.base_url).expect("invalid azure pipelines URL");
+        url.query_pairs_mut()
+            .append_pair("branchName", &format!("refs/heads/{}", branch))
+            .append_pair("queryOrder", "queueTimeDescending")
+            .append_pair("api-version", Self::API_VERSION);
+        let builds: BuildList = self.client.get(url.as_str()).await?;
+        Ok(builds.value)
+    }
+
+    async fn retry_build(&self, build_id: u64) -> Result<NoBody> {
+        let url = format!(
+            "{}/{}?retry=true&api-version={}",
+            self.base_url,
+            build_id,
+            Self::API_VERSION
+        );
+        self.client.patch(&url, &()).await
+    }
+}
+
+/// Retries failed Azure Pipelines builds for a pull request's head commit
+pub struct AzurePipelinesWorkflowRunner<C> {
+    client: Arc<C>,
+    organization: String,
+}
+
+impl<C: AzurePipelinesClient> AzurePipelinesWorkflowRunner<C> {
+    pub fn new(client: Arc<C>, organization: String) -> Self {
+        Self {
+            client,
+            organization,
+        }
+    }
+
+    /// Whether a status URL points to a build in our organization, either under
+    /// `dev.azure.com/{organization}` or the legacy `{organization}.visualstudio.com`
+    fn is_build_url(&self, url: &Url) -> bool {
+        let domain = match url.domain() {
+            Some(domain) => domain,
+            None => return false,
+        };
+        if domain == "dev.azure.com" {
+            let organization = url.path_segments().and_then(|mut segments| segments.next());
+            organization == Some(self.organization.as_str())
+        } else {
+            domain.eq_ignore_ascii_case(&format!("{}.visualstudio.com", self.organization))
+        }
+    }
+}
+
+#[async_trait]
+impl<C: AzurePipelinesClient + Send + Sync> WorkflowRunner for AzurePipelinesWorkflowRunner<C> {
+    async fn process_failed_jobs(
+        &self,
+        pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> std::result::Result<WorkflowStatus, Error> {
+        if !job_urls.iter().any(|url| self.is_build_url(url)) {
+            return Ok(WorkflowStatus::Success);
+        }
+        let builds = self.client.builds(&pull_request.head.name).await?;
+        let mut seen_definitions = HashSet::new();
+        let mut pending = Vec::new();
+        let mut failed = Vec::new();
+        for build in builds {
+            // Only the latest build for each pipeline matters
+            if build.source_version != pull_request.head.sha
+                || !seen_definitions.insert(build.definition.id)
+            {
+                continue;
+            }
+            match (&build.status, &build.result) {
+                (BuildStatus::Completed, Some(BuildResult::Failed | BuildResult::Canceled)) => {
+                    failed.push(build)
+                }
+                (BuildStatus::Completed, _) | (BuildStatus::Unknown, _) => (),
+                _ => pending.push(build),
+            }
+        }
+        if !pending.is_empty() {
+            info!("{} azure pipelines builds are still running", pending.len());
+            return Ok(WorkflowStatus::Pending);
+        }
+        if failed.is_empty() {
+            return Ok(WorkflowStatus::Success);
+        }
+        for build in failed {
+            info!(
+                "Retrying failed azure pipelines build for '{}'",
+                build.definition.name
+            );
+            self.client.retry_build(build.id).await?;
+        }
+        Ok(WorkflowStatus::Triggered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Branch;
+    use rstest::rstest;
+    use std::future;
+
+    fn make_build(id: u64, status: BuildStatus, result: Option<BuildResult>) -> Build {
+        Build {
+            id,
+            status,
+            result,
+            source_version: "mysha".into(),
+            definition: Definition {
+                id: 1,
+                name: "ci".into(),
+            },
+        }
+    }
+
+    #[rstest]
+    #[case::dev_azure("https://dev.azure.com/potato/smasher/_build/results?buildId=1", true)]
+    #[case::visualstudio(
+        "https://potato.visualstudio.com/smasher/_build/results?buildId=1",
+        true
+    )]
+    #[case::other_organization("https://dev.azure.com/tomato/smasher/_build/results", false)]
+    #[case::other_service("https://circleci.com/gh/potato/smasher/1", false)]
+    fn test_is_build_url(#[case] url: &str, #[case] expected: bool) {
+        let runner = AzurePipelinesWorkflowRunner::new(
+            Arc::new(MockAzurePipelinesClient::default()),
+            "potato".into(),
+        );
+        assert_eq!(runner.is_build_url(&Url::parse(url).unwrap()), expected);
+    }
+
+    #[rstest]
+    #[case::in_progress(BuildStatus::InProgress, None, WorkflowStatus::Pending)]
+    #[case::succeeded(
+        BuildStatus::Completed,
+        Some(BuildResult::Succeeded),
+        WorkflowStatus::Success
+    )]
+    #[case::failed(
+        BuildStatus::Completed,
+        Some(BuildResult::Failed),
+        WorkflowStatus::Triggered
+    )]
+    #[tokio::test]
+    async fn test_process_failed_jobs(
+        #[case] status: BuildStatus,
+        #[case] result: Option<BuildResult>,
+        #[case] expected: WorkflowStatus,
+    ) {
+        let mut client = MockAzurePipelinesClient::default();
+        // The older build for the same pipeline should be ignored
+        let builds = vec![
+            make_build(2, status, result),
+            make_build(1, BuildStatus::Completed, Some(BuildResult::Failed)),
+        ];
+        client
+            .expect_builds()
+            .withf(|branch| branch == "my-branch")
+            .returning(move |_| Box::pin(future::ready(Ok(builds.clone()))));
+        client
+            .expect_retry_build()
+            .withf(|build_id| *build_id == 2)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+        let runner = AzurePipelinesWorkflowRunner::new(Arc::new(client), "potato".into());
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                name: "my-branch".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job_urls =
+            vec![
+                Url::parse("https://dev.azure.com/potato/smasher/_build/results?buildId=2")
+                    .unwrap(),
+            ];
+        let status = runner
+            .process_failed_jobs(&pull_request, &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, expected);
+    }
+}
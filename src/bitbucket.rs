@@ -0,0 +1,585 @@
+use crate::client::{ApiClient, Result};
+use crate::config::{MergeConfig, ReviewsConfig};
+use crate::github::MergeMethod;
+use crate::processing::steps::CheckNotFrozen;
+use crate::processing::{DirectorState, Error, PullRequestProcessor};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use reqwest::Url;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+const API_BASE: &str = "https://api.bitbucket.org/2.0";
+const APP_HOST: &str = "bitbucket.org";
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum PullRequestState {
+    #[serde(rename = "OPEN")]
+    Open,
+
+    #[serde(rename = "MERGED")]
+    Merged,
+
+    #[serde(rename = "DECLINED")]
+    Declined,
+
+    #[serde(rename = "SUPERSEDED")]
+    Superseded,
+
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Commit {
+    pub hash: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Endpoint {
+    pub commit: Commit,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct User {
+    pub display_name: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Participant {
+    pub user: User,
+    pub approved: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PullRequest {
+    pub id: u64,
+    pub title: String,
+    pub state: PullRequestState,
+    pub source: Endpoint,
+
+    #[serde(default)]
+    pub draft: bool,
+
+    #[serde(default)]
+    pub participants: Vec<Participant>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum BuildState {
+    #[serde(rename = "SUCCESSFUL")]
+    Successful,
+
+    #[serde(rename = "FAILED")]
+    Failed,
+
+    #[serde(rename = "STOPPED")]
+    Stopped,
+
+    #[serde(other)]
+    InProgress,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BuildStatus {
+    pub name: String,
+    pub state: BuildState,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub values: Vec<T>,
+}
+
+/// The ways bitbucket can merge a pull request
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum MergeStrategy {
+    #[serde(rename = "merge_commit")]
+    MergeCommit,
+
+    #[serde(rename = "squash")]
+    Squash,
+
+    /// Doesn't create a merge commit, which requires the branch to be up to date
+    #[serde(rename = "fast_forward")]
+    FastForward,
+}
+
+impl From<&MergeMethod> for MergeStrategy {
+    fn from(method: &MergeMethod) -> Self {
+        match method {
+            MergeMethod::Merge => Self::MergeCommit,
+            MergeMethod::Squash => Self::Squash,
+            MergeMethod::Rebase => Self::FastForward,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MergePullRequestBody {
+    pub merge_strategy: MergeStrategy,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NoBody {}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PullRequestIdentifier {
+    pub workspace: String,
+    pub repo: String,
+    pub id: u64,
+}
+
+impl PullRequestIdentifier {
+    /// Checks whether this URL points to bitbucket cloud
+    pub fn is_pull_request_url(url: &Url) -> bool {
+        url.host_str() == Some(APP_HOST)
+    }
+
+    pub fn from_app_url(url: &Url) -> std::result::Result<Self, InvalidUrlError> {
+        if !Self::is_pull_request_url(url) {
+            return Err(InvalidUrlError::InvalidDomain);
+        }
+        // URLs look like `/{workspace}/{repo}/pull-requests/{id}[/overview]`
+        let path_parts: Vec<_> = url
+            .path_segments()
+            .ok_or(InvalidUrlError::NotPullRequestUrl)?
+            .collect();
+        if path_parts.len() < 4 || path_parts[2] != "pull-requests" {
+            return Err(InvalidUrlError::NotPullRequestUrl);
+        }
+        if path_parts[0].is_empty() || path_parts[1].is_empty() {
+            return Err(InvalidUrlError::NotPullRequestUrl);
+        }
+        let id = path_parts[3]
+            .parse()
+            .map_err(|_| InvalidUrlError::NotPullRequestUrl)?;
+        Ok(Self {
+            workspace: path_parts[0].into(),
+            repo: path_parts[1].into(),
+            id,
+        })
+    }
+
+    pub fn app_url(&self) -> String {
+        format!(
+            "https://{}/{}/{}/pull-requests/{}",
+            APP_HOST, self.workspace, self.repo, self.id
+        )
+    }
+}
+
+impl fmt::Display for PullRequestIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/pull-requests/{}",
+            self.workspace, self.repo, self.id
+        )
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum InvalidUrlError {
+    #[error("invalid domain")]
+    InvalidDomain,
+
+    #[error("not a pull request URL")]
+    NotPullRequestUrl,
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait BitbucketClient: Send + Sync {
+    async fn pull_request_info(&self, id: &PullRequestIdentifier) -> Result<PullRequest>;
+    async fn pull_request_statuses(&self, id: &PullRequestIdentifier) -> Result<Page<BuildStatus>>;
+    async fn merge_pull_request(
+        &self,
+        id: &PullRequestIdentifier,
+        body: &MergePullRequestBody,
+    ) -> Result<NoBody>;
+}
+
+#[derive(Clone)]
+pub struct DefaultBitbucketClient {
+    client: ApiClient,
+}
+
+impl DefaultBitbucketClient {
+    /// Creates a client that authenticates using an app password
+    pub fn new<U: Into<String>, P: Into<String>>(username: U, app_password: P) -> Self {
+        Self {
+            client: ApiClient::from_credentials(username, app_password),
+        }
+    }
+
+    fn make_pull_request_url(id: &PullRequestIdentifier) -> String {
+        format!(
+            "{}/repositories/{}/{}/pullrequests/{}",
+            API_BASE, id.workspace, id.repo, id.id
+        )
+    }
+}
+
+#[async_trait]
+impl BitbucketClient for DefaultBitbucketClient {
+    async fn pull_request_info(&self, id: &PullRequestIdentifier) -> Result<PullRequest> {
+        self.client.get(&Self::make_pull_request_url(id)).await
+    }
+
+    async fn pull_request_statuses(&self, id: &PullRequestIdentifier) -> Result<Page<BuildStatus>> {
+        let url = format!("{}/statuses?pagelen=100", Self::make_pull_request_url(id));
+        self.client.get(&url).await
+    }
+
+    async fn merge_pull_request(
+        &self,
+        id: &PullRequestIdentifier,
+        body: &MergePullRequestBody,
+    ) -> Result<NoBody> {
+        let url = format!("{}/merge", Self::make_pull_request_url(id));
+        self.client.post(&url, body).await
+    }
+}
+
+/// The checks run on a pull request, in the order they're run
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Check {
+    Frozen,
+    Builds,
+    Merge,
+}
+
+/// Runs the same checks as the github `Director` but on bitbucket cloud pull requests
+pub struct BitbucketDirector {
+    bitbucket: Arc<dyn BitbucketClient>,
+    identifier: PullRequestIdentifier,
+    reviews: Option<ReviewsConfig>,
+    merge_method: MergeMethod,
+    dry_run: bool,
+    pull_request: Option<PullRequest>,
+    merged_with: Option<MergeMethod>,
+    last_waiting: Option<(Check, String)>,
+    progressed: bool,
+    freeze_check: Option<CheckNotFrozen>,
+}
+
+impl BitbucketDirector {
+    pub fn new(
+        bitbucket: Arc<dyn BitbucketClient>,
+        identifier: PullRequestIdentifier,
+        reviews: Option<ReviewsConfig>,
+        merge: MergeConfig,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            bitbucket,
+            identifier,
+            reviews,
+            merge_method: merge.default_method,
+            dry_run,
+            pull_request: None,
+            merged_with: None,
+            last_waiting: None,
+            progressed: false,
+            freeze_check: None,
+        }
+    }
+
+    /// Waits instead of merging while the given check finds merges are frozen
+    pub fn with_freeze_check(mut self, freeze_check: CheckNotFrozen) -> Self {
+        self.freeze_check = Some(freeze_check);
+        self
+    }
+
+    /// Checks whether the pull request can be processed, returning the state to finish with if
+    /// it was already merged or closed
+    fn check_current_state(
+        pull_request: &PullRequest,
+    ) -> std::result::Result<Option<DirectorState>, Error> {
+        match pull_request.state {
+            PullRequestState::Open if pull_request.draft => {
+                Err(Error::as_generic("pull request is a draft"))
+            }
+            PullRequestState::Open => Ok(None),
+            PullRequestState::Merged => {
+                info!("Pull request was already merged");
+                Ok(Some(DirectorState::Done))
+            }
+            PullRequestState::Declined | PullRequestState::Superseded => {
+                info!("Pull request was closed without merging");
+                Ok(Some(DirectorState::Closed))
+            }
+            PullRequestState::Unknown => Err(Error::UnsupportedPullRequestState(
+                "pull request state is unknown".into(),
+            )),
+        }
+    }
+
+    async fn check_builds(&self) -> std::result::Result<bool, Error> {
+        let statuses = self
+            .bitbucket
+            .pull_request_statuses(&self.identifier)
+            .await?;
+        let mut pending = 0;
+        for status in statuses.values {
+            match status.state {
+                BuildState::Successful => (),
+                BuildState::Failed | BuildState::Stopped => {
+                    return Err(Error::as_generic(format!("build '{}' failed", status.name)));
+                }
+                BuildState::InProgress => pending += 1,
+            };
+        }
+        if pending > 0 {
+            info!("Waiting for {} builds to finish", pending);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn check_approvals(&self, pull_request: &PullRequest) -> std::result::Result<(), Error> {
+        let reviews = match &self.reviews {
+            Some(reviews) => reviews,
+            None => return Ok(()),
+        };
+        let total_approved = pull_request
+            .participants
+            .iter()
+            .filter(|participant| participant.approved)
+            .count();
+        if total_approved < reviews.approvals as usize {
+            return Err(Error::as_generic(format!(
+                "not enough approvals (need {}, have {})",
+                reviews.approvals, total_approved
+            )));
+        }
+        Ok(())
+    }
+
+    async fn merge(&mut self) -> std::result::Result<DirectorState, Error> {
+        let method = self.merge_method.clone();
+        if self.dry_run {
+            info!("Skipping pull request merge step");
+            self.merged_with = Some(method);
+            return Ok(DirectorState::Done);
+        }
+        let body = MergePullRequestBody {
+            merge_strategy: MergeStrategy::from(&method),
+        };
+        match self
+            .bitbucket
+            .merge_pull_request(&self.identifier, &body)
+            .await
+        {
+            Ok(_) => {
+                info!("Pull request merged ✔️");
+                self.merged_with = Some(method);
+                Ok(DirectorState::Done)
+            }
+            Err(e) if e.conflict() => {
+                warn!("Pull request can't be merged yet");
+                Ok(DirectorState::Waiting)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn wait(&mut self, check: Check, pull_request: &PullRequest) -> DirectorState {
+        info!("Check '{:?}' is pending", check);
+        let waiting = Some((check, pull_request.source.commit.hash.clone()));
+        self.progressed = self.last_waiting != waiting;
+        self.last_waiting = waiting;
+        DirectorState::Waiting
+    }
+}
+
+#[async_trait(?Send)]
+impl PullRequestProcessor for BitbucketDirector {
+    async fn run(&mut self) -> std::result::Result<DirectorState, Error> {
+        debug!("Fetching current state for pull request");
+        let pull_request = self.bitbucket.pull_request_info(&self.identifier).await?;
+        self.pull_request = Some(pull_request.clone());
+        if let Some(state) = Self::check_current_state(&pull_request)? {
+            return Ok(state);
+        }
+        if let Some(freeze_check) = &self.freeze_check {
+            if freeze_check.frozen()? {
+                return Ok(self.wait(Check::Frozen, &pull_request));
+            }
+        }
+        if !self.check_builds().await? {
+            return Ok(self.wait(Check::Builds, &pull_request));
+        }
+        self.check_approvals(&pull_request)?;
+        info!("All checks passed, pull request is ready to be merged!");
+        match self.merge().await? {
+            DirectorState::Waiting => Ok(self.wait(Check::Merge, &pull_request)),
+            state => Ok(state),
+        }
+    }
+
+    fn progressed(&self) -> bool {
+        self.progressed
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.pull_request.as_ref().map(|pr| pr.title.as_str())
+    }
+
+    fn merge_method(&self) -> Option<&MergeMethod> {
+        self.merged_with.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FreezePeriod;
+    use chrono::{FixedOffset, Utc};
+    use std::future;
+
+    fn make_director(
+        bitbucket: MockBitbucketClient,
+        reviews: Option<ReviewsConfig>,
+    ) -> BitbucketDirector {
+        let identifier = PullRequestIdentifier {
+            workspace: "potato".into(),
+            repo: "smasher".into(),
+            id: 1337,
+        };
+        let merge = MergeConfig {
+            default_method: MergeMethod::Squash,
+            ..Default::default()
+        };
+        BitbucketDirector::new(Arc::new(bitbucket), identifier, reviews, merge, false)
+    }
+
+    fn make_status(state: BuildState) -> BuildStatus {
+        BuildStatus {
+            name: "build".into(),
+            state,
+        }
+    }
+
+    #[test]
+    fn pull_request_from_app_url() {
+        let url =
+            Url::parse("https://bitbucket.org/potato/smasher/pull-requests/1337/overview").unwrap();
+        assert!(PullRequestIdentifier::is_pull_request_url(&url));
+        let pr = PullRequestIdentifier::from_app_url(&url).unwrap();
+        assert_eq!(pr.workspace, "potato");
+        assert_eq!(pr.repo, "smasher");
+        assert_eq!(pr.id, 1337);
+        assert_eq!(
+            pr.app_url(),
+            "https://bitbucket.org/potato/smasher/pull-requests/1337"
+        );
+
+        assert!(PullRequestIdentifier::from_app_url(
+            &Url::parse("https://bitbucket.org/potato/smasher/src/master").unwrap()
+        )
+        .is_err());
+        assert!(PullRequestIdentifier::from_app_url(
+            &Url::parse("https://github.com/potato/smasher/pull-requests/1337").unwrap()
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_merge_when_checks_pass() {
+        let pull_request = PullRequest {
+            state: PullRequestState::Open,
+            participants: vec![Participant {
+                approved: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut bitbucket = MockBitbucketClient::default();
+        bitbucket
+            .expect_pull_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+        bitbucket.expect_pull_request_statuses().returning(|_| {
+            Box::pin(future::ready(Ok(Page {
+                values: vec![make_status(BuildState::Successful)],
+            })))
+        });
+        bitbucket
+            .expect_merge_pull_request()
+            .withf(|_, body| body.merge_strategy == MergeStrategy::Squash)
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let mut director = make_director(bitbucket, Some(ReviewsConfig::default()));
+        assert_eq!(director.run().await.unwrap(), DirectorState::Done);
+        assert_eq!(director.merge_method(), Some(&MergeMethod::Squash));
+    }
+
+    #[tokio::test]
+    async fn test_waits_while_frozen() {
+        let pull_request = PullRequest {
+            state: PullRequestState::Open,
+            ..Default::default()
+        };
+        let mut bitbucket = MockBitbucketClient::default();
+        bitbucket
+            .expect_pull_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+
+        let now = Utc::now().with_timezone(&FixedOffset::east(0));
+        let freeze = FreezePeriod {
+            start: now - chrono::Duration::hours(1),
+            end: now + chrono::Duration::hours(1),
+            reason: None,
+        };
+        let mut director = make_director(bitbucket, None)
+            .with_freeze_check(CheckNotFrozen::new(vec![freeze], None));
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_pending_builds() {
+        let pull_request = PullRequest {
+            state: PullRequestState::Open,
+            ..Default::default()
+        };
+        let mut bitbucket = MockBitbucketClient::default();
+        bitbucket
+            .expect_pull_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+        bitbucket.expect_pull_request_statuses().returning(|_| {
+            Box::pin(future::ready(Ok(Page {
+                values: vec![
+                    make_status(BuildState::Successful),
+                    make_status(BuildState::InProgress),
+                ],
+            })))
+        });
+
+        let mut director = make_director(bitbucket, None);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        assert!(director.progressed());
+    }
+
+    #[tokio::test]
+    async fn test_not_enough_approvals() {
+        let pull_request = PullRequest {
+            state: PullRequestState::Open,
+            ..Default::default()
+        };
+        let mut bitbucket = MockBitbucketClient::default();
+        bitbucket
+            .expect_pull_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+        bitbucket
+            .expect_pull_request_statuses()
+            .returning(|_| Box::pin(future::ready(Ok(Page { values: vec![] }))));
+
+        let mut director = make_director(bitbucket, Some(ReviewsConfig::default()));
+        assert!(director.run().await.is_err());
+    }
+}
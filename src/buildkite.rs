@@ -0,0 +1,217 @@
+use crate::client::{ApiClient, Result};
+use crate::github::PullRequest;
+use crate::processing::Error;
+use crate::processing::{WorkflowRunner, WorkflowStatus};
+use async_trait::async_trait;
+use log::info;
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Build {
+    pub number: u64,
+    pub state: BuildState,
+    pub web_url: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildState {
+    Creating,
+    Scheduled,
+    Running,
+    Passed,
+    Failing,
+    Failed,
+
+    /// Waiting on someone to unblock a manual step
+    Blocked,
+
+    Canceling,
+    Canceled,
+    Skipped,
+    NotRun,
+
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NoBody {}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait BuildkiteClient {
+    /// The pipeline's builds for a commit, most recent first
+    async fn builds(&self, commit: &str) -> Result<Vec<Build>>;
+    async fn rebuild(&self, build_number: u64) -> Result<NoBody>;
+}
+
+pub struct DefaultBuildkiteClient {
+    client: ApiClient,
+    pipeline_url: String,
+}
+
+impl DefaultBuildkiteClient {
+    const API_BASE: &'static str = "https://api.buildkite.com/v2";
+
+    pub fn new(organization: &str, pipeline: &str, token: String) -> Self {
+        Self {
+            client: ApiClient::from_bearer_token(token),
+            pipeline_url: format!(
+                "{}/organizations/{}/pipelines/{}",
+                Self::API_BASE,
+                organization,
+                pipeline
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl BuildkiteClient for DefaultBuildkiteClient {
+    async fn builds(&self, commit: &str) -> Result<Vec<Build>> {
+        let url = format!("{}/builds?commit={}", self.pipeline_url, commit);
+        self.client.get(&url).await
+    }
+
+    async fn rebuild(&self, build_number: u64) -> Result<NoBody> {
+        let url = format!("{}/builds/{}/rebuild", self.pipeline_url, build_number);
+        self.client.put(&url, &()).await
+    }
+}
+
+/// Rebuilds failed Buildkite builds for a pull request's head commit
+pub struct BuildkiteWorkflowRunner<C> {
+    client: Arc<C>,
+    organization: String,
+    pipeline: String,
+}
+
+impl<C: BuildkiteClient> BuildkiteWorkflowRunner<C> {
+    pub fn new(client: Arc<C>, organization: String, pipeline: String) -> Self {
+        Self {
+            client,
+            organization,
+            pipeline,
+        }
+    }
+
+    /// Whether a status URL points to a build of our pipeline, e.g.
+    /// `https://buildkite.com/{organization}/{pipeline}/builds/{number}`
+    fn is_build_url(&self, url: &Url) -> bool {
+        if url.domain() != Some("buildkite.com") {
+            return false;
+        }
+        let segments: Vec<_> = match url.path_segments() {
+            Some(segments) => segments.take(2).collect(),
+            None => return false,
+        };
+        segments == [self.organization.as_str(), self.pipeline.as_str()]
+    }
+}
+
+#[async_trait]
+impl<C: BuildkiteClient + Send + Sync> WorkflowRunner for BuildkiteWorkflowRunner<C> {
+    async fn process_failed_jobs(
+        &self,
+        pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> std::result::Result<WorkflowStatus, Error> {
+        if !job_urls.iter().any(|url| self.is_build_url(url)) {
+            return Ok(WorkflowStatus::Success);
+        }
+        let builds = self.client.builds(&pull_request.head.sha).await?;
+        // Only the latest build for the commit matters, older ones were already rebuilt
+        let build = match builds.into_iter().next() {
+            Some(build) => build,
+            None => return Ok(WorkflowStatus::Success),
+        };
+        match build.state {
+            BuildState::Blocked => {
+                info!(
+                    "Buildkite build #{} is blocked, someone needs to unblock it at {}",
+                    build.number, build.web_url
+                );
+                Ok(WorkflowStatus::Pending)
+            }
+            BuildState::Creating
+            | BuildState::Scheduled
+            | BuildState::Running
+            | BuildState::Failing
+            | BuildState::Canceling => {
+                info!("Buildkite build #{} is still running", build.number);
+                Ok(WorkflowStatus::Pending)
+            }
+            BuildState::Failed | BuildState::Canceled => {
+                info!("Rebuilding failed buildkite build #{}", build.number);
+                self.client.rebuild(build.number).await?;
+                Ok(WorkflowStatus::Triggered)
+            }
+            BuildState::Passed | BuildState::Skipped | BuildState::NotRun | BuildState::Unknown => {
+                Ok(WorkflowStatus::Success)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Branch;
+    use rstest::rstest;
+    use std::future;
+
+    fn make_runner(client: MockBuildkiteClient) -> BuildkiteWorkflowRunner<MockBuildkiteClient> {
+        BuildkiteWorkflowRunner::new(Arc::new(client), "potato".into(), "smasher".into())
+    }
+
+    #[rstest]
+    #[case::build("https://buildkite.com/potato/smasher/builds/42", true)]
+    #[case::other_pipeline("https://buildkite.com/potato/masher/builds/42", false)]
+    #[case::other_service("https://circleci.com/potato/smasher/builds/42", false)]
+    fn test_is_build_url(#[case] url: &str, #[case] expected: bool) {
+        let runner = make_runner(MockBuildkiteClient::default());
+        assert_eq!(runner.is_build_url(&Url::parse(url).unwrap()), expected);
+    }
+
+    #[rstest]
+    #[case::running(BuildState::Running, WorkflowStatus::Pending)]
+    #[case::blocked(BuildState::Blocked, WorkflowStatus::Pending)]
+    #[case::passed(BuildState::Passed, WorkflowStatus::Success)]
+    #[case::failed(BuildState::Failed, WorkflowStatus::Triggered)]
+    #[case::canceled(BuildState::Canceled, WorkflowStatus::Triggered)]
+    #[tokio::test]
+    async fn test_process_failed_jobs(#[case] state: BuildState, #[case] expected: WorkflowStatus) {
+        let mut client = MockBuildkiteClient::default();
+        client
+            .expect_builds()
+            .withf(|commit| commit == "mysha")
+            .returning(move |_| {
+                Box::pin(future::ready(Ok(vec![Build {
+                    number: 42,
+                    state: state.clone(),
+                    web_url: "https://buildkite.com/potato/smasher/builds/42".into(),
+                }])))
+            });
+        client
+            .expect_rebuild()
+            .withf(|build_number| *build_number == 42)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+        let runner = make_runner(client);
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job_urls = vec![Url::parse("https://buildkite.com/potato/smasher/builds/42").unwrap()];
+        let status = runner
+            .process_failed_jobs(&pull_request, &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, expected);
+    }
+}
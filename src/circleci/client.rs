@@ -1,12 +1,17 @@
-use super::{Job, NoBody};
+use super::{Job, JobMetrics, JobMetricsPage, NoBody};
 use crate::client::{ApiClient, Result};
 use async_trait::async_trait;
 use serde_derive::Serialize;
 
 #[async_trait]
+#[cfg_attr(test, mockall::automock)]
 pub trait CircleCiClient {
     async fn job_info(&self, owner: &str, repo: &str, job_id: u64) -> Result<Job>;
     async fn rerun_workflow(&self, workflow_id: &str) -> Result<NoBody>;
+
+    /// Metrics about the recent runs of each job in a workflow
+    async fn job_metrics(&self, owner: &str, repo: &str, workflow: &str)
+        -> Result<Vec<JobMetrics>>;
 }
 
 pub struct DefaultCircleCiClient {
@@ -41,6 +46,23 @@ impl CircleCiClient for DefaultCircleCiClient {
         let body = RerunWorkflowBody { from_failed: true };
         self.client.post(&url, &body).await
     }
+
+    async fn job_metrics(
+        &self,
+        owner: &str,
+        repo: &str,
+        workflow: &str,
+    ) -> Result<Vec<JobMetrics>> {
+        let url = format!(
+            "{}/insights/gh/{}/{}/workflows/{}/jobs",
+            Self::API_BASE,
+            owner,
+            repo,
+            workflow
+        );
+        let page: JobMetricsPage = self.client.get(&url).await?;
+        Ok(page.items)
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -5,11 +5,18 @@ pub struct Job {
     pub name: String,
     pub latest_workflow: WorkflowSummary,
     pub status: JobStatus,
+
+    /// Unset until the job starts running
+    #[serde(default)]
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct WorkflowSummary {
     pub id: String,
+
+    #[serde(default)]
+    pub name: String,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -19,6 +26,38 @@ pub enum JobStatus {
 
     #[serde(rename = "success")]
     Success,
+
+    #[serde(rename = "running")]
+    Running,
+
+    #[serde(rename = "queued")]
+    Queued,
+
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct JobMetrics {
+    pub name: String,
+    pub metrics: Metrics,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub duration_metrics: DurationMetrics,
+}
+
+/// Durations of past runs, in seconds
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DurationMetrics {
+    pub median: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct JobMetricsPage {
+    #[serde(default)]
+    pub items: Vec<JobMetrics>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -1,22 +1,44 @@
-use super::CircleCiClient;
+use super::{CircleCiClient, JobStatus};
+use crate::github::PullRequest;
 use crate::processing::Error;
 use crate::processing::{WorkflowRunner, WorkflowStatus};
 use async_trait::async_trait;
-use log::info;
+use chrono::{DateTime, Utc};
+use log::{debug, info};
 use reqwest::Url;
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub struct CircleCiWorkflowRunner<C> {
     client: Arc<C>,
+    required_workflows: Vec<String>,
 }
 
 impl<C: CircleCiClient> CircleCiWorkflowRunner<C> {
     pub fn new(client: Arc<C>) -> Self {
-        Self { client }
+        Self {
+            client,
+            required_workflows: Vec::new(),
+        }
+    }
+
+    /// Only consider jobs in these workflows, accepting failures in any other. All workflows are
+    /// considered if empty.
+    pub fn with_required_workflows(mut self, required_workflows: Vec<String>) -> Self {
+        self.required_workflows = required_workflows;
+        self
+    }
+
+    fn is_required(&self, workflow: &str) -> bool {
+        self.required_workflows.is_empty()
+            || self
+                .required_workflows
+                .iter()
+                .any(|required| required == workflow)
     }
 
-    fn parse_job_url(url: &Url) -> Result<JobUrl, Error> {
+    fn parse_job_url(url: &Url) -> Result<JobUrl<'_>, Error> {
         if url.domain() != Some("circleci.com") {
             return Ok(JobUrl::Unrelated);
         }
@@ -36,12 +58,51 @@ impl<C: CircleCiClient> CircleCiWorkflowRunner<C> {
             job_id,
         })
     }
+
+    /// How long until a job finishes, going by the median duration of its recent runs
+    async fn remaining_time(
+        &self,
+        owner: &str,
+        repo: &str,
+        job_id: u64,
+    ) -> Result<Option<Duration>, Error> {
+        let job = self.client.job_info(owner, repo, job_id).await?;
+        if !matches!(job.status, JobStatus::Running | JobStatus::Queued) {
+            return Ok(None);
+        }
+        let metrics = self
+            .client
+            .job_metrics(owner, repo, &job.latest_workflow.name)
+            .await?;
+        let median = match metrics.into_iter().find(|metrics| metrics.name == job.name) {
+            Some(metrics) => Duration::from_secs(metrics.metrics.duration_metrics.median),
+            None => return Ok(None),
+        };
+        Ok(Some(remaining_time(median, job.started_at, Utc::now())))
+    }
+}
+
+/// The time left for a job typically taking `median` to finish, or all of it if it hasn't started
+fn remaining_time(
+    median: Duration,
+    started_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Duration {
+    let elapsed = started_at
+        .and_then(|started_at| (now - started_at).to_std().ok())
+        .unwrap_or_default();
+    median.saturating_sub(elapsed)
 }
 
 #[async_trait]
 impl<C: CircleCiClient + Send + Sync> WorkflowRunner for CircleCiWorkflowRunner<C> {
-    async fn process_failed_jobs(&self, job_urls: &[Url]) -> Result<WorkflowStatus, Error> {
+    async fn process_failed_jobs(
+        &self,
+        _pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> Result<WorkflowStatus, Error> {
         let mut failed_workflow_ids = HashSet::new();
+        let mut ignored_job_urls = Vec::new();
         for job_url in job_urls {
             let (owner, repo, job_id) = match Self::parse_job_url(job_url)? {
                 JobUrl::Job {
@@ -52,10 +113,26 @@ impl<C: CircleCiClient + Send + Sync> WorkflowRunner for CircleCiWorkflowRunner<
                 JobUrl::Unrelated => continue,
             };
             let job_info = self.client.job_info(owner, repo, job_id).await?;
-            failed_workflow_ids.insert(job_info.latest_workflow.id);
+            let workflow = &job_info.latest_workflow;
+            if self.is_required(&workflow.name) {
+                debug!(
+                    "Considering failed circleci job '{}' in workflow '{}'",
+                    job_info.name, workflow.name
+                );
+                failed_workflow_ids.insert(workflow.id.clone());
+            } else {
+                info!(
+                    "Ignoring failed circleci job '{}' in workflow '{}' as it's not required",
+                    job_info.name, workflow.name
+                );
+                ignored_job_urls.push(job_url.clone());
+            }
         }
         if failed_workflow_ids.is_empty() {
-            return Ok(WorkflowStatus::Success);
+            return match ignored_job_urls.is_empty() {
+                true => Ok(WorkflowStatus::Success),
+                false => Ok(WorkflowStatus::Accepted(ignored_job_urls)),
+            };
         }
         info!(
             "Re-running {} failed circleci workflows",
@@ -66,6 +143,33 @@ impl<C: CircleCiClient + Send + Sync> WorkflowRunner for CircleCiWorkflowRunner<
         }
         Ok(WorkflowStatus::Triggered)
     }
+
+    async fn estimated_completion(
+        &self,
+        _pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> Option<Duration> {
+        let mut estimate = None;
+        for job_url in job_urls {
+            let (owner, repo, job_id) = match Self::parse_job_url(job_url) {
+                Ok(JobUrl::Job {
+                    owner,
+                    repo,
+                    job_id,
+                }) => (owner, repo, job_id),
+                Ok(JobUrl::Unrelated) | Err(_) => continue,
+            };
+            // This is only informative, so failing to estimate shouldn't hold anything up
+            match self.remaining_time(owner, repo, job_id).await {
+                Ok(remaining) => estimate = estimate.max(remaining),
+                Err(e) => debug!(
+                    "Failed to estimate circleci job {}'s duration: {}",
+                    job_id, e
+                ),
+            }
+        }
+        estimate
+    }
 }
 
 enum JobUrl<'a> {
@@ -76,3 +180,72 @@ enum JobUrl<'a> {
     },
     Unrelated,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circleci::{Job, MockCircleCiClient, NoBody, WorkflowSummary};
+    use chrono::TimeZone;
+    use std::future;
+
+    #[tokio::test]
+    async fn test_ignores_workflows_not_required() {
+        let mut client = MockCircleCiClient::default();
+        client.expect_job_info().returning(|_, _, job_id| {
+            let workflow = match job_id {
+                1 => "build",
+                _ => "nightly",
+            };
+            Box::pin(future::ready(Ok(Job {
+                name: format!("job-{}", job_id),
+                latest_workflow: WorkflowSummary {
+                    id: format!("{}-id", workflow),
+                    name: workflow.into(),
+                },
+                status: JobStatus::Failed,
+                started_at: None,
+            })))
+        });
+        client
+            .expect_rerun_workflow()
+            .withf(|workflow_id| workflow_id == "build-id")
+            .times(1)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+        let runner = CircleCiWorkflowRunner::new(Arc::new(client))
+            .with_required_workflows(vec!["build".into()]);
+        let job_urls = vec![
+            Url::parse("https://circleci.com/gh/potato/smasher/2").unwrap(),
+            Url::parse("https://circleci.com/gh/potato/smasher/1").unwrap(),
+        ];
+
+        let status = runner
+            .process_failed_jobs(&PullRequest::default(), &job_urls[..1])
+            .await
+            .unwrap();
+        assert_eq!(status, WorkflowStatus::Accepted(job_urls[..1].to_vec()));
+
+        let status = runner
+            .process_failed_jobs(&PullRequest::default(), &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, WorkflowStatus::Triggered);
+    }
+
+    #[test]
+    fn test_remaining_time() {
+        let now = Utc.timestamp(1_600_000_000, 0);
+        let median = Duration::from_secs(600);
+        let started_at = now - chrono::Duration::minutes(4);
+        assert_eq!(
+            remaining_time(median, Some(started_at), now),
+            Duration::from_secs(360)
+        );
+        assert_eq!(remaining_time(median, None, now), median);
+
+        let started_at = now - chrono::Duration::minutes(20);
+        assert_eq!(
+            remaining_time(median, Some(started_at), now),
+            Duration::ZERO
+        );
+    }
+}
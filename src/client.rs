@@ -1,41 +1,152 @@
+use async_trait::async_trait;
 use backoff::{backoff::Backoff, ExponentialBackoff};
 use log::info;
-use reqwest::{Client, ClientBuilder, RequestBuilder, StatusCode};
+use reqwest::{
+    header::{HeaderMap, AUTHORIZATION, RETRY_AFTER},
+    Certificate, Client, ClientBuilder, RequestBuilder, Response, StatusCode,
+};
 use serde::{de::DeserializeOwned, Serialize};
 use std::fmt::Debug;
+use std::fs;
 use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tokio::time::sleep;
 
-static USER_AGENT: &str = "mergebro";
+static USER_AGENT: &str = concat!("mergebro/", env!("CARGO_PKG_VERSION"));
+
+/// The HTTP client shared by every API client, so connections and settings are reused
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Builds the HTTP client every API client uses, trusting an extra PEM encoded CA certificate if
+/// given. Proxies are picked up from the `HTTPS_PROXY` and `NO_PROXY` environment variables. This
+/// needs to be called before creating any client for the certificate to be used.
+pub fn init_http_client(
+    ca_cert_path: Option<&Path>,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let mut builder = ClientBuilder::new().user_agent(USER_AGENT);
+    if let Some(path) = ca_cert_path {
+        let pem = fs::read(path)
+            .map_err(|e| format!("failed to read CA certificate '{}': {}", path.display(), e))?;
+        let certificate = Certificate::from_pem(&pem)
+            .map_err(|e| format!("invalid CA certificate '{}': {}", path.display(), e))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    HTTP_CLIENT
+        .set(builder.build()?)
+        .map_err(|_| "the HTTP client was already initialized")?;
+    Ok(())
+}
+
+/// The shared HTTP client, using the default settings unless `init_http_client` was called
+pub fn http_client() -> Client {
+    HTTP_CLIENT
+        .get_or_init(|| ClientBuilder::new().user_agent(USER_AGENT).build().unwrap())
+        .clone()
+}
+
+/// The rate limit status as reported by the last API response
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitStatus {
+    pub remaining: u32,
+    pub reset: SystemTime,
+}
+
+impl RateLimitStatus {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name)?.to_str().ok()?.parse::<u64>().ok();
+        let remaining = header("x-ratelimit-remaining")?;
+        let reset = header("x-ratelimit-reset")?;
+        Some(Self {
+            remaining: remaining as u32,
+            reset: UNIX_EPOCH + Duration::from_secs(reset),
+        })
+    }
+
+    pub fn time_until_reset(&self) -> Duration {
+        self.reset
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+}
+
+/// Provides tokens that change over time, e.g. because they expire and need to be refreshed
+#[async_trait]
+pub trait TokenSource: Send + Sync {
+    async fn token(&self) -> Result<String>;
+}
+
+#[derive(Clone)]
+enum Credentials {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+    /// Sent as `Authorization: token <token>`, which some CI APIs expect
+    Token(String),
+    /// Sent as a bearer token, fetching it right before every request
+    Source(Arc<dyn TokenSource>),
+}
+
 #[derive(Clone)]
 pub struct ApiClient {
     client: Client,
-    username: String,
-    password: Option<String>,
+    credentials: Credentials,
+    headers: HeaderMap,
+    rate_limit: Arc<Mutex<Option<RateLimitStatus>>>,
 }
 
 impl ApiClient {
     pub fn from_username<S: Into<String>>(username: S) -> Self {
-        ApiClient::new(username.into(), None)
+        ApiClient::new(Credentials::Basic {
+            username: username.into(),
+            password: None,
+        })
     }
 
     pub fn from_credentials<U: Into<String>, P: Into<String>>(username: U, password: P) -> Self {
-        ApiClient::new(username.into(), Some(password.into()))
+        ApiClient::new(Credentials::Basic {
+            username: username.into(),
+            password: Some(password.into()),
+        })
+    }
+
+    pub fn from_bearer_token<S: Into<String>>(token: S) -> Self {
+        ApiClient::new(Credentials::Bearer(token.into()))
+    }
+
+    pub fn from_token<S: Into<String>>(token: S) -> Self {
+        ApiClient::new(Credentials::Token(token.into()))
+    }
+
+    pub fn from_token_source(source: Arc<dyn TokenSource>) -> Self {
+        ApiClient::new(Credentials::Source(source))
     }
 
-    fn new(username: String, password: Option<String>) -> Self {
-        let client = ClientBuilder::new().user_agent(USER_AGENT).build().unwrap();
+    /// Sends these headers on every request
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    fn new(credentials: Credentials) -> Self {
         Self {
-            client,
-            username,
-            password,
+            client: http_client(),
+            credentials,
+            headers: HeaderMap::new(),
+            rate_limit: Arc::default(),
         }
     }
 
+    pub fn rate_limit_status(&self) -> Option<RateLimitStatus> {
+        self.rate_limit.lock().unwrap().clone()
+    }
+
     pub async fn get<O>(&self, endpoint: &str) -> Result<O>
     where
         O: DeserializeOwned + Debug,
@@ -47,6 +158,30 @@ impl ApiClient {
         .await
     }
 
+    /// Sends a GET request, returning the response's headers rather than its body
+    pub async fn get_headers(&self, endpoint: &str) -> Result<HeaderMap> {
+        retry_request_if_needed(|| async {
+            let builder = self.client.get(endpoint);
+            let response = self.send(builder).await?;
+            Ok(response.headers().clone())
+        })
+        .await
+    }
+
+    /// Sends a GET request, returning the response's headers along with its body
+    pub async fn get_with_headers<O>(&self, endpoint: &str) -> Result<(O, HeaderMap)>
+    where
+        O: DeserializeOwned + Debug,
+    {
+        retry_request_if_needed(|| async {
+            let builder = self.client.get(endpoint);
+            let response = self.send(builder).await?;
+            let headers = response.headers().clone();
+            Ok((Self::parse_body(response).await?, headers))
+        })
+        .await
+    }
+
     pub async fn post<I, O>(&self, endpoint: &str, body: &I) -> Result<O>
     where
         I: Serialize,
@@ -71,18 +206,116 @@ impl ApiClient {
         .await
     }
 
+    pub async fn patch<I, O>(&self, endpoint: &str, body: &I) -> Result<O>
+    where
+        I: Serialize,
+        O: DeserializeOwned + Debug,
+    {
+        retry_request_if_needed(|| {
+            let builder = self.client.patch(endpoint).json(body);
+            self.submit(builder)
+        })
+        .await
+    }
+
+    pub async fn delete<O>(&self, endpoint: &str) -> Result<O>
+    where
+        O: DeserializeOwned + Debug,
+    {
+        retry_request_if_needed(|| {
+            let builder = self.client.delete(endpoint);
+            self.submit(builder)
+        })
+        .await
+    }
+
     async fn submit<O>(&self, builder: RequestBuilder) -> Result<O>
     where
         O: DeserializeOwned,
     {
-        let builder = builder.basic_auth(&self.username, self.password.as_ref());
+        let response = self.send(builder).await?;
+        Self::parse_body(response).await
+    }
+
+    async fn parse_body<O>(response: Response) -> Result<O>
+    where
+        O: DeserializeOwned,
+    {
+        let body = response.bytes().await?;
+        // Some APIs reply with an empty body, e.g. when triggering a build
+        let body: &[u8] = if body.is_empty() { b"{}" } else { &body };
+        Ok(serde_json::from_slice(body)?)
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        let builder = match &self.credentials {
+            Credentials::Basic { username, password } => {
+                builder.basic_auth(username, password.as_ref())
+            }
+            Credentials::Bearer(token) => builder.bearer_auth(token),
+            Credentials::Token(token) => builder.header(AUTHORIZATION, format!("token {}", token)),
+            Credentials::Source(source) => builder.bearer_auth(source.token().await?),
+        };
+        let builder = builder.headers(self.headers.clone());
         let response = builder.send().await?;
+        if let Some(status) = RateLimitStatus::from_headers(response.headers()) {
+            *self.rate_limit.lock().unwrap() = Some(status);
+        }
         if response.status().is_success() {
-            Ok(response.json().await?)
-        } else {
-            Err(Error::Http(response.status()))
+            return Ok(response);
+        }
+        // Github's secondary rate limits reply with a 403 that says how long to back off for
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+        match (response.status(), retry_after) {
+            (StatusCode::FORBIDDEN, Some(seconds)) => {
+                Err(Error::SecondaryRateLimit(Duration::from_secs(seconds)))
+            }
+            (StatusCode::FORBIDDEN, None) => {
+                let headers = response.headers().clone();
+                let body = response.bytes().await.unwrap_or_default();
+                match permission_denied_reason(&headers, &body) {
+                    Some(reason) => Err(Error::PermissionDenied(reason)),
+                    None => Err(Error::Http(StatusCode::FORBIDDEN)),
+                }
+            }
+            (status, _) => Err(Error::Http(status)),
+        }
+    }
+}
+
+/// Why a 403 response was returned if it was because the credentials lack a scope or permission,
+/// rather than e.g. a rate limit or a repo rule
+fn permission_denied_reason(headers: &HeaderMap, body: &[u8]) -> Option<String> {
+    let header = |name| headers.get(name)?.to_str().ok();
+    if let (Some(accepted), Some(granted)) =
+        (header("x-accepted-oauth-scopes"), header("x-oauth-scopes"))
+    {
+        let split = |value: &str| -> Vec<String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|scope| !scope.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let accepted = split(accepted);
+        let granted = split(granted);
+        if !accepted.is_empty() && !accepted.iter().any(|scope| granted.contains(scope)) {
+            return Some(format!(
+                "the token needs one of these scopes: {}",
+                accepted.join(", ")
+            ));
         }
     }
+    let body: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let message = body.get("message")?.as_str()?;
+    // What github replies with when a token or app installation lacks a permission
+    message
+        .starts_with("Resource not accessible by")
+        .then(|| message.to_string())
 }
 
 #[derive(Error, Debug)]
@@ -93,8 +326,24 @@ pub enum Error {
     #[error("request failed with status code {0}")]
     Http(StatusCode),
 
+    #[error("secondary rate limit hit, retry after {}s", .0.as_secs())]
+    SecondaryRateLimit(Duration),
+
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("graphql query failed: {0}")]
+    GraphQl(String),
+
+    #[error("authentication failed: {0}")]
+    Auth(String),
+
+    /// A 403 caused by missing token scopes or permissions
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
 }
 
 impl Error {
@@ -102,6 +351,15 @@ impl Error {
         matches!(self, Self::Http(StatusCode::NOT_FOUND))
     }
 
+    /// Whether the credentials used are invalid or lack the scopes or permissions needed for the
+    /// resource. Other 403s, like ones caused by repo rules, don't count.
+    pub fn unauthorized(&self) -> bool {
+        matches!(
+            self,
+            Self::Http(StatusCode::UNAUTHORIZED) | Self::PermissionDenied(_)
+        )
+    }
+
     pub fn unprocessable_entity(&self) -> bool {
         matches!(self, Self::Http(StatusCode::UNPROCESSABLE_ENTITY))
     }
@@ -114,16 +372,35 @@ impl Error {
         matches!(self, Self::Http(StatusCode::TOO_MANY_REQUESTS))
     }
 
+    pub fn forbidden(&self) -> bool {
+        matches!(
+            self,
+            Self::Http(StatusCode::FORBIDDEN) | Self::PermissionDenied(_)
+        )
+    }
+
     pub fn conflict(&self) -> bool {
         matches!(self, Self::Http(StatusCode::CONFLICT))
     }
+
+    pub fn secondary_rate_limited(&self) -> bool {
+        matches!(self, Self::SecondaryRateLimit(_))
+    }
+
+    /// Whether this is likely a temporary failure, meaning a server error or a timeout
+    pub fn transient(&self) -> bool {
+        match self {
+            Self::Http(status) => status.is_server_error(),
+            Self::Reqwest(e) => e.is_timeout(),
+            _ => false,
+        }
+    }
 }
 
 async fn retry_request_if_needed<F, R, O>(requestor: F) -> Result<O>
 where
     F: Fn() -> R,
     R: Future<Output = Result<O>>,
-    O: DeserializeOwned + Debug,
 {
     // TODO: make configurable
     let mut backoff = ExponentialBackoff::default();
@@ -1,11 +1,31 @@
+use crate::common::RepoIdentifier;
+use crate::config_keys;
 use crate::github::MergeMethod;
-use config::{Config, ConfigError, Environment, File};
+use crate::processing::merge::MessageTemplate;
+use crate::processing::steps::build_glob_pattern;
+use chrono::{DateTime, FixedOffset, NaiveTime, Weekday};
+use chrono_tz::Tz;
+use config::{Config, ConfigError, Environment, File, FileSourceFile, Source};
+use log::debug;
+use serde::{de, Deserializer};
 use serde_derive::Deserialize;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The service name used to look up tokens in the system keyring
+const KEYRING_SERVICE: &str = "mergebro";
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct MergebroConfig {
     pub github: GithubConfig,
 
+    pub gitlab: Option<GitlabConfig>,
+
+    pub bitbucket: Option<BitbucketConfig>,
+
     #[serde(default)]
     pub merge: MergeConfig,
 
@@ -20,55 +40,776 @@ pub struct MergebroConfig {
 
     #[serde(default)]
     pub repos: Vec<RepoConfig>,
+
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    pub webhook: Option<WebhookConfig>,
+
+    pub audit: Option<AuditConfig>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct PollConfig {
     pub delay_seconds: u8,
+
+    #[serde(default = "default_max_delay_seconds")]
+    pub max_delay_seconds: u32,
+
+    #[serde(default = "default_backoff_factor")]
+    pub backoff_factor: f64,
+
+    #[serde(default = "default_rate_limit_threshold")]
+    pub rate_limit_threshold: u32,
+
+    /// How long a single read only step can take before it's retried on the next poll, unless it
+    /// has its own timeout like external checks do. Steps that change things aren't cut off
+    #[serde(default = "default_step_timeout_seconds")]
+    pub step_timeout_seconds: u64,
+
+    /// How many times to re-fetch a pull request while github is computing whether it's mergeable
+    #[serde(default = "default_mergeability_retries")]
+    pub mergeability_retries: u32,
+
+    /// How long to wait before each of those re-fetches
+    #[serde(default = "default_mergeability_retry_delay_seconds")]
+    pub mergeability_retry_delay_seconds: u64,
+
+    /// Overrides of the initial delay for specific repos
+    #[serde(default)]
+    pub repos: Vec<RepoPollConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RepoPollConfig {
+    pub repo: String,
+    pub delay_seconds: u8,
 }
 
 impl Default for PollConfig {
     fn default() -> PollConfig {
-        PollConfig { delay_seconds: 30 }
+        PollConfig {
+            delay_seconds: 30,
+            max_delay_seconds: default_max_delay_seconds(),
+            backoff_factor: default_backoff_factor(),
+            rate_limit_threshold: default_rate_limit_threshold(),
+            step_timeout_seconds: default_step_timeout_seconds(),
+            mergeability_retries: default_mergeability_retries(),
+            mergeability_retry_delay_seconds: default_mergeability_retry_delay_seconds(),
+            repos: Vec::new(),
+        }
     }
 }
 
+fn default_max_delay_seconds() -> u32 {
+    300
+}
+
+fn default_mergeability_retries() -> u32 {
+    3
+}
+
+fn default_mergeability_retry_delay_seconds() -> u64 {
+    2
+}
+
+fn default_backoff_factor() -> f64 {
+    1.5
+}
+
+fn default_rate_limit_threshold() -> u32 {
+    50
+}
+
+fn default_step_timeout_seconds() -> u64 {
+    30
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct MergeConfig {
     pub default_method: MergeMethod,
+
+    #[serde(default = "default_auto_update_branch")]
+    pub auto_update_branch: bool,
+
+    /// The methods to try, in order, if the default one isn't allowed in the repo
+    #[serde(default = "default_method_fallback_order")]
+    pub method_fallback_order: Vec<MergeMethod>,
+
+    /// Whether to try other methods at all if the default one isn't allowed
+    #[serde(default = "default_method_fallback")]
+    pub method_fallback: bool,
+
+    /// The template for squash commit messages, the pull request's body is used if unset
+    #[serde(default)]
+    pub squash_message_template: Option<MessageTemplate>,
+
+    /// Where the title of merge and squash commits comes from
+    #[serde(default)]
+    pub commit_title_source: CommitTitleSource,
+
+    /// The template for commit titles, required when they come from a template
+    #[serde(default)]
+    pub commit_title_template: Option<MessageTemplate>,
+
+    /// The windows of time in which pull requests can be merged, any time if unset
+    #[serde(default)]
+    pub allowed_windows: Option<MergeWindowsConfig>,
+
+    /// How many times to retry merging right away if it conflicts because the head sha changed
+    #[serde(default)]
+    pub conflict_retries: u32,
+
+    /// Pull requests changing more files than this aren't merged unless they have the override label
+    #[serde(default)]
+    pub max_changed_files: Option<u32>,
+
+    /// Pull requests adding and deleting more lines than this aren't merged unless they have the
+    /// override label
+    #[serde(default)]
+    pub max_total_lines: Option<u32>,
+
+    /// The label that lets pull requests over the size limits be merged
+    #[serde(default = "default_size_override_label")]
+    pub size_override_label: String,
+
+    /// A regex pull request titles must match, as they're used as the merge commit's title
+    #[serde(default)]
+    pub title_pattern: Option<String>,
+
+    /// Whether to credit the authors of a pull request's commits via `Co-authored-by` trailers when
+    /// squashing it
+    #[serde(default)]
+    pub preserve_coauthors: bool,
+
+    /// Whether to delete the pull request's head branch once it's merged
+    #[serde(default)]
+    pub delete_branch_on_merge: bool,
+
+    /// Glob patterns of the base branches pull requests can be merged into, any if empty
+    #[serde(default)]
+    pub allowed_base_branches: Vec<String>,
+
+    /// A branch the base branch can't be behind, for pull requests stacked on top of others
+    #[serde(default)]
+    pub require_base_current_with: Option<String>,
+
+    /// How long pull requests need to have been open for before they're merged
+    #[serde(default)]
+    pub min_open_duration_seconds: u64,
+
+    /// Periods of time in which nothing is merged, e.g. deploy freezes around holidays
+    #[serde(default)]
+    pub freeze_periods: Vec<FreezePeriod>,
+
+    /// A file with more freeze periods under a `periods` key, which is read again on every check so
+    /// it can be updated without restarting
+    #[serde(default)]
+    pub freeze_periods_file: Option<String>,
+}
+
+impl MergeConfig {
+    /// This config with a repo's overrides applied on top of it
+    pub fn with_overrides(&self, overrides: &RepoMergeConfig) -> Self {
+        let mut config = self.clone();
+        if let Some(default_method) = &overrides.default_method {
+            config.default_method = default_method.clone();
+        }
+        if let Some(method_fallback_order) = &overrides.method_fallback_order {
+            config.method_fallback_order = method_fallback_order.clone();
+        }
+        if let Some(method_fallback) = overrides.method_fallback {
+            config.method_fallback = method_fallback;
+        }
+        config
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitTitleSource {
+    /// The pull request's title
+    #[default]
+    PrTitle,
+
+    /// The subject of the pull request's earliest commit
+    FirstCommit,
+
+    /// The rendered `commit_title_template`
+    Template,
+}
+
+/// The merge settings that can be overridden for specific repos
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RepoMergeConfig {
+    pub default_method: Option<MergeMethod>,
+    pub method_fallback_order: Option<Vec<MergeMethod>>,
+    pub method_fallback: Option<bool>,
+}
+
+/// A period of time in which merges are blocked. Its start and end are RFC 3339 timestamps.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FreezePeriod {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FreezeCalendar {
+    #[serde(default)]
+    periods: Vec<FreezePeriod>,
+}
+
+/// Loads the freeze periods listed in a file, in any of the formats config files can use
+pub fn load_freeze_periods(path: &Path) -> Result<Vec<FreezePeriod>, ConfigError> {
+    let mut calendar = Config::new();
+    calendar.merge(File::from(path))?;
+    let calendar: FreezeCalendar = calendar.try_into()?;
+    Ok(calendar.periods)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MergeWindowsConfig {
+    pub timezone: Tz,
+    pub windows: Vec<MergeWindow>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MergeWindow {
+    pub days: Vec<Weekday>,
+
+    /// The time the window opens at, in `HH:MM` format
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: NaiveTime,
+
+    /// The time the window closes at, in `HH:MM` format
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: NaiveTime,
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time: String = serde::Deserialize::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&time, "%H:%M").map_err(de::Error::custom)
+}
+
+impl MergeConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.method_fallback_order.is_empty() {
+            return Err(ConfigError::Message(
+                "merge.method_fallback_order can't be empty".into(),
+            ));
+        }
+        for (index, method) in self.method_fallback_order.iter().enumerate() {
+            if self.method_fallback_order[..index].contains(method) {
+                return Err(ConfigError::Message(format!(
+                    "merge.method_fallback_order contains '{:?}' more than once",
+                    method
+                )));
+            }
+        }
+        if self.commit_title_source == CommitTitleSource::Template
+            && self.commit_title_template.is_none()
+        {
+            return Err(ConfigError::Message(
+                "merge.commit_title_template is required when commit_title_source is 'template'"
+                    .into(),
+            ));
+        }
+        if let Some(allowed_windows) = &self.allowed_windows {
+            if allowed_windows.windows.is_empty() {
+                return Err(ConfigError::Message(
+                    "merge.allowed_windows.windows can't be empty".into(),
+                ));
+            }
+            for window in &allowed_windows.windows {
+                if window.days.is_empty() || window.start >= window.end {
+                    return Err(ConfigError::Message(format!(
+                        "invalid merge window {:?} {}-{}, it needs at least one day and to start before it ends",
+                        window.days, window.start, window.end
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for MergeConfig {
     fn default() -> MergeConfig {
         MergeConfig {
             default_method: MergeMethod::Merge,
+            auto_update_branch: default_auto_update_branch(),
+            method_fallback_order: default_method_fallback_order(),
+            method_fallback: default_method_fallback(),
+            squash_message_template: None,
+            commit_title_source: CommitTitleSource::default(),
+            commit_title_template: None,
+            allowed_windows: None,
+            conflict_retries: 0,
+            max_changed_files: None,
+            max_total_lines: None,
+            size_override_label: default_size_override_label(),
+            title_pattern: None,
+            preserve_coauthors: false,
+            delete_branch_on_merge: false,
+            allowed_base_branches: Vec::new(),
+            require_base_current_with: None,
+            min_open_duration_seconds: 0,
+            freeze_periods: Vec::new(),
+            freeze_periods_file: None,
         }
     }
 }
 
+fn default_auto_update_branch() -> bool {
+    true
+}
+
+fn default_size_override_label() -> String {
+    "allow-large-merge".into()
+}
+
+fn default_github_base_url() -> String {
+    "https://github.com".into()
+}
+
+fn default_method_fallback_order() -> Vec<MergeMethod> {
+    vec![MergeMethod::Squash, MergeMethod::Merge, MergeMethod::Rebase]
+}
+
+fn default_method_fallback() -> bool {
+    true
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GithubConfig {
+    /// Not needed when authenticating as a github app
+    #[serde(default)]
     pub username: String,
+
+    /// The URL of the github instance to use, e.g. a github enterprise server
+    #[serde(default = "default_github_base_url")]
+    pub base_url: String,
+
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Falls back to the `GITHUB_TOKEN` environment variable and then the system keyring
+    #[serde(default)]
+    pub token: String,
+
+    /// Authenticates as a github app installation instead of using a username and token
+    #[serde(default)]
+    pub app: Option<GithubAppConfig>,
+
+    /// A PEM encoded CA certificate to trust on top of the system ones, e.g. for a corporate
+    /// proxy. This applies to every other service mergebro talks to as well.
+    #[serde(default)]
+    pub ca_cert_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GithubAppConfig {
+    pub app_id: u64,
+    pub installation_id: u64,
+
+    /// The path to the app's PEM encoded private key
+    pub private_key_path: String,
+}
+
+/// How to retry github API reads and other requests that are safe to repeat when they fail with
+/// server errors or time out
+#[derive(Deserialize, Debug, Clone)]
+pub struct RetryConfig {
+    /// The maximum number of times to attempt a request, including the first one
+    pub max_attempts: u32,
+
+    /// The delay before the first retry, which doubles after every attempt
+    pub base_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 3,
+            base_delay_ms: 500,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GitlabConfig {
     pub token: String,
+
+    /// How many times a failed pipeline is retried before the merge request is considered failed
+    #[serde(default = "default_max_pipeline_retries")]
+    pub max_pipeline_retries: u32,
+}
+
+fn default_max_pipeline_retries() -> u32 {
+    3
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BitbucketConfig {
+    pub username: String,
+
+    /// Falls back to the `BITBUCKET_APP_PASSWORD` environment variable and then the system keyring
+    #[serde(default)]
+    pub app_password: String,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct WorkflowsConfig {
+    pub azure_pipelines: Option<AzurePipelinesConfig>,
+    pub buildkite: Option<BuildkiteConfig>,
     pub circleci: Option<CircleCiConfig>,
+    pub github_actions: Option<GithubActionsConfig>,
+    pub gitlab_ci: Option<GitlabCiConfig>,
+    pub jenkins: Option<JenkinsConfig>,
+    pub teamcity: Option<TeamCityConfig>,
+    pub travis: Option<TravisConfig>,
+
+    /// Status checks and workflows to ignore failures on, as glob patterns like `coverage/*`
+    #[serde(default)]
+    pub ignored_contexts: Vec<String>,
+
+    /// Whether to wait until CI reports the checks required by the base branch, or any checks if
+    /// none are required, rather than considering a pull request without checks as passing
+    #[serde(default)]
+    pub require_checks_present: bool,
+
+    /// How long checks need to have been green before a pull request is merged, so ones that
+    /// regress shortly after passing are caught
+    #[serde(default)]
+    pub min_green_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AzurePipelinesConfig {
+    pub organization: String,
+    pub project: String,
+
+    /// A personal access token. Falls back to the `AZURE_PIPELINES_PAT` environment variable and
+    /// then the system keyring
+    #[serde(default)]
+    pub pat: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BuildkiteConfig {
+    pub organization: String,
+    pub pipeline: String,
+
+    /// Falls back to the `BUILDKITE_TOKEN` environment variable and then the system keyring
+    #[serde(default)]
+    pub token: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct CircleCiConfig {
+    /// Falls back to the `CIRCLECI_TOKEN` environment variable and then the system keyring
+    #[serde(default)]
+    pub token: String,
+
+    /// The workflows whose failed jobs hold up pull requests, all of them if empty
+    #[serde(default)]
+    pub required_workflows: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GitlabCiConfig {
+    #[serde(default = "default_gitlab_base_url")]
+    pub base_url: String,
+
+    /// The numeric ID or the full path, e.g. `group/project`, of the project pipelines run on
+    pub project_id: String,
+
+    /// Falls back to the `GITLAB_CI_TOKEN` environment variable and then the system keyring
+    #[serde(default)]
     pub token: String,
 }
 
+fn default_gitlab_base_url() -> String {
+    "https://gitlab.com".into()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct JenkinsConfig {
+    pub base_url: String,
+    pub username: String,
+
+    /// Falls back to the `JENKINS_API_TOKEN` environment variable and then the system keyring
+    #[serde(default)]
+    pub api_token: String,
+
+    /// Whether unstable builds, e.g. ones with failed tests, should be considered failed
+    #[serde(default = "default_unstable_as_failure")]
+    pub unstable_as_failure: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TeamCityConfig {
+    pub base_url: String,
+
+    /// The ID of the build configuration that builds pull requests
+    pub build_type_id: String,
+
+    /// Falls back to the `TEAMCITY_TOKEN` environment variable and then the system keyring
+    #[serde(default)]
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TravisConfig {
+    /// Falls back to the `TRAVIS_TOKEN` environment variable and then the system keyring
+    #[serde(default)]
+    pub token: String,
+}
+
+fn default_unstable_as_failure() -> bool {
+    true
+}
+
+/// Github actions use the github token so there's nothing to configure other than enabling it
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct GithubActionsConfig {}
+
+/// Configuration for receiving github webhooks via the `serve` command
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    /// The secret webhooks are signed with. Falls back to the `WEBHOOK_SECRET` environment
+    /// variable and then the system keyring
+    #[serde(default)]
+    pub secret: String,
+
+    /// Only pull requests with this label are processed
+    #[serde(default = "default_webhook_label")]
+    pub label: String,
+}
+
+fn default_webhook_label() -> String {
+    "mergebro".into()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AuditConfig {
+    /// The SQLite database merge attempts are recorded in, created if it doesn't exist
+    pub sqlite_path: String,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct NotificationsConfig {
+    pub slack: Option<SlackConfig>,
+    pub discord: Option<DiscordConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SlackConfig {
+    pub webhook_url: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DiscordConfig {
+    pub webhook_url: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct ReviewsConfig {
     pub approvals: u32,
+
+    #[serde(default)]
+    pub require_codeowners: bool,
+
+    /// Labels that prevent a pull request from being merged, compared case insensitively
+    #[serde(default)]
+    pub blocking_labels: Vec<String>,
+
+    /// Labels that must all be present before a pull request is merged, compared case insensitively
+    #[serde(default)]
+    pub required_labels: Vec<String>,
+
+    /// Whether pull requests need to be attached to a milestone before they're merged
+    #[serde(default)]
+    pub require_milestone: bool,
+
+    /// Whether pull requests need to reference an issue they close, like `Closes #42`
+    #[serde(default)]
+    pub require_linked_issue: bool,
+
+    /// Whether the issues referenced need to exist and be open
+    #[serde(default)]
+    pub verify_linked_issue: bool,
+
+    /// Glob patterns the pull request's head branch needs to match one of, any if empty
+    #[serde(default)]
+    pub allowed_branch_patterns: Vec<String>,
+
+    /// A regex matching titles of pull requests that are still a work in progress
+    #[serde(default)]
+    pub wip_title_pattern: Option<String>,
+
+    /// Users that must all approve a pull request, on top of the required number of approvals
+    #[serde(default)]
+    pub required_approvers: Vec<String>,
+
+    /// Whether to ignore reviews by the pull request's author, so only independent ones count
+    #[serde(default = "default_exclude_author")]
+    pub exclude_author: bool,
+
+    /// Users whose reviews never count, e.g. bots that approve pull requests
+    #[serde(default)]
+    pub excluded_reviewers: Vec<String>,
+
+    /// Whether every review thread needs to be resolved before merging
+    #[serde(default)]
+    pub require_resolved_threads: bool,
+
+    /// Whether only approvals given after the last push count, unless github already dismisses
+    /// stale approvals
+    #[serde(default)]
+    pub require_approval_after_last_push: bool,
+
+    /// How long approvals count for, even without new pushes. They never expire if unset.
+    #[serde(default)]
+    pub approval_max_age_seconds: Option<u64>,
+
+    /// Teams that each need to approve a pull request, on top of the required number of approvals
+    #[serde(default)]
+    pub required_teams: Vec<TeamApproval>,
+
+    /// Whether a force push after a pull request was approved makes it wait until it's reviewed
+    /// again
+    #[serde(default)]
+    pub block_on_force_push_after_approval: bool,
+
+    /// Commands that each need to succeed before a pull request is merged
+    #[serde(default)]
+    pub external_checks: Vec<ExternalCheck>,
+
+    /// Lets authorized users approve pull requests by commenting, on top of reviews
+    #[serde(default)]
+    pub comment_approval: Option<CommentApprovalConfig>,
+
+    /// A label that lets pull requests skip the steps in `overridable_steps` while it's on them
+    #[serde(default)]
+    pub override_label: Option<String>,
+
+    /// The names of the steps the override label skips. Steps that keep merging safe, like
+    /// checking reviews or that the head didn't change, can't be skipped.
+    #[serde(default)]
+    pub overridable_steps: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CommentApprovalConfig {
+    /// A line in a comment that approves the pull request
+    #[serde(default = "default_approval_phrase")]
+    pub phrase: String,
+
+    /// A line in a comment that withdraws an approval given by an earlier comment
+    #[serde(default = "default_retract_phrase")]
+    pub retract_phrase: String,
+
+    /// The teams whose members can approve via comments, in the same format as `required_teams`
+    pub authorized_teams: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ExternalCheck {
+    pub name: String,
+
+    /// The command to run, using `sh -c`
+    pub command: String,
+
+    /// How long the command can run for before it's considered failed
+    #[serde(default = "default_external_check_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TeamApproval {
+    /// The team's slug, prefixed by its organization like `my-org/security` unless it belongs to
+    /// the repo's owner
+    pub team: String,
+
+    /// How many of the team's members need to approve
+    #[serde(default = "default_team_approvals")]
+    pub approvals: u32,
+}
+
+impl TeamApproval {
+    /// The team's organization and slug
+    pub fn org_and_slug<'a>(&'a self, default_org: &'a str) -> (&'a str, &'a str) {
+        team_org_and_slug(&self.team, default_org)
+    }
+}
+
+/// The organization and slug of a team written as `org/slug`, or just `slug` if it belongs to
+/// `default_org`
+pub fn team_org_and_slug<'a>(team: &'a str, default_org: &'a str) -> (&'a str, &'a str) {
+    match team.split_once('/') {
+        Some((org, slug)) => (org, slug),
+        None => (default_org, team),
+    }
+}
+
+fn default_team_approvals() -> u32 {
+    1
+}
+
+fn default_external_check_timeout_seconds() -> u64 {
+    300
+}
+
+fn default_approval_phrase() -> String {
+    "/lgtm".into()
+}
+
+fn default_retract_phrase() -> String {
+    "/hold".into()
+}
+
+fn default_exclude_author() -> bool {
+    true
+}
+
+impl Default for ReviewsConfig {
+    fn default() -> ReviewsConfig {
+        default_reviews_config()
+    }
 }
 
 fn default_reviews_config() -> ReviewsConfig {
-    ReviewsConfig { approvals: 1 }
+    ReviewsConfig {
+        approvals: 1,
+        require_codeowners: false,
+        blocking_labels: Vec::new(),
+        required_labels: Vec::new(),
+        require_milestone: false,
+        require_linked_issue: false,
+        verify_linked_issue: false,
+        allowed_branch_patterns: Vec::new(),
+        wip_title_pattern: None,
+        required_approvers: Vec::new(),
+        exclude_author: default_exclude_author(),
+        excluded_reviewers: Vec::new(),
+        require_resolved_threads: false,
+        require_approval_after_last_push: false,
+        approval_max_age_seconds: None,
+        required_teams: Vec::new(),
+        block_on_force_push_after_approval: false,
+        external_checks: Vec::new(),
+        comment_approval: None,
+        override_label: None,
+        overridable_steps: Vec::new(),
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -77,6 +818,8 @@ pub struct RepoConfig {
 
     pub reviews: Option<ReviewsConfig>,
 
+    pub merge: Option<RepoMergeConfig>,
+
     #[serde(default)]
     pub statuses: Vec<StatusConfig>,
 }
@@ -95,11 +838,548 @@ pub struct StatusFailuresConfig {
 }
 
 impl MergebroConfig {
-    pub fn new(config_file_path: &str) -> Result<Self, ConfigError> {
-        let mut config = Config::new();
-        let config_file_path = shellexpand::tilde(config_file_path);
-        config.merge(File::with_name(&config_file_path).required(false))?;
+    /// Loads the given config files in order, so later ones override earlier ones. Directories
+    /// are expanded to the YAML files in them, sorted by name, and environment variables override
+    /// every file.
+    pub fn new(config_file_paths: &[&str]) -> Result<Self, ConfigError> {
+        let mut config = Self::load_files(config_file_paths)?;
         config.merge(Environment::with_prefix("mergebro").separator("_"))?;
-        config.try_into()
+        if config.get_table("github").is_err() {
+            return Err(ConfigError::Message(format!(
+                "no github section found in config files '{}' nor environment variables",
+                config_file_paths.join("', '")
+            )));
+        }
+        let mut config: Self = config.try_into()?;
+        config.merge.validate()?;
+        for repo_config in &config.repos {
+            if let Some(overrides) = &repo_config.merge {
+                config
+                    .merge
+                    .with_overrides(overrides)
+                    .validate()
+                    .map_err(|e| {
+                        ConfigError::Message(format!("repo '{}': {}", repo_config.repo, e))
+                    })?;
+            }
+        }
+        if config.github.app.is_none() {
+            if config.github.username.is_empty() {
+                return Err(ConfigError::Message(
+                    "github.username is required unless github.app is configured".into(),
+                ));
+            }
+            config.github.token = resolve_token("github", &config.github.token, "GITHUB_TOKEN")?;
+        }
+        if let Some(azure) = &mut config.workflows.azure_pipelines {
+            azure.pat = resolve_token("azure_pipelines", &azure.pat, "AZURE_PIPELINES_PAT")?;
+        }
+        if let Some(buildkite) = &mut config.workflows.buildkite {
+            buildkite.token = resolve_token("buildkite", &buildkite.token, "BUILDKITE_TOKEN")?;
+        }
+        if let Some(circleci) = &mut config.workflows.circleci {
+            circleci.token = resolve_token("circleci", &circleci.token, "CIRCLECI_TOKEN")?;
+        }
+        if let Some(gitlab_ci) = &mut config.workflows.gitlab_ci {
+            gitlab_ci.token = resolve_token("gitlab_ci", &gitlab_ci.token, "GITLAB_CI_TOKEN")?;
+        }
+        if let Some(jenkins) = &mut config.workflows.jenkins {
+            jenkins.api_token = resolve_token("jenkins", &jenkins.api_token, "JENKINS_API_TOKEN")?;
+        }
+        if let Some(teamcity) = &mut config.workflows.teamcity {
+            teamcity.token = resolve_token("teamcity", &teamcity.token, "TEAMCITY_TOKEN")?;
+        }
+        if let Some(travis) = &mut config.workflows.travis {
+            travis.token = resolve_token("travis", &travis.token, "TRAVIS_TOKEN")?;
+        }
+        if let Some(webhook) = &mut config.webhook {
+            webhook.secret = resolve_token("webhook", &webhook.secret, "WEBHOOK_SECRET")?;
+        }
+        if let Some(bitbucket) = &mut config.bitbucket {
+            bitbucket.app_password = resolve_token(
+                "bitbucket",
+                &bitbucket.app_password,
+                "BITBUCKET_APP_PASSWORD",
+            )?;
+        }
+        Ok(config)
+    }
+
+    /// Merges the given config files in order, without environment variables
+    fn load_files(config_file_paths: &[&str]) -> Result<Config, ConfigError> {
+        let mut config = Config::new();
+        for config_file_path in config_file_paths {
+            let config_file_path = shellexpand::tilde(config_file_path);
+            let path = Path::new(config_file_path.as_ref());
+            if path.is_dir() {
+                for file_path in config_files_in(path)? {
+                    config.merge(load_config_file(
+                        File::from(file_path.as_path()),
+                        &file_path,
+                    )?)?;
+                }
+            } else {
+                let source = File::with_name(&config_file_path).required(false);
+                config.merge(load_config_file(source, path)?)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// The keys set in the given config files that aren't known settings, which are likely typos
+    pub fn unknown_keys(config_file_paths: &[&str]) -> Result<Vec<String>, ConfigError> {
+        let value: serde_json::Value = Self::load_files(config_file_paths)?.try_into()?;
+        let unknown = config_keys::unknown_keys::<Self>(&value)
+            .into_iter()
+            .filter(|key| key != "extends")
+            .collect();
+        Ok(unknown)
+    }
+
+    /// Looks for settings that are valid on their own but can't work or likely aren't intended
+    pub fn check(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+        let mut repos = HashSet::new();
+        for repo_config in &self.repos {
+            match repo_config.repo.parse::<RepoIdentifier>() {
+                Ok(repo) => {
+                    if !repos.insert(repo) {
+                        issues.push(ConfigIssue::Error(format!(
+                            "repo '{}' is configured more than once",
+                            repo_config.repo
+                        )));
+                    }
+                }
+                Err(e) => issues.push(ConfigIssue::Error(format!(
+                    "invalid repo '{}': {}",
+                    repo_config.repo, e
+                ))),
+            }
+        }
+        for repo_config in &self.repos {
+            let context = format!(" for repo '{}'", repo_config.repo);
+            if let Some(reviews) = &repo_config.reviews {
+                check_reviews_patterns(reviews, &context, &mut issues);
+            }
+            if let Some(overrides) = &repo_config.merge {
+                if let Err(e) = self.merge.with_overrides(overrides).validate() {
+                    issues.push(ConfigIssue::Error(format!("{}{}", e, context)));
+                }
+            }
+        }
+        for repo_config in &self.poll.repos {
+            if let Err(e) = repo_config.repo.parse::<RepoIdentifier>() {
+                issues.push(ConfigIssue::Error(format!(
+                    "invalid poll repo '{}': {}",
+                    repo_config.repo, e
+                )));
+            }
+        }
+        if let Some(title_pattern) = &self.merge.title_pattern {
+            if let Err(e) = regex::Regex::new(title_pattern) {
+                issues.push(ConfigIssue::Error(format!(
+                    "invalid merge.title_pattern: {}",
+                    e
+                )));
+            }
+        }
+        check_globs(
+            "merge.allowed_base_branches",
+            &self.merge.allowed_base_branches,
+            "",
+            &mut issues,
+        );
+        check_reviews_patterns(&self.reviews, "", &mut issues);
+        for period in &self.merge.freeze_periods {
+            if period.end <= period.start {
+                issues.push(ConfigIssue::Warning(format!(
+                    "freeze period starting at {} ends before it starts",
+                    period.start
+                )));
+            }
+        }
+        if self.poll.delay_seconds == 0 {
+            issues.push(ConfigIssue::Warning(
+                "poll.delay_seconds is 0, pull requests will be polled without pausing".into(),
+            ));
+        }
+        if self.poll.max_delay_seconds < self.poll.delay_seconds as u32 {
+            issues.push(ConfigIssue::Warning(format!(
+                "poll.max_delay_seconds ({}) is lower than poll.delay_seconds ({})",
+                self.poll.max_delay_seconds, self.poll.delay_seconds
+            )));
+        }
+        if self.poll.backoff_factor < 1.0 {
+            issues.push(ConfigIssue::Warning(format!(
+                "poll.backoff_factor ({}) is lower than 1, delays will shrink over time",
+                self.poll.backoff_factor
+            )));
+        }
+        if self.reviews.approvals == 0 {
+            issues.push(ConfigIssue::Warning(
+                "reviews.approvals is 0, pull requests can be merged without approvals unless \
+                 their branch requires them"
+                    .into(),
+            ));
+        }
+        issues
+    }
+}
+
+/// Reports the regexes and globs in a reviews config that can't be used. `context` is appended to
+/// every message, e.g. to name the repo the config is for.
+fn check_reviews_patterns(reviews: &ReviewsConfig, context: &str, issues: &mut Vec<ConfigIssue>) {
+    if let Some(wip_title_pattern) = &reviews.wip_title_pattern {
+        if let Err(e) = regex::Regex::new(wip_title_pattern) {
+            issues.push(ConfigIssue::Error(format!(
+                "invalid reviews.wip_title_pattern{}: {}",
+                context, e
+            )));
+        }
+    }
+    check_globs(
+        "reviews.allowed_branch_patterns",
+        &reviews.allowed_branch_patterns,
+        context,
+        issues,
+    );
+}
+
+/// Reports glob patterns that can't be built, or that are empty and so never match a branch
+fn check_globs(key: &str, patterns: &[String], context: &str, issues: &mut Vec<ConfigIssue>) {
+    for pattern in patterns {
+        if pattern.is_empty() {
+            issues.push(ConfigIssue::Error(format!(
+                "{}{} contains an empty pattern",
+                key, context
+            )));
+        } else if let Err(e) = build_glob_pattern(pattern) {
+            issues.push(ConfigIssue::Error(format!(
+                "invalid {} pattern '{}'{}: {}",
+                key, pattern, context, e
+            )));
+        }
+    }
+}
+
+/// A problem found when checking a config
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigIssue {
+    /// The config can't be used as is
+    Error(String),
+
+    Warning(String),
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error(message) => write!(f, "error: {}", message),
+            Self::Warning(message) => write!(f, "warning: {}", message),
+        }
+    }
+}
+
+/// Loads a config source on top of the file it `extends`, if any. Relative paths are resolved
+/// against the directory of the file that contains them. `parents` holds the files that are
+/// currently being loaded, so circular includes can be detected.
+fn load_with_parents<S>(
+    source: S,
+    path: &Path,
+    parents: &mut Vec<PathBuf>,
+) -> Result<Config, ConfigError>
+where
+    S: Source + Send + Sync + 'static,
+{
+    let mut own = Config::new();
+    own.merge(source)?;
+    let mut config = Config::new();
+    if let Ok(extends) = own.get_str("extends") {
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let parent_path = directory.join(shellexpand::tilde(&extends).as_ref());
+        let canonical = parent_path.canonicalize().map_err(|e| {
+            ConfigError::Message(format!(
+                "failed to load config file '{}' extended by '{}': {}",
+                parent_path.display(),
+                path.display(),
+                e
+            ))
+        })?;
+        if parents.contains(&canonical) {
+            return Err(ConfigError::Message(format!(
+                "circular config extends: '{}' is already being loaded",
+                canonical.display()
+            )));
+        }
+        parents.push(canonical);
+        config = load_with_parents(File::from(parent_path.as_path()), &parent_path, parents)?;
+        parents.pop();
+    }
+    config.merge(own)?;
+    Ok(config)
+}
+
+fn load_config_file(source: File<FileSourceFile>, path: &Path) -> Result<Config, ConfigError> {
+    let mut parents: Vec<_> = path.canonicalize().into_iter().collect();
+    load_with_parents(source, path, &mut parents)
+}
+
+/// The YAML files in a directory, sorted by name
+fn config_files_in(directory: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let entries = fs::read_dir(directory).map_err(|e| {
+        ConfigError::Message(format!(
+            "failed to read config directory '{}': {}",
+            directory.display(),
+            e
+        ))
+    })?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && matches!(
+                    path.extension().and_then(|extension| extension.to_str()),
+                    Some("yaml") | Some("yml")
+                )
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Resolves a token by looking it up in the config, then the given environment variable and
+/// finally the system keyring, under the `mergebro` service and the given name as user.
+fn resolve_token(name: &str, configured: &str, env_var: &str) -> Result<String, ConfigError> {
+    if !configured.is_empty() {
+        return Ok(configured.into());
+    }
+    if let Ok(token) = env::var(env_var) {
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    }
+    match keyring::Entry::new(KEYRING_SERVICE, name).and_then(|entry| entry.get_password()) {
+        Ok(token) => return Ok(token),
+        Err(e) => debug!("Failed to find {} token in keyring: {}", name, e),
+    };
+    Err(ConfigError::Message(format!(
+        "no {} token found, tried: config file, {} environment variable, system keyring (service '{}', user '{}')",
+        name, env_var, KEYRING_SERVICE, name
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_config_with_overrides() {
+        let config = MergeConfig::default();
+        let overrides = RepoMergeConfig {
+            default_method: Some(MergeMethod::Rebase),
+            method_fallback: Some(false),
+            ..Default::default()
+        };
+        let config = config.with_overrides(&overrides);
+        assert_eq!(config.default_method, MergeMethod::Rebase);
+        assert!(!config.method_fallback);
+        assert_eq!(
+            config.method_fallback_order,
+            MergeConfig::default().method_fallback_order
+        );
+    }
+
+    fn make_config_dir(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("mergebro-{}-{}", name, std::process::id()));
+        fs::create_dir_all(directory.join("shared")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_load_with_parents() {
+        let directory = make_config_dir("extends");
+        fs::write(
+            directory.join("shared/base.yaml"),
+            "merge:\n  default_method: squash\n  method_fallback: false\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("config.yaml"),
+            "extends: shared/base.yaml\nmerge:\n  default_method: rebase\n",
+        )
+        .unwrap();
+
+        let path = directory.join("config.yaml");
+        let config = load_with_parents(File::from(path.as_path()), &path, &mut vec![]).unwrap();
+        assert_eq!(config.get_str("merge.default_method").unwrap(), "rebase");
+        assert!(!config.get_bool("merge.method_fallback").unwrap());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_config_files_in_directory() {
+        let directory = make_config_dir("directory");
+        fs::write(
+            directory.join("shared/10-github.yaml"),
+            "github:\n  username: potato\n  token: secret\nmerge:\n  default_method: squash\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("shared/20-merge.yml"),
+            "merge:\n  default_method: rebase\n",
+        )
+        .unwrap();
+        fs::write(directory.join("shared/README.md"), "Not a config").unwrap();
+        fs::write(
+            directory.join("config.yaml"),
+            "merge:\n  method_fallback: false\n",
+        )
+        .unwrap();
+
+        let shared = directory.join("shared");
+        let config_file = directory.join("config.yaml");
+        let config =
+            MergebroConfig::new(&[shared.to_str().unwrap(), config_file.to_str().unwrap()])
+                .unwrap();
+        assert_eq!(config.github.username, "potato");
+        assert_eq!(config.merge.default_method, MergeMethod::Rebase);
+        assert!(!config.merge.method_fallback);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_new_validates_repo_merge_overrides() {
+        let directory = make_config_dir("repo_merge");
+        let config_file = directory.join("config.yaml");
+        fs::write(
+            &config_file,
+            "github:\n  username: potato\n  token: secret\nrepos:\n  - repo: potato/smasher\n    merge:\n      method_fallback_order: [squash, squash]\n",
+        )
+        .unwrap();
+
+        let error = MergebroConfig::new(&[config_file.to_str().unwrap()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "repo 'potato/smasher': merge.method_fallback_order contains 'Squash' more than once"
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_load_with_parents_circular() {
+        let directory = make_config_dir("circular");
+        fs::write(directory.join("config.yaml"), "extends: shared/base.yaml\n").unwrap();
+        fs::write(
+            directory.join("shared/base.yaml"),
+            "extends: ../config.yaml\n",
+        )
+        .unwrap();
+
+        let path = directory.join("config.yaml");
+        let mut parents = vec![path.canonicalize().unwrap()];
+        let result = load_with_parents(File::from(path.as_path()), &path, &mut parents);
+        assert!(result.is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let directory = make_config_dir("validate");
+        let config_file = directory.join("config.yaml");
+        fs::write(
+            &config_file,
+            concat!(
+                "github:\n  username: potato\n  token: secret\n",
+                "merge:\n  default_method: squash\n  defualt_method: rebase\n",
+                "poll:\n  delay_seconds: 0\n",
+                "repos:\n  - repo: potato\n  - repo: potato/smasher\n    reviws: {}\n",
+            ),
+        )
+        .unwrap();
+
+        let config_file = config_file.to_str().unwrap();
+        let config = MergebroConfig::new(&[config_file]).unwrap();
+        assert_eq!(
+            config.check(),
+            vec![
+                ConfigIssue::Error(
+                    "invalid repo 'potato': malformed repo name: too few slashes".into()
+                ),
+                ConfigIssue::Warning(
+                    "poll.delay_seconds is 0, pull requests will be polled without pausing".into()
+                ),
+            ]
+        );
+        assert_eq!(
+            MergebroConfig::unknown_keys(&[config_file]).unwrap(),
+            vec!["merge.defualt_method", "repos[1].reviws"]
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_check_repo_patterns() {
+        let directory = make_config_dir("check_repos");
+        let config_file = directory.join("config.yaml");
+        fs::write(
+            &config_file,
+            concat!(
+                "github:\n  username: potato\n  token: secret\n",
+                "merge:\n  default_method: merge\n  allowed_base_branches: [main, '']\n",
+                "repos:\n  - repo: potato/smasher\n    reviews:\n      approvals: 1\n",
+                "      allowed_branch_patterns: ['feature/*', '']\n",
+                "      wip_title_pattern: '[wip'\n",
+            ),
+        )
+        .unwrap();
+
+        let config = MergebroConfig::new(&[config_file.to_str().unwrap()]).unwrap();
+        let issues = config.check();
+        assert_eq!(issues.len(), 3, "{:?}", issues);
+        assert!(matches!(
+            &issues[0],
+            ConfigIssue::Error(message)
+                if message.starts_with("invalid reviews.wip_title_pattern for repo 'potato/smasher': ")
+        ));
+        assert_eq!(
+            issues[1],
+            ConfigIssue::Error(
+                "reviews.allowed_branch_patterns for repo 'potato/smasher' contains an empty pattern"
+                    .into()
+            )
+        );
+        assert_eq!(
+            issues[2],
+            ConfigIssue::Error("merge.allowed_base_branches contains an empty pattern".into())
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_load_freeze_periods() {
+        let directory = make_config_dir("freeze");
+        let path = directory.join("freezes.yaml");
+        fs::write(
+            &path,
+            concat!(
+                "periods:\n",
+                "  - start: 2026-12-20T00:00:00-05:00\n",
+                "    end: 2027-01-04T09:00:00-05:00\n",
+                "    reason: Holiday freeze\n",
+                "  - start: 2027-03-01T00:00:00Z\n",
+                "    end: 2027-03-02T00:00:00Z\n",
+            ),
+        )
+        .unwrap();
+
+        let periods = load_freeze_periods(&path).unwrap();
+        assert_eq!(periods.len(), 2);
+        assert_eq!(
+            periods[0].start,
+            DateTime::parse_from_rfc3339("2026-12-20T05:00:00Z").unwrap()
+        );
+        assert_eq!(periods[0].reason.as_deref(), Some("Holiday freeze"));
+        assert_eq!(periods[1].reason, None);
+        fs::remove_dir_all(directory).unwrap();
     }
 }
@@ -0,0 +1,212 @@
+use serde::de::{
+    self, value::StringDeserializer, DeserializeOwned, DeserializeSeed, Deserializer,
+    IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+
+/// Finds the keys in a config that don't match any field of `T`, as dotted paths like
+/// `merge.default_mehtod`. These are otherwise silently ignored when deserializing it.
+pub fn unknown_keys<T: DeserializeOwned>(value: &Value) -> Vec<String> {
+    let unknown = RefCell::new(Vec::new());
+    let tracker = KeyTracker {
+        value,
+        path: String::new(),
+        unknown: &unknown,
+    };
+    // Invalid values are reported when loading the config, this only cares about the keys
+    let _ = T::deserialize(tracker);
+    let mut unknown = unknown.into_inner();
+    unknown.sort();
+    unknown
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", path, key),
+    }
+}
+
+/// Deserializes a value, recording the keys of any struct in it that the struct doesn't have
+struct KeyTracker<'a> {
+    value: &'a Value,
+    path: String,
+    unknown: &'a RefCell<Vec<String>>,
+}
+
+impl<'de, 'a> Deserializer<'de> for KeyTracker<'a> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Object(map) => visitor.visit_map(MapTracker::new(map, self.path, self.unknown)),
+            Value::Array(values) => visitor.visit_seq(SeqTracker {
+                values: values.iter().enumerate(),
+                path: self.path,
+                unknown: self.unknown,
+            }),
+            value => value.clone().deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if let Value::Object(map) = self.value {
+            let unknown = map
+                .keys()
+                .filter(|key| !fields.contains(&key.as_str()))
+                .map(|key| join_path(&self.path, key));
+            self.unknown.borrow_mut().extend(unknown);
+        }
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.value.clone().deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+struct MapTracker<'a> {
+    entries: serde_json::map::Iter<'a>,
+    value: Option<(&'a String, &'a Value)>,
+    path: String,
+    unknown: &'a RefCell<Vec<String>>,
+}
+
+impl<'a> MapTracker<'a> {
+    fn new(map: &'a Map<String, Value>, path: String, unknown: &'a RefCell<Vec<String>>) -> Self {
+        Self {
+            entries: map.iter(),
+            value: None,
+            path,
+            unknown,
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for MapTracker<'a> {
+    type Error = serde_json::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some((key, value));
+                let key: StringDeserializer<Self::Error> = key.clone().into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before its key"))?;
+        seed.deserialize(KeyTracker {
+            value,
+            path: join_path(&self.path, key),
+            unknown: self.unknown,
+        })
+    }
+}
+
+struct SeqTracker<'a> {
+    values: std::iter::Enumerate<std::slice::Iter<'a, Value>>,
+    path: String,
+    unknown: &'a RefCell<Vec<String>>,
+}
+
+impl<'de, 'a> SeqAccess<'de> for SeqTracker<'a> {
+    type Error = serde_json::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.values.next() {
+            Some((index, value)) => seed
+                .deserialize(KeyTracker {
+                    value,
+                    path: format!("{}[{}]", self.path, index),
+                    unknown: self.unknown,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::Deserialize;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Config {
+        name: String,
+        nested: Option<Nested>,
+
+        #[serde(default)]
+        items: Vec<Nested>,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Nested {
+        #[serde(rename = "value")]
+        potato: u32,
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let value = serde_json::json!({
+            "name": "smasher",
+            "nmae": "typo",
+            "nested": {"value": 1, "vaule": 2},
+            "items": [{"value": 1}, {"value": 2, "extra": true}],
+        });
+        assert_eq!(
+            unknown_keys::<Config>(&value),
+            vec!["items[1].extra", "nested.vaule", "nmae"]
+        );
+    }
+}
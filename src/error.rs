@@ -0,0 +1,138 @@
+use crate::client;
+use crate::common::{MalformedRepoNameError, RepoMapError};
+use crate::processing;
+use config::ConfigError;
+use std::borrow::Cow;
+use thiserror::Error;
+
+/// The kinds of failures mergebro runs into, so callers can tell them apart, e.g. to pick the
+/// status code to exit with
+#[derive(Error, Debug)]
+pub enum MergebroError {
+    /// The configuration is invalid or is missing something that's needed
+    #[error("{0}")]
+    Config(Cow<'static, str>),
+
+    /// The credentials used were rejected or don't grant access to what's needed
+    #[error("{0}")]
+    Auth(Cow<'static, str>),
+
+    #[error("github API error: {0}")]
+    Github(client::Error),
+
+    /// An external CI service failed while looking at or re-running failed jobs
+    #[error(transparent)]
+    WorkflowRunner(Box<processing::Error>),
+
+    #[error("pull request has conflicts")]
+    Conflicts,
+
+    #[error("{0}")]
+    Generic(Cow<'static, str>),
+}
+
+impl MergebroError {
+    pub fn as_config<T>(message: T) -> Self
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        Self::Config(message.into())
+    }
+
+    pub fn as_generic<T>(message: T) -> Self
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        Self::Generic(message.into())
+    }
+}
+
+impl From<client::Error> for MergebroError {
+    fn from(error: client::Error) -> Self {
+        if matches!(error, client::Error::Auth(_)) || error.unauthorized() {
+            return Self::Auth(error.to_string().into());
+        }
+        Self::Github(error)
+    }
+}
+
+impl From<processing::Error> for MergebroError {
+    fn from(error: processing::Error) -> Self {
+        match error {
+            processing::Error::Client(e) => e.into(),
+            processing::Error::Conflicts => Self::Conflicts,
+            processing::Error::WorkflowRunner(e) => Self::WorkflowRunner(e),
+            error => Self::Generic(error.to_string().into()),
+        }
+    }
+}
+
+impl From<ConfigError> for MergebroError {
+    fn from(error: ConfigError) -> Self {
+        Self::Config(error.to_string().into())
+    }
+}
+
+impl From<regex::Error> for MergebroError {
+    fn from(error: regex::Error) -> Self {
+        Self::Config(error.to_string().into())
+    }
+}
+
+impl From<MalformedRepoNameError> for MergebroError {
+    fn from(error: MalformedRepoNameError) -> Self {
+        Self::Config(error.to_string().into())
+    }
+}
+
+impl From<RepoMapError> for MergebroError {
+    fn from(error: RepoMapError) -> Self {
+        Self::Config(error.to_string().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_from_processing_error() {
+        let error = processing::Error::Client(client::Error::Http(StatusCode::UNAUTHORIZED));
+        assert!(matches!(MergebroError::from(error), MergebroError::Auth(_)));
+
+        let error = processing::Error::Client(client::Error::Http(StatusCode::NOT_FOUND));
+        assert!(matches!(
+            MergebroError::from(error),
+            MergebroError::Github(e) if e.not_found()
+        ));
+
+        let error = processing::Error::Client(client::Error::PermissionDenied(
+            "Resource not accessible by integration".into(),
+        ));
+        assert!(matches!(MergebroError::from(error), MergebroError::Auth(_)));
+
+        let error = processing::Error::Client(client::Error::Http(StatusCode::FORBIDDEN));
+        assert!(matches!(
+            MergebroError::from(error),
+            MergebroError::Github(e) if e.forbidden()
+        ));
+
+        let error = processing::Error::WorkflowRunner(Box::new(processing::Error::Client(
+            client::Error::Http(StatusCode::NOT_FOUND),
+        )));
+        assert!(matches!(
+            MergebroError::from(error),
+            MergebroError::WorkflowRunner(_)
+        ));
+
+        assert!(matches!(
+            MergebroError::from(processing::Error::Conflicts),
+            MergebroError::Conflicts
+        ));
+        assert!(matches!(
+            MergebroError::from(processing::Error::as_generic("not enough approvals")),
+            MergebroError::Generic(_)
+        ));
+    }
+}
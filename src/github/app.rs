@@ -0,0 +1,168 @@
+use crate::client::{ApiClient, Error, Result, TokenSource};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use log::debug;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+/// Authenticates as a github app installation. A JWT signed with the app's private key is
+/// exchanged for an installation token, which is refreshed shortly before it expires.
+pub struct GithubAppAuth {
+    app_id: u64,
+    installation_id: u64,
+    key: PKey<Private>,
+    api_base: String,
+    token: Mutex<Option<InstallationToken>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+struct AccessTokenRequest {}
+
+#[derive(Serialize, Debug)]
+struct JwtHeader {
+    alg: &'static str,
+    typ: &'static str,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct JwtClaims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+impl GithubAppAuth {
+    /// How long before an installation token expires to get a new one
+    const REFRESH_MARGIN_MINUTES: i64 = 5;
+
+    /// Creates an authenticator for an app installation given the app's PEM encoded private key,
+    /// using the github API at `api_base`
+    pub fn new(
+        app_id: u64,
+        installation_id: u64,
+        private_key_pem: &[u8],
+        api_base: String,
+    ) -> std::result::Result<Self, ErrorStack> {
+        Ok(Self {
+            app_id,
+            installation_id,
+            key: PKey::private_key_from_pem(private_key_pem)?,
+            api_base,
+            token: Mutex::default(),
+        })
+    }
+
+    /// A JWT used to authenticate as the app itself. Github rejects ones that are valid for more
+    /// than 10 minutes, and it's backdated a bit in case our clock is ahead of github's.
+    fn make_jwt(&self, now: DateTime<Utc>) -> std::result::Result<String, ErrorStack> {
+        let encode = |value: &[u8]| base64::encode_config(value, base64::URL_SAFE_NO_PAD);
+        let header = JwtHeader {
+            alg: "RS256",
+            typ: "JWT",
+        };
+        let claims = JwtClaims {
+            iat: (now - Duration::seconds(60)).timestamp(),
+            exp: (now + Duration::minutes(9)).timestamp(),
+            iss: self.app_id.to_string(),
+        };
+        // Serializing these can't fail, they only contain strings and numbers
+        let message = format!(
+            "{}.{}",
+            encode(&serde_json::to_vec(&header).unwrap()),
+            encode(&serde_json::to_vec(&claims).unwrap())
+        );
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(message.as_bytes())?;
+        let signature = signer.sign_to_vec()?;
+        Ok(format!("{}.{}", message, encode(&signature)))
+    }
+
+    async fn fetch_token(&self) -> Result<InstallationToken> {
+        let jwt = self
+            .make_jwt(Utc::now())
+            .map_err(|e| Error::Auth(format!("failed to sign github app JWT: {}", e)))?;
+        let url = format!(
+            "{}/app/installations/{}/access_tokens",
+            self.api_base, self.installation_id
+        );
+        let token: InstallationToken = ApiClient::from_bearer_token(jwt)
+            .post(&url, &AccessTokenRequest {})
+            .await?;
+        debug!(
+            "Got github app installation token expiring at {}",
+            token.expires_at
+        );
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl TokenSource for GithubAppAuth {
+    async fn token(&self) -> Result<String> {
+        let mut cached = self.token.lock().await;
+        let refresh_after = Utc::now() + Duration::minutes(Self::REFRESH_MARGIN_MINUTES);
+        match &*cached {
+            Some(token) if token.expires_at > refresh_after => Ok(token.token.clone()),
+            _ => {
+                let token = self.fetch_token().await?;
+                let value = token.token.clone();
+                *cached = Some(token);
+                Ok(value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use openssl::rsa::Rsa;
+    use openssl::sign::Verifier;
+
+    #[test]
+    fn test_make_jwt() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let pem = key.private_key_to_pem_pkcs8().unwrap();
+        let auth = GithubAppAuth::new(1337, 42, &pem, "https://api.github.com".into()).unwrap();
+        let now = Utc.timestamp(1_600_000_000, 0);
+        let jwt = auth.make_jwt(now).unwrap();
+
+        let parts: Vec<_> = jwt.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        let decode = |part| base64::decode_config(part, base64::URL_SAFE_NO_PAD).unwrap();
+        let header: serde_json::Value = serde_json::from_slice(&decode(parts[0])).unwrap();
+        assert_eq!(header, serde_json::json!({"alg": "RS256", "typ": "JWT"}));
+        let claims: JwtClaims = serde_json::from_slice(&decode(parts[1])).unwrap();
+        assert_eq!(
+            claims,
+            JwtClaims {
+                iat: 1_600_000_000 - 60,
+                exp: 1_600_000_000 + 540,
+                iss: "1337".into(),
+            }
+        );
+
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &key).unwrap();
+        verifier
+            .update(format!("{}.{}", parts[0], parts[1]).as_bytes())
+            .unwrap();
+        assert!(verifier.verify(&decode(parts[2])).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_private_key() {
+        let result = GithubAppAuth::new(1337, 42, b"potato", "https://api.github.com".into());
+        assert!(result.is_err());
+    }
+}
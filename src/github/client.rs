@@ -1,11 +1,26 @@
 use super::models::{
-    ActionRuns, Branch, BranchProtection, NoBody, PullRequest, PullRequestIdentifier,
-    PullRequestReview, Repository, Status,
+    ActionRuns, Branch, BranchProtection, CheckRuns, CombinedStatus, Commit, CommitComparison,
+    FileContents, Issue, IssueComment, Label, NoBody, PullRequest, PullRequestFile,
+    PullRequestIdentifier, PullRequestReview, PullRequestSummary, Repository, RequiredStatusChecks,
+    ReviewThread, Status, TimelineEvent, User,
 };
-use crate::client::{ApiClient, Result};
-use crate::github::MergeMethod;
+use crate::client::{ApiClient, Error, RateLimitStatus, Result};
+use crate::config::RetryConfig;
+use crate::github::{GithubAppAuth, MergeMethod};
 use async_trait::async_trait;
-use serde_derive::Serialize;
+use log::{debug, info};
+use openssl::error::ErrorStack;
+use reqwest::header::{HeaderMap, HeaderValue, LINK};
+use reqwest::Url;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde_derive::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
 #[async_trait]
 #[cfg_attr(test, mockall::automock)]
@@ -16,15 +31,126 @@ pub trait GithubClient: Send + Sync {
         pull_request: &PullRequest,
     ) -> Result<Vec<PullRequestReview>>;
     async fn pull_request_statuses(&self, pull_request: &PullRequest) -> Result<Vec<Status>>;
+    async fn pull_request_files(&self, pull_request: &PullRequest) -> Result<Vec<PullRequestFile>>;
+    async fn pull_request_commits(&self, pull_request: &PullRequest) -> Result<Vec<Commit>>;
+    async fn review_threads(&self, pull_request: &PullRequest) -> Result<Vec<ReviewThread>>;
+    async fn timeline_events(&self, pull_request: &PullRequest) -> Result<Vec<TimelineEvent>>;
+    async fn request_reviewers(
+        &self,
+        pull_request: &PullRequest,
+        reviewers: &[String],
+    ) -> Result<NoBody>;
+    async fn file_contents(
+        &self,
+        repo: &Repository,
+        path: &str,
+        git_ref: &str,
+    ) -> Result<FileContents>;
+    async fn team_members(&self, org: &str, team_slug: &str) -> Result<Vec<User>>;
     async fn branch_protection(&self, branch: &Branch) -> Result<BranchProtection>;
+    async fn required_status_checks(&self, branch: &Branch) -> Result<RequiredStatusChecks>;
     async fn update_branch(&self, pull_request: &PullRequest) -> Result<NoBody>;
+    async fn delete_branch(&self, branch: &Branch) -> Result<NoBody>;
     async fn action_runs(&self, pull_request: &PullRequest) -> Result<ActionRuns>;
+    async fn action_runs_for_sha(&self, repo: &Repository, head_sha: &str) -> Result<ActionRuns>;
+    async fn check_runs(&self, repo: &Repository, sha: &str) -> Result<CheckRuns>;
+    async fn combined_status(&self, repo: &Repository, sha: &str) -> Result<CombinedStatus>;
     async fn rerun_workflow(&self, repo: &Repository, run_id: u64) -> Result<NoBody>;
+    async fn commit(&self, repo: &Repository, sha: &str) -> Result<Commit>;
+    async fn compare_commits(
+        &self,
+        repo: &Repository,
+        base: &str,
+        head: &str,
+    ) -> Result<CommitComparison>;
+    async fn commit_pull_requests(
+        &self,
+        repo: &Repository,
+        sha: &str,
+    ) -> Result<Vec<PullRequestSummary>>;
+    /// An issue in a repo given as `owner/repo`
+    async fn issue(&self, repo: &str, number: u32) -> Result<Issue>;
+    async fn add_label(&self, pull_request: &PullRequest, label: &str) -> Result<Vec<Label>>;
+    async fn remove_label(&self, pull_request: &PullRequest, label: &str) -> Result<Vec<Label>>;
+    async fn list_comments(&self, pull_request: &PullRequest) -> Result<Vec<IssueComment>>;
+    async fn create_comment(&self, pull_request: &PullRequest, body: &str) -> Result<IssueComment>;
+    async fn update_comment(
+        &self,
+        repo: &Repository,
+        comment_id: u64,
+        body: &str,
+    ) -> Result<IssueComment>;
     async fn merge_pull_request(
         &self,
         pull_request: &PullRequest,
         body: &MergeRequestBody,
     ) -> Result<NoBody>; // TODO: add body
+    /// Whether the repo's settings allow enabling auto-merge on its pull requests
+    async fn auto_merge_allowed(&self, repo: &Repository) -> Result<bool>;
+    async fn enable_auto_merge(
+        &self,
+        pull_request: &PullRequest,
+        method: &MergeMethod,
+    ) -> Result<()>;
+    fn rate_limit_status(&self) -> Option<RateLimitStatus>;
+
+    /// The scopes granted to the token being used, or `None` if it doesn't have any, like
+    /// fine-grained tokens
+    async fn token_scopes(&self) -> Result<Option<Vec<String>>>;
+}
+
+/// The classic token scopes needed to read and merge pull requests
+pub const REQUIRED_TOKEN_SCOPES: &[&str] = &["repo"];
+
+/// The required scopes that aren't in the given ones
+pub fn missing_token_scopes(scopes: &[String]) -> Vec<&'static str> {
+    REQUIRED_TOKEN_SCOPES
+        .iter()
+        .filter(|required| !scopes.iter().any(|scope| scope == *required))
+        .copied()
+        .collect()
+}
+
+/// The URL of the next page of a list response, taken from its `Link` header
+fn next_page_url(headers: &HeaderMap) -> Option<String> {
+    let header = headers.get(LINK)?.to_str().ok()?;
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
+}
+
+/// Fetches pages starting at the given URL until one doesn't link to a next one
+async fn collect_pages<T, F, R>(url: String, fetch_page: F) -> Result<Vec<T>>
+where
+    F: Fn(String) -> R,
+    R: Future<Output = Result<(Vec<T>, Option<String>)>>,
+{
+    let mut items = Vec::new();
+    let mut next_url = Some(url);
+    while let Some(url) = next_url {
+        let (page, next) = fetch_page(url).await?;
+        items.extend(page);
+        next_url = next;
+    }
+    Ok(items)
+}
+
+fn parse_token_scopes(header: &str) -> Vec<String> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .map(String::from)
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -39,23 +165,176 @@ pub struct MergeRequestBody {
 #[derive(Clone)]
 pub struct DefaultGithubClient {
     client: ApiClient,
+    api_base: String,
+    graphql_url: String,
+    retry: RetryConfig,
 }
 
 impl DefaultGithubClient {
-    const API_BASE: &'static str = "https://api.github.com";
+    const PUBLIC_API_BASE: &'static str = "https://api.github.com";
 
-    pub fn new<U: Into<String>, P: Into<String>>(username: U, password: P) -> Self {
+    /// Creates a client for the github instance at the given base URL, e.g. `https://github.com`
+    pub fn new<U: Into<String>, P: Into<String>>(
+        username: U,
+        password: P,
+        base_url: &Url,
+        retry: RetryConfig,
+    ) -> Self {
         Self {
             client: ApiClient::from_credentials(username, password),
+            api_base: Self::make_api_base(base_url),
+            graphql_url: Self::make_graphql_url(base_url),
+            retry,
         }
     }
 
-    fn make_pull_request_url(pull_request: &PullRequest) -> String {
+    /// Creates a client that authenticates as a github app installation
+    pub fn from_app(
+        app_id: u64,
+        installation_id: u64,
+        private_key_pem: &[u8],
+        base_url: &Url,
+        retry: RetryConfig,
+    ) -> std::result::Result<Self, ErrorStack> {
+        let api_base = Self::make_api_base(base_url);
+        let auth = GithubAppAuth::new(app_id, installation_id, private_key_pem, api_base.clone())?;
+        Ok(Self {
+            client: ApiClient::from_token_source(Arc::new(auth)),
+            api_base,
+            graphql_url: Self::make_graphql_url(base_url),
+            retry,
+        })
+    }
+
+    /// Sends the given ID in the `X-Request-Id` header of every request, so they can be correlated
+    /// with a specific run
+    pub fn with_request_id(self, request_id: &Uuid) -> Self {
+        let mut headers = HeaderMap::new();
+        // The hyphenated form of a UUID is always a valid header value
+        let value = HeaderValue::from_str(&request_id.to_string()).unwrap();
+        headers.insert(REQUEST_ID_HEADER, value);
+        Self {
+            client: self.client.with_headers(headers),
+            ..self
+        }
+    }
+
+    /// Runs a request, retrying it with exponential backoff if it fails with a transient error.
+    /// Only use this for requests that can safely be sent more than once.
+    async fn with_retries<O, F, R>(&self, request: F) -> Result<O>
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<O>>,
+    {
+        self.retry_request(request, true).await
+    }
+
+    /// Runs a request that isn't safe to send twice, only retrying it when github rejected it
+    /// because of a secondary rate limit. A server error or timeout could've happened after the
+    /// request went through.
+    async fn with_rate_limit_retries<O, F, R>(&self, request: F) -> Result<O>
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<O>>,
+    {
+        self.retry_request(request, false).await
+    }
+
+    async fn retry_request<O, F, R>(&self, request: F, retry_transient: bool) -> Result<O>
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<O>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(Error::SecondaryRateLimit(delay)) if attempt < self.retry.max_attempts => {
+                    info!("Secondary rate limit hit, retrying in {}s", delay.as_secs());
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) if retry_transient && e.transient() && attempt < self.retry.max_attempts => {
+                    let delay = self
+                        .retry
+                        .base_delay_ms
+                        .saturating_mul(2u64.saturating_pow(attempt - 1));
+                    debug!(
+                        "Request attempt {} failed, retrying in {}ms: {}",
+                        attempt, delay, e
+                    );
+                    sleep(Duration::from_millis(delay)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Fetches every page of a list endpoint, retrying each page on its own
+    async fn get_all_pages<T>(&self, url: String) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned + std::fmt::Debug,
+    {
+        collect_pages(url, |url| async move {
+            let (page, headers) = self
+                .with_retries(|| self.client.get_with_headers(&url))
+                .await?;
+            Ok((page, next_page_url(&headers)))
+        })
+        .await
+    }
+
+    /// Public github serves its API on a separate host while enterprise servers serve it under
+    /// `/api/v3` in the same one
+    fn make_api_base(base_url: &Url) -> String {
+        if base_url.host_str() == Some("github.com") {
+            return Self::PUBLIC_API_BASE.into();
+        }
+        format!("{}/api/v3", base_url.as_str().trim_end_matches('/'))
+    }
+
+    fn make_graphql_url(base_url: &Url) -> String {
+        if base_url.host_str() == Some("github.com") {
+            return format!("{}/graphql", Self::PUBLIC_API_BASE);
+        }
+        format!("{}/api/graphql", base_url.as_str().trim_end_matches('/'))
+    }
+
+    async fn graphql<V, D>(&self, query: &'static str, variables: V) -> Result<D>
+    where
+        V: serde::Serialize + Send + Sync,
+        D: DeserializeOwned + std::fmt::Debug,
+    {
+        let body = GraphQlRequest { query, variables };
+        let response: GraphQlResponse<D> = self
+            .with_retries(|| self.client.post(&self.graphql_url, &body))
+            .await?;
+        response.into_data()
+    }
+
+    async fn graphql_mutation<V, D>(&self, query: &'static str, variables: V) -> Result<D>
+    where
+        V: serde::Serialize + Send + Sync,
+        D: DeserializeOwned + std::fmt::Debug,
+    {
+        let body = GraphQlRequest { query, variables };
+        let response: GraphQlResponse<D> = self
+            .with_rate_limit_retries(|| self.client.post(&self.graphql_url, &body))
+            .await?;
+        response.into_data()
+    }
+
+    fn make_issue_url(&self, pull_request: &PullRequest) -> String {
+        format!(
+            "{}/repos/{}/issues/{}",
+            self.api_base, pull_request.base.repo.full_name, pull_request.number
+        )
+    }
+
+    fn make_pull_request_url(&self, pull_request: &PullRequest) -> String {
         format!(
             "{}/repos/{}/pulls/{}",
-            Self::API_BASE,
-            pull_request.base.repo.full_name,
-            pull_request.number
+            self.api_base, pull_request.base.repo.full_name, pull_request.number
         )
     }
 }
@@ -65,69 +344,271 @@ impl GithubClient for DefaultGithubClient {
     async fn pull_request_info(&self, id: &PullRequestIdentifier) -> Result<PullRequest> {
         let url = format!(
             "{}/repos/{}/{}/pulls/{}",
-            Self::API_BASE,
-            id.owner,
-            id.repo,
-            id.pull_number
+            self.api_base, id.owner, id.repo, id.pull_number
         );
-        self.client.get(&url).await
+        self.with_retries(|| self.client.get(&url)).await
     }
 
     async fn pull_request_reviews(
         &self,
         pull_request: &PullRequest,
     ) -> Result<Vec<PullRequestReview>> {
-        let url = format!("{}/reviews", Self::make_pull_request_url(pull_request));
-        self.client.get(&url).await
+        let url = format!("{}/reviews", self.make_pull_request_url(pull_request));
+        self.with_retries(|| self.client.get(&url)).await
     }
 
     async fn pull_request_statuses(&self, pull_request: &PullRequest) -> Result<Vec<Status>> {
-        self.client.get(&pull_request.links.statuses).await
+        self.with_retries(|| self.client.get(&pull_request.links.statuses))
+            .await
+    }
+
+    async fn pull_request_files(&self, pull_request: &PullRequest) -> Result<Vec<PullRequestFile>> {
+        let url = format!(
+            "{}/files?per_page=100",
+            self.make_pull_request_url(pull_request)
+        );
+        self.get_all_pages(url).await
+    }
+
+    async fn pull_request_commits(&self, pull_request: &PullRequest) -> Result<Vec<Commit>> {
+        let url = format!(
+            "{}/commits?per_page=100",
+            self.make_pull_request_url(pull_request)
+        );
+        self.get_all_pages(url).await
+    }
+
+    async fn timeline_events(&self, pull_request: &PullRequest) -> Result<Vec<TimelineEvent>> {
+        let url = format!(
+            "{}/timeline?per_page=100",
+            self.make_issue_url(pull_request)
+        );
+        self.get_all_pages(url).await
+    }
+
+    async fn request_reviewers(
+        &self,
+        pull_request: &PullRequest,
+        reviewers: &[String],
+    ) -> Result<NoBody> {
+        let url = format!(
+            "{}/requested_reviewers",
+            self.make_pull_request_url(pull_request)
+        );
+        let body = RequestReviewersRequest {
+            reviewers: reviewers.to_vec(),
+        };
+        self.with_retries(|| self.client.post(&url, &body)).await
+    }
+
+    async fn review_threads(&self, pull_request: &PullRequest) -> Result<Vec<ReviewThread>> {
+        let variables = ReviewThreadsVariables {
+            owner: pull_request.base.repo.owner.login.clone(),
+            repo: pull_request.base.repo.name.clone(),
+            number: pull_request.number,
+        };
+        let data: ReviewThreadsData = self.graphql(REVIEW_THREADS_QUERY, variables).await?;
+        let threads = data
+            .repository
+            .pull_request
+            .review_threads
+            .nodes
+            .into_iter()
+            .map(|thread| ReviewThread {
+                is_resolved: thread.is_resolved,
+                url: thread
+                    .comments
+                    .nodes
+                    .into_iter()
+                    .next()
+                    .map(|comment| comment.url),
+            })
+            .collect();
+        Ok(threads)
+    }
+
+    async fn file_contents(
+        &self,
+        repo: &Repository,
+        path: &str,
+        git_ref: &str,
+    ) -> Result<FileContents> {
+        let url = format!(
+            "{}/repos/{}/{}/contents/{}?ref={}",
+            self.api_base, repo.owner.login, repo.name, path, git_ref
+        );
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn team_members(&self, org: &str, team_slug: &str) -> Result<Vec<User>> {
+        let url = format!(
+            "{}/orgs/{}/teams/{}/members?per_page=100",
+            self.api_base, org, team_slug
+        );
+        self.get_all_pages(url).await
     }
 
     async fn branch_protection(&self, branch: &Branch) -> Result<BranchProtection> {
         let url = format!(
             "{}/repos/{}/{}/branches/{}/protection",
-            Self::API_BASE,
-            branch.user.login,
-            branch.repo.name,
-            branch.name,
+            self.api_base, branch.user.login, branch.repo.name, branch.name,
         );
-        self.client.get(&url).await
+        self.with_retries(|| self.client.get(&url)).await
     }
 
-    async fn update_branch(&self, pull_request: &PullRequest) -> Result<NoBody> {
+    async fn required_status_checks(&self, branch: &Branch) -> Result<RequiredStatusChecks> {
         let url = format!(
-            "{}/update-branch",
-            Self::make_pull_request_url(pull_request)
+            "{}/repos/{}/{}/branches/{}/protection/required_status_checks",
+            self.api_base, branch.user.login, branch.repo.name, branch.name,
         );
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn update_branch(&self, pull_request: &PullRequest) -> Result<NoBody> {
+        let url = format!("{}/update-branch", self.make_pull_request_url(pull_request));
         let body = UpdateBranchRequest {
             expected_head_sha: pull_request.head.sha.clone(),
         };
-        self.client.put(&url, &body).await
+        self.with_rate_limit_retries(|| self.client.put(&url, &body))
+            .await
+    }
+
+    async fn delete_branch(&self, branch: &Branch) -> Result<NoBody> {
+        let url = format!(
+            "{}/repos/{}/git/refs/heads/{}",
+            self.api_base, branch.repo.full_name, branch.name
+        );
+        self.with_retries(|| self.client.delete(&url)).await
     }
 
     async fn action_runs(&self, pull_request: &PullRequest) -> Result<ActionRuns> {
         let url = format!(
             "{}/repos/{}/{}/actions/runs?branch={}&actor={}",
-            Self::API_BASE,
+            self.api_base,
             pull_request.base.repo.owner.login,
             pull_request.base.repo.name,
             pull_request.head.name,
             pull_request.creator.login
         );
-        self.client.get(&url).await
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn action_runs_for_sha(&self, repo: &Repository, head_sha: &str) -> Result<ActionRuns> {
+        let url = format!(
+            "{}/repos/{}/{}/actions/runs?head_sha={}",
+            self.api_base, repo.owner.login, repo.name, head_sha
+        );
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn check_runs(&self, repo: &Repository, sha: &str) -> Result<CheckRuns> {
+        let url = format!(
+            "{}/repos/{}/{}/commits/{}/check-runs?per_page=100",
+            self.api_base, repo.owner.login, repo.name, sha
+        );
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn combined_status(&self, repo: &Repository, sha: &str) -> Result<CombinedStatus> {
+        let url = format!(
+            "{}/repos/{}/{}/commits/{}/status?per_page=100",
+            self.api_base, repo.owner.login, repo.name, sha
+        );
+        self.with_retries(|| self.client.get(&url)).await
     }
 
     async fn rerun_workflow(&self, repo: &Repository, run_id: u64) -> Result<NoBody> {
         let url = format!(
             "{}/repos/{}/{}/actions/runs/{}/rerun",
-            Self::API_BASE,
-            repo.owner.login,
-            repo.name,
-            run_id,
+            self.api_base, repo.owner.login, repo.name, run_id,
+        );
+        self.with_rate_limit_retries(|| self.client.post(&url, &()))
+            .await
+    }
+
+    async fn commit(&self, repo: &Repository, sha: &str) -> Result<Commit> {
+        let url = format!(
+            "{}/repos/{}/{}/commits/{}",
+            self.api_base, repo.owner.login, repo.name, sha
+        );
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn compare_commits(
+        &self,
+        repo: &Repository,
+        base: &str,
+        head: &str,
+    ) -> Result<CommitComparison> {
+        let url = format!(
+            "{}/repos/{}/{}/compare/{}...{}",
+            self.api_base, repo.owner.login, repo.name, base, head
+        );
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn commit_pull_requests(
+        &self,
+        repo: &Repository,
+        sha: &str,
+    ) -> Result<Vec<PullRequestSummary>> {
+        let url = format!(
+            "{}/repos/{}/{}/commits/{}/pulls",
+            self.api_base, repo.owner.login, repo.name, sha
+        );
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn issue(&self, repo: &str, number: u32) -> Result<Issue> {
+        let url = format!("{}/repos/{}/issues/{}", self.api_base, repo, number);
+        self.with_retries(|| self.client.get(&url)).await
+    }
+
+    async fn add_label(&self, pull_request: &PullRequest, label: &str) -> Result<Vec<Label>> {
+        let url = format!("{}/labels", self.make_issue_url(pull_request));
+        let body = AddLabelsRequest {
+            labels: vec![label.into()],
+        };
+        self.with_retries(|| self.client.post(&url, &body)).await
+    }
+
+    async fn remove_label(&self, pull_request: &PullRequest, label: &str) -> Result<Vec<Label>> {
+        let url = format!("{}/labels", self.make_issue_url(pull_request));
+        let mut url = Url::parse(&url).expect("invalid issue URL");
+        // Label names can contain anything so they need to be escaped
+        url.path_segments_mut()
+            .expect("invalid issue URL")
+            .push(label);
+        self.with_retries(|| self.client.delete(url.as_str())).await
+    }
+
+    async fn list_comments(&self, pull_request: &PullRequest) -> Result<Vec<IssueComment>> {
+        let url = format!(
+            "{}/comments?per_page=100",
+            self.make_issue_url(pull_request)
+        );
+        self.get_all_pages(url).await
+    }
+
+    async fn create_comment(&self, pull_request: &PullRequest, body: &str) -> Result<IssueComment> {
+        let url = format!("{}/comments", self.make_issue_url(pull_request));
+        let body = CommentRequest { body: body.into() };
+        self.with_rate_limit_retries(|| self.client.post(&url, &body))
+            .await
+    }
+
+    async fn update_comment(
+        &self,
+        repo: &Repository,
+        comment_id: u64,
+        body: &str,
+    ) -> Result<IssueComment> {
+        let url = format!(
+            "{}/repos/{}/issues/comments/{}",
+            self.api_base, repo.full_name, comment_id
         );
-        self.client.post(&url, &()).await
+        let body = CommentRequest { body: body.into() };
+        self.with_retries(|| self.client.patch(&url, &body)).await
     }
 
     async fn merge_pull_request(
@@ -135,8 +616,71 @@ impl GithubClient for DefaultGithubClient {
         pull_request: &PullRequest,
         body: &MergeRequestBody,
     ) -> Result<NoBody> {
-        let url = format!("{}/merge", Self::make_pull_request_url(pull_request));
-        self.client.put(&url, body).await
+        let url = format!("{}/merge", self.make_pull_request_url(pull_request));
+        match self
+            .with_rate_limit_retries(|| self.client.put(&url, body))
+            .await
+        {
+            Err(e) if e.transient() => {
+                // The merge may have gone through even if its response didn't make it back, so
+                // look at the pull request rather than sending the merge again
+                let url = self.make_pull_request_url(pull_request);
+                let current: PullRequest = self.with_retries(|| self.client.get(&url)).await?;
+                if current.merged {
+                    info!(
+                        "Merge request failed with {} but the pull request was merged",
+                        e
+                    );
+                    return Ok(NoBody {});
+                }
+                Err(e)
+            }
+            result => result,
+        }
+    }
+
+    async fn auto_merge_allowed(&self, repo: &Repository) -> Result<bool> {
+        let variables = RepositoryVariables {
+            owner: repo.owner.login.clone(),
+            repo: repo.name.clone(),
+        };
+        let data: AutoMergeAllowedData = self.graphql(AUTO_MERGE_ALLOWED_QUERY, variables).await?;
+        Ok(data.repository.auto_merge_allowed)
+    }
+
+    async fn enable_auto_merge(
+        &self,
+        pull_request: &PullRequest,
+        method: &MergeMethod,
+    ) -> Result<()> {
+        let merge_method = match method {
+            MergeMethod::Merge => "MERGE",
+            MergeMethod::Squash => "SQUASH",
+            MergeMethod::Rebase => "REBASE",
+        };
+        let variables = EnableAutoMergeVariables {
+            pull_request_id: pull_request.node_id.clone(),
+            merge_method,
+        };
+        let _: IgnoredAny = self
+            .graphql_mutation(ENABLE_AUTO_MERGE_MUTATION, variables)
+            .await?;
+        Ok(())
+    }
+
+    fn rate_limit_status(&self) -> Option<RateLimitStatus> {
+        self.client.rate_limit_status()
+    }
+
+    async fn token_scopes(&self) -> Result<Option<Vec<String>>> {
+        let url = format!("{}/user", self.api_base);
+        let headers = self.with_retries(|| self.client.get_headers(&url)).await?;
+        // Only classic tokens get this header back
+        let scopes = headers
+            .get("x-oauth-scopes")
+            .and_then(|header| header.to_str().ok())
+            .map(parse_token_scopes);
+        Ok(scopes)
     }
 }
 
@@ -144,3 +688,391 @@ impl GithubClient for DefaultGithubClient {
 struct UpdateBranchRequest {
     expected_head_sha: String,
 }
+
+#[derive(Serialize, Debug, PartialEq)]
+struct AddLabelsRequest {
+    labels: Vec<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct RequestReviewersRequest {
+    reviewers: Vec<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct CommentRequest {
+    body: String,
+}
+
+const REVIEW_THREADS_QUERY: &str = "
+query($owner: String!, $repo: String!, $number: Int!) {
+  repository(owner: $owner, name: $repo) {
+    pullRequest(number: $number) {
+      reviewThreads(first: 100) {
+        nodes {
+          isResolved
+          comments(first: 1) {
+            nodes {
+              url
+            }
+          }
+        }
+      }
+    }
+  }
+}";
+
+const AUTO_MERGE_ALLOWED_QUERY: &str = "
+query($owner: String!, $repo: String!) {
+  repository(owner: $owner, name: $repo) {
+    autoMergeAllowed
+  }
+}";
+
+const ENABLE_AUTO_MERGE_MUTATION: &str = "
+mutation($pullRequestId: ID!, $mergeMethod: PullRequestMergeMethod!) {
+  enablePullRequestAutoMerge(input: {pullRequestId: $pullRequestId, mergeMethod: $mergeMethod}) {
+    clientMutationId
+  }
+}";
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EnableAutoMergeVariables {
+    pull_request_id: String,
+    merge_method: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+struct GraphQlRequest<V> {
+    query: &'static str,
+    variables: V,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphQlResponse<D> {
+    data: Option<D>,
+
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+impl<D> GraphQlResponse<D> {
+    /// Github replies with a successful status code even if the query failed
+    fn into_data(self) -> Result<D> {
+        if let Some(error) = self.errors.into_iter().next() {
+            return Err(Error::GraphQl(error.message));
+        }
+        self.data
+            .ok_or_else(|| Error::GraphQl("response has no data".into()))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphQlNodes<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(Serialize, Debug)]
+struct RepositoryVariables {
+    owner: String,
+    repo: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct AutoMergeAllowedData {
+    repository: AutoMergeAllowedRepository,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AutoMergeAllowedRepository {
+    auto_merge_allowed: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct ReviewThreadsVariables {
+    owner: String,
+    repo: String,
+    number: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReviewThreadsData {
+    repository: ReviewThreadsRepository,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReviewThreadsRepository {
+    pull_request: ReviewThreadsPullRequest,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReviewThreadsPullRequest {
+    review_threads: GraphQlNodes<ReviewThreadNode>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReviewThreadNode {
+    is_resolved: bool,
+    comments: GraphQlNodes<ReviewThreadComment>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReviewThreadComment {
+    url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use std::future;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+
+    fn make_client(max_attempts: u32) -> DefaultGithubClient {
+        let retry = RetryConfig {
+            max_attempts,
+            base_delay_ms: 0,
+        };
+        let base_url = Url::parse("https://github.com").unwrap();
+        DefaultGithubClient::new("potato", "token", &base_url, retry)
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let client = make_client(3);
+        let attempts = AtomicU32::new(0);
+        let result = client
+            .with_retries(|| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                let result = match attempt {
+                    0 | 1 => Err(crate::client::Error::Http(StatusCode::BAD_GATEWAY)),
+                    _ => Ok(42),
+                };
+                future::ready(result)
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let client = make_client(3);
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = client
+            .with_retries(|| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                future::ready(Err(crate::client::Error::Http(StatusCode::NOT_FOUND)))
+            })
+            .await;
+        assert!(result.unwrap_err().not_found());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_retries_do_not_retry_server_errors() {
+        let client = make_client(3);
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = client
+            .with_rate_limit_retries(|| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                future::ready(Err(crate::client::Error::Http(StatusCode::BAD_GATEWAY)))
+            })
+            .await;
+        assert!(result.unwrap_err().transient());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let attempts = AtomicU32::new(0);
+        let result = client
+            .with_rate_limit_retries(|| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                let result = match attempt {
+                    0 => Err(Error::SecondaryRateLimit(Duration::ZERO)),
+                    _ => Ok(42),
+                };
+                future::ready(result)
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_secondary_rate_limits() {
+        let client = make_client(2);
+        let attempts = AtomicU32::new(0);
+        let result = client
+            .with_retries(|| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                let result = match attempt {
+                    0 => Err(Error::SecondaryRateLimit(Duration::from_millis(1))),
+                    _ => Ok(42),
+                };
+                future::ready(result)
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+
+        let result: Result<()> = client
+            .with_retries(|| future::ready(Err(Error::SecondaryRateLimit(Duration::ZERO))))
+            .await;
+        assert!(result.unwrap_err().secondary_rate_limited());
+    }
+
+    #[test]
+    fn test_next_page_url() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(next_page_url(&headers), None);
+
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(
+                r#"<https://api.github.com/repositories/1/issues/2/comments?per_page=100&page=2>; rel="next", <https://api.github.com/repositories/1/issues/2/comments?per_page=100&page=5>; rel="last""#,
+            ),
+        );
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://api.github.com/repositories/1/issues/2/comments?per_page=100&page=2")
+        );
+
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(
+                r#"<https://api.github.com/repositories/1/issues/2/comments?per_page=100&page=1>; rel="prev", <https://api.github.com/repositories/1/issues/2/comments?per_page=100&page=1>; rel="first""#,
+            ),
+        );
+        assert_eq!(next_page_url(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_collect_pages() {
+        let requested = Mutex::new(Vec::new());
+        let comments = collect_pages("page1".to_string(), |url| {
+            requested.lock().unwrap().push(url.clone());
+            let page = match url.as_str() {
+                "page1" => (vec![1, 2], Some("page2".to_string())),
+                _ => (vec![3], None),
+            };
+            future::ready(Ok(page))
+        })
+        .await
+        .unwrap();
+        assert_eq!(comments, vec![1, 2, 3]);
+        assert_eq!(*requested.lock().unwrap(), vec!["page1", "page2"]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_pages_of_files() {
+        let make_files = |range: std::ops::Range<u32>| {
+            range
+                .map(|index| PullRequestFile {
+                    filename: format!("src/file{}.rs", index),
+                })
+                .collect::<Vec<_>>()
+        };
+        let files = collect_pages("files?page=1".to_string(), |url| {
+            let page = match url.as_str() {
+                "files?page=1" => (make_files(0..100), Some("files?page=2".to_string())),
+                _ => (make_files(100..150), None),
+            };
+            future::ready(Ok(page))
+        })
+        .await
+        .unwrap();
+        assert_eq!(files.len(), 150);
+        assert_eq!(files[149].filename, "src/file149.rs");
+    }
+
+    #[test]
+    fn test_token_scopes() {
+        let scopes = parse_token_scopes("read:org, repo ,workflow");
+        assert_eq!(scopes, vec!["read:org", "repo", "workflow"]);
+        assert!(missing_token_scopes(&scopes).is_empty());
+
+        let scopes = parse_token_scopes("public_repo");
+        assert_eq!(missing_token_scopes(&scopes), vec!["repo"]);
+        assert_eq!(missing_token_scopes(&parse_token_scopes("")), vec!["repo"]);
+    }
+
+    #[test]
+    fn test_make_api_base() {
+        let api_base = |url| DefaultGithubClient::make_api_base(&Url::parse(url).unwrap());
+        assert_eq!(api_base("https://github.com"), "https://api.github.com");
+        assert_eq!(
+            api_base("https://github.potato.com"),
+            "https://github.potato.com/api/v3"
+        );
+        assert_eq!(
+            api_base("https://github.potato.com:8443/"),
+            "https://github.potato.com:8443/api/v3"
+        );
+    }
+
+    #[test]
+    fn test_make_graphql_url() {
+        let graphql_url = |url| DefaultGithubClient::make_graphql_url(&Url::parse(url).unwrap());
+        assert_eq!(
+            graphql_url("https://github.com"),
+            "https://api.github.com/graphql"
+        );
+        assert_eq!(
+            graphql_url("https://github.potato.com/"),
+            "https://github.potato.com/api/graphql"
+        );
+    }
+
+    #[test]
+    fn test_graphql_response_errors() {
+        let response: GraphQlResponse<ReviewThreadsData> = serde_json::from_str(
+            r#"{"data": null, "errors": [{"message": "Could not resolve to a Repository"}]}"#,
+        )
+        .unwrap();
+        let error = response.into_data().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "graphql query failed: Could not resolve to a Repository"
+        );
+
+        let response: GraphQlResponse<ReviewThreadsData> = serde_json::from_str(
+            r#"{"data": {"repository": {"pullRequest": {"reviewThreads": {"nodes": [
+                {"isResolved": false, "comments": {"nodes": [{"url": "https://potato.com"}]}}
+            ]}}}}}"#,
+        )
+        .unwrap();
+        let data = response.into_data().unwrap();
+        let threads = data.repository.pull_request.review_threads.nodes;
+        assert_eq!(threads.len(), 1);
+        assert!(!threads[0].is_resolved);
+    }
+
+    #[test]
+    fn test_auto_merge_allowed_response() {
+        let response: GraphQlResponse<AutoMergeAllowedData> =
+            serde_json::from_str(r#"{"data": {"repository": {"autoMergeAllowed": false}}}"#)
+                .unwrap();
+        assert!(!response.into_data().unwrap().repository.auto_merge_allowed);
+    }
+
+    #[test]
+    fn test_enable_auto_merge_variables() {
+        let variables = EnableAutoMergeVariables {
+            pull_request_id: "PR_potato".into(),
+            merge_method: "SQUASH",
+        };
+        assert_eq!(
+            serde_json::to_string(&variables).unwrap(),
+            r#"{"pullRequestId":"PR_potato","mergeMethod":"SQUASH"}"#
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use regex::Regex;
+
+/// The owner of a path as listed in a CODEOWNERS file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CodeOwner {
+    User(String),
+    Team { org: String, slug: String },
+}
+
+impl CodeOwner {
+    fn parse(owner: &str) -> Option<Self> {
+        // Owners can also be emails but there's no way to map those to users
+        let owner = owner.strip_prefix('@')?;
+        match owner.split_once('/') {
+            Some((org, slug)) => Some(Self::Team {
+                org: org.into(),
+                slug: slug.into(),
+            }),
+            None => Some(Self::User(owner.into())),
+        }
+    }
+}
+
+struct Rule {
+    pattern: Regex,
+    owners: Vec<CodeOwner>,
+}
+
+/// A parsed CODEOWNERS file
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+    pub fn parse(contents: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut chunks = line.split_whitespace();
+            let pattern = match chunks.next().and_then(Self::build_pattern) {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            let owners = chunks
+                .take_while(|chunk| !chunk.starts_with('#'))
+                .filter_map(CodeOwner::parse)
+                .collect();
+            rules.push(Rule { pattern, owners });
+        }
+        Self { rules }
+    }
+
+    /// Returns the owners for the given path, if any. As in github, the last matching rule wins.
+    pub fn owners(&self, path: &str) -> Option<&[CodeOwner]> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.pattern.is_match(path))
+            .map(|rule| rule.owners.as_slice())
+    }
+
+    fn build_pattern(pattern: &str) -> Option<Regex> {
+        // Same semantics as gitignore: patterns with a slash anywhere but at the end are relative
+        // to the repo root, otherwise they match at any depth.
+        let anchored = pattern.trim_end_matches('/').contains('/');
+        let directory_only = pattern.ends_with('/');
+        let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+        let mut regex = String::from(if anchored { "^" } else { "^(.*/)?" });
+        let mut remaining = pattern;
+        while let Some(c) = remaining.chars().next() {
+            if let Some(rest) = remaining.strip_prefix("**/") {
+                regex.push_str("(.*/)?");
+                remaining = rest;
+                continue;
+            }
+            if let Some(rest) = remaining.strip_prefix("**") {
+                regex.push_str(".*");
+                remaining = rest;
+                continue;
+            }
+            match c {
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            };
+            remaining = &remaining[c.len_utf8()..];
+        }
+        regex.push_str(if directory_only { "/.*$" } else { "(/.*)?$" });
+        Regex::new(&regex).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(login: &str) -> CodeOwner {
+        CodeOwner::User(login.into())
+    }
+
+    #[test]
+    fn test_codeowners_matching() {
+        let codeowners = CodeOwners::parse(
+            r#"
+            # Default owners
+            *       @alice
+            *.rs    @bob @potato/rustaceans
+            /docs/  @carol docs@example.com
+            src/**/models.rs @dave # inline comment
+            "#,
+        );
+        assert_eq!(codeowners.owners("README.md"), Some(&[user("alice")][..]));
+        assert_eq!(
+            codeowners.owners("src/main.rs"),
+            Some(
+                &[
+                    user("bob"),
+                    CodeOwner::Team {
+                        org: "potato".into(),
+                        slug: "rustaceans".into()
+                    }
+                ][..]
+            )
+        );
+        assert_eq!(
+            codeowners.owners("docs/index.md"),
+            Some(&[user("carol")][..])
+        );
+        assert_eq!(
+            codeowners.owners("other/docs/index.md"),
+            Some(&[user("alice")][..])
+        );
+        assert_eq!(
+            codeowners.owners("src/models.rs"),
+            Some(&[user("dave")][..])
+        );
+        assert_eq!(
+            codeowners.owners("src/github/models.rs"),
+            Some(&[user("dave")][..])
+        );
+    }
+
+    #[test]
+    fn test_codeowners_no_match() {
+        let codeowners = CodeOwners::parse("/src/ @alice");
+        assert_eq!(codeowners.owners("src/main.rs"), Some(&[user("alice")][..]));
+        assert_eq!(codeowners.owners("other/src/main.rs"), None);
+        assert_eq!(codeowners.owners("README.md"), None);
+    }
+}
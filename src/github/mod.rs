@@ -1,5 +1,9 @@
+pub mod app;
 pub mod client;
+pub mod codeowners;
 pub mod models;
 
+pub use app::GithubAppAuth;
 pub use client::*;
+pub use codeowners::{CodeOwner, CodeOwners};
 pub use models::*;
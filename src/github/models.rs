@@ -1,9 +1,10 @@
 use reqwest::Url;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
 use std::ops::Deref;
 use thiserror::Error;
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum MergeableState {
     #[serde(rename = "behind")]
     Behind,
@@ -20,17 +21,18 @@ pub enum MergeableState {
     #[serde(rename = "dirty")]
     Dirty,
 
+    #[serde(rename = "draft")]
+    Draft,
+
+    #[serde(rename = "has_hooks")]
+    HasHooks,
+
+    #[default]
     #[serde(other, rename = "unknown")]
     Unknown,
 }
 
-impl Default for MergeableState {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum PullRequestState {
     #[serde(rename = "open")]
     Open,
@@ -38,16 +40,11 @@ pub enum PullRequestState {
     #[serde(rename = "closed")]
     Closed,
 
+    #[default]
     #[serde(other)]
     Unknown,
 }
 
-impl Default for PullRequestState {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Link {
     href: String,
@@ -71,6 +68,46 @@ pub struct User {
     pub login: String,
 }
 
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct IssueComment {
+    pub id: u64,
+    pub user: User,
+    pub body: Option<String>,
+}
+
+/// A review conversation on a pull request
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReviewThread {
+    pub is_resolved: bool,
+
+    /// The URL of the first comment in the thread
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Label {
+    pub name: String,
+}
+
+impl Label {
+    /// Whether this label has the given name, compared case insensitively
+    pub fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Issue {
+    pub number: u32,
+    pub state: PullRequestState,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Milestone {
+    pub number: u32,
+    pub title: String,
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub enum ReviewState {
     #[serde(rename = "APPROVED")]
@@ -103,6 +140,15 @@ pub struct Repository {
     pub full_name: String,
 }
 
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de> + Default,
+{
+    let value: Option<T> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(value.unwrap_or_default())
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Branch {
     pub sha: String,
@@ -111,11 +157,21 @@ pub struct Branch {
     pub name: String,
 
     pub user: User,
+
+    /// The repo the branch lives in. For pull requests coming from forks this is the fork, which
+    /// github reports as null if it was deleted.
+    #[serde(deserialize_with = "null_as_default")]
     pub repo: Repository,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct PullRequest {
+    /// The ID used to refer to the pull request in the GraphQL API
+    #[serde(default)]
+    pub node_id: String,
+
+    /// Whether the pull request can be merged, unset while github is still computing it
+    pub mergeable: Option<bool>,
     pub mergeable_state: MergeableState,
 
     #[serde(rename = "_links")]
@@ -124,6 +180,10 @@ pub struct PullRequest {
     #[serde(rename = "user")]
     pub creator: User,
 
+    /// Whether maintainers of the base repo can push to the head branch when it's in a fork
+    #[serde(default)]
+    pub maintainer_can_modify: bool,
+
     pub state: PullRequestState,
     pub title: String,
     pub head: Branch,
@@ -132,22 +192,175 @@ pub struct PullRequest {
     pub draft: bool,
     pub body: Option<String>,
     pub number: u32,
+
+    #[serde(default)]
+    pub labels: Vec<Label>,
+
+    pub milestone: Option<Milestone>,
+
+    #[serde(default)]
+    pub created_at: Option<chrono::DateTime<chrono::Local>>,
+
+    /// The size of the change, only returned when fetching a single pull request
+    #[serde(default)]
+    pub changed_files: u32,
+
+    #[serde(default)]
+    pub additions: u32,
+
+    #[serde(default)]
+    pub deletions: u32,
+}
+
+impl PullRequest {
+    pub fn has_label(&self, name: &str) -> bool {
+        self.labels.iter().any(|label| label.name == name)
+    }
+
+    /// Whether the head branch lives in a different repo than the one being merged into
+    pub fn is_from_fork(&self) -> bool {
+        self.head.repo.full_name != self.base.repo.full_name
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct BranchProtection {
-    #[serde(rename = "required_pull_request_reviews")]
+    /// Missing if the branch doesn't require reviews
+    #[serde(default, rename = "required_pull_request_reviews")]
     pub reviews: BranchProtectionReviews,
+
+    #[serde(default)]
+    pub required_linear_history: BranchProtectionSetting,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BranchProtectionSetting {
+    pub enabled: bool,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct BranchProtectionReviews {
     #[serde(rename = "required_approving_review_count")]
     pub approvals: u32,
+
+    /// Whether github dismisses approvals when new commits are pushed
+    #[serde(default)]
+    pub dismiss_stale_reviews: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PullRequestFile {
+    pub filename: String,
+}
+
+/// A pull request as listed in responses that don't include every field
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PullRequestSummary {
+    pub number: u32,
+    pub state: PullRequestState,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub sha: String,
+    pub commit: CommitDetails,
+
+    /// The github user the commit's author email belongs to, if any
+    #[serde(default)]
+    pub author: Option<User>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CommitDetails {
+    pub author: CommitSignature,
+    pub committer: CommitSignature,
+
+    #[serde(default)]
+    pub message: String,
+}
+
+/// An entry in an issue or pull request's timeline. Only the fields shared by every kind of event
+/// are kept, as their payloads vary wildly.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TimelineEvent {
+    pub event: String,
+
+    #[serde(default)]
+    pub actor: Option<User>,
+
+    /// The label that was added or removed, for `labeled` and `unlabeled` events
+    #[serde(default)]
+    pub label: Option<Label>,
+
+    #[serde(default)]
+    pub created_at: Option<chrono::DateTime<chrono::Local>>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CommitSignature {
+    #[serde(default)]
+    pub name: String,
+
+    #[serde(default)]
+    pub email: String,
+
+    pub date: chrono::DateTime<chrono::Local>,
+}
+
+/// The difference between two commits, as returned by the compare API
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CommitComparison {
+    pub ahead_by: u32,
+    pub behind_by: u32,
+
+    #[serde(default)]
+    pub files: Vec<ChangedFile>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ChangedFile {
+    pub filename: String,
+    pub additions: u32,
+    pub deletions: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FileContents {
+    pub content: String,
+    pub encoding: String,
+}
+
+impl FileContents {
+    pub fn decode(&self) -> Result<String, DecodeError> {
+        if self.encoding != "base64" {
+            return Err(DecodeError::UnsupportedEncoding(self.encoding.clone()));
+        }
+        // Github splits the base64 encoded contents in lines
+        let content: String = self.content.split_whitespace().collect();
+        let content = base64::decode(content).map_err(|_| DecodeError::MalformedContents)?;
+        String::from_utf8(content).map_err(|_| DecodeError::MalformedContents)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum DecodeError {
+    #[error("unsupported file encoding: {0}")]
+    UnsupportedEncoding(String),
+
+    #[error("malformed file contents")]
+    MalformedContents,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RequiredStatusChecks {
+    pub contexts: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct PullRequestIdentifier {
+    /// The URL of the github instance the pull request is in, e.g. `https://github.com`
+    pub base_url: String,
+
     pub owner: String,
     pub repo: String,
     pub pull_number: u32,
@@ -155,9 +368,7 @@ pub struct PullRequestIdentifier {
 
 impl PullRequestIdentifier {
     pub fn from_app_url(url: &Url) -> Result<Self, InvalidUrlError> {
-        if url.domain() != Some("github.com") {
-            return Err(InvalidUrlError::InvalidDomain);
-        }
+        let host = url.host_str().ok_or(InvalidUrlError::InvalidDomain)?;
         let path_parts: Vec<_> = url
             .path_segments()
             .ok_or(InvalidUrlError::NotPullRequestUrl)?
@@ -165,15 +376,78 @@ impl PullRequestIdentifier {
         if path_parts.len() != 4 || path_parts[2] != "pull" {
             return Err(InvalidUrlError::NotPullRequestUrl);
         }
-        let pull_number = path_parts[3]
+        Self::from_parts(
+            Self::make_base_url(url, host),
+            path_parts[0],
+            path_parts[1],
+            path_parts[3],
+        )
+    }
+
+    /// Parses a pull request's API URL, like `https://api.github.com/repos/{owner}/{repo}/pulls/{n}`
+    /// or `https://{host}/api/v3/repos/...` for github enterprise
+    pub fn from_api_url(url: &Url) -> Result<Self, InvalidUrlError> {
+        let host = url.host_str().ok_or(InvalidUrlError::InvalidDomain)?;
+        let path_parts: Vec<_> = url
+            .path_segments()
+            .ok_or(InvalidUrlError::NotPullRequestUrl)?
+            .collect();
+        let (base_url, path_parts) = match (host, path_parts.as_slice()) {
+            ("api.github.com", parts) => (format!("{}://github.com", url.scheme()), parts),
+            (_, ["api", "v3", parts @ ..]) => (Self::make_base_url(url, host), parts),
+            _ => return Err(InvalidUrlError::NotPullRequestUrl),
+        };
+        match path_parts {
+            ["repos", owner, repo, "pulls", number] => {
+                Self::from_parts(base_url, owner, repo, number)
+            }
+            _ => Err(InvalidUrlError::NotPullRequestUrl),
+        }
+    }
+
+    /// Parses either a pull request's web or API URL
+    pub fn from_any_url(url: &Url) -> Result<Self, InvalidUrlError> {
+        Self::from_app_url(url).or_else(|_| Self::from_api_url(url))
+    }
+
+    fn make_base_url(url: &Url, host: &str) -> String {
+        match url.port() {
+            Some(port) => format!("{}://{}:{}", url.scheme(), host, port),
+            None => format!("{}://{}", url.scheme(), host),
+        }
+    }
+
+    fn from_parts(
+        base_url: String,
+        owner: &str,
+        repo: &str,
+        number: &str,
+    ) -> Result<Self, InvalidUrlError> {
+        if owner.is_empty() || repo.is_empty() {
+            return Err(InvalidUrlError::NotPullRequestUrl);
+        }
+        let pull_number = number
             .parse()
             .map_err(|_| InvalidUrlError::NotPullRequestUrl)?;
-        let pull_request_url = Self {
-            owner: path_parts[0].into(),
-            repo: path_parts[1].into(),
+        Ok(Self {
+            base_url,
+            owner: owner.into(),
+            repo: repo.into(),
             pull_number,
-        };
-        Ok(pull_request_url)
+        })
+    }
+
+    pub fn app_url(&self) -> String {
+        format!(
+            "{}/{}/{}/pull/{}",
+            self.base_url, self.owner, self.repo, self.pull_number
+        )
+    }
+}
+
+impl fmt::Display for PullRequestIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/pulls/{}", self.owner, self.repo, self.pull_number)
     }
 }
 
@@ -191,6 +465,42 @@ pub struct ActionRuns {
     pub workflow_runs: Vec<WorkflowRun>,
 }
 
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CheckRuns {
+    pub check_runs: Vec<CheckRun>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CheckRun {
+    pub name: String,
+
+    /// The app that created the check run
+    #[serde(default)]
+    pub app: Option<CheckRunApp>,
+
+    #[serde(default)]
+    pub html_url: Option<String>,
+
+    /// Unset until the check run completes
+    #[serde(default)]
+    pub conclusion: Option<WorkflowRunConclusion>,
+
+    #[serde(default)]
+    pub completed_at: Option<chrono::DateTime<chrono::Local>>,
+}
+
+impl CheckRun {
+    /// Whether this check run is a github actions job, which are tracked via their workflow runs
+    pub fn is_github_actions(&self) -> bool {
+        matches!(&self.app, Some(app) if app.slug == "github-actions")
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CheckRunApp {
+    pub slug: String,
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct WorkflowRun {
     pub id: u64,
@@ -201,7 +511,7 @@ pub struct WorkflowRun {
     pub conclusion: Option<WorkflowRunConclusion>,
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum WorfklowRunStatus {
     #[serde(rename = "completed")]
     Completed,
@@ -212,16 +522,11 @@ pub enum WorfklowRunStatus {
     #[serde(rename = "in_progress")]
     InProgress,
 
+    #[default]
     #[serde(other)]
     Unknown,
 }
 
-impl Default for WorfklowRunStatus {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub enum WorkflowRunConclusion {
     #[serde(rename = "success")]
@@ -230,10 +535,23 @@ pub enum WorkflowRunConclusion {
     #[serde(rename = "failure")]
     Failure,
 
+    #[serde(rename = "neutral")]
+    Neutral,
+
+    #[serde(rename = "skipped")]
+    Skipped,
+
     #[serde(other)]
     Unknown,
 }
 
+/// The state of a commit according to the latest status of each context
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CombinedStatus {
+    pub state: StatusState,
+    pub statuses: Vec<Status>,
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct Status {
     pub target_url: String,
@@ -276,6 +594,24 @@ pub struct NoBody {}
 mod tests {
     use super::*;
 
+    #[test]
+    fn file_contents_decode() {
+        let contents = FileContents {
+            content: "KiBAcG90
+YXRvCg==
+"
+            .into(),
+            encoding: "base64".into(),
+        };
+        assert_eq!(contents.decode().unwrap(), "* @potato\n");
+
+        let contents = FileContents {
+            encoding: "none".into(),
+            ..contents
+        };
+        assert!(contents.decode().is_err());
+    }
+
     #[test]
     fn pull_request_from_app_url() {
         let pr = PullRequestIdentifier::from_app_url(
@@ -285,6 +621,17 @@ mod tests {
         assert_eq!(pr.owner, "potato");
         assert_eq!(pr.repo, "smasher");
         assert_eq!(pr.pull_number, 1337);
+        assert_eq!(pr.app_url(), "https://github.com/potato/smasher/pull/1337");
+
+        let pr = PullRequestIdentifier::from_app_url(
+            &Url::parse("https://github.potato.com/potato/smasher/pull/1337").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(pr.base_url, "https://github.potato.com");
+        assert_eq!(
+            pr.app_url(),
+            "https://github.potato.com/potato/smasher/pull/1337"
+        );
 
         assert!(PullRequestIdentifier::from_app_url(
             &Url::parse("https://github.com/potato/smasher/pull/").unwrap()
@@ -299,4 +646,64 @@ mod tests {
         )
         .is_err());
     }
+
+    #[test]
+    fn pull_request_from_api_url() {
+        let pr = PullRequestIdentifier::from_api_url(
+            &Url::parse("https://api.github.com/repos/potato/smasher/pulls/1337").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(pr.base_url, "https://github.com");
+        assert_eq!(pr.owner, "potato");
+        assert_eq!(pr.repo, "smasher");
+        assert_eq!(pr.pull_number, 1337);
+
+        let pr = PullRequestIdentifier::from_api_url(
+            &Url::parse("https://github.potato.com:8443/api/v3/repos/potato/smasher/pulls/1337")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            pr.app_url(),
+            "https://github.potato.com:8443/potato/smasher/pull/1337"
+        );
+
+        for url in [
+            "https://api.github.com/repos/potato/smasher/pulls/",
+            "https://api.github.com/repos/potato/smasher/issues/1337",
+            "https://api.github.com/repos//smasher/pulls/1337",
+            "https://github.potato.com/repos/potato/smasher/pulls/1337",
+            "https://github.com/potato/smasher/pull/1337",
+        ] {
+            assert!(
+                PullRequestIdentifier::from_api_url(&Url::parse(url).unwrap()).is_err(),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn pull_request_from_any_url() {
+        for url in [
+            "https://github.com/potato/smasher/pull/1337",
+            "https://api.github.com/repos/potato/smasher/pulls/1337",
+        ] {
+            let pr = PullRequestIdentifier::from_any_url(&Url::parse(url).unwrap()).unwrap();
+            assert_eq!(pr.app_url(), "https://github.com/potato/smasher/pull/1337");
+        }
+        assert!(PullRequestIdentifier::from_any_url(
+            &Url::parse("https://github.com/potato/smasher/issues/1337").unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn branch_from_deleted_fork() {
+        let branch: Branch = serde_json::from_str(
+            r#"{"sha": "abc", "ref": "potatoes", "user": {"login": "alice"}, "repo": null}"#,
+        )
+        .unwrap();
+        assert_eq!(branch.repo, Repository::default());
+    }
 }
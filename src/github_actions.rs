@@ -0,0 +1,113 @@
+use crate::github::{GithubClient, PullRequest, WorkflowRunConclusion};
+use crate::processing::Error;
+use crate::processing::{WorkflowRunner, WorkflowStatus};
+use async_trait::async_trait;
+use log::info;
+use reqwest::Url;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Re-runs github actions workflows that reported a failed status on a pull request
+pub struct GithubActionsWorkflowRunner {
+    github: Arc<dyn GithubClient>,
+    base_url: Url,
+}
+
+impl GithubActionsWorkflowRunner {
+    pub fn new(github: Arc<dyn GithubClient>, base_url: Url) -> Self {
+        Self { github, base_url }
+    }
+
+    fn parse_run_url(&self, url: &Url) -> Option<u64> {
+        if url.host() != self.base_url.host() {
+            return None;
+        }
+        // URLs look like `{base_url}/{owner}/{repo}/actions/runs/{run_id}[/job/{job_id}]`
+        let segments: Vec<_> = url.path_segments()?.collect();
+        if segments.len() < 5 || segments[2] != "actions" || segments[3] != "runs" {
+            return None;
+        }
+        segments[4].parse().ok()
+    }
+}
+
+#[async_trait]
+impl WorkflowRunner for GithubActionsWorkflowRunner {
+    async fn process_failed_jobs(
+        &self,
+        pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> Result<WorkflowStatus, Error> {
+        let failed_run_ids: HashSet<_> = job_urls
+            .iter()
+            .filter_map(|url| self.parse_run_url(url))
+            .collect();
+        if failed_run_ids.is_empty() {
+            return Ok(WorkflowStatus::Success);
+        }
+        let repo = &pull_request.base.repo;
+        let action_runs = self
+            .github
+            .action_runs_for_sha(repo, &pull_request.head.sha)
+            .await?;
+        let mut pending = Vec::new();
+        let mut failed = Vec::new();
+        for run in action_runs.workflow_runs {
+            if !failed_run_ids.contains(&run.id) {
+                continue;
+            }
+            match run.conclusion {
+                None => pending.push(run),
+                Some(WorkflowRunConclusion::Failure) => failed.push(run),
+                _ => (),
+            }
+        }
+        if !pending.is_empty() {
+            info!(
+                "{} failed github actions workflows are still running",
+                pending.len()
+            );
+            return Ok(WorkflowStatus::Pending);
+        }
+        if failed.is_empty() {
+            return Ok(WorkflowStatus::Success);
+        }
+        info!(
+            "Re-running {} failed github actions workflows",
+            failed.len()
+        );
+        for run in failed {
+            self.github.rerun_workflow(repo, run.id).await?;
+        }
+        Ok(WorkflowStatus::Triggered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::client::MockGithubClient;
+
+    #[test]
+    fn test_parse_run_url() {
+        let runner = GithubActionsWorkflowRunner::new(
+            Arc::new(MockGithubClient::default()),
+            Url::parse("https://github.com").unwrap(),
+        );
+        let parse = |url| runner.parse_run_url(&Url::parse(url).unwrap());
+        assert_eq!(
+            parse("https://github.com/potato/smasher/actions/runs/1337"),
+            Some(1337)
+        );
+        assert_eq!(
+            parse("https://github.com/potato/smasher/actions/runs/1337/job/42"),
+            Some(1337)
+        );
+        assert_eq!(parse("https://github.com/potato/smasher/pull/1337"), None);
+        assert_eq!(
+            parse("https://github.com/potato/smasher/actions/runs/abc"),
+            None
+        );
+        assert_eq!(parse("https://circleci.com/gh/potato/smasher/1337"), None);
+    }
+}
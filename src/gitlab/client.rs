@@ -0,0 +1,88 @@
+use super::models::{
+    Approvals, MergeMergeRequestBody, MergeRequest, MergeRequestIdentifier, NoBody,
+};
+use crate::client::{ApiClient, Result};
+use async_trait::async_trait;
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait GitlabClient: Send + Sync {
+    async fn merge_request_info(&self, id: &MergeRequestIdentifier) -> Result<MergeRequest>;
+    async fn merge_request_approvals(&self, id: &MergeRequestIdentifier) -> Result<Approvals>;
+    async fn rebase_merge_request(&self, id: &MergeRequestIdentifier) -> Result<NoBody>;
+    async fn retry_pipeline(&self, id: &MergeRequestIdentifier, pipeline_id: u64)
+        -> Result<NoBody>;
+    async fn merge_merge_request(
+        &self,
+        id: &MergeRequestIdentifier,
+        body: &MergeMergeRequestBody,
+    ) -> Result<NoBody>;
+}
+
+#[derive(Clone)]
+pub struct DefaultGitlabClient {
+    client: ApiClient,
+}
+
+impl DefaultGitlabClient {
+    pub fn new<S: Into<String>>(token: S) -> Self {
+        Self {
+            client: ApiClient::from_bearer_token(token),
+        }
+    }
+
+    fn make_project_url(id: &MergeRequestIdentifier) -> String {
+        format!(
+            "{}/api/v4/projects/{}",
+            id.base_url,
+            id.project.replace('/', "%2F")
+        )
+    }
+
+    fn make_merge_request_url(id: &MergeRequestIdentifier) -> String {
+        format!("{}/merge_requests/{}", Self::make_project_url(id), id.iid)
+    }
+}
+
+#[async_trait]
+impl GitlabClient for DefaultGitlabClient {
+    async fn merge_request_info(&self, id: &MergeRequestIdentifier) -> Result<MergeRequest> {
+        let url = format!(
+            "{}?include_diverged_commits_count=true",
+            Self::make_merge_request_url(id)
+        );
+        self.client.get(&url).await
+    }
+
+    async fn merge_request_approvals(&self, id: &MergeRequestIdentifier) -> Result<Approvals> {
+        let url = format!("{}/approvals", Self::make_merge_request_url(id));
+        self.client.get(&url).await
+    }
+
+    async fn rebase_merge_request(&self, id: &MergeRequestIdentifier) -> Result<NoBody> {
+        let url = format!("{}/rebase", Self::make_merge_request_url(id));
+        self.client.put(&url, &()).await
+    }
+
+    async fn retry_pipeline(
+        &self,
+        id: &MergeRequestIdentifier,
+        pipeline_id: u64,
+    ) -> Result<NoBody> {
+        let url = format!(
+            "{}/pipelines/{}/retry",
+            Self::make_project_url(id),
+            pipeline_id
+        );
+        self.client.post(&url, &()).await
+    }
+
+    async fn merge_merge_request(
+        &self,
+        id: &MergeRequestIdentifier,
+        body: &MergeMergeRequestBody,
+    ) -> Result<NoBody> {
+        let url = format!("{}/merge", Self::make_merge_request_url(id));
+        self.client.put(&url, body).await
+    }
+}
@@ -0,0 +1,372 @@
+use super::{
+    GitlabClient, MergeMergeRequestBody, MergeRequest, MergeRequestIdentifier, MergeRequestState,
+    PipelineStatus,
+};
+use crate::config::{MergeConfig, ReviewsConfig};
+use crate::github::MergeMethod;
+use crate::processing::steps::CheckNotFrozen;
+use crate::processing::{DirectorState, Error, PullRequestProcessor};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The checks run on a merge request, in the order they're run
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Check {
+    Frozen,
+    BehindTarget,
+    Pipeline,
+    Merge,
+}
+
+/// Processes gitlab merge requests with a fixed subset of the github `Director`'s checks: the
+/// merge request has to be open, not a draft, without conflicts and up to date with its target
+/// branch, its pipeline has to pass and it needs enough approvals. Other review settings like
+/// blocking and required labels, required approvers, excluded reviewers and external checks, as
+/// well as merge windows, aren't enforced. Freeze periods are, if given a `CheckNotFrozen`.
+pub struct GitlabDirector {
+    gitlab: Arc<dyn GitlabClient>,
+    identifier: MergeRequestIdentifier,
+    reviews: Option<ReviewsConfig>,
+    merge_method: MergeMethod,
+    dry_run: bool,
+    merge_request: Option<MergeRequest>,
+    merged_with: Option<MergeMethod>,
+    last_waiting: Option<(Check, String)>,
+    progressed: bool,
+    max_pipeline_retries: u32,
+    /// How many times each pipeline was retried, by ID
+    pipeline_retries: HashMap<u64, u32>,
+    freeze_check: Option<CheckNotFrozen>,
+}
+
+impl GitlabDirector {
+    pub fn new(
+        gitlab: Arc<dyn GitlabClient>,
+        identifier: MergeRequestIdentifier,
+        reviews: Option<ReviewsConfig>,
+        merge: MergeConfig,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            gitlab,
+            identifier,
+            reviews,
+            merge_method: merge.default_method,
+            dry_run,
+            merge_request: None,
+            merged_with: None,
+            last_waiting: None,
+            progressed: false,
+            max_pipeline_retries: 3,
+            pipeline_retries: HashMap::new(),
+            freeze_check: None,
+        }
+    }
+
+    /// Waits instead of merging while the given check finds merges are frozen
+    pub fn with_freeze_check(mut self, freeze_check: CheckNotFrozen) -> Self {
+        self.freeze_check = Some(freeze_check);
+        self
+    }
+
+    /// Fails the merge request once its pipeline failed after being retried this many times
+    pub fn with_max_pipeline_retries(mut self, max_pipeline_retries: u32) -> Self {
+        self.max_pipeline_retries = max_pipeline_retries;
+        self
+    }
+
+    /// Checks whether the merge request can be processed, returning the state to finish with if
+    /// it was already merged or closed
+    fn check_current_state(merge_request: &MergeRequest) -> Result<Option<DirectorState>, Error> {
+        match merge_request.state {
+            MergeRequestState::Opened if merge_request.draft => {
+                Err(Error::as_generic("merge request is a draft"))
+            }
+            MergeRequestState::Opened if merge_request.has_conflicts => {
+                Err(Error::as_generic("merge request has conflicts"))
+            }
+            MergeRequestState::Opened => Ok(None),
+            MergeRequestState::Merged => {
+                info!("Merge request was already merged");
+                Ok(Some(DirectorState::Done))
+            }
+            MergeRequestState::Closed => {
+                info!("Merge request was closed without merging");
+                Ok(Some(DirectorState::Closed))
+            }
+            MergeRequestState::Unknown => Err(Error::UnsupportedPullRequestState(
+                "merge request state is unknown".into(),
+            )),
+        }
+    }
+
+    async fn check_behind_target(&self, merge_request: &MergeRequest) -> Result<bool, Error> {
+        if merge_request.diverged_commits_count.unwrap_or(0) == 0 {
+            return Ok(true);
+        }
+        warn!("Merge request is behind its target branch, rebasing it");
+        self.gitlab.rebase_merge_request(&self.identifier).await?;
+        Ok(false)
+    }
+
+    async fn check_pipeline(&mut self, merge_request: &MergeRequest) -> Result<bool, Error> {
+        let pipeline = match &merge_request.head_pipeline {
+            Some(pipeline) => pipeline,
+            None => return Ok(true),
+        };
+        match pipeline.status {
+            PipelineStatus::Success | PipelineStatus::Skipped => Ok(true),
+            PipelineStatus::Failed => {
+                let retries = self.pipeline_retries.entry(pipeline.id).or_insert(0);
+                if *retries >= self.max_pipeline_retries {
+                    return Err(Error::as_generic(format!(
+                        "pipeline {} still failed after {} retries",
+                        pipeline.id, retries
+                    )));
+                }
+                *retries += 1;
+                warn!("Pipeline {} failed, retrying it", pipeline.id);
+                self.gitlab
+                    .retry_pipeline(&self.identifier, pipeline.id)
+                    .await?;
+                Ok(false)
+            }
+            PipelineStatus::Canceled => Err(Error::as_generic("pipeline was canceled")),
+            PipelineStatus::Manual => {
+                info!("Pipeline {} is waiting for a manual action", pipeline.id);
+                Ok(false)
+            }
+            PipelineStatus::Pending => {
+                info!("Waiting for pipeline {} to finish", pipeline.id);
+                Ok(false)
+            }
+        }
+    }
+
+    async fn check_approvals(&self) -> Result<(), Error> {
+        let reviews = match &self.reviews {
+            Some(reviews) => reviews,
+            None => return Ok(()),
+        };
+        let approvals = self
+            .gitlab
+            .merge_request_approvals(&self.identifier)
+            .await?;
+        let total_approved = approvals.approved_by.len();
+        if approvals.approvals_left > 0 || total_approved < reviews.approvals as usize {
+            return Err(Error::as_generic(format!(
+                "not enough approvals (need {}, have {})",
+                (reviews.approvals as usize)
+                    .max(total_approved + approvals.approvals_left as usize),
+                total_approved
+            )));
+        }
+        Ok(())
+    }
+
+    async fn merge(&mut self, merge_request: &MergeRequest) -> Result<DirectorState, Error> {
+        // The merge method is a project level setting in gitlab so the most we can do is squash
+        let method = match self.merge_method {
+            MergeMethod::Squash => MergeMethod::Squash,
+            _ => MergeMethod::Merge,
+        };
+        if self.dry_run {
+            info!("Skipping merge request merge step");
+            self.merged_with = Some(method);
+            return Ok(DirectorState::Done);
+        }
+        let body = MergeMergeRequestBody {
+            sha: merge_request.sha.clone(),
+            squash: method == MergeMethod::Squash,
+        };
+        match self
+            .gitlab
+            .merge_merge_request(&self.identifier, &body)
+            .await
+        {
+            Ok(_) => {
+                info!("Merge request merged ✔️");
+                self.merged_with = Some(method);
+                Ok(DirectorState::Done)
+            }
+            Err(e) if e.conflict() => {
+                warn!("Branch sha may have been modified manually");
+                Ok(DirectorState::Waiting)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn wait(&mut self, check: Check, merge_request: &MergeRequest) -> DirectorState {
+        info!("Check '{:?}' is pending", check);
+        let waiting = Some((check, merge_request.sha.clone()));
+        self.progressed = self.last_waiting != waiting;
+        self.last_waiting = waiting;
+        DirectorState::Waiting
+    }
+}
+
+#[async_trait(?Send)]
+impl PullRequestProcessor for GitlabDirector {
+    async fn run(&mut self) -> Result<DirectorState, Error> {
+        debug!("Fetching current state for merge request");
+        let merge_request = self.gitlab.merge_request_info(&self.identifier).await?;
+        self.merge_request = Some(merge_request.clone());
+        if let Some(state) = Self::check_current_state(&merge_request)? {
+            return Ok(state);
+        }
+        if let Some(freeze_check) = &self.freeze_check {
+            if freeze_check.frozen()? {
+                return Ok(self.wait(Check::Frozen, &merge_request));
+            }
+        }
+        if !self.check_behind_target(&merge_request).await? {
+            return Ok(self.wait(Check::BehindTarget, &merge_request));
+        }
+        if !self.check_pipeline(&merge_request).await? {
+            return Ok(self.wait(Check::Pipeline, &merge_request));
+        }
+        self.check_approvals().await?;
+        info!("All checks passed, merge request is ready to be merged!");
+        match self.merge(&merge_request).await? {
+            DirectorState::Waiting => Ok(self.wait(Check::Merge, &merge_request)),
+            state => Ok(state),
+        }
+    }
+
+    fn progressed(&self) -> bool {
+        self.progressed
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.merge_request.as_ref().map(|mr| mr.title.as_str())
+    }
+
+    fn merge_method(&self) -> Option<&MergeMethod> {
+        self.merged_with.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FreezePeriod;
+    use crate::gitlab::{MockGitlabClient, NoBody, Pipeline};
+    use chrono::{FixedOffset, Utc};
+    use std::future;
+
+    fn make_director(gitlab: MockGitlabClient) -> GitlabDirector {
+        let identifier = MergeRequestIdentifier {
+            base_url: "https://gitlab.com".into(),
+            project: "potato/smasher".into(),
+            iid: 1337,
+        };
+        GitlabDirector::new(
+            Arc::new(gitlab),
+            identifier,
+            None,
+            MergeConfig::default(),
+            false,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_failed_pipeline_is_retried() {
+        let merge_request = MergeRequest {
+            state: MergeRequestState::Opened,
+            sha: "mysha".into(),
+            head_pipeline: Some(Pipeline {
+                id: 42,
+                status: PipelineStatus::Failed,
+            }),
+            ..Default::default()
+        };
+        let mut gitlab = MockGitlabClient::default();
+        gitlab
+            .expect_merge_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(merge_request.clone()))));
+        gitlab
+            .expect_retry_pipeline()
+            .withf(|_, pipeline_id| *pipeline_id == 42)
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let mut director = make_director(gitlab);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        assert!(director.progressed());
+    }
+
+    #[tokio::test]
+    async fn test_failed_pipeline_retries_are_capped() {
+        let merge_request = MergeRequest {
+            state: MergeRequestState::Opened,
+            sha: "mysha".into(),
+            head_pipeline: Some(Pipeline {
+                id: 42,
+                status: PipelineStatus::Failed,
+            }),
+            ..Default::default()
+        };
+        let mut gitlab = MockGitlabClient::default();
+        gitlab
+            .expect_merge_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(merge_request.clone()))));
+        gitlab
+            .expect_retry_pipeline()
+            .times(2)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let mut director = make_director(gitlab).with_max_pipeline_retries(2);
+        for _ in 0..2 {
+            assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        }
+        assert!(director.run().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_waits_while_frozen() {
+        let merge_request = MergeRequest {
+            state: MergeRequestState::Opened,
+            sha: "mysha".into(),
+            ..Default::default()
+        };
+        let mut gitlab = MockGitlabClient::default();
+        gitlab
+            .expect_merge_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(merge_request.clone()))));
+
+        let now = Utc::now().with_timezone(&FixedOffset::east(0));
+        let freeze = FreezePeriod {
+            start: now - chrono::Duration::hours(1),
+            end: now + chrono::Duration::hours(1),
+            reason: None,
+        };
+        let mut director =
+            make_director(gitlab).with_freeze_check(CheckNotFrozen::new(vec![freeze], None));
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+    }
+
+    #[tokio::test]
+    async fn test_merge_when_checks_pass() {
+        let merge_request = MergeRequest {
+            state: MergeRequestState::Opened,
+            sha: "mysha".into(),
+            ..Default::default()
+        };
+        let mut gitlab = MockGitlabClient::default();
+        gitlab
+            .expect_merge_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(merge_request.clone()))));
+        gitlab
+            .expect_merge_merge_request()
+            .withf(|_, body| body.sha == "mysha" && !body.squash)
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let mut director = make_director(gitlab);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Done);
+        assert_eq!(director.merge_method(), Some(&MergeMethod::Merge));
+    }
+}
@@ -0,0 +1,7 @@
+pub mod client;
+pub mod director;
+pub mod models;
+
+pub use client::*;
+pub use director::GitlabDirector;
+pub use models::*;
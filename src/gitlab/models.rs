@@ -0,0 +1,184 @@
+use reqwest::Url;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum MergeRequestState {
+    #[serde(rename = "opened")]
+    Opened,
+
+    #[serde(rename = "closed")]
+    Closed,
+
+    #[serde(rename = "merged")]
+    Merged,
+
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum PipelineStatus {
+    #[serde(rename = "success")]
+    Success,
+
+    #[serde(rename = "failed")]
+    Failed,
+
+    #[serde(rename = "canceled")]
+    Canceled,
+
+    #[serde(rename = "skipped")]
+    Skipped,
+
+    #[serde(rename = "manual")]
+    Manual,
+
+    #[serde(other)]
+    Pending,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub id: u64,
+    pub status: PipelineStatus,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct MergeRequest {
+    pub iid: u64,
+    pub title: String,
+    pub description: Option<String>,
+    pub state: MergeRequestState,
+    pub sha: String,
+
+    #[serde(default)]
+    pub draft: bool,
+
+    #[serde(default)]
+    pub has_conflicts: bool,
+
+    pub diverged_commits_count: Option<u32>,
+    pub head_pipeline: Option<Pipeline>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub username: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Approver {
+    pub user: User,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Approvals {
+    pub approvals_left: u32,
+    pub approved_by: Vec<Approver>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MergeMergeRequestBody {
+    pub sha: String,
+    pub squash: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NoBody {}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MergeRequestIdentifier {
+    /// The scheme and host of the gitlab instance, e.g. `https://gitlab.com`
+    pub base_url: String,
+
+    /// The full path to the project, including any subgroups
+    pub project: String,
+
+    pub iid: u64,
+}
+
+impl MergeRequestIdentifier {
+    /// Checks whether this URL looks like a gitlab merge request URL
+    pub fn is_merge_request_url(url: &Url) -> bool {
+        url.path().contains("/-/merge_requests/")
+    }
+
+    pub fn from_app_url(url: &Url) -> Result<Self, InvalidUrlError> {
+        let host = url.host_str().ok_or(InvalidUrlError::InvalidDomain)?;
+        let (project, iid) = url
+            .path()
+            .trim_start_matches('/')
+            .split_once("/-/merge_requests/")
+            .ok_or(InvalidUrlError::NotMergeRequestUrl)?;
+        if project.is_empty() {
+            return Err(InvalidUrlError::NotMergeRequestUrl);
+        }
+        let iid = iid
+            .trim_end_matches('/')
+            .parse()
+            .map_err(|_| InvalidUrlError::NotMergeRequestUrl)?;
+        let base_url = match url.port() {
+            Some(port) => format!("{}://{}:{}", url.scheme(), host, port),
+            None => format!("{}://{}", url.scheme(), host),
+        };
+        Ok(Self {
+            base_url,
+            project: project.into(),
+            iid,
+        })
+    }
+
+    pub fn app_url(&self) -> String {
+        format!(
+            "{}/{}/-/merge_requests/{}",
+            self.base_url, self.project, self.iid
+        )
+    }
+}
+
+impl fmt::Display for MergeRequestIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/merge_requests/{}", self.project, self.iid)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum InvalidUrlError {
+    #[error("invalid domain")]
+    InvalidDomain,
+
+    #[error("not a merge request URL")]
+    NotMergeRequestUrl,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_request_from_app_url() {
+        let url = Url::parse("https://gitlab.example.com/potato/sub/smasher/-/merge_requests/1337")
+            .unwrap();
+        assert!(MergeRequestIdentifier::is_merge_request_url(&url));
+        let mr = MergeRequestIdentifier::from_app_url(&url).unwrap();
+        assert_eq!(mr.base_url, "https://gitlab.example.com");
+        assert_eq!(mr.project, "potato/sub/smasher");
+        assert_eq!(mr.iid, 1337);
+        assert_eq!(mr.app_url(), url.as_str());
+
+        assert!(MergeRequestIdentifier::from_app_url(
+            &Url::parse("https://gitlab.com/potato/smasher/-/merge_requests/").unwrap()
+        )
+        .is_err());
+        assert!(MergeRequestIdentifier::from_app_url(
+            &Url::parse("https://gitlab.com/potato/smasher/merge_requests/1337").unwrap()
+        )
+        .is_err());
+        assert!(!MergeRequestIdentifier::is_merge_request_url(
+            &Url::parse("https://github.com/potato/smasher/pull/1337").unwrap()
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::client::{ApiClient, Result};
+use crate::github::PullRequest;
+use crate::gitlab::PipelineStatus;
+use crate::processing::Error;
+use crate::processing::{WorkflowRunner, WorkflowStatus};
+use async_trait::async_trait;
+use log::info;
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub id: u64,
+    pub status: PipelineStatus,
+    pub web_url: String,
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait GitlabCiClient {
+    /// The project's pipelines for a commit, most recent first
+    async fn pipelines(&self, sha: &str) -> Result<Vec<Pipeline>>;
+}
+
+pub struct DefaultGitlabCiClient {
+    client: ApiClient,
+    project_url: String,
+}
+
+impl DefaultGitlabCiClient {
+    pub fn new(base_url: &Url, project_id: &str, token: String) -> Self {
+        Self {
+            client: ApiClient::from_bearer_token(token),
+            project_url: format!(
+                "{}/api/v4/projects/{}",
+                base_url.as_str().trim_end_matches('/'),
+                project_id.replace('/', "%2F")
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl GitlabCiClient for DefaultGitlabCiClient {
+    async fn pipelines(&self, sha: &str) -> Result<Vec<Pipeline>> {
+        let url = format!("{}/pipelines?sha={}", self.project_url, sha);
+        self.client.get(&url).await
+    }
+}
+
+/// Reports the status of the gitlab CI pipelines mirrored from a pull request's head commit. These
+/// are never re-ran, failures have to be dealt with in gitlab.
+pub struct GitlabCiWorkflowRunner<C> {
+    client: Arc<C>,
+    base_url: Url,
+}
+
+impl<C: GitlabCiClient> GitlabCiWorkflowRunner<C> {
+    pub fn new(client: Arc<C>, base_url: Url) -> Self {
+        Self { client, base_url }
+    }
+
+    fn is_pipeline_url(&self, url: &Url) -> bool {
+        url.host() == self.base_url.host() && url.path().starts_with(self.base_url.path())
+    }
+}
+
+#[async_trait]
+impl<C: GitlabCiClient + Send + Sync> WorkflowRunner for GitlabCiWorkflowRunner<C> {
+    async fn process_failed_jobs(
+        &self,
+        pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> std::result::Result<WorkflowStatus, Error> {
+        let pipeline_urls: Vec<_> = job_urls
+            .iter()
+            .filter(|url| self.is_pipeline_url(url))
+            .cloned()
+            .collect();
+        if pipeline_urls.is_empty() {
+            return Ok(WorkflowStatus::Success);
+        }
+        let pipelines = self.client.pipelines(&pull_request.head.sha).await?;
+        let pipeline = match pipelines.into_iter().next() {
+            Some(pipeline) => pipeline,
+            None => return Ok(WorkflowStatus::Success),
+        };
+        match pipeline.status {
+            PipelineStatus::Pending => {
+                info!("Gitlab pipeline #{} is still running", pipeline.id);
+                Ok(WorkflowStatus::Pending)
+            }
+            PipelineStatus::Manual => {
+                info!(
+                    "Gitlab pipeline #{} is waiting for a manual action at {}",
+                    pipeline.id, pipeline.web_url
+                );
+                Ok(WorkflowStatus::Pending)
+            }
+            // The status reported on github is outdated, e.g. the pipeline was retried in gitlab
+            PipelineStatus::Success | PipelineStatus::Skipped => {
+                Ok(WorkflowStatus::Accepted(pipeline_urls))
+            }
+            PipelineStatus::Failed | PipelineStatus::Canceled => Err(Error::as_generic(format!(
+                "gitlab pipeline #{} didn't succeed: {}",
+                pipeline.id, pipeline.web_url
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Branch;
+    use rstest::rstest;
+    use std::future;
+
+    const PIPELINE_URL: &str = "https://gitlab.example.com/potato/smasher/-/pipelines/42";
+
+    fn make_runner(client: MockGitlabCiClient) -> GitlabCiWorkflowRunner<MockGitlabCiClient> {
+        GitlabCiWorkflowRunner::new(
+            Arc::new(client),
+            Url::parse("https://gitlab.example.com").unwrap(),
+        )
+    }
+
+    #[rstest]
+    #[case::pending(PipelineStatus::Pending, Some(WorkflowStatus::Pending))]
+    #[case::manual(PipelineStatus::Manual, Some(WorkflowStatus::Pending))]
+    #[case::success(
+        PipelineStatus::Success,
+        Some(WorkflowStatus::Accepted(vec![Url::parse(PIPELINE_URL).unwrap()]))
+    )]
+    #[case::skipped(
+        PipelineStatus::Skipped,
+        Some(WorkflowStatus::Accepted(vec![Url::parse(PIPELINE_URL).unwrap()]))
+    )]
+    #[case::failed(PipelineStatus::Failed, None)]
+    #[case::canceled(PipelineStatus::Canceled, None)]
+    #[tokio::test]
+    async fn test_process_failed_jobs(
+        #[case] status: PipelineStatus,
+        #[case] expected: Option<WorkflowStatus>,
+    ) {
+        let mut client = MockGitlabCiClient::default();
+        client
+            .expect_pipelines()
+            .withf(|sha| sha == "mysha")
+            .returning(move |_| {
+                Box::pin(future::ready(Ok(vec![Pipeline {
+                    id: 42,
+                    status: status.clone(),
+                    web_url: PIPELINE_URL.into(),
+                }])))
+            });
+        let runner = make_runner(client);
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job_urls = vec![
+            Url::parse(PIPELINE_URL).unwrap(),
+            Url::parse("https://circleci.com/gh/potato/smasher/1").unwrap(),
+        ];
+        let status = runner.process_failed_jobs(&pull_request, &job_urls).await;
+        assert_eq!(status.ok(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_ignores_other_urls() {
+        let runner = make_runner(MockGitlabCiClient::default());
+        let job_urls = vec![Url::parse("https://circleci.com/gh/potato/smasher/1").unwrap()];
+        let status = runner
+            .process_failed_jobs(&PullRequest::default(), &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, WorkflowStatus::Success);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::client::{ApiClient, Result};
+use crate::github::PullRequest;
+use crate::processing::Error;
+use crate::processing::{WorkflowRunner, WorkflowStatus};
+use async_trait::async_trait;
+use log::info;
+use reqwest::Url;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Build {
+    pub building: bool,
+    pub result: Option<BuildResult>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum BuildResult {
+    #[serde(rename = "SUCCESS")]
+    Success,
+
+    #[serde(rename = "FAILURE")]
+    Failure,
+
+    #[serde(rename = "UNSTABLE")]
+    Unstable,
+
+    #[serde(rename = "ABORTED")]
+    Aborted,
+
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NoBody {}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait JenkinsClient {
+    async fn last_build(&self, job: &JobPath) -> Result<Build>;
+    async fn rebuild(&self, job: &JobPath) -> Result<NoBody>;
+}
+
+pub struct DefaultJenkinsClient {
+    client: ApiClient,
+    base_url: Url,
+}
+
+impl DefaultJenkinsClient {
+    pub fn new<U: Into<String>, T: Into<String>>(base_url: Url, username: U, api_token: T) -> Self {
+        Self {
+            client: ApiClient::from_credentials(username, api_token),
+            base_url,
+        }
+    }
+
+    fn make_job_url(&self, job: &JobPath) -> String {
+        let base_url = self.base_url.as_str().trim_end_matches('/');
+        format!(
+            "{}/job/{}/view/change-requests/job/PR-{}",
+            base_url,
+            job.jobs.join("/job/"),
+            job.pull_number
+        )
+    }
+}
+
+#[async_trait]
+impl JenkinsClient for DefaultJenkinsClient {
+    async fn last_build(&self, job: &JobPath) -> Result<Build> {
+        let url = format!("{}/lastBuild/api/json", self.make_job_url(job));
+        self.client.get(&url).await
+    }
+
+    async fn rebuild(&self, job: &JobPath) -> Result<NoBody> {
+        let url = format!("{}/build", self.make_job_url(job));
+        self.client.post(&url, &EmptyBody {}).await
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct EmptyBody {}
+
+/// The multibranch pipeline job that builds a pull request
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JobPath {
+    /// The job names, outermost first, for jobs nested in folders
+    pub jobs: Vec<String>,
+    pub pull_number: u32,
+}
+
+/// Re-runs Jenkins multibranch pipeline builds that reported a failed status on a pull request
+pub struct JenkinsWorkflowRunner<C> {
+    client: Arc<C>,
+    base_url: Url,
+    unstable_as_failure: bool,
+}
+
+impl<C: JenkinsClient> JenkinsWorkflowRunner<C> {
+    pub fn new(client: Arc<C>, base_url: Url, unstable_as_failure: bool) -> Self {
+        Self {
+            client,
+            base_url,
+            unstable_as_failure,
+        }
+    }
+
+    fn parse_job_url(&self, url: &Url) -> Option<JobPath> {
+        if url.host() != self.base_url.host() || url.port() != self.base_url.port() {
+            return None;
+        }
+        // URLs look like `{base_url}/job/{job}[/job/{nested job}]/job/PR-{n}/{build}/...`
+        let base_path = self.base_url.path().trim_end_matches('/');
+        let path = url.path().strip_prefix(base_path)?;
+        if !path.starts_with('/') {
+            return None;
+        }
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        let mut jobs = Vec::new();
+        while segments.next()? == "job" {
+            let name = segments.next()?;
+            if let Some(pull_number) = name.strip_prefix("PR-") {
+                if jobs.is_empty() {
+                    return None;
+                }
+                let pull_number = pull_number.parse().ok()?;
+                return Some(JobPath { jobs, pull_number });
+            }
+            jobs.push(name.to_string());
+        }
+        None
+    }
+
+    fn build_outcome(&self, build: &Build) -> BuildOutcome {
+        if build.building {
+            return BuildOutcome::Pending;
+        }
+        match build.result {
+            Some(BuildResult::Failure) | Some(BuildResult::Aborted) => BuildOutcome::Failed,
+            Some(BuildResult::Unstable) if self.unstable_as_failure => BuildOutcome::Failed,
+            Some(BuildResult::Unstable) => BuildOutcome::Accepted,
+            _ => BuildOutcome::Passed,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: JenkinsClient + Send + Sync> WorkflowRunner for JenkinsWorkflowRunner<C> {
+    async fn process_failed_jobs(
+        &self,
+        _pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> std::result::Result<WorkflowStatus, Error> {
+        let mut pending = HashSet::new();
+        let mut failed = HashSet::new();
+        let mut accepted = Vec::new();
+        for job_url in job_urls {
+            let job = match self.parse_job_url(job_url) {
+                Some(job) => job,
+                None => continue,
+            };
+            let build = self.client.last_build(&job).await?;
+            match self.build_outcome(&build) {
+                BuildOutcome::Pending => {
+                    pending.insert(job);
+                }
+                BuildOutcome::Failed => {
+                    failed.insert(job);
+                }
+                BuildOutcome::Accepted => accepted.push(job_url.clone()),
+                BuildOutcome::Passed => (),
+            };
+        }
+        if !pending.is_empty() {
+            info!("{} failed jenkins builds are still running", pending.len());
+            return Ok(WorkflowStatus::Pending);
+        }
+        if !failed.is_empty() {
+            info!("Re-running {} failed jenkins builds", failed.len());
+            for job in failed {
+                self.client.rebuild(&job).await?;
+            }
+            return Ok(WorkflowStatus::Triggered);
+        }
+        if !accepted.is_empty() {
+            info!(
+                "Considering {} unstable jenkins builds as successful",
+                accepted.len()
+            );
+            return Ok(WorkflowStatus::Accepted(accepted));
+        }
+        Ok(WorkflowStatus::Success)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum BuildOutcome {
+    Pending,
+    Failed,
+    Accepted,
+    Passed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::future;
+
+    fn make_runner(unstable_as_failure: bool) -> JenkinsWorkflowRunner<MockJenkinsClient> {
+        make_runner_with_client(MockJenkinsClient::default(), unstable_as_failure)
+    }
+
+    fn make_runner_with_client(
+        client: MockJenkinsClient,
+        unstable_as_failure: bool,
+    ) -> JenkinsWorkflowRunner<MockJenkinsClient> {
+        let base_url = Url::parse("https://ci.potato.com/jenkins/").unwrap();
+        JenkinsWorkflowRunner::new(Arc::new(client), base_url, unstable_as_failure)
+    }
+
+    #[tokio::test]
+    async fn test_failed_build_rebuilt() {
+        let mut client = MockJenkinsClient::default();
+        client.expect_last_build().returning(|_| {
+            Box::pin(future::ready(Ok(Build {
+                building: false,
+                result: Some(BuildResult::Failure),
+            })))
+        });
+        client
+            .expect_rebuild()
+            .withf(|job| job.pull_number == 1337)
+            .times(1)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+        let runner = make_runner_with_client(client, true);
+        let job_urls = vec![
+            Url::parse("https://ci.potato.com/jenkins/job/smasher/job/PR-1337/3/").unwrap(),
+            Url::parse("https://circleci.com/gh/potato/smasher/1337").unwrap(),
+        ];
+        let status = runner
+            .process_failed_jobs(&PullRequest::default(), &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, WorkflowStatus::Triggered);
+    }
+
+    #[test]
+    fn test_parse_job_url() {
+        let runner = make_runner(true);
+        let parse = |url| runner.parse_job_url(&Url::parse(url).unwrap());
+        assert_eq!(
+            parse("https://ci.potato.com/jenkins/job/smasher/job/PR-1337/3/display/redirect"),
+            Some(JobPath {
+                jobs: vec!["smasher".into()],
+                pull_number: 1337
+            })
+        );
+        assert_eq!(
+            parse("https://ci.potato.com/jenkins/job/potato/job/smasher/job/PR-42/1/"),
+            Some(JobPath {
+                jobs: vec!["potato".into(), "smasher".into()],
+                pull_number: 42
+            })
+        );
+        assert_eq!(parse("https://ci.potato.com/jenkins/job/smasher/3/"), None);
+        assert_eq!(parse("https://ci.potato.com/jenkins/job/PR-42/3/"), None);
+        assert_eq!(
+            parse("https://ci.potato.com/job/smasher/job/PR-42/3/"),
+            None
+        );
+        assert_eq!(parse("https://circleci.com/gh/potato/smasher/1337"), None);
+    }
+
+    #[rstest]
+    #[case::building(true, None, true, BuildOutcome::Pending)]
+    #[case::success(false, Some(BuildResult::Success), true, BuildOutcome::Passed)]
+    #[case::failure(false, Some(BuildResult::Failure), false, BuildOutcome::Failed)]
+    #[case::aborted(false, Some(BuildResult::Aborted), false, BuildOutcome::Failed)]
+    #[case::unstable_failure(false, Some(BuildResult::Unstable), true, BuildOutcome::Failed)]
+    #[case::unstable_accepted(false, Some(BuildResult::Unstable), false, BuildOutcome::Accepted)]
+    fn test_build_outcome(
+        #[case] building: bool,
+        #[case] result: Option<BuildResult>,
+        #[case] unstable_as_failure: bool,
+        #[case] expected: BuildOutcome,
+    ) {
+        let build = Build { building, result };
+        let runner = make_runner(unstable_as_failure);
+        assert_eq!(runner.build_outcome(&build), expected);
+    }
+}
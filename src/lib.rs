@@ -1,9 +1,27 @@
+pub mod audit;
+pub mod azure_pipelines;
+pub mod bitbucket;
+pub mod buildkite;
 pub mod circleci;
 pub mod client;
 pub mod common;
 pub mod config;
+pub mod config_keys;
+pub mod error;
 pub mod github;
+pub mod github_actions;
+pub mod gitlab;
+pub mod gitlab_ci;
+pub mod jenkins;
+pub mod metrics;
+pub mod notifications;
 pub mod processing;
+pub mod teamcity;
+pub mod travis;
+pub mod webhook;
 
 pub use crate::config::MergebroConfig;
-pub use processing::{Director, DirectorState, WorkflowRunner};
+pub use crate::error::MergebroError;
+pub use processing::{
+    Director, DirectorOptions, DirectorState, PullRequestProcessor, StepOverride, WorkflowRunner,
+};
@@ -1,34 +1,144 @@
+use backoff::backoff::Backoff;
+use chrono::{DateTime, Local};
 use env_logger::Env;
-use log::{error, info};
+use futures::future::join_all;
+use log::{debug, error, info, warn};
 use mergebro::{
+    audit::AuditLog,
+    azure_pipelines::{AzurePipelinesWorkflowRunner, DefaultAzurePipelinesClient},
+    bitbucket::{self, BitbucketDirector, DefaultBitbucketClient},
+    buildkite::{BuildkiteWorkflowRunner, DefaultBuildkiteClient},
     circleci::{CircleCiWorkflowRunner, DefaultCircleCiClient},
+    client::init_http_client,
     common::{RepoIdentifier, RepoMap},
-    config::{ReviewsConfig, StatusFailuresConfig},
-    github::{DefaultGithubClient, GithubClient, PullRequestIdentifier},
+    config::{
+        ConfigIssue, GithubConfig, MergeConfig, PollConfig, ReviewsConfig, StatusFailuresConfig,
+    },
+    github::{
+        missing_token_scopes, Branch, DefaultGithubClient, GithubClient, MergeMethod,
+        PullRequestIdentifier, PullRequestState,
+    },
+    github_actions::GithubActionsWorkflowRunner,
+    gitlab::{DefaultGitlabClient, GitlabDirector, MergeRequestIdentifier},
+    gitlab_ci::{DefaultGitlabCiClient, GitlabCiWorkflowRunner},
+    jenkins::{DefaultJenkinsClient, JenkinsWorkflowRunner},
+    metrics::{MetricsServer, METRICS},
+    notifications::{DiscordNotifier, MergeEvent, MergeOutcome, Notifier, SlackNotifier},
     processing::{
+        poll::{build_poll_backoff, poll_config_for_repo},
+        status::{StepOutcome, StepReport},
         steps::{
-            CheckBehindMaster, CheckBuildFailed, CheckCurrentStateStep, CheckReviewsStep, Step,
+            CheckApprovedAfterLastPush, CheckBaseBranchAllowed, CheckBaseCurrent,
+            CheckBehindMaster, CheckBlockingLabels, CheckBranchNamingConvention, CheckBuildFailed,
+            CheckCurrentStateStep, CheckExternalScript, CheckHeadUnchanged, CheckLinkedIssue,
+            CheckMergeWindow, CheckMergeable, CheckMilestoneAssigned, CheckMinimumAge,
+            CheckNoForcePushSinceApproval, CheckNotDraft, CheckNotFrozen, CheckPullRequestSize,
+            CheckRequiredChecksPresent, CheckRequiredLabels, CheckReviewsStep, CheckTitleNotWip,
+            CheckTitlePattern, Step,
         },
         DefaultPullRequestMerger, DummyPullRequestMerger, PullRequestMerger,
     },
-    Director, DirectorState, MergebroConfig, WorkflowRunner,
+    teamcity::{DefaultTeamCityClient, TeamCityWorkflowRunner},
+    travis::{DefaultTravisClient, TravisWorkflowRunner},
+    webhook::{WebhookServer, WebhookTrigger},
+    Director, DirectorOptions, DirectorState, MergebroConfig, MergebroError, PullRequestProcessor,
+    StepOverride, WorkflowRunner,
 };
 use reqwest::Url;
-use std::collections::HashMap;
+use serde_derive::Serialize;
+use std::collections::{hash_map::Entry, HashMap};
 use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::future;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
+use tokio::sync::{mpsc, watch, Semaphore};
 use tokio::time::sleep;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+/// The format log lines are written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LogFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format '{}'", format)),
+        }
+    }
+}
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "mergebro")]
 struct Options {
-    /// The path to the YAML configuration file
-    #[structopt(short, long, default_value = "~/.mergebro/config.yaml")]
-    config_file: String,
+    /// The paths to the YAML configuration files or directories containing them, comma separated
+    /// or repeated. Later ones override earlier ones
+    #[structopt(
+        short,
+        long,
+        default_value = "~/.mergebro/config.yaml",
+        use_delimiter = true,
+        number_of_values = 1,
+        global = true
+    )]
+    config_file: Vec<String>,
+
+    /// The format to write logs in
+    #[structopt(
+        long,
+        default_value = "human",
+        possible_values = &["human", "json"],
+        global = true
+    )]
+    log_format: LogFormat,
+
+    /// Logs more details, once for debug logs and twice for trace logs. Ignored if RUST_LOG is set
+    #[structopt(short, long, parse(from_occurrences), global = true)]
+    verbose: u8,
 
+    /// Only logs warnings and errors. Ignored if RUST_LOG is set
+    #[structopt(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Whether to skip checking that the github token has the scopes needed on startup
+    #[structopt(long, global = true)]
+    skip_token_check: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Waits for the checks on pull requests to pass and merges them
+    Merge(MergeOptions),
+
+    /// Runs every check on a pull request once and prints their results, without merging it
+    Status(StatusOptions),
+
+    /// Listens for github webhooks and processes the pull requests they're about
+    Serve(ServeOptions),
+
+    /// Checks the configuration for errors and likely mistakes without processing any pull request
+    Validate,
+}
+
+#[derive(StructOpt, Debug)]
+struct MergeOptions {
     /// Whether to simply run checks but not actually merge the pull request
     #[structopt(short, long)]
     dry_run: bool,
@@ -37,30 +147,199 @@ struct Options {
     #[structopt(short = "r")]
     ignore_reviews: bool,
 
-    /// The pull request to be processed
-    #[structopt(name = "pull_request_url")]
+    /// The maximum number of seconds to wait for checks to pass before giving up
+    #[structopt(long = "wait-for-checks-timeout")]
+    wait_timeout_seconds: Option<u64>,
+
+    /// A label to add to pull requests when a check fails, which is removed once checks pass
+    #[structopt(long)]
+    label_when_blocked: Option<String>,
+
+    /// Whether to keep a comment on the pull request up to date with the status of each check
+    #[structopt(long)]
+    status_comment: bool,
+
+    /// A user that must approve pull requests before they're merged. Can be used multiple times
+    #[structopt(long = "required-approver", number_of_values = 1)]
+    required_approvers: Vec<String>,
+
+    /// Whether to process all pull requests concurrently rather than one after the other
+    #[structopt(long)]
+    parallel: bool,
+
+    /// The maximum number of pull requests to process at the same time when using `--parallel`
+    #[structopt(long, default_value = "4")]
+    max_concurrency: usize,
+
+    /// Whether to enable github's auto-merge on the pull requests and exit rather than waiting
+    #[structopt(long)]
+    enable_auto_merge: bool,
+
+    /// Whether to run checks once and exit rather than waiting for them to pass. Exits with a
+    /// status code of 75 if the pull requests aren't ready to be merged yet
+    #[structopt(long)]
+    once: bool,
+
+    /// A port to serve prometheus metrics on, under `/metrics`
+    #[structopt(long)]
+    metrics_port: Option<u16>,
+
+    /// A file to write a JSON summary of the outcome of each pull request to once done
+    #[structopt(long, parse(from_os_str))]
+    output_file: Option<PathBuf>,
+
+    /// A branch to check pull requests against instead of their base branch, e.g. when it was
+    /// just changed and github still reports the previous one
+    #[structopt(long)]
+    base_branch_override: Option<String>,
+
+    /// The pull requests to be processed
+    #[structopt(name = "pull_request_url", required = true)]
+    pull_request_urls: Vec<String>,
+}
+
+#[derive(StructOpt, Debug)]
+struct StatusOptions {
+    /// Whether to ignore checks for pull request reviews
+    #[structopt(short = "r")]
+    ignore_reviews: bool,
+
+    /// A user that must approve the pull request. Can be used multiple times
+    #[structopt(long = "required-approver", number_of_values = 1)]
+    required_approvers: Vec<String>,
+
+    /// The pull request to be checked
     pull_request_url: String,
 }
 
-fn parse_pull_request_url(url: &str) -> Result<PullRequestIdentifier, Box<dyn Error>> {
-    let url = Url::parse(url)?;
-    let pull_request_id = PullRequestIdentifier::from_app_url(&url)?;
-    Ok(pull_request_id)
+#[derive(StructOpt, Debug)]
+struct ServeOptions {
+    /// The port to listen for webhooks on
+    #[structopt(long, default_value = "8080")]
+    port: u16,
+
+    /// Whether to simply run checks but not actually merge pull requests
+    #[structopt(short, long)]
+    dry_run: bool,
+
+    /// Whether to ignore checks for pull request reviews
+    #[structopt(short = "r")]
+    ignore_reviews: bool,
+
+    /// A label to add to pull requests when a check fails, which is removed once checks pass
+    #[structopt(long)]
+    label_when_blocked: Option<String>,
+
+    /// Whether to keep a comment on pull requests up to date with the status of each check
+    #[structopt(long)]
+    status_comment: bool,
+
+    /// A user that must approve pull requests before they're merged. Can be used multiple times
+    #[structopt(long = "required-approver", number_of_values = 1)]
+    required_approvers: Vec<String>,
+}
+
+/// A pull request in any of the supported platforms
+enum Target {
+    Github(PullRequestIdentifier),
+    Gitlab(MergeRequestIdentifier),
+    Bitbucket(bitbucket::PullRequestIdentifier),
+}
+
+impl Target {
+    /// The owner and name of the repo the pull request belongs to
+    fn repo(&self) -> (&str, &str) {
+        match self {
+            Self::Github(identifier) => (&identifier.owner, &identifier.repo),
+            Self::Gitlab(identifier) => identifier.project.rsplit_once('/').unwrap_or(("", "")),
+            Self::Bitbucket(identifier) => (&identifier.workspace, &identifier.repo),
+        }
+    }
+
+    fn app_url(&self) -> String {
+        match self {
+            Self::Github(identifier) => identifier.app_url(),
+            Self::Gitlab(identifier) => identifier.app_url(),
+            Self::Bitbucket(identifier) => identifier.app_url(),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Github(identifier) => write!(f, "{}", identifier),
+            Self::Gitlab(identifier) => write!(f, "{}", identifier),
+            Self::Bitbucket(identifier) => write!(f, "{}", identifier),
+        }
+    }
+}
+
+fn parse_pull_request_url(url: &str, github_base_url: &Url) -> Result<Target, MergebroError> {
+    let invalid_url =
+        |e: &dyn Error| MergebroError::as_config(format!("invalid URL '{}': {}", url, e));
+    let url = Url::parse(url).map_err(|e| invalid_url(&e))?;
+    if MergeRequestIdentifier::is_merge_request_url(&url) {
+        let merge_request_id =
+            MergeRequestIdentifier::from_app_url(&url).map_err(|e| invalid_url(&e))?;
+        return Ok(Target::Gitlab(merge_request_id));
+    }
+    if bitbucket::PullRequestIdentifier::is_pull_request_url(&url) {
+        let pull_request_id =
+            bitbucket::PullRequestIdentifier::from_app_url(&url).map_err(|e| invalid_url(&e))?;
+        return Ok(Target::Bitbucket(pull_request_id));
+    }
+    // API URLs can be in a different host, e.g. api.github.com
+    let pull_request_id = PullRequestIdentifier::from_any_url(&url);
+    let host = match &pull_request_id {
+        Ok(identifier) => Url::parse(&identifier.base_url)
+            .map_err(|e| invalid_url(&e))?
+            .host_str()
+            .map(String::from),
+        Err(_) => url.host_str().map(String::from),
+    };
+    if host.as_deref() != github_base_url.host_str() {
+        return Err(MergebroError::as_config(format!(
+            "not a pull request in {}",
+            github_base_url
+        )));
+    }
+    pull_request_id
+        .map(Target::Github)
+        .map_err(|e| invalid_url(&e))
+}
+
+/// Adds the given users as required approvers in the default and every repo's reviews config
+fn add_required_approvers(config: &mut MergebroConfig, approvers: &[String]) {
+    config
+        .reviews
+        .required_approvers
+        .extend_from_slice(approvers);
+    for repo_config in &mut config.repos {
+        if let Some(reviews) = &mut repo_config.reviews {
+            reviews.required_approvers.extend_from_slice(approvers);
+        }
+    }
 }
 
 struct SplitRepoConfigs {
     reviews_config: RepoMap<ReviewsConfig>,
+    merge_config: RepoMap<MergeConfig>,
     status_failures_config: RepoMap<HashMap<String, StatusFailuresConfig>>,
 }
 
-fn split_repo_configs(config: &MergebroConfig) -> Result<SplitRepoConfigs, Box<dyn Error>> {
+fn split_repo_configs(config: &MergebroConfig) -> Result<SplitRepoConfigs, MergebroError> {
     let mut reviews_config = RepoMap::new(config.reviews.clone());
+    let mut merge_config = RepoMap::new(config.merge.clone());
     let mut status_failures_config = RepoMap::default();
     for repo_config in &config.repos {
         let repo: RepoIdentifier = repo_config.repo.parse()?;
         if let Some(reviews) = &repo_config.reviews {
             reviews_config.insert(repo.clone(), reviews.clone())?;
         }
+        if let Some(merge) = &repo_config.merge {
+            merge_config.insert(repo.clone(), config.merge.with_overrides(merge))?;
+        }
         if !repo_config.statuses.is_empty() {
             let mut status_config = HashMap::new();
             for status in &repo_config.statuses {
@@ -72,121 +351,1372 @@ fn split_repo_configs(config: &MergebroConfig) -> Result<SplitRepoConfigs, Box<d
     }
     Ok(SplitRepoConfigs {
         reviews_config,
+        merge_config,
         status_failures_config,
     })
 }
 
+/// Builds the steps to run on a pull request. When `read_only` is set, steps never update the
+/// pull request's branch nor re-run its failed jobs.
 fn build_steps(
     id: &PullRequestIdentifier,
     github_client: Arc<dyn GithubClient>,
-    workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
+    mut workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
     config: &MergebroConfig,
     ignore_reviews: bool,
-) -> Result<Vec<Box<dyn Step>>, Box<dyn Error>> {
+    read_only: bool,
+    base_branch_override: Option<&str>,
+) -> Result<Vec<Box<dyn Step>>, MergebroError> {
+    if read_only {
+        workflow_runners.clear();
+    }
     let split_repo_configs = split_repo_configs(config)?;
+    let reviews_config = split_repo_configs.reviews_config.get(&id.owner, &id.repo);
     let mut steps: Vec<Box<dyn Step>> = vec![
-        Box::new(CheckCurrentStateStep::default()),
-        Box::new(CheckBehindMaster::new(github_client.clone())),
-        Box::new(CheckBuildFailed::new(
-            github_client.clone(),
-            workflow_runners,
-            split_repo_configs
-                .status_failures_config
-                .get(&id.owner, &id.repo)
-                .clone(),
-        )?),
+        Box::new(CheckCurrentStateStep),
+        Box::new(CheckNotDraft),
+        Box::new(CheckMergeable),
+        Box::new(CheckBlockingLabels::new(
+            reviews_config.blocking_labels.clone(),
+        )),
+        Box::new(CheckRequiredLabels::new(
+            reviews_config.required_labels.clone(),
+        )),
     ];
-    if !ignore_reviews {
-        steps.push(Box::new(CheckReviewsStep::new(
+    if !config.merge.allowed_base_branches.is_empty() {
+        steps.push(Box::new(CheckBaseBranchAllowed::new(
+            &config.merge.allowed_base_branches,
+        )?));
+    }
+    if let Some(parent_branch) = &config.merge.require_base_current_with {
+        steps.push(Box::new(CheckBaseCurrent::new(
             github_client.clone(),
-            split_repo_configs
-                .reviews_config
-                .get(&id.owner, &id.repo)
-                .clone(),
+            parent_branch,
+        )));
+    }
+    if reviews_config.require_milestone {
+        steps.push(Box::new(CheckMilestoneAssigned));
+    }
+    if !reviews_config.allowed_branch_patterns.is_empty() {
+        steps.push(Box::new(CheckBranchNamingConvention::new(
+            &reviews_config.allowed_branch_patterns,
         )?));
     }
+    if reviews_config.require_linked_issue {
+        steps.push(Box::new(CheckLinkedIssue::new(
+            github_client.clone(),
+            reviews_config.verify_linked_issue,
+        )));
+    }
+    if let Some(wip_title_pattern) = &reviews_config.wip_title_pattern {
+        steps.push(Box::new(CheckTitleNotWip::new(wip_title_pattern)?));
+    }
+    if let Some(title_pattern) = &config.merge.title_pattern {
+        steps.push(Box::new(CheckTitlePattern::new(title_pattern)?));
+    }
+    if config.merge.max_changed_files.is_some() || config.merge.max_total_lines.is_some() {
+        steps.push(Box::new(CheckPullRequestSize::new(
+            config.merge.max_changed_files,
+            config.merge.max_total_lines,
+            config.merge.size_override_label.clone(),
+        )));
+    }
+    if config.merge.min_open_duration_seconds > 0 {
+        steps.push(Box::new(CheckMinimumAge::new(Duration::from_secs(
+            config.merge.min_open_duration_seconds,
+        ))));
+    }
+    // Updating the branch would merge the base branch github knows of, not the overridden one
+    let mut check_behind_master = CheckBehindMaster::new(
+        github_client.clone(),
+        config.merge.auto_update_branch && !read_only && base_branch_override.is_none(),
+    );
+    if base_branch_override.is_some() {
+        check_behind_master = check_behind_master.with_compare_branches();
+    }
+    steps.push(Box::new(check_behind_master));
+    if config.workflows.require_checks_present {
+        steps.push(Box::new(CheckRequiredChecksPresent::new(
+            github_client.clone(),
+        )));
+    }
+    let mut check_build_failed = CheckBuildFailed::new(
+        github_client.clone(),
+        workflow_runners,
+        split_repo_configs
+            .status_failures_config
+            .get(&id.owner, &id.repo)
+            .clone(),
+        &config.workflows.ignored_contexts,
+        !read_only,
+    )?;
+    if let Some(min_green_seconds) = config.workflows.min_green_seconds {
+        check_build_failed =
+            check_build_failed.with_min_green(Duration::from_secs(min_green_seconds));
+    }
+    steps.push(Box::new(check_build_failed));
+    if !ignore_reviews {
+        steps.push(Box::new(
+            CheckReviewsStep::new(github_client.clone(), reviews_config.clone())
+                .with_review_requests(!read_only),
+        ));
+        if reviews_config.require_approval_after_last_push {
+            steps.push(Box::new(CheckApprovedAfterLastPush::new(
+                github_client.clone(),
+                reviews_config.clone(),
+            )));
+        }
+        if reviews_config.block_on_force_push_after_approval {
+            steps.push(Box::new(CheckNoForcePushSinceApproval::new(
+                github_client.clone(),
+                !read_only,
+            )));
+        }
+    }
+    for check in &reviews_config.external_checks {
+        steps.push(Box::new(CheckExternalScript::new(check.clone())));
+    }
+    if let Some(allowed_windows) = &config.merge.allowed_windows {
+        steps.push(Box::new(CheckMergeWindow::new(allowed_windows.clone())));
+    }
+    if let Some(freeze_check) = build_freeze_check(config) {
+        steps.push(Box::new(freeze_check));
+    }
+    steps.push(Box::new(CheckHeadUnchanged::new(github_client, id.clone())));
     Ok(steps)
 }
 
-#[tokio::main]
-async fn main() {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+/// Checks for freeze periods if any are configured, for every kind of pull request
+fn build_freeze_check(config: &MergebroConfig) -> Option<CheckNotFrozen> {
+    if config.merge.freeze_periods.is_empty() && config.merge.freeze_periods_file.is_none() {
+        return None;
+    }
+    let periods_file = config
+        .merge
+        .freeze_periods_file
+        .as_ref()
+        .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()));
+    Some(CheckNotFrozen::new(
+        config.merge.freeze_periods.clone(),
+        periods_file,
+    ))
+}
 
-    let options = Options::from_args();
-    let config = match MergebroConfig::new(&options.config_file) {
-        Ok(config) => config,
+struct Context {
+    github_client: Arc<dyn GithubClient>,
+    workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
+    notifiers: Vec<Arc<dyn Notifier>>,
+    config: MergebroConfig,
+    ignore_reviews: bool,
+    dry_run: bool,
+    wait_timeout: Option<Duration>,
+    once: bool,
+    director_options: DirectorOptions,
+    /// The resolved base branch override for each repo, keyed by owner and name
+    base_branch_overrides: HashMap<(String, String), Branch>,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl Context {
+    /// Sleeps for the given time, returning `false` early if we're shutting down
+    async fn sleep(&self, duration: Duration) -> bool {
+        let mut shutdown = self.shutdown.clone();
+        if *shutdown.borrow() {
+            return false;
+        }
+        tokio::select! {
+            _ = sleep(duration) => true,
+            _ = shutdown.changed() => false,
+        }
+    }
+
+    async fn notify(
+        &self,
+        processor: &dyn PullRequestProcessor,
+        target: &Target,
+        outcome: MergeOutcome,
+        elapsed: Duration,
+    ) {
+        let (owner, repo) = target.repo();
+        let event = MergeEvent {
+            url: target.app_url(),
+            title: processor.title().map(String::from),
+            repo: format!("{}/{}", owner, repo),
+            elapsed,
+            dry_run: self.dry_run,
+            outcome,
+        };
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.notify(&event).await {
+                warn!("Failed to send notification: {}", e);
+            }
+        }
+    }
+
+    fn build_processor(
+        &self,
+        target: &Target,
+    ) -> Result<Box<dyn PullRequestProcessor>, MergebroError> {
+        let (owner, repo) = target.repo();
+        let split_repo_configs = split_repo_configs(&self.config)?;
+        let merge_config = split_repo_configs.merge_config.get(owner, repo).clone();
+        match target {
+            Target::Github(identifier) => {
+                match &self.config.github.app {
+                    Some(app) => info!(
+                        "Starting loop on pull request: {} using github app {}",
+                        identifier, app.app_id
+                    ),
+                    None => info!(
+                        "Starting loop on pull request: {} using github user {}",
+                        identifier, self.config.github.username
+                    ),
+                };
+                let base_branch_override = self
+                    .base_branch_overrides
+                    .get(&(owner.to_string(), repo.to_string()))
+                    .cloned();
+                let steps = build_steps(
+                    identifier,
+                    self.github_client.clone(),
+                    self.workflow_runners.clone(),
+                    &self.config,
+                    self.ignore_reviews,
+                    false,
+                    base_branch_override.as_ref().map(|base| base.name.as_str()),
+                )?;
+                let director_options = DirectorOptions {
+                    step_override: build_step_override(
+                        split_repo_configs.reviews_config.get(owner, repo),
+                    ),
+                    base_branch_override,
+                    ..self.director_options.clone()
+                };
+                Ok(Box::new(Director::new(
+                    self.github_client.clone(),
+                    build_merger(merge_config, self.dry_run),
+                    steps,
+                    identifier.clone(),
+                    director_options,
+                )))
+            }
+            Target::Gitlab(identifier) => {
+                let gitlab = self
+                    .config
+                    .gitlab
+                    .as_ref()
+                    .ok_or_else(|| MergebroError::as_config("gitlab is not configured"))?;
+                info!("Starting loop on merge request: {}", identifier);
+                let reviews = if self.ignore_reviews {
+                    None
+                } else {
+                    Some(self.config.reviews.clone())
+                };
+                let director = GitlabDirector::new(
+                    Arc::new(DefaultGitlabClient::new(gitlab.token.clone())),
+                    identifier.clone(),
+                    reviews,
+                    merge_config,
+                    self.dry_run,
+                )
+                .with_max_pipeline_retries(gitlab.max_pipeline_retries);
+                let director = match build_freeze_check(&self.config) {
+                    Some(freeze_check) => director.with_freeze_check(freeze_check),
+                    None => director,
+                };
+                Ok(Box::new(director))
+            }
+            Target::Bitbucket(identifier) => {
+                let bitbucket = self
+                    .config
+                    .bitbucket
+                    .as_ref()
+                    .ok_or_else(|| MergebroError::as_config("bitbucket is not configured"))?;
+                info!("Starting loop on bitbucket pull request: {}", identifier);
+                let reviews = if self.ignore_reviews {
+                    None
+                } else {
+                    Some(self.config.reviews.clone())
+                };
+                let client = DefaultBitbucketClient::new(
+                    bitbucket.username.clone(),
+                    bitbucket.app_password.clone(),
+                );
+                let director = BitbucketDirector::new(
+                    Arc::new(client),
+                    identifier.clone(),
+                    reviews,
+                    merge_config,
+                    self.dry_run,
+                );
+                let director = match build_freeze_check(&self.config) {
+                    Some(freeze_check) => director.with_freeze_check(freeze_check),
+                    None => director,
+                };
+                Ok(Box::new(director))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum PullRequestOutcome {
+    Merged,
+    TimedOut,
+    Closed,
+    Failed,
+    Waiting,
+    InvalidConfig,
+    Unauthorized,
+    NotFound,
+    Conflicts,
+    Interrupted,
+}
+
+impl PullRequestOutcome {
+    fn from_error(error: &MergebroError) -> Self {
+        match error {
+            MergebroError::Config(_) => Self::InvalidConfig,
+            MergebroError::Auth(_) => Self::Unauthorized,
+            MergebroError::Github(e) if e.not_found() => Self::NotFound,
+            MergebroError::Conflicts => Self::Conflicts,
+            _ => Self::Failed,
+        }
+    }
+
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::Merged => ExitCode::Success,
+            Self::TimedOut => ExitCode::TimedOut,
+            Self::Closed => ExitCode::Closed,
+            Self::Failed => ExitCode::Failed,
+            Self::Waiting => ExitCode::Waiting,
+            Self::InvalidConfig => ExitCode::InvalidConfig,
+            Self::Unauthorized => ExitCode::Unauthorized,
+            Self::NotFound => ExitCode::NotFound,
+            Self::Conflicts => ExitCode::Conflicts,
+            Self::Interrupted => ExitCode::Interrupted,
+        }
+    }
+}
+
+/// The status codes mergebro exits with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitCode {
+    Success = 0,
+
+    /// A check failed, or processing failed for a reason not covered by other codes
+    Failed = 1,
+
+    TimedOut = 2,
+    Closed = 3,
+    InvalidConfig = 4,
+    Unauthorized = 5,
+    NotFound = 6,
+    Conflicts = 7,
+
+    /// EX_TEMPFAIL, so schedulers know to try again later
+    Waiting = 75,
+
+    /// We were told to shut down before pull requests were merged, following the 128 + SIGINT
+    /// convention
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    /// From most to least severe. When processing several pull requests, the first code that
+    /// applies to any of them is used.
+    const BY_SEVERITY: [Self; 10] = [
+        Self::InvalidConfig,
+        Self::Unauthorized,
+        Self::NotFound,
+        Self::Failed,
+        Self::Conflicts,
+        Self::TimedOut,
+        Self::Closed,
+        Self::Interrupted,
+        Self::Waiting,
+        Self::Success,
+    ];
+
+    fn most_severe(codes: &[Self]) -> Self {
+        Self::BY_SEVERITY
+            .into_iter()
+            .find(|code| codes.contains(code))
+            .unwrap_or(Self::Success)
+    }
+}
+
+impl fmt::Display for PullRequestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Merged => write!(f, "merged"),
+            Self::TimedOut => write!(f, "timed out"),
+            Self::Closed => write!(f, "closed"),
+            Self::Failed => write!(f, "failed"),
+            Self::Waiting => write!(f, "waiting"),
+            Self::InvalidConfig => write!(f, "invalid config"),
+            Self::Unauthorized => write!(f, "unauthorized"),
+            Self::NotFound => write!(f, "not found"),
+            Self::Conflicts => write!(f, "conflicts"),
+            Self::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// The final result of processing a pull request, as written to `--output-file`
+#[derive(Serialize, Debug)]
+struct PullRequestResult {
+    url: String,
+    outcome: PullRequestOutcome,
+    merge_method: Option<MergeMethod>,
+    steps: Vec<StepReport>,
+    started_at: DateTime<Local>,
+    finished_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Debug)]
+struct RunResult<'a> {
+    pull_requests: &'a [PullRequestResult],
+}
+
+fn write_output_file(path: &Path, results: &[PullRequestResult]) -> Result<(), Box<dyn Error>> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(
+        file,
+        &RunResult {
+            pull_requests: results,
+        },
+    )?;
+    Ok(())
+}
+
+/// The log filter to use unless `RUST_LOG` is set. Verbosity only applies to our own logs, as
+/// debug logs from dependencies are mostly noise.
+fn default_log_filter(verbose: u8, quiet: bool) -> &'static str {
+    match (quiet, verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "info,mergebro=debug",
+        (false, _) => "info,mergebro=trace",
+    }
+}
+
+fn init_logger(format: LogFormat, default_filter: &str) {
+    match format {
+        LogFormat::Human => {
+            env_logger::Builder::from_env(Env::default().default_filter_or(default_filter)).init()
+        }
+        LogFormat::Json => {
+            let filter = EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new(default_filter));
+            // This also forwards everything logged via the `log` crate
+            tracing_subscriber::fmt()
+                .json()
+                .flatten_event(true)
+                .with_env_filter(filter)
+                .init()
+        }
+    }
+}
+
+async fn process_pull_request(target: &Target, context: &Context) -> PullRequestResult {
+    let span = tracing::info_span!("pull_request", pr_url = %target.app_url());
+    let mut result = PullRequestResult {
+        url: target.app_url(),
+        outcome: PullRequestOutcome::Failed,
+        merge_method: None,
+        steps: Vec::new(),
+        started_at: Local::now(),
+        finished_at: Local::now(),
+    };
+    let (owner, repo) = target.repo();
+    let poll_config = match poll_config_for_repo(&context.config.poll, owner, repo) {
+        Ok(poll_config) => poll_config,
         Err(e) => {
-            error!("Error parsing config: {}", e);
-            exit(1);
+            error!("Invalid poll config for {}: {}", target, e);
+            result.outcome = PullRequestOutcome::InvalidConfig;
+            return result;
         }
     };
-
-    let github_client = Arc::new(DefaultGithubClient::new(
-        &config.github.username,
-        config.github.token.clone(),
-    ));
-    let identifier = match parse_pull_request_url(&options.pull_request_url) {
-        Ok(identifier) => identifier,
+    let mut processor = match context.build_processor(target) {
+        Ok(processor) => processor,
         Err(e) => {
-            error!("Error parsing pull request URL: {}", e);
-            exit(1);
+            error!("Failed to initialize step checks for {}: {}", target, e);
+            result.outcome = PullRequestOutcome::InvalidConfig;
+            return result;
         }
     };
+    result.outcome = poll_pull_request(target, context, &poll_config, &mut *processor)
+        .instrument(span)
+        .await;
+    result.merge_method = processor.merge_method().cloned();
+    result.steps = processor.step_reports().to_vec();
+    result.finished_at = Local::now();
+    result
+}
+
+async fn poll_pull_request(
+    target: &Target,
+    context: &Context,
+    poll_config: &PollConfig,
+    processor: &mut dyn PullRequestProcessor,
+) -> PullRequestOutcome {
+    let mut poll_backoff = build_poll_backoff(poll_config);
+    let start_time = Instant::now();
+    loop {
+        info!("Running checks on pull request {}...", target);
+        match processor.run().await {
+            Ok(DirectorState::Waiting) => {
+                if context.once {
+                    info!("{} isn't ready to be merged yet", target);
+                    return PullRequestOutcome::Waiting;
+                }
+                if let Some(timeout) = context.wait_timeout {
+                    if start_time.elapsed() >= timeout {
+                        error!("Gave up waiting after {} seconds", timeout.as_secs());
+                        return PullRequestOutcome::TimedOut;
+                    }
+                }
+                if processor.progressed() {
+                    poll_backoff.reset();
+                }
+                let mut sleep_duration = poll_backoff
+                    .next_backoff()
+                    .unwrap_or(poll_backoff.max_interval);
+                if let Some(status) = context.github_client.rate_limit_status() {
+                    if status.remaining < poll_config.rate_limit_threshold {
+                        info!("Rate limit nearly exhausted, sleeping until reset");
+                        sleep_duration = sleep_duration.max(status.time_until_reset());
+                    }
+                }
+                info!("Sleeping for {} seconds", sleep_duration.as_secs());
+                if !context.sleep(sleep_duration).await {
+                    info!("Stopped waiting on {} to shut down", target);
+                    return PullRequestOutcome::Interrupted;
+                }
+            }
+            Ok(DirectorState::Done) => {
+                if !context.dry_run {
+                    METRICS.observe_time_to_merge(start_time.elapsed());
+                }
+                if let Some(method) = processor.merge_method() {
+                    let outcome = MergeOutcome::Merged(method.clone());
+                    context
+                        .notify(processor, target, outcome, start_time.elapsed())
+                        .await;
+                }
+                return PullRequestOutcome::Merged;
+            }
+            Ok(DirectorState::Closed) => {
+                warn!("{} was closed without merging", target);
+                return PullRequestOutcome::Closed;
+            }
+            Err(e) => {
+                error!("Error processing pull request {}: {}", target, e);
+                let outcome = MergeOutcome::Failed(e.to_string());
+                context
+                    .notify(processor, target, outcome, start_time.elapsed())
+                    .await;
+                return PullRequestOutcome::from_error(&e.into());
+            }
+        }
+    }
+}
 
+fn build_workflow_runners(
+    config: &MergebroConfig,
+    github_client: Arc<dyn GithubClient>,
+    github_base_url: &Url,
+) -> Result<Vec<Arc<dyn WorkflowRunner>>, MergebroError> {
     let mut workflow_runners: Vec<Arc<dyn WorkflowRunner>> = Vec::new();
-    if config.workflows.circleci.is_some() {
-        let token = config.workflows.circleci.as_ref().unwrap().token.clone();
-        let circleci_client = Arc::new(DefaultCircleCiClient::new(token));
-        workflow_runners.push(Arc::new(CircleCiWorkflowRunner::new(circleci_client)));
+    if let Some(azure) = &config.workflows.azure_pipelines {
+        let azure_client = Arc::new(DefaultAzurePipelinesClient::new(
+            &azure.organization,
+            &azure.project,
+            azure.pat.clone(),
+        ));
+        workflow_runners.push(Arc::new(AzurePipelinesWorkflowRunner::new(
+            azure_client,
+            azure.organization.clone(),
+        )));
+    }
+    if let Some(buildkite) = &config.workflows.buildkite {
+        let buildkite_client = Arc::new(DefaultBuildkiteClient::new(
+            &buildkite.organization,
+            &buildkite.pipeline,
+            buildkite.token.clone(),
+        ));
+        workflow_runners.push(Arc::new(BuildkiteWorkflowRunner::new(
+            buildkite_client,
+            buildkite.organization.clone(),
+            buildkite.pipeline.clone(),
+        )));
+    }
+    if let Some(circleci) = &config.workflows.circleci {
+        let circleci_client = Arc::new(DefaultCircleCiClient::new(circleci.token.clone()));
+        workflow_runners.push(Arc::new(
+            CircleCiWorkflowRunner::new(circleci_client)
+                .with_required_workflows(circleci.required_workflows.clone()),
+        ));
+    }
+    if config.workflows.github_actions.is_some() {
+        workflow_runners.push(Arc::new(GithubActionsWorkflowRunner::new(
+            github_client,
+            github_base_url.clone(),
+        )));
+    }
+    if let Some(gitlab_ci) = &config.workflows.gitlab_ci {
+        let base_url = Url::parse(&gitlab_ci.base_url).map_err(|e| {
+            MergebroError::as_config(format!(
+                "invalid gitlab base URL '{}': {}",
+                gitlab_ci.base_url, e
+            ))
+        })?;
+        let gitlab_ci_client = Arc::new(DefaultGitlabCiClient::new(
+            &base_url,
+            &gitlab_ci.project_id,
+            gitlab_ci.token.clone(),
+        ));
+        workflow_runners.push(Arc::new(GitlabCiWorkflowRunner::new(
+            gitlab_ci_client,
+            base_url,
+        )));
+    }
+    if let Some(jenkins) = &config.workflows.jenkins {
+        let base_url = Url::parse(&jenkins.base_url).map_err(|e| {
+            MergebroError::as_config(format!(
+                "invalid jenkins base URL '{}': {}",
+                jenkins.base_url, e
+            ))
+        })?;
+        let jenkins_client = Arc::new(DefaultJenkinsClient::new(
+            base_url.clone(),
+            jenkins.username.clone(),
+            jenkins.api_token.clone(),
+        ));
+        workflow_runners.push(Arc::new(JenkinsWorkflowRunner::new(
+            jenkins_client,
+            base_url,
+            jenkins.unstable_as_failure,
+        )));
+    }
+    if let Some(teamcity) = &config.workflows.teamcity {
+        let base_url = Url::parse(&teamcity.base_url).map_err(|e| {
+            MergebroError::as_config(format!(
+                "invalid teamcity base URL '{}': {}",
+                teamcity.base_url, e
+            ))
+        })?;
+        let teamcity_client = Arc::new(DefaultTeamCityClient::new(
+            &base_url,
+            teamcity.build_type_id.clone(),
+            teamcity.token.clone(),
+        ));
+        workflow_runners.push(Arc::new(TeamCityWorkflowRunner::new(
+            teamcity_client,
+            base_url,
+        )));
+    }
+    if let Some(travis) = &config.workflows.travis {
+        let travis_client = Arc::new(DefaultTravisClient::new(travis.token.clone()));
+        workflow_runners.push(Arc::new(TravisWorkflowRunner::new(travis_client)));
+    }
+    Ok(workflow_runners)
+}
+
+fn build_merger(config: MergeConfig, dry_run: bool) -> Arc<dyn PullRequestMerger> {
+    if dry_run {
+        Arc::new(DummyPullRequestMerger::new(config))
+    } else {
+        Arc::new(DefaultPullRequestMerger::new(config))
+    }
+}
+
+/// The steps the override label skips for a repo's pull requests, if it's configured
+fn build_step_override(reviews_config: &ReviewsConfig) -> Option<StepOverride> {
+    reviews_config
+        .override_label
+        .as_ref()
+        .map(|label| StepOverride {
+            label: label.clone(),
+            steps: reviews_config.overridable_steps.clone(),
+        })
+}
+
+fn build_notifiers(config: &MergebroConfig) -> Vec<Arc<dyn Notifier>> {
+    let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
+    if let Some(slack) = &config.notifications.slack {
+        notifiers.push(Arc::new(SlackNotifier::new(slack.webhook_url.clone())));
+    }
+    if let Some(discord) = &config.notifications.discord {
+        notifiers.push(Arc::new(DiscordNotifier::new(discord.webhook_url.clone())));
+    }
+    notifiers
+}
+
+/// Opens the audit log if one is configured. Dry runs aren't recorded as nothing is merged.
+fn build_audit_log(
+    config: &MergebroConfig,
+    dry_run: bool,
+) -> Result<Option<Arc<AuditLog>>, MergebroError> {
+    let audit = match &config.audit {
+        Some(audit) if !dry_run => audit,
+        _ => return Ok(None),
+    };
+    let path = shellexpand::tilde(&audit.sqlite_path);
+    let audit_log = AuditLog::open(path.as_ref()).map_err(|e| {
+        MergebroError::as_config(format!("failed to open audit log '{}': {}", path, e))
+    })?;
+    Ok(Some(Arc::new(audit_log)))
+}
+
+/// Enables auto-merge on github pull requests so github merges them once their checks pass
+async fn enable_auto_merge(
+    targets: &[Target],
+    config: &MergebroConfig,
+    github_client: &dyn GithubClient,
+    dry_run: bool,
+) -> Result<(), Box<dyn Error>> {
+    let merge_config = split_repo_configs(config)?.merge_config;
+    for target in targets {
+        let identifier = match target {
+            Target::Github(identifier) => identifier,
+            _ => return Err(format!("auto-merge is only supported on github: {}", target).into()),
+        };
+        let method = &merge_config
+            .get(&identifier.owner, &identifier.repo)
+            .default_method;
+        if dry_run {
+            info!(
+                "Skipping enabling auto-merge on {} using {:?}",
+                identifier, method
+            );
+            continue;
+        }
+        let pull_request = github_client.pull_request_info(identifier).await?;
+        if !github_client
+            .auto_merge_allowed(&pull_request.base.repo)
+            .await?
+        {
+            return Err(format!(
+                "auto-merge is not enabled in the settings of the repo for {}",
+                identifier
+            )
+            .into());
+        }
+        github_client
+            .enable_auto_merge(&pull_request, method)
+            .await?;
+        info!("Enabled auto-merge on {} using {:?}", identifier, method);
+    }
+    Ok(())
+}
+
+async fn merge(
+    options: MergeOptions,
+    config: MergebroConfig,
+    github_client: Arc<DefaultGithubClient>,
+    github_base_url: Url,
+) -> ExitCode {
+    if options.max_concurrency == 0 {
+        error!("--max-concurrency needs to be at least 1");
+        return ExitCode::InvalidConfig;
+    }
+    let mut targets = Vec::new();
+    for url in &options.pull_request_urls {
+        match parse_pull_request_url(url, &github_base_url) {
+            Ok(target) => targets.push(target),
+            Err(e) => {
+                error!("Error parsing pull request URL '{}': {}", url, e);
+                return ExitCode::InvalidConfig;
+            }
+        };
+    }
+    if options.enable_auto_merge {
+        let result = enable_auto_merge(&targets, &config, &*github_client, options.dry_run).await;
+        return match result {
+            Ok(_) => ExitCode::Success,
+            Err(e) => {
+                error!("Error enabling auto-merge: {}", e);
+                ExitCode::Failed
+            }
+        };
     }
 
+    let workflow_runners =
+        match build_workflow_runners(&config, github_client.clone(), &github_base_url) {
+            Ok(workflow_runners) => workflow_runners,
+            Err(e) => {
+                error!("Error configuring workflow runners: {}", e);
+                return ExitCode::InvalidConfig;
+            }
+        };
     if workflow_runners.is_empty() {
         info!("No external workflow runners configured");
     } else {
         info!("Using {} external workflow runners", workflow_runners.len());
     }
 
-    let merger: Arc<dyn PullRequestMerger> = if options.dry_run {
+    if options.dry_run {
         info!("Running in dry-run mode");
-        Arc::new(DummyPullRequestMerger::default())
+    }
+    if let Some(branch) = &options.base_branch_override {
+        warn!(
+            "Base branch override active, checking pull requests against '{}' rather than their base branch",
+            branch
+        );
+    }
+    let mut base_branch_overrides = HashMap::new();
+    if let Some(branch) = &options.base_branch_override {
+        for target in &targets {
+            let identifier = match target {
+                Target::Github(identifier) => identifier,
+                _ => continue,
+            };
+            let key = (identifier.owner.clone(), identifier.repo.clone());
+            if base_branch_overrides.contains_key(&key) {
+                continue;
+            }
+            match Director::resolve_base_branch(&*github_client, identifier, branch).await {
+                Ok(base) => {
+                    base_branch_overrides.insert(key, base);
+                }
+                Err(e) => {
+                    error!("Error resolving base branch override for {}: {}", target, e);
+                    return ExitCode::InvalidConfig;
+                }
+            };
+        }
+    }
+    let metrics_server = match options.metrics_port.map(MetricsServer::start) {
+        Some(Ok(server)) => Some(server),
+        Some(Err(e)) => {
+            error!("Failed to start metrics server: {}", e);
+            return ExitCode::Failed;
+        }
+        None => None,
+    };
+
+    let notifiers = build_notifiers(&config);
+    let audit_log = match build_audit_log(&config, options.dry_run) {
+        Ok(audit_log) => audit_log,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::InvalidConfig;
+        }
+    };
+    let director_options = DirectorOptions {
+        blocked_label: options.label_when_blocked,
+        status_comment: options.status_comment,
+        conflict_retries: config.merge.conflict_retries,
+        step_timeout: Some(Duration::from_secs(config.poll.step_timeout_seconds)),
+        audit_log,
+        step_override: None,
+        mergeability_retries: config.poll.mergeability_retries,
+        mergeability_retry_delay: Duration::from_secs(config.poll.mergeability_retry_delay_seconds),
+        base_branch_override: None,
+    };
+    let context = Context {
+        github_client,
+        workflow_runners,
+        notifiers,
+        config,
+        ignore_reviews: options.ignore_reviews,
+        dry_run: options.dry_run,
+        wait_timeout: options.wait_timeout_seconds.map(Duration::from_secs),
+        once: options.once,
+        director_options,
+        base_branch_overrides,
+        shutdown: listen_for_shutdown(),
+    };
+    let results = if options.parallel {
+        // Processing too many pull requests at once can trigger github's secondary rate limits
+        let semaphore = &Semaphore::new(options.max_concurrency);
+        let context = &context;
+        let futures = targets.iter().map(|target| async move {
+            let _permit = match semaphore.try_acquire() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    info!("Waiting for a free slot to process {}", target);
+                    // The semaphore is never closed
+                    semaphore.acquire().await.unwrap()
+                }
+            };
+            process_pull_request(target, context).await
+        });
+        join_all(futures).await
     } else {
-        Arc::new(DefaultPullRequestMerger::new(config.merge.clone()))
+        let mut results = Vec::new();
+        for target in &targets {
+            results.push(process_pull_request(target, &context).await);
+        }
+        results
     };
+    if let Some(server) = metrics_server {
+        server.shutdown().await;
+    }
+    if let Some(path) = &options.output_file {
+        if let Err(e) = write_output_file(path, &results) {
+            error!("Failed to write output file {}: {}", path.display(), e);
+        }
+    }
 
-    let sleep_duration = Duration::from_secs(config.poll.delay_seconds as u64);
-    info!(
-        "Starting loop on pull request: {}/{}/pulls/{} using github user {}",
-        identifier.owner, identifier.repo, identifier.pull_number, config.github.username
-    );
+    let outcomes: Vec<_> = results.iter().map(|result| result.outcome).collect();
+
+    if targets.len() > 1 {
+        info!("Summary:");
+        for (target, outcome) in targets.iter().zip(&outcomes) {
+            info!("  {:<60} {}", target.to_string(), outcome);
+        }
+    }
+    let exit_codes: Vec<_> = outcomes.iter().map(|outcome| outcome.exit_code()).collect();
+    ExitCode::most_severe(&exit_codes)
+}
+
+/// Prints the problems found in the config, failing if any of them make it unusable
+fn validate(config: &MergebroConfig, config_files: &[&str]) -> ExitCode {
+    let mut issues = config.check();
+    match MergebroConfig::unknown_keys(config_files) {
+        Ok(keys) => {
+            let unknown = keys
+                .into_iter()
+                .map(|key| ConfigIssue::Warning(format!("unknown key '{}'", key)));
+            issues.extend(unknown);
+        }
+        Err(e) => issues.push(ConfigIssue::Error(format!("failed to read config: {}", e))),
+    };
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if issues
+        .iter()
+        .any(|issue| matches!(issue, ConfigIssue::Error(_)))
+    {
+        return ExitCode::InvalidConfig;
+    }
+    println!("Config is valid");
+    ExitCode::Success
+}
+
+/// Runs every check on a pull request once, printing a report. Returns whether it's mergeable.
+async fn status(
+    options: StatusOptions,
+    config: MergebroConfig,
+    github_client: Arc<DefaultGithubClient>,
+    github_base_url: Url,
+) -> Result<bool, Box<dyn Error>> {
+    let identifier = match parse_pull_request_url(&options.pull_request_url, &github_base_url)? {
+        Target::Github(identifier) => identifier,
+        Target::Gitlab(_) => return Err("gitlab merge requests are not supported".into()),
+        Target::Bitbucket(_) => return Err("bitbucket pull requests are not supported".into()),
+    };
     let steps = build_steps(
         &identifier,
         github_client.clone(),
-        workflow_runners,
+        Vec::new(),
         &config,
         options.ignore_reviews,
+        true,
+        None,
+    )?;
+    let mut director = Director::new(
+        github_client,
+        Arc::new(DummyPullRequestMerger::new(config.merge.clone())),
+        steps,
+        identifier.clone(),
+        DirectorOptions {
+            step_override: build_step_override(
+                split_repo_configs(&config)?
+                    .reviews_config
+                    .get(&identifier.owner, &identifier.repo),
+            ),
+            mergeability_retries: config.poll.mergeability_retries,
+            mergeability_retry_delay: Duration::from_secs(
+                config.poll.mergeability_retry_delay_seconds,
+            ),
+            ..Default::default()
+        },
     );
-    let steps = match steps {
-        Ok(steps) => steps,
-        Err(e) => {
-            error!("Failed to initialize step checks: {}", e);
-            exit(1);
+    let reports = director.check().await?;
+    println!("{}", identifier.app_url());
+    for report in &reports {
+        println!("  {:<40} {}", report.name, report.outcome);
+        if let Some(checks) = report.checks.as_ref().filter(|checks| !checks.is_empty()) {
+            println!("  {:<40} {}", "", checks);
+        }
+        if let Some(approvals) = &report.approvals {
+            println!("  {:<40} {}", "", approvals);
         }
+    }
+    Ok(reports.iter().all(|report| {
+        matches!(
+            report.outcome,
+            StepOutcome::Passed | StepOutcome::Overridden
+        )
+    }))
+}
+
+/// Processes pull requests as github webhooks about them arrive rather than polling them
+async fn serve(
+    options: ServeOptions,
+    config: MergebroConfig,
+    github_client: Arc<DefaultGithubClient>,
+    github_base_url: Url,
+) -> Result<(), Box<dyn Error>> {
+    let webhook = config.webhook.clone().ok_or("webhook is not configured")?;
+    if options.dry_run {
+        info!("Running in dry-run mode");
+    }
+    let workflow_runners =
+        build_workflow_runners(&config, github_client.clone(), &github_base_url)?;
+    let context = Context {
+        github_client: github_client.clone(),
+        workflow_runners,
+        notifiers: build_notifiers(&config),
+        director_options: DirectorOptions {
+            blocked_label: options.label_when_blocked,
+            status_comment: options.status_comment,
+            conflict_retries: config.merge.conflict_retries,
+            step_timeout: Some(Duration::from_secs(config.poll.step_timeout_seconds)),
+            audit_log: build_audit_log(&config, options.dry_run)?,
+            step_override: None,
+            mergeability_retries: config.poll.mergeability_retries,
+            mergeability_retry_delay: Duration::from_secs(
+                config.poll.mergeability_retry_delay_seconds,
+            ),
+            base_branch_override: None,
+        },
+        config,
+        ignore_reviews: options.ignore_reviews,
+        dry_run: options.dry_run,
+        wait_timeout: None,
+        once: true,
+        base_branch_overrides: HashMap::new(),
+        shutdown: listen_for_shutdown(),
     };
-    let mut director = Director::new(github_client, merger, steps, identifier);
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let server = WebhookServer::start(options.port, webhook.secret, sender)?;
+    // Waiting pull requests keep their processor so state like failure counters isn't lost
+    let mut processors: HashMap<String, (Box<dyn PullRequestProcessor>, Instant)> = HashMap::new();
+    let mut shutdown = context.shutdown.clone();
     loop {
-        info!("Running checks on pull request...");
-        match director.run().await {
-            Ok(DirectorState::Waiting) => {
-                info!("Sleeping for {} seconds", sleep_duration.as_secs());
-                sleep(sleep_duration).await;
+        let trigger = tokio::select! {
+            trigger = receiver.recv() => match trigger {
+                Some(trigger) => trigger,
+                None => break,
+            },
+            _ = shutdown.changed() => break,
+        };
+        // Bursts of events about the same pull request only need a single run
+        let mut triggers = vec![trigger];
+        while let Ok(trigger) = receiver.try_recv() {
+            triggers.push(trigger);
+        }
+        let mut identifiers = Vec::new();
+        for trigger in triggers {
+            match resolve_trigger(trigger, &*github_client, &github_base_url).await {
+                Ok(resolved) => {
+                    for identifier in resolved {
+                        if !identifiers.contains(&identifier) {
+                            identifiers.push(identifier);
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to find pull requests for webhook: {}", e),
             }
-            Ok(DirectorState::Done) => {
-                break;
+        }
+        for identifier in identifiers {
+            let target = Target::Github(identifier);
+            let span = tracing::info_span!("pull_request", pr_url = %target.app_url());
+            process_triggered_pull_request(&target, &webhook.label, &context, &mut processors)
+                .instrument(span)
+                .await;
+        }
+    }
+    server.shutdown().await;
+    Ok(())
+}
+
+/// Listens for SIGINT and SIGTERM, flagging the returned receiver once either arrives so we stop
+/// waiting on pull requests without interrupting merges that are in flight. A second signal exits
+/// right away.
+fn listen_for_shutdown() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutting down once in flight operations finish, send the signal again to exit now");
+        let _ = sender.send(true);
+        wait_for_signal().await;
+        warn!("Exiting right away");
+        exit(ExitCode::Interrupted as i32);
+    });
+    receiver
+}
+
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
             }
             Err(e) => {
-                error!("Error processing pull request: {}", e);
-                break;
+                warn!("Failed to listen for SIGTERM: {}", e);
+                future::pending::<()>().await;
             }
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
     }
 }
+
+/// Finds the pull requests a webhook is about
+async fn resolve_trigger(
+    trigger: WebhookTrigger,
+    github_client: &dyn GithubClient,
+    github_base_url: &Url,
+) -> Result<Vec<PullRequestIdentifier>, Box<dyn Error>> {
+    let (repo, numbers) = match trigger {
+        WebhookTrigger::PullRequests { repo, numbers } => (repo, numbers),
+        WebhookTrigger::Commit { repo, sha } => {
+            let pull_requests = github_client.commit_pull_requests(&repo, &sha).await?;
+            let numbers = pull_requests
+                .into_iter()
+                .filter(|pull_request| pull_request.state == PullRequestState::Open)
+                .map(|pull_request| pull_request.number)
+                .collect();
+            (repo, numbers)
+        }
+    };
+    let identifiers = numbers
+        .into_iter()
+        .map(|pull_number| PullRequestIdentifier {
+            base_url: github_base_url.as_str().trim_end_matches('/').into(),
+            owner: repo.owner.login.clone(),
+            repo: repo.name.clone(),
+            pull_number,
+        })
+        .collect();
+    Ok(identifiers)
+}
+
+async fn process_triggered_pull_request(
+    target: &Target,
+    label: &str,
+    context: &Context,
+    processors: &mut HashMap<String, (Box<dyn PullRequestProcessor>, Instant)>,
+) {
+    let identifier = match target {
+        Target::Github(identifier) => identifier,
+        _ => return,
+    };
+    let key = target.app_url();
+    match context.github_client.pull_request_info(identifier).await {
+        Ok(pull_request) if pull_request.has_label(label) => (),
+        Ok(_) => {
+            debug!("Ignoring {} as it's not labeled '{}'", target, label);
+            processors.remove(&key);
+            return;
+        }
+        Err(e) => {
+            error!("Failed to fetch pull request {}: {}", target, e);
+            return;
+        }
+    };
+    let (processor, start_time) = match processors.entry(key.clone()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => match context.build_processor(target) {
+            Ok(processor) => entry.insert((processor, Instant::now())),
+            Err(e) => {
+                error!("Failed to initialize step checks for {}: {}", target, e);
+                return;
+            }
+        },
+    };
+    info!("Running checks on pull request {}...", target);
+    let outcome = match processor.run().await {
+        Ok(DirectorState::Waiting) => return,
+        Ok(DirectorState::Done) => processor.merge_method().cloned().map(MergeOutcome::Merged),
+        Ok(DirectorState::Closed) => {
+            warn!("{} was closed without merging", target);
+            None
+        }
+        Err(e) => {
+            error!("Error processing pull request {}: {}", target, e);
+            Some(MergeOutcome::Failed(e.to_string()))
+        }
+    };
+    if let Some(outcome) = outcome {
+        context
+            .notify(&**processor, target, outcome, start_time.elapsed())
+            .await;
+    }
+    processors.remove(&key);
+}
+
+fn build_github_client(
+    config: &GithubConfig,
+    base_url: &Url,
+    run_id: &Uuid,
+) -> Result<DefaultGithubClient, MergebroError> {
+    let app = match &config.app {
+        Some(app) => app,
+        None => {
+            let client = DefaultGithubClient::new(
+                &config.username,
+                config.token.clone(),
+                base_url,
+                config.retry.clone(),
+            );
+            return Ok(client.with_request_id(run_id));
+        }
+    };
+    let path = shellexpand::tilde(&app.private_key_path);
+    let private_key = fs::read(path.as_ref()).map_err(|e| {
+        MergebroError::as_config(format!(
+            "failed to read github app private key '{}': {}",
+            path, e
+        ))
+    })?;
+    let client = DefaultGithubClient::from_app(
+        app.app_id,
+        app.installation_id,
+        &private_key,
+        base_url,
+        config.retry.clone(),
+    )
+    .map_err(|e| {
+        MergebroError::as_config(format!("invalid github app private key '{}': {}", path, e))
+    })?;
+    Ok(client.with_request_id(run_id))
+}
+
+/// Fails early if the github token is invalid or lacks scopes, which would otherwise surface as
+/// a 403 somewhere in the middle of processing a pull request
+async fn check_github_token(github_client: &dyn GithubClient) -> Result<(), MergebroError> {
+    match github_client.token_scopes().await {
+        Ok(Some(scopes)) => {
+            let missing = missing_token_scopes(&scopes);
+            if missing.is_empty() {
+                Ok(())
+            } else {
+                Err(MergebroError::Auth(
+                    format!(
+                        "Github token is missing required scopes: {}",
+                        missing.join(", ")
+                    )
+                    .into(),
+                ))
+            }
+        }
+        Ok(None) => {
+            debug!("Github token has no scopes, assuming it's a fine-grained token");
+            Ok(())
+        }
+        Err(e) if e.unauthorized() => Err(MergebroError::Auth(
+            format!(
+                "Github token was rejected, use --skip-token-check if this is expected: {}",
+                e
+            )
+            .into(),
+        )),
+        Err(e) => Err(MergebroError::as_generic(format!(
+            "Failed to check github token: {}",
+            e
+        ))),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let options = Options::from_args();
+    init_logger(
+        options.log_format,
+        default_log_filter(options.verbose, options.quiet),
+    );
+    // Sent along with every github request so they can be traced back to this run
+    let run_id = Uuid::new_v4();
+    info!("Starting run {}", run_id);
+
+    let config_files: Vec<_> = options.config_file.iter().map(String::as_str).collect();
+    let mut config = match MergebroConfig::new(&config_files) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Error parsing config: {}", e);
+            exit(ExitCode::InvalidConfig as i32);
+        }
+    };
+    let required_approvers = match &options.command {
+        Command::Merge(options) => &options.required_approvers,
+        Command::Status(options) => &options.required_approvers,
+        Command::Serve(options) => &options.required_approvers,
+        // Validating shouldn't need github to be reachable
+        Command::Validate => exit(validate(&config, &config_files) as i32),
+    };
+    add_required_approvers(&mut config, required_approvers);
+
+    let github_base_url = match Url::parse(&config.github.base_url) {
+        Ok(url) => url,
+        Err(e) => {
+            error!(
+                "Invalid github base URL '{}': {}",
+                config.github.base_url, e
+            );
+            exit(ExitCode::InvalidConfig as i32);
+        }
+    };
+    let ca_cert_path = config
+        .github
+        .ca_cert_path
+        .as_ref()
+        .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()));
+    if let Err(e) = init_http_client(ca_cert_path.as_deref()) {
+        error!("Error configuring the HTTP client: {}", e);
+        exit(ExitCode::InvalidConfig as i32);
+    }
+    let github_client = match build_github_client(&config.github, &github_base_url, &run_id) {
+        Ok(client) => Arc::new(client),
+        Err(e) => {
+            error!("Error configuring github authentication: {}", e);
+            exit(ExitCode::InvalidConfig as i32);
+        }
+    };
+    // App installation tokens have no scopes and can't access /user
+    if !options.skip_token_check && config.github.app.is_none() {
+        if let Err(e) = check_github_token(&*github_client).await {
+            error!("{}", e);
+            exit(PullRequestOutcome::from_error(&e).exit_code() as i32);
+        }
+    }
+    let exit_code = match options.command {
+        Command::Merge(options) => merge(options, config, github_client, github_base_url).await,
+        Command::Status(options) => {
+            match status(options, config, github_client, github_base_url).await {
+                Ok(true) => ExitCode::Success,
+                Ok(false) => ExitCode::Failed,
+                Err(e) => {
+                    error!("Error checking pull request: {}", e);
+                    ExitCode::Failed
+                }
+            }
+        }
+        Command::Validate => unreachable!("the config is validated before connecting to github"),
+        Command::Serve(options) => {
+            match serve(options, config, github_client, github_base_url).await {
+                Ok(_) => ExitCode::Success,
+                Err(e) => {
+                    error!("Error serving webhooks: {}", e);
+                    ExitCode::Failed
+                }
+            }
+        }
+    };
+    exit(exit_code as i32);
+}
@@ -0,0 +1,212 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// The metrics for this process, exposed via `MetricsServer`
+pub static METRICS: Metrics = Metrics::new();
+
+/// Upper bounds for the time to merge histogram buckets, in seconds
+const TIME_TO_MERGE_BUCKETS: [u64; 8] = [60, 300, 600, 1800, 3600, 7200, 21600, 86400];
+
+pub struct Metrics {
+    merges_attempted: AtomicU64,
+    merges_succeeded: AtomicU64,
+    merges_failed: AtomicU64,
+    merge_conflicts: AtomicU64,
+    time_to_merge: Histogram,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            merges_attempted: AtomicU64::new(0),
+            merges_succeeded: AtomicU64::new(0),
+            merges_failed: AtomicU64::new(0),
+            merge_conflicts: AtomicU64::new(0),
+            time_to_merge: Histogram::new(),
+        }
+    }
+
+    pub fn merge_attempted(&self) {
+        self.merges_attempted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn merge_succeeded(&self) {
+        self.merges_succeeded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn merge_failed(&self) {
+        self.merges_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn merge_conflict(&self) {
+        self.merge_conflicts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the time it took since we started processing a pull request until it was merged
+    pub fn observe_time_to_merge(&self, elapsed: Duration) {
+        self.time_to_merge.observe(elapsed);
+    }
+
+    /// Renders all metrics in the prometheus text format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let counters = [
+            (
+                "mergebro_merges_attempted_total",
+                "Pull request merges attempted",
+                &self.merges_attempted,
+            ),
+            (
+                "mergebro_merges_succeeded_total",
+                "Pull requests merged successfully",
+                &self.merges_succeeded,
+            ),
+            (
+                "mergebro_merges_failed_total",
+                "Pull request merges that failed",
+                &self.merges_failed,
+            ),
+            (
+                "mergebro_merge_conflicts_total",
+                "Pull request merges that hit a conflict",
+                &self.merge_conflicts,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} counter", name);
+            let _ = writeln!(output, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        self.time_to_merge.render(
+            "mergebro_time_to_merge_seconds",
+            "Time from starting to process a pull request until it was merged",
+            &mut output,
+        );
+        output
+    }
+}
+
+struct Histogram {
+    buckets: [AtomicU64; TIME_TO_MERGE_BUCKETS.len()],
+    count: AtomicU64,
+    sum_millis: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        Self {
+            buckets: [ZERO; TIME_TO_MERGE_BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_millis: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bound, bucket) in TIME_TO_MERGE_BUCKETS.iter().zip(&self.buckets) {
+            if seconds <= *bound as f64 {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_millis
+            .fetch_add(elapsed.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, help: &str, output: &mut String) {
+        let _ = writeln!(output, "# HELP {} {}", name, help);
+        let _ = writeln!(output, "# TYPE {} histogram", name);
+        for (bound, bucket) in TIME_TO_MERGE_BUCKETS.iter().zip(&self.buckets) {
+            let _ = writeln!(
+                output,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_millis.load(Ordering::Relaxed) as f64 / 1000.0;
+        let _ = writeln!(output, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(output, "{}_sum {}", name, sum);
+        let _ = writeln!(output, "{}_count {}", name, count);
+    }
+}
+
+async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::new(Body::from(METRICS.render())),
+        _ => {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            response
+        }
+    };
+    Ok(response)
+}
+
+/// An HTTP server exposing metrics under `/metrics`, running on its own task
+pub struct MetricsServer {
+    shutdown: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    pub fn start(port: u16) -> Result<Self, hyper::Error> {
+        let address = SocketAddr::from(([0, 0, 0, 0], port));
+        let make_service =
+            make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let server = Server::try_bind(&address)?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                shutdown_receiver.await.ok();
+            });
+        info!("Serving metrics on {}", address);
+        let handle = tokio::spawn(async {
+            if let Err(e) = server.await {
+                error!("Metrics server failed: {}", e);
+            }
+        });
+        Ok(Self { shutdown, handle })
+    }
+
+    /// Stops accepting connections and waits for in flight requests to finish
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.handle.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.merge_attempted();
+        metrics.merge_attempted();
+        metrics.merge_succeeded();
+        metrics.observe_time_to_merge(Duration::from_secs(90));
+        let output = metrics.render();
+        assert!(output.contains("mergebro_merges_attempted_total 2\n"));
+        assert!(output.contains("mergebro_merges_succeeded_total 1\n"));
+        assert!(output.contains("mergebro_merges_failed_total 0\n"));
+        assert!(output.contains("mergebro_time_to_merge_seconds_bucket{le=\"60\"} 0\n"));
+        assert!(output.contains("mergebro_time_to_merge_seconds_bucket{le=\"300\"} 1\n"));
+        assert!(output.contains("mergebro_time_to_merge_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(output.contains("mergebro_time_to_merge_seconds_sum 90\n"));
+        assert!(output.contains("mergebro_time_to_merge_seconds_count 1\n"));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::client::http_client;
+use crate::client::{Error, Result};
+use crate::github::MergeMethod;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_derive::Serialize;
+use std::time::Duration;
+
+/// An event emitted once mergebro is done processing a pull request
+#[derive(Debug, Clone)]
+pub struct MergeEvent {
+    pub url: String,
+    pub title: Option<String>,
+
+    /// The repo the pull request belongs to, as `owner/repo`
+    pub repo: String,
+
+    /// How long it took since mergebro started processing the pull request
+    pub elapsed: Duration,
+
+    pub dry_run: bool,
+    pub outcome: MergeOutcome,
+}
+
+#[derive(Debug, Clone)]
+pub enum MergeOutcome {
+    Merged(MergeMethod),
+    Failed(String),
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, event: &MergeEvent) -> Result<()>;
+}
+
+/// Posts merge events to a slack incoming webhook
+pub struct SlackNotifier {
+    client: Client,
+    webhook_url: String,
+}
+
+impl SlackNotifier {
+    pub fn new<S: Into<String>>(webhook_url: S) -> Self {
+        Self {
+            client: http_client(),
+            webhook_url: webhook_url.into(),
+        }
+    }
+
+    fn build_message(event: &MergeEvent) -> String {
+        let link = match &event.title {
+            Some(title) => format!("<{}|{}>", event.url, title),
+            None => format!("<{}>", event.url),
+        };
+        let prefix = if event.dry_run { "[dry run] " } else { "" };
+        match &event.outcome {
+            MergeOutcome::Merged(method) => format!(
+                "{}:white_check_mark: Merged pull request {} using '{:?}' merge method",
+                prefix, link, method
+            ),
+            MergeOutcome::Failed(reason) => format!(
+                "{}:x: Failed to merge pull request {}: {}",
+                prefix, link, reason
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, event: &MergeEvent) -> Result<()> {
+        let body = SlackMessage {
+            text: Self::build_message(event),
+        };
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&body)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Http(response.status()))
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct SlackMessage {
+    text: String,
+}
+
+/// Posts merge events as embeds to a discord webhook
+pub struct DiscordNotifier {
+    client: Client,
+    webhook_url: String,
+}
+
+impl DiscordNotifier {
+    const SUCCESS_COLOR: u32 = 0x2ecc71;
+    const FAILURE_COLOR: u32 = 0xe74c3c;
+    const DRY_RUN_COLOR: u32 = 0x95a5a6;
+
+    pub fn new<S: Into<String>>(webhook_url: S) -> Self {
+        Self {
+            client: http_client(),
+            webhook_url: webhook_url.into(),
+        }
+    }
+
+    fn build_embed(event: &MergeEvent) -> DiscordEmbed {
+        let prefix = if event.dry_run { "[dry run] " } else { "" };
+        let (description, color) = match &event.outcome {
+            MergeOutcome::Merged(method) => (
+                format!(
+                    "{}Merged pull request using '{:?}' merge method",
+                    prefix, method
+                ),
+                Self::SUCCESS_COLOR,
+            ),
+            MergeOutcome::Failed(reason) => (
+                format!("{}Failed to merge pull request: {}", prefix, reason),
+                Self::FAILURE_COLOR,
+            ),
+        };
+        DiscordEmbed {
+            title: event.title.clone().unwrap_or_else(|| event.url.clone()),
+            url: event.url.clone(),
+            description,
+            color: if event.dry_run {
+                Self::DRY_RUN_COLOR
+            } else {
+                color
+            },
+            fields: vec![
+                DiscordEmbedField {
+                    name: "Repository".into(),
+                    value: event.repo.clone(),
+                    inline: true,
+                },
+                DiscordEmbedField {
+                    name: "Elapsed".into(),
+                    value: format_elapsed(event.elapsed),
+                    inline: true,
+                },
+            ],
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    async fn notify(&self, event: &MergeEvent) -> Result<()> {
+        let body = DiscordMessage {
+            embeds: vec![Self::build_embed(event)],
+        };
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&body)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Http(response.status()))
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct DiscordMessage {
+    embeds: Vec<DiscordEmbed>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct DiscordEmbed {
+    title: String,
+    url: String,
+    description: String,
+    color: u32,
+    fields: Vec<DiscordEmbedField>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct DiscordEmbedField {
+    name: String,
+    value: String,
+    inline: bool,
+}
+
+/// Formats a duration like `1h 2m 3s`, skipping leading zero units
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slack_message() {
+        let mut event = MergeEvent {
+            url: "https://github.com/potato/smasher/pull/1337".into(),
+            title: Some("Smash potatoes".into()),
+            repo: "potato/smasher".into(),
+            elapsed: Duration::from_secs(3725),
+            dry_run: false,
+            outcome: MergeOutcome::Merged(MergeMethod::Squash),
+        };
+        assert_eq!(
+            SlackNotifier::build_message(&event),
+            ":white_check_mark: Merged pull request <https://github.com/potato/smasher/pull/1337|Smash potatoes> using 'Squash' merge method"
+        );
+
+        event.dry_run = true;
+        event.outcome = MergeOutcome::Failed("pull request is closed".into());
+        assert_eq!(
+            SlackNotifier::build_message(&event),
+            "[dry run] :x: Failed to merge pull request <https://github.com/potato/smasher/pull/1337|Smash potatoes>: pull request is closed"
+        );
+    }
+
+    #[test]
+    fn test_discord_embed() {
+        let mut event = MergeEvent {
+            url: "https://github.com/potato/smasher/pull/1337".into(),
+            title: None,
+            repo: "potato/smasher".into(),
+            elapsed: Duration::from_secs(125),
+            dry_run: false,
+            outcome: MergeOutcome::Failed("pull request is closed".into()),
+        };
+        let embed = DiscordNotifier::build_embed(&event);
+        assert_eq!(embed.title, "https://github.com/potato/smasher/pull/1337");
+        assert_eq!(
+            embed.description,
+            "Failed to merge pull request: pull request is closed"
+        );
+        assert_eq!(embed.color, DiscordNotifier::FAILURE_COLOR);
+        assert_eq!(embed.fields[0].value, "potato/smasher");
+        assert_eq!(embed.fields[1].value, "2m 5s");
+
+        event.outcome = MergeOutcome::Merged(MergeMethod::Squash);
+        assert_eq!(
+            DiscordNotifier::build_embed(&event).color,
+            DiscordNotifier::SUCCESS_COLOR
+        );
+
+        event.dry_run = true;
+        assert_eq!(
+            DiscordNotifier::build_embed(&event).color,
+            DiscordNotifier::DRY_RUN_COLOR
+        );
+    }
+}
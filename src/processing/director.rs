@@ -1,14 +1,97 @@
-use super::steps::{Step, StepStatus};
+use super::status::{render_status_comment, StepOutcome, StepReport, STATUS_COMMENT_MARKER};
+use super::steps::{CheckReviewsStep, Step, StepStatus};
 use super::{merge::MergeResult, Error, PullRequestMerger};
-use crate::github::{GithubClient, PullRequestIdentifier};
-use log::{debug, info};
+use crate::audit::{AuditLog, AuditOutcome, AuditRecord};
+use crate::github::{
+    Branch, GithubClient, MergeMethod, MergeableState, PullRequest, PullRequestIdentifier,
+    PullRequestState,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::join_all;
+use log::{debug, info, warn};
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Drives a pull request, or its equivalent in other platforms, to be merged
+#[async_trait(?Send)]
+pub trait PullRequestProcessor {
+    /// Runs a single round of checks, merging the pull request if all of them passed
+    async fn run(&mut self) -> Result<DirectorState, Error>;
+
+    /// Whether the last run made progress compared to the one before it
+    fn progressed(&self) -> bool;
+
+    /// The pull request's title, if it was fetched already
+    fn title(&self) -> Option<&str>;
+
+    /// The method the pull request was merged with, or would have been in a dry run
+    fn merge_method(&self) -> Option<&MergeMethod>;
+
+    /// The outcome of each step in the last run, if the processor keeps track of them
+    fn step_reports(&self) -> &[StepReport] {
+        &[]
+    }
+}
+
+/// Optional behaviors for a `Director`
+#[derive(Debug, Clone, Default)]
+pub struct DirectorOptions {
+    /// A label to add to the pull request when a check fails
+    pub blocked_label: Option<String>,
+
+    /// Whether to keep a comment on the pull request up to date with the status of each step
+    pub status_comment: bool,
+
+    /// How many times to retry merging after a conflict caused by the head sha changing
+    pub conflict_retries: u32,
+
+    /// How long a single step can run before it's considered pending until the next run
+    pub step_timeout: Option<Duration>,
+
+    /// Where to record merge attempts and how processing pull requests ended
+    pub audit_log: Option<Arc<AuditLog>>,
+
+    /// Steps that are skipped while the pull request has a label
+    pub step_override: Option<StepOverride>,
+
+    /// How many times to re-fetch the pull request while github computes whether it's mergeable
+    pub mergeability_retries: u32,
+
+    /// How long to wait before each of those re-fetches
+    pub mergeability_retry_delay: Duration,
+
+    /// A branch to check and merge pull requests against rather than the base github reports,
+    /// as resolved by `Director::resolve_base_branch`
+    pub base_branch_override: Option<Branch>,
+}
+
+/// Lets a label on a pull request skip some steps, as long as they're overridable
+#[derive(Debug, Clone)]
+pub struct StepOverride {
+    pub label: String,
+
+    /// The names of the steps to skip
+    pub steps: Vec<String>,
+}
 
 pub struct Director {
     github: Arc<dyn GithubClient>,
     identifier: PullRequestIdentifier,
     steps: Vec<Box<dyn Step>>,
     merger: Arc<dyn PullRequestMerger>,
+    options: DirectorOptions,
+    pull_request: Option<PullRequest>,
+    merge_method: Option<MergeMethod>,
+    merge_conflicted: bool,
+    dry_run_merged: bool,
+    last_waiting: Option<(usize, String)>,
+    progressed: bool,
+    status_comment_id: Option<u64>,
+    last_status_comment: Option<String>,
+    last_reports: Vec<StepReport>,
 }
 
 impl Director {
@@ -17,36 +100,550 @@ impl Director {
         merger: Arc<dyn PullRequestMerger>,
         steps: Vec<Box<dyn Step>>,
         identifier: PullRequestIdentifier,
+        options: DirectorOptions,
     ) -> Self {
+        if let Some(step_override) = &options.step_override {
+            let non_overridable = steps
+                .iter()
+                .filter(|step| !step.overridable())
+                .map(|step| step.to_string())
+                .filter(|name| step_override.steps.contains(name));
+            for name in non_overridable {
+                warn!(
+                    "Step '{}' can't be skipped by the '{}' label",
+                    name, step_override.label
+                );
+            }
+        }
         Self {
             github,
             identifier,
             steps,
             merger,
+            options,
+            pull_request: None,
+            merge_method: None,
+            merge_conflicted: false,
+            dry_run_merged: false,
+            last_waiting: None,
+            progressed: false,
+            status_comment_id: None,
+            last_status_comment: None,
+            last_reports: Vec::new(),
         }
     }
 
-    pub async fn run(&mut self) -> Result<DirectorState, Error> {
-        debug!("Fetching current state for pull request");
-        let pull_request = self.github.pull_request_info(&self.identifier).await?;
-        for step in &mut self.steps {
-            let step_status = step.execute(&pull_request).await?;
-            match step_status {
-                StepStatus::Waiting => {
-                    info!("Step '{}' is pending", step);
-                    return Ok(DirectorState::Waiting);
-                }
-                StepStatus::Passed => debug!("Step '{}' passed", step),
+    /// The pull request as it was last fetched, if any
+    pub fn pull_request(&self) -> Option<&PullRequest> {
+        self.pull_request.as_ref()
+    }
+
+    /// Fetches the pull request, giving github a few chances to finish computing whether it's
+    /// mergeable as it does that asynchronously after every push
+    async fn fetch_pull_request(&mut self) -> Result<PullRequest, Error> {
+        let mut pull_request = self.github.pull_request_info(&self.identifier).await?;
+        for _ in 0..self.options.mergeability_retries {
+            // Github doesn't compute it for merged or closed pull requests
+            if pull_request.mergeable.is_some() || pull_request.state != PullRequestState::Open {
+                break;
+            }
+            info!("Waiting for GitHub to compute mergeability");
+            tokio::time::sleep(self.options.mergeability_retry_delay).await;
+            pull_request = self.github.pull_request_info(&self.identifier).await?;
+        }
+        if let Some(base) = &self.options.base_branch_override {
+            if base.name != pull_request.base.name {
+                debug!(
+                    "Using base branch '{}' instead of '{}' reported by github",
+                    base.name, pull_request.base.name
+                );
+            }
+            pull_request.base = base.clone();
+        }
+        self.pull_request = Some(pull_request.clone());
+        Ok(pull_request)
+    }
+
+    /// Resolves a branch in the pull request's base repo to use as its base instead of the one
+    /// github reports, failing if it doesn't exist
+    pub async fn resolve_base_branch(
+        github: &dyn GithubClient,
+        identifier: &PullRequestIdentifier,
+        branch: &str,
+    ) -> Result<Branch, Error> {
+        let mut base = github.pull_request_info(identifier).await?.base;
+        let commit = match github.commit(&base.repo, branch).await {
+            Ok(commit) => commit,
+            Err(e) if e.not_found() || e.unprocessable_entity() => {
+                return Err(Error::as_generic(format!(
+                    "base branch override '{}' doesn't exist",
+                    branch
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        base.name = branch.to_string();
+        base.sha = commit.sha;
+        Ok(base)
+    }
+
+    /// Runs every check once without merging, returning a report for each step
+    pub async fn check(&mut self) -> Result<Vec<StepReport>, Error> {
+        let pull_request = self.fetch_pull_request().await?;
+        let mut reports = Vec::new();
+        if let Err(e) = self.evaluate_steps(&pull_request, &mut reports).await {
+            debug!("Checks failed: {}", e);
+        }
+        self.add_unchecked_reports(&mut reports);
+        Ok(reports)
+    }
+
+    /// Runs steps until one doesn't pass, returning the resulting state in that case. Consecutive
+    /// read only steps run concurrently, but their results are still processed in order.
+    async fn evaluate_steps(
+        &mut self,
+        pull_request: &PullRequest,
+        reports: &mut Vec<StepReport>,
+    ) -> Result<Option<DirectorState>, Error> {
+        let overridden = self.overridden_steps(pull_request).await;
+        let mut index = 0;
+        while index < self.steps.len() {
+            let batch_size = match self.steps[index].is_readonly() {
+                true => self.steps[index..]
+                    .iter()
+                    .take_while(|step| step.is_readonly())
+                    .count(),
+                false => 1,
             };
+            let step_timeout = self.options.step_timeout;
+            let batch = self.steps[index..index + batch_size]
+                .iter_mut()
+                .enumerate()
+                .map(|(offset, step)| {
+                    let span = tracing::info_span!("step", step = %step);
+                    let skip = overridden.contains(&(index + offset));
+                    async move {
+                        match skip {
+                            true => Ok(StepStatus::Passed),
+                            false => {
+                                Self::execute_step(step.as_mut(), pull_request, step_timeout).await
+                            }
+                        }
+                    }
+                    .instrument(span)
+                });
+            let results = join_all(batch).await;
+            for (offset, step_status) in results.into_iter().enumerate() {
+                if overridden.contains(&(index + offset)) {
+                    reports.push(StepReport {
+                        name: self.steps[index + offset].to_string(),
+                        outcome: StepOutcome::Overridden,
+                        checks: None,
+                        approvals: None,
+                    });
+                    continue;
+                }
+                let state =
+                    self.process_step_status(index + offset, pull_request, step_status, reports)?;
+                if state.is_some() {
+                    return Ok(state);
+                }
+            }
+            index += batch_size;
+        }
+        Ok(None)
+    }
+
+    /// The indexes of the steps skipped because the pull request has the override label
+    async fn overridden_steps(&self, pull_request: &PullRequest) -> HashSet<usize> {
+        let step_override = match &self.options.step_override {
+            Some(step_override) => step_override,
+            None => return HashSet::new(),
+        };
+        let labeled = pull_request
+            .labels
+            .iter()
+            .any(|label| label.matches(&step_override.label));
+        if !labeled {
+            return HashSet::new();
+        }
+        let overridden: HashSet<_> = self
+            .steps
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| step.overridable())
+            .filter(|(_, step)| step_override.steps.contains(&step.to_string()))
+            .map(|(index, _)| index)
+            .collect();
+        if overridden.is_empty() {
+            return overridden;
+        }
+        let added_by = match self
+            .label_added_by(pull_request, &step_override.label)
+            .await
+        {
+            Ok(Some(user)) => user,
+            Ok(None) => "an unknown user".into(),
+            Err(e) => {
+                warn!(
+                    "Failed to find who added the '{}' label: {}",
+                    step_override.label, e
+                );
+                "an unknown user".into()
+            }
+        };
+        for index in &overridden {
+            warn!(
+                "Skipping step '{}' as the '{}' label was added by {}",
+                self.steps[*index], step_override.label, added_by
+            );
+        }
+        overridden
+    }
+
+    /// The user that last added a label to the pull request, going by its timeline
+    async fn label_added_by(
+        &self,
+        pull_request: &PullRequest,
+        label: &str,
+    ) -> Result<Option<String>, Error> {
+        let events = self.github.timeline_events(pull_request).await?;
+        let user = events
+            .into_iter()
+            .filter(|event| event.event == "labeled")
+            .filter(|event| {
+                event
+                    .label
+                    .as_ref()
+                    .map(|l| l.matches(label))
+                    .unwrap_or(false)
+            })
+            .filter_map(|event| event.actor)
+            .next_back();
+        Ok(user.map(|user| user.login))
+    }
+
+    /// Executes a step, treating it as pending if it doesn't finish within its own timeout or the
+    /// given one so it's retried on the next run. Steps that change things only get their own, so
+    /// they don't get cancelled halfway through e.g. updating the branch
+    async fn execute_step(
+        step: &mut dyn Step,
+        pull_request: &PullRequest,
+        timeout: Option<Duration>,
+    ) -> Result<StepStatus, Error> {
+        let timeout = match step.timeout().or(timeout.filter(|_| step.is_readonly())) {
+            Some(timeout) => timeout,
+            None => return step.execute(pull_request).await,
+        };
+        let name = step.to_string();
+        match tokio::time::timeout(timeout, step.execute(pull_request)).await {
+            Ok(step_status) => step_status,
+            Err(_) => {
+                warn!(
+                    "Step '{}' timed out after {}s, retrying it on the next run",
+                    name,
+                    timeout.as_secs_f64()
+                );
+                Ok(StepStatus::Waiting)
+            }
+        }
+    }
+
+    fn process_step_status(
+        &mut self,
+        index: usize,
+        pull_request: &PullRequest,
+        step_status: Result<StepStatus, Error>,
+        reports: &mut Vec<StepReport>,
+    ) -> Result<Option<DirectorState>, Error> {
+        let step = &self.steps[index];
+        let outcome = match &step_status {
+            Ok(StepStatus::Passed) => StepOutcome::Passed,
+            Ok(StepStatus::Waiting) => StepOutcome::Waiting,
+            Ok(StepStatus::AlreadyMerged) => StepOutcome::Passed,
+            Ok(StepStatus::Closed) => StepOutcome::Failed("pull request is closed".into()),
+            Err(e) => StepOutcome::Failed(e.to_string()),
+        };
+        reports.push(StepReport {
+            name: step.to_string(),
+            outcome,
+            checks: step.check_states(),
+            approvals: step.approvals(),
+        });
+        match step_status {
+            Ok(StepStatus::Waiting) => {
+                info!("Step '{}' is pending", step);
+                let waiting = Some((index, pull_request.head.sha.clone()));
+                self.progressed = self.last_waiting != waiting;
+                self.last_waiting = waiting;
+                Ok(Some(DirectorState::Waiting))
+            }
+            Ok(StepStatus::Passed) => {
+                debug!("Step '{}' passed", step);
+                Ok(None)
+            }
+            Ok(StepStatus::AlreadyMerged) => {
+                info!("Pull request was already merged");
+                Ok(Some(DirectorState::Done))
+            }
+            Ok(StepStatus::Closed) => {
+                info!("PR was closed without merging");
+                Ok(Some(DirectorState::Closed))
+            }
+            Err(e) => Err(e),
         }
+    }
+
+    async fn run_steps(
+        &mut self,
+        pull_request: &PullRequest,
+        reports: &mut Vec<StepReport>,
+    ) -> Result<DirectorState, Error> {
+        match self.evaluate_steps(pull_request, reports).await {
+            Ok(Some(state)) => return Ok(state),
+            Ok(None) => (),
+            Err(e) => {
+                self.add_blocked_label(pull_request).await;
+                return Err(e);
+            }
+        };
         info!("All checks passed, pull request is ready to be merged!");
-        match self.merger.merge(&pull_request, &*self.github).await? {
-            MergeResult::Success => Ok(DirectorState::Done),
-            MergeResult::Conflict => {
-                info!("Found conflict while attempting merge");
-                Ok(DirectorState::Waiting)
+        self.remove_blocked_label(pull_request).await?;
+        self.merge(pull_request, reports).await
+    }
+
+    /// Merges the pull request, retrying if the merge conflicts only because its head changed.
+    /// A new head goes through every step again before merging it.
+    async fn merge(
+        &mut self,
+        pull_request: &PullRequest,
+        reports: &mut Vec<StepReport>,
+    ) -> Result<DirectorState, Error> {
+        let mut pull_request = pull_request.clone();
+        let mut attempt = 0;
+        loop {
+            match self.merger.merge(&pull_request, &*self.github).await? {
+                MergeResult::Success(method) => {
+                    self.merge_method = Some(method);
+                    return Ok(DirectorState::Done);
+                }
+                MergeResult::DryRun(method) => {
+                    self.merge_method = Some(method);
+                    self.dry_run_merged = true;
+                    return Ok(DirectorState::Done);
+                }
+                MergeResult::Conflict => {
+                    info!("Found conflict while attempting merge");
+                }
+            }
+            if attempt >= self.options.conflict_retries {
+                self.merge_conflicted = true;
+                return Ok(DirectorState::Waiting);
+            }
+            attempt += 1;
+            let previous_sha = pull_request.head.sha.clone();
+            pull_request = self.fetch_pull_request().await?;
+            // A clean state means github still considers it mergeable with all checks passing on
+            // the new head, anything else needs to go through the steps again
+            match pull_request.mergeable_state {
+                MergeableState::Clean if pull_request.head.sha != previous_sha => {
+                    info!(
+                        "Head changed from {} to {}, running checks on it before retrying merge",
+                        previous_sha, pull_request.head.sha
+                    );
+                    reports.clear();
+                    match self.evaluate_steps(&pull_request, reports).await {
+                        Ok(Some(state)) => {
+                            self.merge_conflicted = true;
+                            return Ok(state);
+                        }
+                        Ok(None) => info!(
+                            "Retrying merge using head sha {} ({}/{})",
+                            pull_request.head.sha, attempt, self.options.conflict_retries
+                        ),
+                        Err(e) => {
+                            self.add_blocked_label(&pull_request).await;
+                            return Err(e);
+                        }
+                    }
+                }
+                MergeableState::Clean => info!(
+                    "Retrying merge using head sha {} ({}/{})",
+                    pull_request.head.sha, attempt, self.options.conflict_retries
+                ),
+                MergeableState::Dirty => {
+                    info!("Pull request conflicts with its base branch, not retrying merge");
+                    self.merge_conflicted = true;
+                    return Ok(DirectorState::Waiting);
+                }
+                state => {
+                    info!("Pull request is in {:?} state, not retrying merge", state);
+                    self.merge_conflicted = true;
+                    return Ok(DirectorState::Waiting);
+                }
+            }
+        }
+    }
+
+    /// Records how a run ended in the audit log, unless it's just waiting on a step
+    async fn record_run(
+        &self,
+        audit_log: &AuditLog,
+        pull_request: &PullRequest,
+        result: &Result<DirectorState, Error>,
+    ) {
+        let outcome = match (result, &self.merge_method) {
+            // Nothing happened to the pull request
+            (Ok(DirectorState::Done), _) if self.dry_run_merged => return,
+            (Ok(DirectorState::Done), Some(_)) => AuditOutcome::Merged,
+            (Ok(DirectorState::Done), None) => AuditOutcome::AlreadyMerged,
+            (Ok(DirectorState::Closed), _) => AuditOutcome::Closed,
+            (Ok(DirectorState::Waiting), _) if self.merge_conflicted => AuditOutcome::Conflict,
+            (Ok(DirectorState::Waiting), _) => return,
+            (Err(e), _) => AuditOutcome::Failed(e.to_string()),
+        };
+        let approvers = match self.github.pull_request_reviews(pull_request).await {
+            Ok(reviews) => {
+                let mut approvers: Vec<_> = CheckReviewsStep::approved_users(&reviews)
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                approvers.sort_unstable();
+                approvers
+            }
+            Err(e) => {
+                warn!("Failed to fetch approvers for the audit log: {}", e);
+                Vec::new()
+            }
+        };
+        let record = AuditRecord {
+            url: self.identifier.app_url(),
+            repo: format!("{}/{}", self.identifier.owner, self.identifier.repo),
+            title: pull_request.title.clone(),
+            outcome,
+            method: self.merge_method.clone(),
+            approvers,
+            timestamp: Utc::now(),
+        };
+        if let Err(e) = audit_log.record(&record) {
+            warn!("Failed to write to the audit log: {}", e);
+        }
+    }
+
+    async fn add_blocked_label(&self, pull_request: &PullRequest) {
+        let label = match &self.options.blocked_label {
+            Some(label) if !pull_request.has_label(label) => label,
+            _ => return,
+        };
+        info!("Labeling pull request as '{}'", label);
+        if let Err(e) = self.github.add_label(pull_request, label).await {
+            warn!("Failed to label pull request: {}", e);
+        }
+    }
+
+    async fn remove_blocked_label(&self, pull_request: &PullRequest) -> Result<(), Error> {
+        let label = match &self.options.blocked_label {
+            Some(label) if pull_request.has_label(label) => label,
+            _ => return Ok(()),
+        };
+        info!("Removing '{}' label from pull request", label);
+        match self.github.remove_label(pull_request, label).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.not_found() => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn find_status_comment(&self, pull_request: &PullRequest) -> Result<Option<u64>, Error> {
+        let comments = self.github.list_comments(pull_request).await?;
+        let comment = comments.into_iter().find(|comment| {
+            comment
+                .body
+                .as_deref()
+                .map(|body| body.starts_with(STATUS_COMMENT_MARKER))
+                .unwrap_or(false)
+        });
+        Ok(comment.map(|comment| comment.id))
+    }
+
+    fn add_unchecked_reports(&self, reports: &mut Vec<StepReport>) {
+        for step in &self.steps[reports.len()..] {
+            reports.push(StepReport {
+                name: step.to_string(),
+                outcome: StepOutcome::NotChecked,
+                checks: None,
+                approvals: None,
+            });
+        }
+    }
+
+    async fn publish_status_comment(
+        &mut self,
+        pull_request: &PullRequest,
+        reports: Vec<StepReport>,
+    ) -> Result<(), Error> {
+        let body = render_status_comment(&reports);
+        if self.last_status_comment.as_ref() == Some(&body) {
+            return Ok(());
+        }
+        if self.status_comment_id.is_none() {
+            self.status_comment_id = self.find_status_comment(pull_request).await?;
+        }
+        match self.status_comment_id {
+            Some(comment_id) => {
+                debug!("Updating status comment");
+                self.github
+                    .update_comment(&pull_request.base.repo, comment_id, &body)
+                    .await?;
+            }
+            None => {
+                debug!("Creating status comment");
+                let comment = self.github.create_comment(pull_request, &body).await?;
+                self.status_comment_id = Some(comment.id);
+            }
+        };
+        self.last_status_comment = Some(body);
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl PullRequestProcessor for Director {
+    async fn run(&mut self) -> Result<DirectorState, Error> {
+        debug!("Fetching current state for pull request");
+        let pull_request = self.fetch_pull_request().await?;
+        let mut reports = Vec::new();
+        self.merge_conflicted = false;
+        let result = self.run_steps(&pull_request, &mut reports).await;
+        if let Some(audit_log) = &self.options.audit_log {
+            self.record_run(audit_log, &pull_request, &result).await;
+        }
+        self.add_unchecked_reports(&mut reports);
+        self.last_reports = reports.clone();
+        if self.options.status_comment {
+            if let Err(e) = self.publish_status_comment(&pull_request, reports).await {
+                warn!("Failed to update status comment: {}", e);
             }
         }
+        result
+    }
+
+    /// Whether the last run made progress, meaning either a different step is now pending or the
+    /// pull request's head sha changed
+    fn progressed(&self) -> bool {
+        self.progressed
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.pull_request.as_ref().map(|pr| pr.title.as_str())
+    }
+
+    fn merge_method(&self) -> Option<&MergeMethod> {
+        self.merge_method.as_ref()
+    }
+
+    fn step_reports(&self) -> &[StepReport] {
+        &self.last_reports
     }
 }
 
@@ -54,4 +651,580 @@ impl Director {
 pub enum DirectorState {
     Done,
     Waiting,
+
+    /// The pull request was closed without being merged
+    Closed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ExternalCheck, MergeConfig};
+    use crate::github::client::MockGithubClient;
+    use crate::github::{
+        BranchProtection, CommitComparison, IssueComment, Label, TimelineEvent, User,
+    };
+    use crate::processing::steps::CheckExternalScript;
+    use crate::processing::DummyPullRequestMerger;
+    use rstest::rstest;
+    use std::fmt;
+    use std::future;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    struct FailingStep;
+
+    #[async_trait]
+    impl Step for FailingStep {
+        async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+            Err(Error::as_generic("nope"))
+        }
+    }
+
+    impl fmt::Display for FailingStep {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "failing step")
+        }
+    }
+
+    /// A failing step that the override label can skip
+    struct OverridableStep;
+
+    #[async_trait]
+    impl Step for OverridableStep {
+        async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+            Err(Error::as_generic("nope"))
+        }
+
+        fn overridable(&self) -> bool {
+            true
+        }
+    }
+
+    impl fmt::Display for OverridableStep {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "overridable step")
+        }
+    }
+
+    /// A step that never finishes in time
+    struct HangingStep;
+
+    #[async_trait]
+    impl Step for HangingStep {
+        async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            Ok(StepStatus::Passed)
+        }
+
+        fn is_readonly(&self) -> bool {
+            true
+        }
+    }
+
+    /// A step that changes things and takes longer than the step timeout used in tests
+    struct SlowWriteStep;
+
+    #[async_trait]
+    impl Step for SlowWriteStep {
+        async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(StepStatus::Passed)
+        }
+    }
+
+    impl fmt::Display for SlowWriteStep {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "slow write step")
+        }
+    }
+
+    impl fmt::Display for HangingStep {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "hanging step")
+        }
+    }
+
+    /// A read only step that always returns the same status
+    struct ReadOnlyStep(StepStatus);
+
+    #[async_trait]
+    impl Step for ReadOnlyStep {
+        async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+            Ok(self.0.clone())
+        }
+
+        fn is_readonly(&self) -> bool {
+            true
+        }
+    }
+
+    impl fmt::Display for ReadOnlyStep {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "read only step {:?}", self.0)
+        }
+    }
+
+    fn make_identifier() -> PullRequestIdentifier {
+        PullRequestIdentifier {
+            base_url: "https://github.com".into(),
+            owner: "potato".into(),
+            repo: "smasher".into(),
+            pull_number: 1337,
+        }
+    }
+
+    fn make_director(github: MockGithubClient, steps: Vec<Box<dyn Step>>) -> Director {
+        Director::new(
+            Arc::new(github),
+            Arc::new(DummyPullRequestMerger::new(MergeConfig::default())),
+            steps,
+            make_identifier(),
+            DirectorOptions {
+                blocked_label: Some("merge-blocked".into()),
+                status_comment: false,
+                conflict_retries: 0,
+                step_timeout: Some(Duration::from_millis(50)),
+                audit_log: None,
+                step_override: None,
+                mergeability_retries: 0,
+                mergeability_retry_delay: Duration::ZERO,
+                base_branch_override: None,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_blocked_label_added_on_failure() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+        github
+            .expect_add_label()
+            .withf(|_, label| label == "merge-blocked")
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(vec![]))));
+
+        let mut director = make_director(github, vec![Box::new(FailingStep)]);
+        assert!(director.run().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_blocked_label_removed_before_merge() {
+        let pull_request = PullRequest {
+            labels: vec![Label {
+                name: "merge-blocked".into(),
+            }],
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+        github
+            .expect_remove_label()
+            .withf(|_, label| label == "merge-blocked")
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(vec![]))));
+        github
+            .expect_branch_protection()
+            .returning(|_| Box::pin(future::ready(Ok(BranchProtection::default()))));
+        github
+            .expect_compare_commits()
+            .returning(|_, _, _| Box::pin(future::ready(Ok(CommitComparison::default()))));
+
+        let mut director = make_director(github, vec![]);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Done);
+    }
+
+    #[tokio::test]
+    async fn test_status_comment_created_once() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+        github
+            .expect_add_label()
+            .returning(|_, _| Box::pin(future::ready(Ok(vec![]))));
+        github
+            .expect_list_comments()
+            .times(1)
+            .returning(|_| Box::pin(future::ready(Ok(vec![]))));
+        github
+            .expect_create_comment()
+            .withf(|_, body| body.starts_with(STATUS_COMMENT_MARKER) && body.contains("nope"))
+            .times(1)
+            .returning(|_, body| {
+                Box::pin(future::ready(Ok(IssueComment {
+                    id: 42,
+                    body: Some(body.to_string()),
+                    ..Default::default()
+                })))
+            });
+
+        let mut director = make_director(github, vec![Box::new(FailingStep)]);
+        director.options.status_comment = true;
+        assert!(director.run().await.is_err());
+        // Same outcome on the second run, so the comment is left alone
+        assert!(director.run().await.is_err());
+        assert_eq!(director.status_comment_id, Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_check_does_not_label_or_merge() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+
+        let mut director = make_director(github, vec![Box::new(FailingStep)]);
+        let reports = director.check().await.unwrap();
+        assert_eq!(
+            reports,
+            vec![StepReport {
+                name: "failing step".into(),
+                outcome: StepOutcome::Failed("nope".into()),
+                checks: None,
+                approvals: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_only_steps_processed_in_order() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+
+        let steps: Vec<Box<dyn Step>> = vec![
+            Box::new(ReadOnlyStep(StepStatus::Passed)),
+            Box::new(ReadOnlyStep(StepStatus::Waiting)),
+            Box::new(ReadOnlyStep(StepStatus::Closed)),
+            Box::new(FailingStep),
+        ];
+        let mut director = make_director(github, steps);
+        let outcomes: Vec<_> = director
+            .check()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|report| report.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                StepOutcome::Passed,
+                StepOutcome::Waiting,
+                StepOutcome::NotChecked,
+                StepOutcome::NotChecked
+            ]
+        );
+    }
+
+    /// Conflicts on the first merge attempt, then succeeds
+    struct ConflictingMerger {
+        attempts: AtomicU32,
+    }
+
+    #[async_trait]
+    impl PullRequestMerger for ConflictingMerger {
+        async fn merge(
+            &self,
+            _pull_request: &PullRequest,
+            _github: &dyn GithubClient,
+        ) -> Result<MergeResult, Error> {
+            match self.attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Ok(MergeResult::Conflict),
+                _ => Ok(MergeResult::Success(MergeMethod::Squash)),
+            }
+        }
+    }
+
+    #[rstest]
+    #[case::clean(MergeableState::Clean, 1, DirectorState::Done)]
+    #[case::dirty(MergeableState::Dirty, 1, DirectorState::Waiting)]
+    #[case::no_retries(MergeableState::Clean, 0, DirectorState::Waiting)]
+    #[tokio::test]
+    async fn test_merge_conflict_retries(
+        #[case] mergeable_state: MergeableState,
+        #[case] conflict_retries: u32,
+        #[case] expected: DirectorState,
+    ) {
+        let pull_request = PullRequest {
+            mergeable_state,
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+
+        let mut director = make_director(github, vec![]);
+        director.merger = Arc::new(ConflictingMerger {
+            attempts: AtomicU32::new(0),
+        });
+        director.options.conflict_retries = conflict_retries;
+        assert_eq!(director.run().await.unwrap(), expected);
+    }
+
+    /// Only previews merges
+    struct DryRunMerger;
+
+    #[async_trait]
+    impl PullRequestMerger for DryRunMerger {
+        async fn merge(
+            &self,
+            _pull_request: &PullRequest,
+            _github: &dyn GithubClient,
+        ) -> Result<MergeResult, Error> {
+            Ok(MergeResult::DryRun(MergeMethod::Squash))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_merge_not_audited() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+
+        // Recording the run would fetch reviews, which the mock doesn't expect
+        let mut director = make_director(github, vec![]);
+        director.merger = Arc::new(DryRunMerger);
+        director.options.audit_log = Some(Arc::new(AuditLog::open(":memory:").unwrap()));
+        assert_eq!(director.run().await.unwrap(), DirectorState::Done);
+        assert_eq!(director.merge_method, Some(MergeMethod::Squash));
+        assert!(director.dry_run_merged);
+    }
+
+    /// A step that only passes on a given head sha
+    struct HeadShaStep(&'static str);
+
+    #[async_trait]
+    impl Step for HeadShaStep {
+        async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+            match pull_request.head.sha == self.0 {
+                true => Ok(StepStatus::Passed),
+                false => Ok(StepStatus::Waiting),
+            }
+        }
+    }
+
+    impl fmt::Display for HeadShaStep {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "head sha step")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_conflict_checks_new_head() {
+        let fetches = Arc::new(AtomicU32::new(0));
+        let mut github = MockGithubClient::default();
+        let counter = fetches.clone();
+        github.expect_pull_request_info().returning(move |_| {
+            let sha = match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => "oldsha",
+                _ => "newsha",
+            };
+            Box::pin(future::ready(Ok(PullRequest {
+                mergeable_state: MergeableState::Clean,
+                head: Branch {
+                    sha: sha.into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })))
+        });
+
+        let mut director = make_director(github, vec![Box::new(HeadShaStep("oldsha"))]);
+        let merger = Arc::new(ConflictingMerger {
+            attempts: AtomicU32::new(0),
+        });
+        director.merger = merger.clone();
+        director.options.conflict_retries = 1;
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        assert_eq!(merger.attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(director.merge_method, None);
+    }
+
+    #[tokio::test]
+    async fn test_step_timeout_waits() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+
+        let mut director = make_director(github, vec![Box::new(HangingStep)]);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+    }
+
+    #[tokio::test]
+    async fn test_step_timeout_skips_write_steps() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+
+        let mut director = make_director(github, vec![Box::new(SlowWriteStep)]);
+        director.merger = Arc::new(DryRunMerger);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Done);
+    }
+
+    #[tokio::test]
+    async fn test_step_own_timeout() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(PullRequest::default()))));
+
+        // Takes longer than the director's 50ms step timeout but less than its own
+        let check = CheckExternalScript::new(ExternalCheck {
+            name: "slow".into(),
+            command: "sleep 0.2".into(),
+            timeout_seconds: 5,
+        });
+        let mut director = make_director(github, vec![Box::new(check)]);
+        director.merger = Arc::new(DryRunMerger);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Done);
+    }
+
+    #[tokio::test]
+    async fn test_override_label_skips_overridable_steps() {
+        let pull_request = PullRequest {
+            labels: vec![Label {
+                name: "Mergebro-Override".into(),
+            }],
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+        github.expect_timeline_events().times(1).returning(|_| {
+            Box::pin(future::ready(Ok(vec![TimelineEvent {
+                event: "labeled".into(),
+                actor: Some(User {
+                    login: "alice".into(),
+                }),
+                label: Some(Label {
+                    name: "mergebro-override".into(),
+                }),
+                ..Default::default()
+            }])))
+        });
+
+        let mut director = make_director(
+            github,
+            vec![Box::new(OverridableStep), Box::new(FailingStep)],
+        );
+        director.options.step_override = Some(StepOverride {
+            label: "mergebro-override".into(),
+            steps: vec!["overridable step".into(), "failing step".into()],
+        });
+        let reports = director.check().await.unwrap();
+        let outcomes: Vec<_> = reports.into_iter().map(|report| report.outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![StepOutcome::Overridden, StepOutcome::Failed("nope".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_mergeability() {
+        let fetches = Arc::new(AtomicU32::new(0));
+        let mut github = MockGithubClient::default();
+        let counter = fetches.clone();
+        github.expect_pull_request_info().returning(move |_| {
+            let fetch = counter.fetch_add(1, Ordering::SeqCst);
+            Box::pin(future::ready(Ok(PullRequest {
+                state: PullRequestState::Open,
+                mergeable: (fetch >= 2).then_some(true),
+                ..Default::default()
+            })))
+        });
+
+        let mut director = make_director(github, vec![]);
+        director.options.mergeability_retries = 3;
+        let pull_request = director.fetch_pull_request().await.unwrap();
+        assert_eq!(pull_request.mergeable, Some(true));
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_closed_skips_mergeability_wait() {
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_info().times(1).returning(|_| {
+            Box::pin(future::ready(Ok(PullRequest {
+                state: PullRequestState::Closed,
+                mergeable: None,
+                ..Default::default()
+            })))
+        });
+
+        let mut director = make_director(github, vec![]);
+        director.options.mergeability_retries = 3;
+        let pull_request = director.fetch_pull_request().await.unwrap();
+        assert_eq!(pull_request.mergeable, None);
+    }
+
+    #[rstest]
+    #[case::existing("stack/base", Some("basesha"))]
+    #[case::missing("potato", None)]
+    #[tokio::test]
+    async fn test_base_branch_override(#[case] branch: &str, #[case] expected_sha: Option<&str>) {
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_info().returning(|_| {
+            Box::pin(future::ready(Ok(PullRequest {
+                mergeable: Some(true),
+                base: Branch {
+                    name: "main".into(),
+                    sha: "mainsha".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })))
+        });
+        github.expect_commit().returning(|_, git_ref| {
+            let result = match git_ref {
+                "stack/base" => Ok(serde_json::from_value(serde_json::json!({
+                    "sha": "basesha",
+                    "commit": {
+                        "author": {"date": "2021-01-01T00:00:00Z"},
+                        "committer": {"date": "2021-01-01T00:00:00Z"},
+                    },
+                }))
+                .unwrap()),
+                _ => Err(crate::client::Error::Http(reqwest::StatusCode::NOT_FOUND)),
+            };
+            Box::pin(future::ready(result))
+        });
+
+        let result = Director::resolve_base_branch(&github, &make_identifier(), branch).await;
+        let base = match expected_sha {
+            Some(sha) => {
+                let base = result.unwrap();
+                assert_eq!(base.name, branch);
+                assert_eq!(base.sha, sha);
+                base
+            }
+            None => {
+                assert!(result.is_err());
+                return;
+            }
+        };
+
+        // The resolved branch is reused without asking github for it again
+        github.checkpoint();
+        github.expect_pull_request_info().returning(|_| {
+            Box::pin(future::ready(Ok(PullRequest {
+                mergeable: Some(true),
+                ..Default::default()
+            })))
+        });
+        let mut director = make_director(github, vec![]);
+        director.options.base_branch_override = Some(base.clone());
+        let pull_request = director.fetch_pull_request().await.unwrap();
+        assert_eq!(pull_request.base, base);
+    }
 }
@@ -6,6 +6,13 @@ pub enum Error {
     #[error(transparent)]
     Client(#[from] crate::client::Error),
 
+    #[error("pull request has conflicts")]
+    Conflicts,
+
+    /// An external CI service failed while processing failed jobs
+    #[error("workflow runner failed: {0}")]
+    WorkflowRunner(Box<Error>),
+
     #[error("unsupported pull request state: {0}")]
     UnsupportedPullRequestState(Cow<'static, str>),
 
@@ -1,15 +1,24 @@
-use crate::config::MergeConfig;
+use crate::config::{CommitTitleSource, MergeConfig};
 use crate::github::{
     client::{GithubClient, MergeRequestBody},
-    MergeMethod, PullRequest,
+    Commit, CommitComparison, MergeMethod, PullRequest,
 };
-use crate::processing::Error;
+use crate::metrics::METRICS;
+use crate::processing::{steps::CheckReviewsStep, Error};
 use async_trait::async_trait;
 use log::{info, warn};
+use serde_derive::Deserialize;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use thiserror::Error;
+use tracing::Instrument;
 
 pub enum MergeResult {
-    Success,
+    Success(MergeMethod),
     Conflict,
+
+    /// Nothing was merged, this is the method that would have been used
+    DryRun(MergeMethod),
 }
 
 #[async_trait]
@@ -21,14 +30,189 @@ pub trait PullRequestMerger {
     ) -> Result<MergeResult, Error>;
 }
 
+#[derive(Debug, Clone, PartialEq)]
+enum Placeholder {
+    Title,
+    Number,
+    Body,
+    Author,
+    Approvers,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A commit message template, where `{title}`, `{number}`, `{body}`, `{author}` and `{approvers}`
+/// are replaced by the pull request's fields. Literal braces can be written as `{{` and `}}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct MessageTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl MessageTemplate {
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError::UnterminatedPlaceholder),
+                        }
+                    }
+                    let placeholder = match name.as_str() {
+                        "title" => Placeholder::Title,
+                        "number" => Placeholder::Number,
+                        "body" => Placeholder::Body,
+                        "author" => Placeholder::Author,
+                        "approvers" => Placeholder::Approvers,
+                        _ => return Err(TemplateError::UnknownPlaceholder(name)),
+                    };
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Placeholder(placeholder));
+                }
+                '}' => return Err(TemplateError::UnmatchedBrace),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Whether rendering this template requires the list of users who approved the pull request
+    pub fn needs_approvers(&self) -> bool {
+        self.parts
+            .contains(&TemplatePart::Placeholder(Placeholder::Approvers))
+    }
+
+    pub fn render(&self, pull_request: &PullRequest, approvers: &[&str]) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(literal) => output.push_str(literal),
+                TemplatePart::Placeholder(Placeholder::Title) => {
+                    output.push_str(&pull_request.title)
+                }
+                TemplatePart::Placeholder(Placeholder::Number) => {
+                    output.push_str(&pull_request.number.to_string())
+                }
+                TemplatePart::Placeholder(Placeholder::Body) => {
+                    output.push_str(pull_request.body.as_deref().unwrap_or_default())
+                }
+                TemplatePart::Placeholder(Placeholder::Author) => {
+                    output.push_str(&pull_request.creator.login)
+                }
+                TemplatePart::Placeholder(Placeholder::Approvers) => {
+                    output.push_str(&approvers.join(", "))
+                }
+            }
+        }
+        output
+    }
+}
+
+impl TryFrom<String> for MessageTemplate {
+    type Error = TemplateError;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        Self::parse(&template)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum TemplateError {
+    #[error("unknown placeholder '{{{0}}}'")]
+    UnknownPlaceholder(String),
+
+    #[error("unterminated placeholder")]
+    UnterminatedPlaceholder,
+
+    #[error("unmatched '}}', use '}}}}' for a literal brace")]
+    UnmatchedBrace,
+}
+
+/// `Co-authored-by` trailers for everyone other than the pull request's author who authored or
+/// co-authored its commits, in the order they first show up
+fn coauthor_trailers(pull_request: &PullRequest, commits: &[Commit]) -> Vec<String> {
+    let mut emails = HashSet::new();
+    let mut trailers = Vec::new();
+    let mut add_trailer = |name: &str, email: &str| {
+        if !email.is_empty() && emails.insert(email.to_lowercase()) {
+            trailers.push(format!("Co-authored-by: {} <{}>", name, email));
+        }
+    };
+    for commit in commits {
+        let by_creator =
+            matches!(&commit.author, Some(author) if author.login == pull_request.creator.login);
+        if !by_creator {
+            add_trailer(&commit.commit.author.name, &commit.commit.author.email);
+        }
+        for line in commit.commit.message.lines() {
+            let coauthor = match line.split_once(':') {
+                Some((key, value)) if key.eq_ignore_ascii_case("co-authored-by") => value.trim(),
+                _ => continue,
+            };
+            if let Some((name, email)) = coauthor.strip_suffix('>').and_then(|c| c.split_once('<'))
+            {
+                add_trailer(name.trim(), email.trim());
+            }
+        }
+    }
+    trailers
+}
+
+/// Whether the last paragraph of a message is made up of trailers like `Signed-off-by: ...`
+fn ends_with_trailers(message: &str) -> bool {
+    let last_paragraph = match message.rsplit_once("\n\n") {
+        Some((_, last_paragraph)) => last_paragraph,
+        None => return false,
+    };
+    last_paragraph.lines().all(|line| {
+        matches!(line.split_once(": "), Some((key, _)) if !key.is_empty() && !key.contains(' '))
+    })
+}
+
 pub struct DefaultPullRequestMerger {
     merge_methods: Vec<MergeMethod>,
+    squash_message_template: Option<MessageTemplate>,
+    commit_title_source: CommitTitleSource,
+    commit_title_template: Option<MessageTemplate>,
+    preserve_coauthors: bool,
+    delete_branch_on_merge: bool,
 }
 
 impl DefaultPullRequestMerger {
     pub fn new(config: MergeConfig) -> Self {
-        let merge_methods = Self::build_merge_methods(config.default_method);
-        Self { merge_methods }
+        let merge_methods = Self::build_merge_methods(&config);
+        Self {
+            merge_methods,
+            squash_message_template: config.squash_message_template,
+            commit_title_source: config.commit_title_source,
+            commit_title_template: config.commit_title_template,
+            preserve_coauthors: config.preserve_coauthors,
+            delete_branch_on_merge: config.delete_branch_on_merge,
+        }
     }
 
     async fn merge_with_method(
@@ -37,54 +221,129 @@ impl DefaultPullRequestMerger {
         github: &dyn GithubClient,
         method: &MergeMethod,
     ) -> Result<(), crate::client::Error> {
-        let commit_message = Self::build_merge_message(pull_request, method);
-        let request_body = MergeRequestBody {
-            sha: pull_request.head.sha.clone(),
-            commit_title: pull_request.title.clone(),
-            commit_message,
-            merge_method: method.clone(),
-        };
+        let request_body = self
+            .build_request_body(pull_request, github, method)
+            .await?;
         github
             .merge_pull_request(pull_request, &request_body)
             .await?;
         Ok(())
     }
 
-    fn build_merge_message(pull_request: &PullRequest, method: &MergeMethod) -> Option<String> {
-        if matches!(method, MergeMethod::Squash) {
-            pull_request.body.clone()
-        } else {
-            None
+    async fn build_request_body(
+        &self,
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
+        method: &MergeMethod,
+    ) -> Result<MergeRequestBody, crate::client::Error> {
+        let body = MergeRequestBody {
+            sha: pull_request.head.sha.clone(),
+            commit_title: self.build_commit_title(pull_request, github).await?,
+            commit_message: self
+                .build_merge_message(pull_request, github, method)
+                .await?,
+            merge_method: method.clone(),
+        };
+        Ok(body)
+    }
+
+    async fn build_commit_title(
+        &self,
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
+    ) -> Result<String, crate::client::Error> {
+        match (&self.commit_title_source, &self.commit_title_template) {
+            (CommitTitleSource::FirstCommit, _) => {
+                let commits = github.pull_request_commits(pull_request).await?;
+                let subject = commits
+                    .first()
+                    .and_then(|commit| commit.commit.message.lines().next())
+                    .map(str::trim)
+                    .filter(|subject| !subject.is_empty());
+                Ok(subject.unwrap_or(&pull_request.title).to_string())
+            }
+            (CommitTitleSource::Template, Some(template)) => {
+                Self::render_template(pull_request, github, template).await
+            }
+            // A missing template is rejected when loading the config
+            (CommitTitleSource::PrTitle, _) | (CommitTitleSource::Template, None) => {
+                Ok(pull_request.title.clone())
+            }
         }
     }
 
-    fn build_merge_methods(default_method: MergeMethod) -> Vec<MergeMethod> {
-        let mut methods = vec![MergeMethod::Squash, MergeMethod::Merge, MergeMethod::Rebase];
-        let default_index = methods
-            .iter()
-            .position(|element| element == &default_method)
-            .unwrap();
-        methods.swap(default_index, 0);
-        methods
+    /// Renders a template, fetching the users who approved the pull request if it needs them
+    async fn render_template(
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
+        template: &MessageTemplate,
+    ) -> Result<String, crate::client::Error> {
+        let reviews = match template.needs_approvers() {
+            true => github.pull_request_reviews(pull_request).await?,
+            false => Vec::new(),
+        };
+        let mut approvers: Vec<_> = CheckReviewsStep::approved_users(&reviews)
+            .into_iter()
+            .collect();
+        approvers.sort_unstable();
+        Ok(template.render(pull_request, &approvers))
     }
-}
 
-#[async_trait]
-impl PullRequestMerger for DefaultPullRequestMerger {
-    async fn merge(
+    async fn build_merge_message(
+        &self,
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
+        method: &MergeMethod,
+    ) -> Result<Option<String>, crate::client::Error> {
+        if !matches!(method, MergeMethod::Squash) {
+            return Ok(None);
+        }
+        let mut message = match &self.squash_message_template {
+            Some(template) => Some(Self::render_template(pull_request, github, template).await?),
+            None => pull_request.body.clone(),
+        };
+        if self.preserve_coauthors {
+            let commits = github.pull_request_commits(pull_request).await?;
+            let existing = message.clone().unwrap_or_default();
+            let trailers: Vec<_> = coauthor_trailers(pull_request, &commits)
+                .into_iter()
+                .filter(|trailer| !existing.contains(trailer.as_str()))
+                .collect();
+            if !trailers.is_empty() {
+                // Trailers need to be in the message's last paragraph to be recognized
+                let mut with_trailers = existing.trim_end().to_string();
+                if ends_with_trailers(&with_trailers) {
+                    with_trailers.push('\n');
+                } else if !with_trailers.is_empty() {
+                    with_trailers.push_str("\n\n");
+                }
+                with_trailers.push_str(&trailers.join("\n"));
+                message = Some(with_trailers);
+            }
+        }
+        Ok(message)
+    }
+
+    async fn merge_with_fallbacks(
         &self,
         pull_request: &PullRequest,
         github: &dyn GithubClient,
     ) -> Result<MergeResult, Error> {
-        for method in &self.merge_methods {
+        let merge_methods = self.allowed_merge_methods(pull_request, github).await?;
+        for method in &merge_methods {
             info!(
                 "Attempting to merge pull request using '{:?}' merge method",
                 method
             );
-            match self.merge_with_method(pull_request, github, method).await {
+            let span = tracing::info_span!("merge", method = ?method);
+            match self
+                .merge_with_method(pull_request, github, method)
+                .instrument(span)
+                .await
+            {
                 Ok(_) => {
                     info!("Pull request merged ✔️");
-                    return Ok(MergeResult::Success);
+                    return Ok(MergeResult::Success(method.clone()));
                 }
                 Err(e) if e.method_not_allowed() => {
                     warn!("Merge method '{:?}' not allowed", method);
@@ -97,39 +356,498 @@ impl PullRequestMerger for DefaultPullRequestMerger {
                 Err(e) => return Err(e.into()),
             }
         }
-        Ok(MergeResult::Conflict)
+        Err(Error::as_generic(format!(
+            "none of the merge methods {:?} are allowed",
+            merge_methods
+        )))
+    }
+
+    /// Deletes the head branch of a merged pull request, unless it lives in a fork
+    async fn delete_head_branch(&self, pull_request: &PullRequest, github: &dyn GithubClient) {
+        let head = &pull_request.head;
+        if pull_request.is_from_fork() {
+            info!(
+                "Not deleting branch '{}' as it belongs to fork '{}'",
+                head.name, head.repo.full_name
+            );
+            return;
+        }
+        // The pull request is already merged so this shouldn't fail the run
+        match github.delete_branch(head).await {
+            Ok(_) => info!("Deleted branch '{}'", head.name),
+            Err(e) => warn!("Failed to delete branch '{}': {}", head.name, e),
+        };
+    }
+
+    /// The merge methods to attempt, in order. Merge commits are left out if the base branch
+    /// requires a linear history.
+    async fn allowed_merge_methods(
+        &self,
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
+    ) -> Result<Vec<MergeMethod>, crate::client::Error> {
+        let linear_history_required = match github.branch_protection(&pull_request.base).await {
+            Ok(protection) => protection.required_linear_history.enabled,
+            Err(e) if e.not_found() || e.forbidden() => false,
+            Err(e) => return Err(e),
+        };
+        if !linear_history_required {
+            return Ok(self.merge_methods.clone());
+        }
+        let mut methods: Vec<_> = self
+            .merge_methods
+            .iter()
+            .filter(|method| !matches!(method, MergeMethod::Merge))
+            .cloned()
+            .collect();
+        if matches!(self.merge_methods.first(), Some(MergeMethod::Merge)) {
+            warn!(
+                "Branch '{}' requires a linear history, squashing instead of creating a merge commit",
+                pull_request.base.name
+            );
+            methods.retain(|method| !matches!(method, MergeMethod::Squash));
+            methods.insert(0, MergeMethod::Squash);
+        }
+        Ok(methods)
+    }
+
+    fn build_merge_methods(config: &MergeConfig) -> Vec<MergeMethod> {
+        let mut methods = vec![config.default_method.clone()];
+        if config.method_fallback {
+            let fallbacks = config
+                .method_fallback_order
+                .iter()
+                .filter(|method| *method != &config.default_method);
+            methods.extend(fallbacks.cloned());
+        }
+        methods
     }
 }
 
-#[derive(Default)]
-pub struct DummyPullRequestMerger;
+#[async_trait]
+impl PullRequestMerger for DefaultPullRequestMerger {
+    async fn merge(
+        &self,
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
+    ) -> Result<MergeResult, Error> {
+        METRICS.merge_attempted();
+        let result = self.merge_with_fallbacks(pull_request, github).await;
+        if matches!(result, Ok(MergeResult::Success(_))) && self.delete_branch_on_merge {
+            self.delete_head_branch(pull_request, github).await;
+        }
+        match &result {
+            Ok(MergeResult::Success(_)) => METRICS.merge_succeeded(),
+            Ok(MergeResult::Conflict) => METRICS.merge_conflict(),
+            Ok(MergeResult::DryRun(_)) => {}
+            Err(_) => METRICS.merge_failed(),
+        };
+        result
+    }
+}
+
+/// A merger that logs the request that would be used to merge a pull request instead of merging it
+pub struct DummyPullRequestMerger {
+    merger: DefaultPullRequestMerger,
+}
+
+impl DummyPullRequestMerger {
+    pub fn new(config: MergeConfig) -> Self {
+        Self {
+            merger: DefaultPullRequestMerger::new(config),
+        }
+    }
+}
 
 #[async_trait]
 impl PullRequestMerger for DummyPullRequestMerger {
     async fn merge(
         &self,
-        _pull_request: &PullRequest,
-        _github: &dyn GithubClient,
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
     ) -> Result<MergeResult, crate::processing::Error> {
-        info!("Skipping pull request merge step");
-        Ok(MergeResult::Success)
+        let merge_methods = self
+            .merger
+            .allowed_merge_methods(pull_request, github)
+            .await?;
+        let method = merge_methods
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::as_generic("no merge methods are allowed"))?;
+        let request_body = self
+            .merger
+            .build_request_body(pull_request, github, &method)
+            .await?;
+        let serialized = serde_json::to_string_pretty(&request_body)
+            .map_err(|e| Error::as_generic(format!("failed to serialize merge request: {}", e)))?;
+        info!(
+            "Skipping pull request merge, would have sent:\n{}",
+            serialized
+        );
+        // This is only a preview so failing to fetch it shouldn't fail the run
+        let base = &pull_request.base;
+        match github
+            .compare_commits(&base.repo, &base.name, &pull_request.head.sha)
+            .await
+        {
+            Ok(comparison) => info!(
+                "Merging would bring into '{}':\n{}",
+                base.name,
+                summarize_comparison(&comparison)
+            ),
+            Err(e) => warn!("Failed to fetch changes preview: {}", e),
+        };
+        Ok(MergeResult::DryRun(method))
+    }
+}
+
+fn summarize_comparison(comparison: &CommitComparison) -> String {
+    let additions: u32 = comparison.files.iter().map(|file| file.additions).sum();
+    let deletions: u32 = comparison.files.iter().map(|file| file.deletions).sum();
+    let mut summary = format!(
+        "{} commits, {} files changed, +{} -{}",
+        comparison.ahead_by,
+        comparison.files.len(),
+        additions,
+        deletions
+    );
+    for file in &comparison.files {
+        summary.push_str(&format!(
+            "\n  {} +{} -{}",
+            file.filename, file.additions, file.deletions
+        ));
     }
+    summary
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::github::{
+        client::MockGithubClient, Branch, BranchProtection, BranchProtectionSetting, ChangedFile,
+        CommitDetails, CommitSignature, NoBody, PullRequestReview, Repository, ReviewState, User,
+    };
     use rstest::rstest;
+    use std::future;
 
     #[rstest]
     fn test_build_merge_methods(
         #[values(MergeMethod::Squash, MergeMethod::Merge, MergeMethod::Rebase)] method: MergeMethod,
     ) {
-        let methods = DefaultPullRequestMerger::build_merge_methods(method.clone());
+        let config = MergeConfig {
+            default_method: method.clone(),
+            ..Default::default()
+        };
+        let methods = DefaultPullRequestMerger::build_merge_methods(&config);
         assert_eq!(methods.len(), 3);
         assert_eq!(methods[0], method);
         for method in [MergeMethod::Squash, MergeMethod::Merge, MergeMethod::Rebase] {
             assert!(methods.iter().position(|m| m == &method).is_some());
         }
     }
+
+    #[tokio::test]
+    async fn test_dry_run_request_body() {
+        let pull_request = PullRequest {
+            title: "Add potatoes".into(),
+            body: Some("So many potatoes".into()),
+            ..Default::default()
+        };
+        let merger = DummyPullRequestMerger::new(MergeConfig {
+            default_method: MergeMethod::Squash,
+            ..Default::default()
+        });
+        let body = merger
+            .merger
+            .build_request_body(
+                &pull_request,
+                &MockGithubClient::default(),
+                &MergeMethod::Squash,
+            )
+            .await
+            .unwrap();
+        assert_eq!(body.commit_title, "Add potatoes");
+        assert_eq!(body.commit_message.as_deref(), Some("So many potatoes"));
+        assert_eq!(body.merge_method, MergeMethod::Squash);
+    }
+
+    #[tokio::test]
+    async fn test_squash_message_template() {
+        let pull_request = PullRequest {
+            title: "Add potatoes".into(),
+            body: Some("So many potatoes".into()),
+            number: 1337,
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_reviews().returning(|_| {
+            let reviews = ["bob", "alice"]
+                .iter()
+                .map(|login| PullRequestReview {
+                    user: User {
+                        login: login.to_string(),
+                    },
+                    state: ReviewState::Approved,
+                    submitted_at: chrono::Local::now(),
+                })
+                .collect();
+            Box::pin(future::ready(Ok(reviews)))
+        });
+        let merger = DummyPullRequestMerger::new(MergeConfig {
+            default_method: MergeMethod::Squash,
+            squash_message_template: Some(
+                MessageTemplate::parse("{title} (#{number})\n\n{body}\n\nApproved-by: {approvers}")
+                    .unwrap(),
+            ),
+            ..Default::default()
+        });
+        let body = merger
+            .merger
+            .build_request_body(&pull_request, &github, &MergeMethod::Squash)
+            .await
+            .unwrap();
+        assert_eq!(body.commit_title, "Add potatoes");
+        assert_eq!(
+            body.commit_message.as_deref(),
+            Some("Add potatoes (#1337)\n\nSo many potatoes\n\nApproved-by: alice, bob")
+        );
+    }
+
+    fn make_commit(login: Option<&str>, name: &str, email: &str, message: &str) -> Commit {
+        let signature = CommitSignature {
+            name: name.into(),
+            email: email.into(),
+            date: chrono::Local::now(),
+        };
+        Commit {
+            sha: "mysha".into(),
+            commit: CommitDetails {
+                author: signature.clone(),
+                committer: signature,
+                message: message.into(),
+            },
+            author: login.map(|login| User {
+                login: login.into(),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_squash_message_coauthors() {
+        let pull_request = PullRequest {
+            title: "Add potatoes".into(),
+            body: Some("So many potatoes\n\nCo-authored-by: Mike <mike@potato.com>".into()),
+            creator: User {
+                login: "alice".into(),
+            },
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_commits().returning(|_| {
+            Box::pin(future::ready(Ok(vec![
+                make_commit(Some("alice"), "Alice", "alice@potato.com", "Add potatoes"),
+                make_commit(
+                    Some("bob"),
+                    "Bob",
+                    "bob@potato.com",
+                    "Smash potatoes\n\nCo-authored-by: Carol <carol@potato.com>",
+                ),
+                make_commit(None, "Bob", "BOB@potato.com", "Mash potatoes"),
+                make_commit(None, "Mike", "mike@potato.com", "Peel potatoes"),
+            ])))
+        });
+        let merger = DummyPullRequestMerger::new(MergeConfig {
+            default_method: MergeMethod::Squash,
+            preserve_coauthors: true,
+            ..Default::default()
+        });
+        let body = merger
+            .merger
+            .build_request_body(&pull_request, &github, &MergeMethod::Squash)
+            .await
+            .unwrap();
+        assert_eq!(
+            body.commit_message.as_deref(),
+            Some(concat!(
+                "So many potatoes\n\n",
+                "Co-authored-by: Mike <mike@potato.com>\n",
+                "Co-authored-by: Bob <bob@potato.com>\n",
+                "Co-authored-by: Carol <carol@potato.com>"
+            ))
+        );
+    }
+
+    #[rstest]
+    #[case::pr_title(CommitTitleSource::PrTitle, "Add potatoes")]
+    #[case::first_commit(CommitTitleSource::FirstCommit, "Peel potatoes")]
+    #[case::template(CommitTitleSource::Template, "potatoes: Add potatoes (#1337)")]
+    #[tokio::test]
+    async fn test_commit_title_source(
+        #[case] commit_title_source: CommitTitleSource,
+        #[case] expected: &str,
+    ) {
+        let pull_request = PullRequest {
+            title: "Add potatoes".into(),
+            number: 1337,
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_commits().returning(|_| {
+            Box::pin(future::ready(Ok(vec![
+                make_commit(
+                    None,
+                    "Alice",
+                    "alice@potato.com",
+                    "Peel potatoes\n\nWith a knife",
+                ),
+                make_commit(None, "Alice", "alice@potato.com", "Smash potatoes"),
+            ])))
+        });
+        let merger = DefaultPullRequestMerger::new(MergeConfig {
+            commit_title_source,
+            commit_title_template: Some(
+                MessageTemplate::parse("potatoes: {title} (#{number})").unwrap(),
+            ),
+            ..Default::default()
+        });
+        let body = merger
+            .build_request_body(&pull_request, &github, &MergeMethod::Squash)
+            .await
+            .unwrap();
+        assert_eq!(body.commit_title, expected);
+    }
+
+    #[rstest]
+    #[case::unknown("{title} {potato}", TemplateError::UnknownPlaceholder("potato".into()))]
+    #[case::unterminated("{title", TemplateError::UnterminatedPlaceholder)]
+    #[case::unmatched("title}", TemplateError::UnmatchedBrace)]
+    fn test_invalid_template(#[case] template: &str, #[case] expected: TemplateError) {
+        assert_eq!(MessageTemplate::parse(template), Err(expected));
+    }
+
+    #[test]
+    fn test_template_escaped_braces() {
+        let template = MessageTemplate::parse("{{{number}}}").unwrap();
+        let pull_request = PullRequest {
+            number: 42,
+            ..Default::default()
+        };
+        assert_eq!(template.render(&pull_request, &[]), "{42}");
+    }
+
+    #[test]
+    fn test_build_merge_methods_custom_order() {
+        let config = MergeConfig {
+            default_method: MergeMethod::Merge,
+            method_fallback_order: vec![MergeMethod::Rebase, MergeMethod::Merge],
+            ..Default::default()
+        };
+        let methods = DefaultPullRequestMerger::build_merge_methods(&config);
+        assert_eq!(methods, vec![MergeMethod::Merge, MergeMethod::Rebase]);
+    }
+
+    #[test]
+    fn test_build_merge_methods_without_fallback() {
+        let config = MergeConfig {
+            default_method: MergeMethod::Rebase,
+            method_fallback: false,
+            ..Default::default()
+        };
+        let methods = DefaultPullRequestMerger::build_merge_methods(&config);
+        assert_eq!(methods, vec![MergeMethod::Rebase]);
+    }
+
+    #[rstest]
+    #[case::merge_default(MergeMethod::Merge, vec![MergeMethod::Squash, MergeMethod::Rebase])]
+    #[case::rebase_default(MergeMethod::Rebase, vec![MergeMethod::Rebase, MergeMethod::Squash])]
+    #[tokio::test]
+    async fn test_linear_history_required(
+        #[case] default_method: MergeMethod,
+        #[case] expected: Vec<MergeMethod>,
+    ) {
+        let mut github = MockGithubClient::default();
+        github.expect_branch_protection().returning(|_| {
+            Box::pin(future::ready(Ok(BranchProtection {
+                required_linear_history: BranchProtectionSetting { enabled: true },
+                ..Default::default()
+            })))
+        });
+        let merger = DefaultPullRequestMerger::new(MergeConfig {
+            default_method,
+            ..Default::default()
+        });
+        let methods = merger
+            .allowed_merge_methods(&PullRequest::default(), &github)
+            .await
+            .unwrap();
+        assert_eq!(methods, expected);
+    }
+
+    #[test]
+    fn test_summarize_comparison() {
+        let comparison = CommitComparison {
+            ahead_by: 2,
+            behind_by: 0,
+            files: vec![
+                ChangedFile {
+                    filename: "src/potato.rs".into(),
+                    additions: 10,
+                    deletions: 2,
+                },
+                ChangedFile {
+                    filename: "README.md".into(),
+                    additions: 1,
+                    deletions: 0,
+                },
+            ],
+        };
+        assert_eq!(
+            summarize_comparison(&comparison),
+            "2 commits, 2 files changed, +11 -2\n  src/potato.rs +10 -2\n  README.md +1 -0"
+        );
+    }
+
+    #[rstest]
+    #[case::same_repo("potato/smasher", true)]
+    #[case::fork("alice/smasher", false)]
+    #[tokio::test]
+    async fn test_delete_branch_on_merge(#[case] head_repo: &str, #[case] deleted: bool) {
+        let repo = |full_name: &str| Repository {
+            full_name: full_name.into(),
+            ..Default::default()
+        };
+        let pull_request = PullRequest {
+            head: Branch {
+                name: "add-potatoes".into(),
+                repo: repo(head_repo),
+                ..Default::default()
+            },
+            base: Branch {
+                name: "main".into(),
+                repo: repo("potato/smasher"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        github.expect_branch_protection().returning(|_| {
+            Box::pin(future::ready(Err(crate::client::Error::Http(
+                reqwest::StatusCode::NOT_FOUND,
+            ))))
+        });
+        github
+            .expect_merge_pull_request()
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        github
+            .expect_delete_branch()
+            .withf(|branch| branch.name == "add-potatoes")
+            .times(deleted as usize)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+        let merger = DefaultPullRequestMerger::new(MergeConfig {
+            delete_branch_on_merge: true,
+            ..Default::default()
+        });
+        let result = merger.merge(&pull_request, &github).await.unwrap();
+        assert!(matches!(result, MergeResult::Success(MergeMethod::Merge)));
+    }
 }
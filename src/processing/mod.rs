@@ -1,10 +1,12 @@
 pub mod director;
 pub mod error;
 pub mod merge;
+pub mod poll;
 pub mod runner;
+pub mod status;
 pub mod steps;
 
-pub use director::{Director, DirectorState};
+pub use director::{Director, DirectorOptions, DirectorState, PullRequestProcessor, StepOverride};
 pub use error::Error;
 pub use merge::{DefaultPullRequestMerger, DummyPullRequestMerger, PullRequestMerger};
 pub use runner::{WorkflowRunner, WorkflowStatus};
@@ -0,0 +1,89 @@
+use crate::common::RepoMap;
+use crate::config::PollConfig;
+use backoff::{ExponentialBackoff, ExponentialBackoffBuilder};
+use std::time::Duration;
+
+/// How much each delay is randomized so multiple instances don't end up polling in lockstep
+const POLL_JITTER: f64 = 0.2;
+
+/// Builds the backoff used to compute the delay between consecutive polls on a pull request
+pub fn build_poll_backoff(config: &PollConfig) -> ExponentialBackoff {
+    let initial_interval = Duration::from_secs(config.delay_seconds as u64);
+    let max_interval = Duration::from_secs(config.max_delay_seconds as u64).max(initial_interval);
+    ExponentialBackoffBuilder::new()
+        .with_initial_interval(initial_interval)
+        .with_randomization_factor(POLL_JITTER)
+        .with_multiplier(config.backoff_factor.max(1.0))
+        .with_max_interval(max_interval)
+        .with_max_elapsed_time(None)
+        .build()
+}
+
+/// Returns the poll config for a repo, using its own delay if one is configured
+pub fn poll_config_for_repo(
+    config: &PollConfig,
+    owner: &str,
+    repo: &str,
+) -> Result<PollConfig, Box<dyn std::error::Error>> {
+    let mut delays = RepoMap::new(config.delay_seconds);
+    for repo_config in &config.repos {
+        delays.insert(repo_config.repo.parse()?, repo_config.delay_seconds)?;
+    }
+    Ok(PollConfig {
+        delay_seconds: *delays.get(owner, repo),
+        ..config.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RepoPollConfig;
+    use backoff::backoff::Backoff;
+
+    #[test]
+    fn test_poll_backoff() {
+        let config = PollConfig {
+            delay_seconds: 10,
+            max_delay_seconds: 40,
+            backoff_factor: 2.0,
+            rate_limit_threshold: 0,
+            step_timeout_seconds: 30,
+            repos: Vec::new(),
+            ..Default::default()
+        };
+        let mut backoff = build_poll_backoff(&config);
+        for expected in [10.0, 20.0, 40.0, 40.0] {
+            let delay = backoff.next_backoff().unwrap().as_secs_f64();
+            assert!(delay >= expected * 0.8 && delay <= expected * 1.2);
+        }
+        backoff.reset();
+        let delay = backoff.next_backoff().unwrap().as_secs_f64();
+        assert!((8.0..=12.0).contains(&delay));
+    }
+
+    #[test]
+    fn test_poll_config_for_repo() {
+        let config = PollConfig {
+            repos: vec![
+                RepoPollConfig {
+                    repo: "potato/smasher".into(),
+                    delay_seconds: 5,
+                },
+                RepoPollConfig {
+                    repo: "potato/*".into(),
+                    delay_seconds: 120,
+                },
+            ],
+            ..Default::default()
+        };
+        let delay = |owner, repo| {
+            poll_config_for_repo(&config, owner, repo)
+                .unwrap()
+                .delay_seconds
+        };
+        assert_eq!(delay("potato", "smasher"), 5);
+        assert_eq!(delay("potato", "masher"), 120);
+        assert_eq!(delay("tomato", "smasher"), 30);
+    }
+}
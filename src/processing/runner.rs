@@ -1,14 +1,39 @@
 use super::Error;
+use crate::github::PullRequest;
 use async_trait::async_trait;
 use reqwest::Url;
+use std::time::Duration;
 
 #[async_trait]
 pub trait WorkflowRunner: Send + Sync {
-    async fn process_failed_jobs(&self, job_urls: &[Url]) -> Result<WorkflowStatus, Error>;
+    async fn process_failed_jobs(
+        &self,
+        pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> Result<WorkflowStatus, Error>;
+
+    /// Estimates how long until the given pending jobs finish. Runners that don't know, or don't
+    /// own any of the jobs, return `None`.
+    async fn estimated_completion(
+        &self,
+        _pull_request: &PullRequest,
+        _job_urls: &[Url],
+    ) -> Option<Duration> {
+        None
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum WorkflowStatus {
+    /// The given failed jobs are acceptable and shouldn't block the pull request
+    Accepted(Vec<Url>),
+
+    /// None of the jobs belong to this runner or there's nothing left to do
     Success,
+
+    /// Failed jobs were re-triggered
     Triggered,
+
+    /// Some of the jobs are still running so they can't be re-triggered yet
+    Pending,
 }
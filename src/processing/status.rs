@@ -0,0 +1,223 @@
+use serde_derive::Serialize;
+use std::fmt;
+
+/// Hidden marker used to find the comment mergebro posts on pull requests
+pub const STATUS_COMMENT_MARKER: &str = "<!-- mergebro-status -->";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "state", content = "reason", rename_all = "snake_case")]
+pub enum StepOutcome {
+    Passed,
+    Waiting,
+    Failed(String),
+    NotChecked,
+
+    /// Skipped because the pull request has the override label
+    Overridden,
+}
+
+impl fmt::Display for StepOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "✅ passed"),
+            Self::Waiting => write!(f, "⏳ waiting"),
+            Self::Failed(reason) => write!(f, "❌ failed: {}", reason),
+            Self::NotChecked => write!(f, "⏸️ not checked yet"),
+            Self::Overridden => write!(f, "⏭️ overridden"),
+        }
+    }
+}
+
+/// The names of the CI checks on a pull request, grouped by their state
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct CheckStates {
+    pub passed: Vec<String>,
+    pub failed: Vec<String>,
+    pub pending: Vec<String>,
+}
+
+impl CheckStates {
+    fn groups(&self) -> [(&'static str, &[String]); 3] {
+        [
+            ("passed", &self.passed),
+            ("failed", &self.failed),
+            ("in progress", &self.pending),
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups().iter().all(|(_, names)| names.is_empty())
+    }
+
+    /// Sorts the names in each group so they're always displayed in the same order
+    pub fn sort(&mut self) {
+        self.passed.sort();
+        self.failed.sort();
+        self.pending.sort();
+    }
+}
+
+impl fmt::Display for CheckStates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups: Vec<_> = self
+            .groups()
+            .iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(state, names)| format!("{}: {}", state, names.join(", ")))
+            .collect();
+        write!(f, "{}", groups.join("; "))
+    }
+}
+
+/// The users whose approvals were counted for a pull request
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Approvals {
+    /// Users whose latest review approves the pull request
+    pub reviewers: Vec<String>,
+
+    /// Users that approved by commenting, who didn't also approve via a review
+    pub commenters: Vec<String>,
+
+    /// The required approvers that approved
+    pub required_approvers: Vec<String>,
+
+    /// The members of each required team whose approvals counted towards it
+    pub teams: Vec<TeamApprovers>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TeamApprovers {
+    pub team: String,
+    pub approvers: Vec<String>,
+}
+
+impl fmt::Display for Approvals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut groups = vec![match self.reviewers.is_empty() {
+            true => "no review approvals".to_string(),
+            false => format!("approved by {}", self.reviewers.join(", ")),
+        }];
+        if !self.commenters.is_empty() {
+            groups.push(format!(
+                "approved by comment: {}",
+                self.commenters.join(", ")
+            ));
+        }
+        if !self.required_approvers.is_empty() {
+            groups.push(format!(
+                "required approvers: {}",
+                self.required_approvers.join(", ")
+            ));
+        }
+        for team in &self.teams {
+            groups.push(format!("team {}: {}", team.team, team.approvers.join(", ")));
+        }
+        write!(f, "{}", groups.join("; "))
+    }
+}
+
+/// The outcome of running a single step on a pull request
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StepReport {
+    pub name: String,
+    pub outcome: StepOutcome,
+
+    /// The state of each CI check, for steps that look at them
+    pub checks: Option<CheckStates>,
+
+    /// The approvals that were counted, for steps that look at them
+    pub approvals: Option<Approvals>,
+}
+
+/// Renders the body of the status comment for the given step reports
+pub fn render_status_comment(reports: &[StepReport]) -> String {
+    let mut body = format!(
+        "{}\n**mergebro status**\n\n| Step | Status |\n| --- | --- |\n",
+        STATUS_COMMENT_MARKER
+    );
+    for report in reports {
+        let mut outcome = report.outcome.to_string();
+        if let Some(checks) = report.checks.as_ref().filter(|checks| !checks.is_empty()) {
+            outcome.push_str(&format!("<br>{}", checks));
+        }
+        if let Some(approvals) = &report.approvals {
+            outcome.push_str(&format!("<br>{}", approvals));
+        }
+        // Pipes would break the table
+        let outcome = outcome.replace('|', "\\|");
+        body.push_str(&format!("| {} | {} |\n", report.name, outcome));
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_status_comment() {
+        let reports = vec![
+            StepReport {
+                name: "check current state".into(),
+                outcome: StepOutcome::Passed,
+                checks: None,
+                approvals: None,
+            },
+            StepReport {
+                name: "check reviews".into(),
+                outcome: StepOutcome::Failed("need 2 | have 1".into()),
+                checks: None,
+                approvals: Some(Approvals {
+                    reviewers: vec!["alice".into()],
+                    commenters: vec!["bob".into()],
+                    ..Default::default()
+                }),
+            },
+            StepReport {
+                name: "check if CI builds failed".into(),
+                outcome: StepOutcome::Waiting,
+                checks: Some(CheckStates {
+                    passed: vec!["lint".into(), "docs".into()],
+                    failed: Vec::new(),
+                    pending: vec!["tests".into()],
+                }),
+                approvals: None,
+            },
+            StepReport {
+                name: "check head unchanged".into(),
+                outcome: StepOutcome::NotChecked,
+                checks: None,
+                approvals: None,
+            },
+        ];
+        let expected = "<!-- mergebro-status -->
+**mergebro status**
+
+| Step | Status |
+| --- | --- |
+| check current state | ✅ passed |
+| check reviews | ❌ failed: need 2 \\| have 1<br>approved by alice; approved by comment: bob |
+| check if CI builds failed | ⏳ waiting<br>passed: lint, docs; in progress: tests |
+| check head unchanged | ⏸️ not checked yet |
+";
+        assert_eq!(render_status_comment(&reports), expected);
+    }
+
+    #[test]
+    fn test_serialize_step_report() {
+        let report = StepReport {
+            name: "check reviews".into(),
+            outcome: StepOutcome::Failed("not enough approvals".into()),
+            checks: None,
+            approvals: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"name":"check reviews","outcome":{"state":"failed","reason":"not enough approvals"},"checks":null,"approvals":null}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&StepOutcome::NotChecked).unwrap(),
+            r#"{"state":"not_checked"}"#
+        );
+    }
+}
@@ -1,32 +1,79 @@
-use super::{Error, WorkflowRunner, WorkflowStatus};
+use super::{
+    status::{Approvals, CheckStates, TeamApprovers},
+    Error, WorkflowRunner, WorkflowStatus,
+};
 use crate::{
-    config::{ReviewsConfig, StatusFailuresConfig},
+    config::{
+        load_freeze_periods, team_org_and_slug, CommentApprovalConfig, ExternalCheck, FreezePeriod,
+        MergeWindowsConfig, ReviewsConfig, StatusFailuresConfig, TeamApproval,
+    },
     github::{
-        Branch, BranchProtection, GithubClient, MergeableState, PullRequest, PullRequestReview,
-        PullRequestState, ReviewState, StatusState, WorkflowRun, WorkflowRunConclusion,
+        Branch, BranchProtection, CodeOwner, CodeOwners, GithubClient, MergeableState, PullRequest,
+        PullRequestIdentifier, PullRequestReview, PullRequestState, ReviewState, StatusState,
+        WorkflowRun, WorkflowRunConclusion,
     },
 };
 use async_trait::async_trait;
-use log::{info, warn};
+use chrono::{DateTime, Datelike, Local, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::{debug, info, warn};
+use regex::Regex;
 use reqwest::Url;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
+use std::process::Stdio;
 use std::sync::Arc;
+use tokio::process::Command;
 
 #[async_trait]
 pub trait Step: fmt::Display {
-    /// Execute this step against the current state of this pull request
+    /// Execute this step against the current state of this pull request. The pull request is
+    /// fetched once per run and shared by every step, so steps shouldn't fetch it again unless
+    /// they need to see changes made during the run, like `CheckHeadUnchanged` does.
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error>;
+
+    /// Whether this step never modifies the pull request or triggers anything, meaning it can
+    /// run concurrently with other read only steps
+    fn is_readonly(&self) -> bool {
+        false
+    }
+
+    /// How long this step can run before the director gives up on it until the next run, if it
+    /// needs longer than the director's step timeout
+    fn timeout(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// The state of each CI check as of the last execution, for steps that look at them
+    fn check_states(&self) -> Option<CheckStates> {
+        None
+    }
+
+    /// The approvals counted as of the last execution, for steps that look at them
+    fn approvals(&self) -> Option<Approvals> {
+        None
+    }
+
+    /// Whether the override label can skip this step. Steps that keep merging safe never are.
+    fn overridable(&self) -> bool {
+        false
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Hash)]
 pub enum StepStatus {
     Passed,
     Waiting,
+
+    /// The pull request was merged already, so there's nothing left to do
+    AlreadyMerged,
+
+    /// The pull request was closed without being merged
+    Closed,
 }
 
-/// Checks whether a pull request is open and in a mergeable state.
+/// Checks whether a pull request is open.
 #[derive(Default)]
 pub struct CheckCurrentStateStep;
 
@@ -34,24 +81,18 @@ pub struct CheckCurrentStateStep;
 impl Step for CheckCurrentStateStep {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
         match pull_request.state {
-            PullRequestState::Open => {
-                if pull_request.draft {
-                    Err(Error::as_generic("pull request is a draft"))
-                } else if matches!(pull_request.mergeable_state, MergeableState::Dirty) {
-                    Err(Error::as_generic("pull request has conflicts"))
-                } else {
-                    Ok(StepStatus::Passed)
-                }
-            }
-            PullRequestState::Closed if pull_request.merged => {
-                Err(Error::as_generic("pull request is already merged"))
-            }
-            PullRequestState::Closed => Err(Error::as_generic("pull request is closed")),
+            PullRequestState::Open => Ok(StepStatus::Passed),
+            PullRequestState::Closed if pull_request.merged => Ok(StepStatus::AlreadyMerged),
+            PullRequestState::Closed => Ok(StepStatus::Closed),
             PullRequestState::Unknown => Err(Error::UnsupportedPullRequestState(
                 "pull request state is unknown".into(),
             )),
         }
     }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
 }
 
 impl fmt::Display for CheckCurrentStateStep {
@@ -60,410 +101,3548 @@ impl fmt::Display for CheckCurrentStateStep {
     }
 }
 
-/// Checks whether a pull request is approved by however many people its branch protection
-/// rules require
-pub struct CheckReviewsStep {
-    github: Arc<dyn GithubClient>,
-    reviews: ReviewsConfig,
-}
-
-impl CheckReviewsStep {
-    pub fn new(
-        github: Arc<dyn GithubClient>,
-        reviews: ReviewsConfig,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self { github, reviews })
-    }
+/// Waits while a pull request is a draft, as someone may mark it as ready for review at any time
+pub struct CheckNotDraft;
 
-    async fn fetch_branch_protection(
-        &self,
-        branch: &Branch,
-    ) -> Result<Option<BranchProtection>, Error> {
-        let branch_protection = self.github.branch_protection(branch).await;
-        match branch_protection {
-            Ok(branch_protection) => Ok(Some(branch_protection)),
-            Err(e) if e.not_found() => Ok(None),
-            Err(e) => Err(e.into()),
+#[async_trait]
+impl Step for CheckNotDraft {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if pull_request.draft {
+            info!("PR is still a draft, waiting");
+            return Ok(StepStatus::Waiting);
         }
+        Ok(StepStatus::Passed)
     }
 
-    fn compute_approvals(reviews: &[PullRequestReview]) -> usize {
-        let mut users_approved = HashSet::new();
-        for review in reviews {
-            match review.state {
-                ReviewState::Approved => users_approved.insert(&review.user.login),
-                ReviewState::ChangesRequested | ReviewState::Dismissed => {
-                    users_approved.remove(&review.user.login)
-                }
-                _ => continue,
-            };
-        }
-        users_approved.len()
+    fn is_readonly(&self) -> bool {
+        true
     }
+}
 
-    fn required_approvals(&self, branch_protection: Option<BranchProtection>) -> u32 {
-        let configured_approvals = self.reviews.approvals;
-        match branch_protection {
-            Some(protection) => protection.reviews.approvals.max(configured_approvals),
-            None => configured_approvals,
-        }
+impl fmt::Display for CheckNotDraft {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check not draft")
     }
 }
 
+/// Checks github's own view on whether a pull request can be merged, waiting while it's still
+/// being computed. Blocked pull requests are left to the steps that can tell why and act on it.
+pub struct CheckMergeable;
+
 #[async_trait]
-impl Step for CheckReviewsStep {
+impl Step for CheckMergeable {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
-        let branch_protection = self.fetch_branch_protection(&pull_request.base).await?;
-        let approvals_needed = self.required_approvals(branch_protection) as usize;
-        let reviews = self.github.pull_request_reviews(pull_request).await?;
-        let total_users_approved = Self::compute_approvals(&reviews);
-
-        if total_users_approved < approvals_needed {
-            let reason = format!(
-                "not enough approvals (need {}, have {})",
-                approvals_needed, total_users_approved
-            );
-            Err(Error::as_generic(reason))
-        } else {
-            Ok(StepStatus::Passed)
+        match (&pull_request.mergeable, &pull_request.mergeable_state) {
+            (_, MergeableState::Dirty) | (Some(false), _) => Err(Error::Conflicts),
+            // The pull request was just marked as ready for review and github hasn't caught up yet
+            (_, MergeableState::Draft) => Ok(StepStatus::Waiting),
+            (None, _) | (_, MergeableState::Unknown) => {
+                info!("Github is still computing whether the pull request is mergeable");
+                Ok(StepStatus::Waiting)
+            }
+            _ => Ok(StepStatus::Passed),
         }
     }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
 }
 
-impl fmt::Display for CheckReviewsStep {
+impl fmt::Display for CheckMergeable {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "check reviews")
+        write!(f, "check mergeable")
     }
 }
 
-/// Checks whether a pull request is behind master, and updates it otherwise
-pub struct CheckBehindMaster {
-    github: Arc<dyn GithubClient>,
+/// Fails if the pull request has any of a set of labels that mark it as not ready to be merged
+pub struct CheckBlockingLabels {
+    blocking_labels: Vec<String>,
 }
 
-impl CheckBehindMaster {
-    pub fn new(github: Arc<dyn GithubClient>) -> Self {
-        Self { github }
+impl CheckBlockingLabels {
+    pub fn new(blocking_labels: Vec<String>) -> Self {
+        Self { blocking_labels }
     }
 }
 
 #[async_trait]
-impl Step for CheckBehindMaster {
+impl Step for CheckBlockingLabels {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
-        if !matches!(pull_request.mergeable_state, MergeableState::Behind) {
-            return Ok(StepStatus::Passed);
+        let blocking_label = pull_request.labels.iter().find(|label| {
+            self.blocking_labels
+                .iter()
+                .any(|blocking| label.matches(blocking))
+        });
+        match blocking_label {
+            Some(label) => {
+                warn!("Pull request has blocking label '{}'", label.name);
+                Err(Error::as_generic(format!(
+                    "pull request has blocking label '{}'",
+                    label.name
+                )))
+            }
+            None => Ok(StepStatus::Passed),
         }
-        warn!("Pull request branch is behind master, updating it");
+    }
 
-        let result = self.github.update_branch(pull_request).await;
-        match result {
-            Ok(_) => Ok(StepStatus::Waiting),
-            // Technically we should retry but this means the head sha has _just_ changed so
-            // odds are someone just did it manually which means we're waiting either way
-            Err(e) if e.unprocessable_entity() => Ok(StepStatus::Waiting),
-            Err(e) => Err(e.into()),
-        }
+    fn is_readonly(&self) -> bool {
+        true
     }
 }
 
-impl fmt::Display for CheckBehindMaster {
+impl fmt::Display for CheckBlockingLabels {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "check if behind master")
+        write!(f, "check blocking labels")
     }
 }
 
-/// Checks whether the build for a pull request failed, re-triggering CI runs if needed
-pub struct CheckBuildFailed {
-    github: Arc<dyn GithubClient>,
-    workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
-    last_head_hash: Option<String>,
-    status_failures_config: HashMap<String, StatusFailuresConfig>,
-    status_failures: HashMap<String, u32>,
+/// Waits until the pull request has all of a set of labels that mark it as ready to be merged
+pub struct CheckRequiredLabels {
+    required_labels: Vec<String>,
 }
 
-impl CheckBuildFailed {
-    pub fn new(
-        github: Arc<dyn GithubClient>,
-        workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
-        status_failures_config: HashMap<String, StatusFailuresConfig>,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
-            github,
-            workflow_runners,
-            last_head_hash: None,
-            status_failures_config,
-            status_failures: HashMap::default(),
-        })
+impl CheckRequiredLabels {
+    pub fn new(required_labels: Vec<String>) -> Self {
+        Self { required_labels }
     }
+}
 
-    async fn check_actions(&self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
-        let split_runs = self.fetch_action_runs(pull_request).await?;
-        match split_runs.pending.len() {
-            0 => {
-                if split_runs.failed.is_empty() {
-                    return Ok(StepStatus::Passed);
-                }
-                self.process_failed_actions(pull_request, &split_runs.failed)
-                    .await?;
-            }
-            1 => info!(
-                "Waiting for '{}' action workflow to finish",
-                split_runs.pending[0].name,
-            ),
-            count => info!("Waiting for {} actions workflow to finish", count),
+#[async_trait]
+impl Step for CheckRequiredLabels {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let missing_labels: Vec<_> = self
+            .required_labels
+            .iter()
+            .filter(|required| {
+                !pull_request
+                    .labels
+                    .iter()
+                    .any(|label| label.matches(required))
+            })
+            .map(String::as_str)
+            .collect();
+        if missing_labels.is_empty() {
+            return Ok(StepStatus::Passed);
         }
+        info!(
+            "Pull request is missing required labels: {}",
+            missing_labels.join(", ")
+        );
         Ok(StepStatus::Waiting)
     }
 
-    async fn check_statuses(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
-        let summaries = self.fetch_status_summaries(pull_request).await?;
-        match summaries.pending.len() {
-            0 => {
-                if summaries.failed.is_empty() {
-                    return Ok(StepStatus::Passed);
-                }
-                self.process_failed_statuses(summaries.failed).await?;
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckRequiredLabels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check required labels")
+    }
+}
+
+/// Waits until the pull request is attached to a milestone
+pub struct CheckMilestoneAssigned;
+
+#[async_trait]
+impl Step for CheckMilestoneAssigned {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        match &pull_request.milestone {
+            Some(milestone) => {
+                debug!("Pull request is part of milestone '{}'", milestone.title);
+                Ok(StepStatus::Passed)
             }
-            1 => {
+            None => {
                 info!(
-                    "Waiting for external job '{}' to finish",
-                    summaries.pending[0].name
+                    "Pull request has no milestone, {} needs to assign one before it can be merged",
+                    pull_request.creator.login
                 );
-            }
-            count => {
-                info!("Waiting for {} external jobs to finish", count);
+                Ok(StepStatus::Waiting)
             }
         }
-        Ok(StepStatus::Waiting)
     }
 
-    async fn process_failed_statuses(&mut self, statuses: Vec<StatusSummary>) -> Result<(), Error> {
-        warn!("Processing {} failed external jobs", statuses.len());
-        self.check_max_failures(&statuses)?;
-        let failed_job_urls: Vec<_> = statuses.into_iter().map(|summary| summary.url).collect();
-        let mut total_triggered = 0;
-        for runner in &self.workflow_runners {
-            if runner.process_failed_jobs(&failed_job_urls).await? == WorkflowStatus::Triggered {
-                total_triggered += 1;
-            }
-        }
-        if total_triggered == 0 {
-            // There's failed jobs but we don't know how to re-trigger them. e.g. we don't support
-            // whatever service they're being ran on.
-            return Err(Error::as_generic(
-                "failed jobs belong to unknown external services",
-            ));
-        }
-        Ok(())
+    fn is_readonly(&self) -> bool {
+        true
     }
 
-    fn check_max_failures(&mut self, failed_statuses: &[StatusSummary]) -> Result<(), Error> {
-        for status in failed_statuses {
-            if let Some(config) = self.status_failures_config.get(&status.name) {
-                let failures = self.status_failures.entry(status.name.clone()).or_insert(0);
-                *failures += 1;
-                if *failures >= config.max_failures {
-                    return Err(Error::as_generic(format!(
-                        "status check '{}' reached {} failures",
-                        status.name, failures
-                    )));
-                }
-            }
-        }
-        Ok(())
+    fn overridable(&self) -> bool {
+        true
     }
+}
 
-    async fn fetch_status_summaries(
-        &self,
-        pull_request: &PullRequest,
-    ) -> Result<StatusSummaries, Error> {
-        let statuses = self.github.pull_request_statuses(pull_request).await?;
-        let mut last_run_per_status = HashMap::new();
-        // Note: there's 0 docs on this so it's unclear but it seems `context` is the thing to group by.
-        for status in statuses {
-            last_run_per_status
-                .entry(status.context.clone())
-                .or_insert(status);
+impl fmt::Display for CheckMilestoneAssigned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check milestone assigned")
+    }
+}
+
+/// Matches the keywords github uses to close issues, like `Closes #42` or `fixes potato/smasher#42`
+const LINKED_ISSUE_PATTERN: &str =
+    r"(?i)\b(?:close[sd]?|fix(?:e[sd])?|resolve[sd]?):?\s+([\w.-]+/[\w.-]+)?#(\d+)\b";
+
+/// An issue a pull request closes when it's merged
+#[derive(Debug, Clone, PartialEq)]
+struct IssueReference {
+    /// The repo the issue belongs to as `owner/repo`, unset when it's the pull request's own repo
+    repo: Option<String>,
+
+    number: u32,
+}
+
+impl fmt::Display for IssueReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}#{}",
+            self.repo.as_deref().unwrap_or_default(),
+            self.number
+        )
+    }
+}
+
+/// Holds back pull requests until their description references an issue they close. When
+/// `verify` is set, the issues referenced also need to exist and be open.
+pub struct CheckLinkedIssue {
+    github: Arc<dyn GithubClient>,
+    verify: bool,
+    pattern: Regex,
+}
+
+impl CheckLinkedIssue {
+    pub fn new(github: Arc<dyn GithubClient>, verify: bool) -> Self {
+        Self {
+            github,
+            verify,
+            pattern: Regex::new(LINKED_ISSUE_PATTERN).expect("invalid linked issue pattern"),
         }
-        let mut failed = Vec::new();
-        let mut pending = Vec::new();
-        for (_, status) in last_run_per_status {
-            let url = Self::parse_status_url(&status.target_url)?;
-            let summary = StatusSummary {
-                url,
-                name: status.context,
+    }
+
+    fn linked_issues(&self, body: &str) -> Vec<IssueReference> {
+        let mut references = Vec::new();
+        for captures in self.pattern.captures_iter(body) {
+            let number = match captures[2].parse() {
+                Ok(number) => number,
+                Err(_) => continue,
             };
-            match status.state {
-                StatusState::Failure => failed.push(summary),
-                StatusState::Pending => pending.push(summary),
-                _ => (),
+            let reference = IssueReference {
+                repo: captures.get(1).map(|repo| repo.as_str().to_string()),
+                number,
             };
+            if !references.contains(&reference) {
+                references.push(reference);
+            }
         }
-        Ok(StatusSummaries { pending, failed })
+        references
     }
 
-    async fn fetch_action_runs(
+    /// Logs why an issue referenced doesn't count and returns whether it does
+    async fn is_open(
         &self,
         pull_request: &PullRequest,
-    ) -> Result<SplitActionRuns, Error> {
-        let action_runs = self.github.action_runs(pull_request).await?;
-        let mut last_run_per_workflow = HashMap::new();
-        for run in action_runs.workflow_runs {
-            if run.head_sha != pull_request.head.sha {
-                continue;
+        reference: &IssueReference,
+    ) -> Result<bool, Error> {
+        let repo = reference
+            .repo
+            .as_deref()
+            .unwrap_or(&pull_request.base.repo.full_name);
+        match self.github.issue(repo, reference.number).await {
+            Ok(issue) if issue.state == PullRequestState::Open => Ok(true),
+            Ok(_) => {
+                info!("Linked issue {} is already closed", reference);
+                Ok(false)
             }
-            last_run_per_workflow.entry(run.workflow_id).or_insert(run);
-        }
-        let mut pending = Vec::new();
-        let mut failed = Vec::new();
-        for run in last_run_per_workflow.into_values() {
-            match run.conclusion {
-                None => pending.push(run),
-                Some(WorkflowRunConclusion::Failure) => failed.push(run),
-                _ => (),
+            Err(e) if e.not_found() => {
+                info!("Linked issue {} doesn't exist", reference);
+                Ok(false)
             }
+            Err(e) => Err(e.into()),
         }
-        Ok(SplitActionRuns { pending, failed })
     }
+}
 
-    async fn process_failed_actions(
-        &self,
-        pull_request: &PullRequest,
-        actions: &[WorkflowRun],
-    ) -> Result<(), Error> {
-        for run in actions {
-            warn!("Actions workflow '{}' failed, re-running it", run.name);
-            self.github
-                .rerun_workflow(&pull_request.base.repo, run.id)
-                .await?;
+#[async_trait]
+impl Step for CheckLinkedIssue {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let references = self.linked_issues(pull_request.body.as_deref().unwrap_or_default());
+        if references.is_empty() {
+            info!(
+                "Pull request doesn't reference an issue it closes, {} needs to add one like 'Closes #42' to its description",
+                pull_request.creator.login
+            );
+            return Ok(StepStatus::Waiting);
         }
-        Ok(())
+        let references_list: Vec<_> = references.iter().map(ToString::to_string).collect();
+        info!("Pull request closes {}", references_list.join(", "));
+        if self.verify {
+            for reference in &references {
+                if !self.is_open(pull_request, reference).await? {
+                    return Ok(StepStatus::Waiting);
+                }
+            }
+        }
+        Ok(StepStatus::Passed)
     }
 
-    fn parse_status_url(url: &str) -> Result<Url, Error> {
-        let url = Url::parse(url)
-            .map_err(|_| Error::as_generic(format!("invalid status target URL: {}", url)))?;
-        Ok(url)
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
     }
 }
 
-struct StatusSummary {
-    url: Url,
-    name: String,
+impl fmt::Display for CheckLinkedIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check linked issue")
+    }
 }
 
-struct StatusSummaries {
-    pending: Vec<StatusSummary>,
-    failed: Vec<StatusSummary>,
+/// Holds back pull requests until they've been open for a while, giving people a chance to object
+/// to them before they're merged
+pub struct CheckMinimumAge {
+    min_age: std::time::Duration,
 }
 
-struct SplitActionRuns {
-    pending: Vec<WorkflowRun>,
-    failed: Vec<WorkflowRun>,
+impl CheckMinimumAge {
+    pub fn new(min_age: std::time::Duration) -> Self {
+        Self { min_age }
+    }
 }
 
 #[async_trait]
-impl Step for CheckBuildFailed {
+impl Step for CheckMinimumAge {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
-        if !matches!(pull_request.mergeable_state, MergeableState::Blocked) {
+        let created_at = match pull_request.created_at {
+            Some(created_at) => created_at,
+            None => return Ok(StepStatus::Passed),
+        };
+        let age = (Local::now() - created_at).to_std().unwrap_or_default();
+        if age >= self.min_age {
             return Ok(StepStatus::Passed);
         }
-        if self.last_head_hash.as_ref() != Some(&pull_request.head.sha) {
-            if self.last_head_hash.is_some() {
-                info!("Resetting failure counters as the head sha changed");
-                self.status_failures.clear();
-            }
-            self.last_head_hash = Some(pull_request.head.sha.clone());
+        info!(
+            "Pull request has been open for {}s, waiting {}s more before merging it",
+            age.as_secs(),
+            (self.min_age - age).as_secs()
+        );
+        Ok(StepStatus::Waiting)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckMinimumAge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check minimum age")
+    }
+}
+
+/// Holds back pull requests that change more files or lines than configured, so accidentally huge
+/// ones aren't merged without someone looking at them. Adding the override label lets them through.
+pub struct CheckPullRequestSize {
+    max_changed_files: Option<u32>,
+    max_total_lines: Option<u32>,
+    override_label: String,
+}
+
+impl CheckPullRequestSize {
+    pub fn new(
+        max_changed_files: Option<u32>,
+        max_total_lines: Option<u32>,
+        override_label: String,
+    ) -> Self {
+        Self {
+            max_changed_files,
+            max_total_lines,
+            override_label,
         }
-        let statuses_result = self.check_statuses(pull_request).await?;
-        let actions_result = self.check_actions(pull_request).await?;
-        if (statuses_result, actions_result) == (StepStatus::Passed, StepStatus::Passed)
-            && pull_request.mergeable_state == MergeableState::Blocked
+    }
+}
+
+#[async_trait]
+impl Step for CheckPullRequestSize {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let total_lines = pull_request.additions + pull_request.deletions;
+        let mut breached = Vec::new();
+        if let Some(max) = self
+            .max_changed_files
+            .filter(|max| pull_request.changed_files > *max)
         {
-            // This means we don't currently support/know whatever led this PR to be unstable
-            return Err(Error::as_generic(
-                "pull request is blocked for unknown reasons",
+            breached.push(format!(
+                "{} changed files (max {})",
+                pull_request.changed_files, max
             ));
-        } else {
-            Ok(StepStatus::Waiting)
+        }
+        if let Some(max) = self.max_total_lines.filter(|max| total_lines > *max) {
+            breached.push(format!("{} changed lines (max {})", total_lines, max));
+        }
+        if breached.is_empty() {
+            return Ok(StepStatus::Passed);
+        }
+        let overridden = pull_request
+            .labels
+            .iter()
+            .any(|label| label.matches(&self.override_label));
+        if overridden {
+            info!(
+                "Pull request is over the size limits with {} but has the '{}' label",
+                breached.join(" and "),
+                self.override_label
+            );
+            return Ok(StepStatus::Passed);
+        }
+        warn!(
+            "Pull request is over the size limits with {}, waiting for the '{}' label",
+            breached.join(" and "),
+            self.override_label
+        );
+        Ok(StepStatus::Waiting)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckPullRequestSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check pull request size")
+    }
+}
+
+/// Waits until the pull request's title matches a pattern, e.g. to enforce a commit message
+/// convention since it becomes the merge commit's title
+pub struct CheckTitlePattern {
+    pattern: Regex,
+}
+
+impl CheckTitlePattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Step for CheckTitlePattern {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if self.pattern.is_match(&pull_request.title) {
+            return Ok(StepStatus::Passed);
+        }
+        warn!(
+            "Pull request title '{}' doesn't match pattern '{}', waiting",
+            pull_request.title, self.pattern
+        );
+        Ok(StepStatus::Waiting)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckTitlePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check title pattern")
+    }
+}
+
+/// Waits while the pull request's title marks it as a work in progress, e.g. `WIP: add potatoes`,
+/// for teams that use that rather than draft pull requests
+pub struct CheckTitleNotWip {
+    pattern: Regex,
+}
+
+impl CheckTitleNotWip {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Step for CheckTitleNotWip {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if !self.pattern.is_match(&pull_request.title) {
+            return Ok(StepStatus::Passed);
+        }
+        info!("PR title still marked WIP, waiting");
+        Ok(StepStatus::Waiting)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckTitleNotWip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check title not WIP")
+    }
+}
+
+/// Fails if the pull request's base branch doesn't match any of the allowed patterns, so pull
+/// requests targeting the wrong branch are never merged
+pub struct CheckBaseBranchAllowed {
+    allowed_branches: Vec<Regex>,
+}
+
+impl CheckBaseBranchAllowed {
+    pub fn new(allowed_branches: &[String]) -> Result<Self, regex::Error> {
+        let allowed_branches = allowed_branches
+            .iter()
+            .map(|pattern| build_glob_pattern(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { allowed_branches })
+    }
+}
+
+#[async_trait]
+impl Step for CheckBaseBranchAllowed {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let base = &pull_request.base.name;
+        if self.allowed_branches.is_empty()
+            || self
+                .allowed_branches
+                .iter()
+                .any(|pattern| pattern.is_match(base))
+        {
+            return Ok(StepStatus::Passed);
+        }
+        Err(Error::as_generic(format!(
+            "base branch '{}' is not one of the branches pull requests can be merged into",
+            base
+        )))
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckBaseBranchAllowed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check base branch allowed")
+    }
+}
+
+/// Fails if the pull request's head branch doesn't match any of the allowed patterns, enforcing
+/// naming conventions like `feature/*`
+pub struct CheckBranchNamingConvention {
+    patterns: Vec<String>,
+    allowed_branches: Vec<Regex>,
+}
+
+impl CheckBranchNamingConvention {
+    pub fn new(patterns: &[String]) -> Result<Self, regex::Error> {
+        let allowed_branches = patterns
+            .iter()
+            .map(|pattern| build_glob_pattern(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            patterns: patterns.to_vec(),
+            allowed_branches,
+        })
+    }
+}
+
+#[async_trait]
+impl Step for CheckBranchNamingConvention {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let head = &pull_request.head.name;
+        if self.allowed_branches.is_empty()
+            || self
+                .allowed_branches
+                .iter()
+                .any(|pattern| pattern.is_match(head))
+        {
+            return Ok(StepStatus::Passed);
+        }
+        Err(Error::as_generic(format!(
+            "branch '{}' doesn't match any of the allowed patterns: {}",
+            head,
+            self.patterns.join(", ")
+        )))
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckBranchNamingConvention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check branch naming convention")
+    }
+}
+
+/// Fails if the pull request's base branch is behind another branch, so pull requests stacked on
+/// top of others aren't merged into a branch that's stale itself
+pub struct CheckBaseCurrent {
+    github: Arc<dyn GithubClient>,
+    parent_branch: String,
+}
+
+impl CheckBaseCurrent {
+    pub fn new<S: Into<String>>(github: Arc<dyn GithubClient>, parent_branch: S) -> Self {
+        Self {
+            github,
+            parent_branch: parent_branch.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Step for CheckBaseCurrent {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let base = &pull_request.base.name;
+        if *base == self.parent_branch {
+            return Ok(StepStatus::Passed);
+        }
+        let comparison = self
+            .github
+            .compare_commits(&pull_request.base.repo, &self.parent_branch, base)
+            .await?;
+        if comparison.behind_by == 0 {
+            return Ok(StepStatus::Passed);
+        }
+        Err(Error::as_generic(format!(
+            "base branch '{}' is {} commits behind '{}', it needs to be updated before this pull request is merged into it",
+            base, comparison.behind_by, self.parent_branch
+        )))
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckBaseCurrent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check base current with {}", self.parent_branch)
+    }
+}
+
+/// Waits until the current time falls within one of the configured merge windows
+pub struct CheckMergeWindow {
+    config: MergeWindowsConfig,
+}
+
+impl CheckMergeWindow {
+    pub fn new(config: MergeWindowsConfig) -> Self {
+        Self { config }
+    }
+
+    fn is_open_at(&self, now: DateTime<Utc>) -> bool {
+        let now = now.with_timezone(&self.config.timezone);
+        let (weekday, time) = (now.weekday(), now.time());
+        self.config.windows.iter().any(|window| {
+            window.days.contains(&weekday) && window.start <= time && time < window.end
+        })
+    }
+
+    fn next_opening_after(&self, now: DateTime<Utc>) -> Option<DateTime<Tz>> {
+        let timezone = self.config.timezone;
+        let now = now.with_timezone(&timezone);
+        let today = now.date().naive_local();
+        // Every window opens at least once a week so looking a week ahead is enough
+        (0..=7)
+            .map(|offset| today + chrono::Duration::days(offset))
+            .flat_map(|date| {
+                self.config
+                    .windows
+                    .iter()
+                    .filter(move |window| window.days.contains(&date.weekday()))
+                    .filter_map(move |window| {
+                        timezone
+                            .from_local_datetime(&date.and_time(window.start))
+                            .earliest()
+                    })
+            })
+            .filter(|opening| opening > &now)
+            .min()
+    }
+}
+
+#[async_trait]
+impl Step for CheckMergeWindow {
+    async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let now = Utc::now();
+        if self.is_open_at(now) {
+            return Ok(StepStatus::Passed);
+        }
+        match self.next_opening_after(now) {
+            Some(opening) => info!("Outside of merge windows, next one opens at {}", opening),
+            None => info!("Outside of merge windows"),
+        };
+        Ok(StepStatus::Waiting)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckMergeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check merge window")
+    }
+}
+
+/// Waits while the current time falls within a freeze period, either configured or listed in a
+/// file that's read on every execution
+pub struct CheckNotFrozen {
+    periods: Vec<FreezePeriod>,
+    periods_file: Option<std::path::PathBuf>,
+}
+
+impl CheckNotFrozen {
+    pub fn new(periods: Vec<FreezePeriod>, periods_file: Option<std::path::PathBuf>) -> Self {
+        Self {
+            periods,
+            periods_file,
+        }
+    }
+
+    /// The freeze period in effect at the given time that ends the latest, if any
+    fn active_freeze(periods: &[FreezePeriod], now: DateTime<Utc>) -> Option<&FreezePeriod> {
+        periods
+            .iter()
+            .filter(|period| period.start <= now && now < period.end)
+            .max_by_key(|period| period.end)
+    }
+
+    /// Whether merges are currently frozen, which is also used by the directors that don't run
+    /// steps
+    pub fn frozen(&self) -> Result<bool, Error> {
+        let mut periods = self.periods.clone();
+        if let Some(path) = &self.periods_file {
+            let file_periods = load_freeze_periods(path).map_err(|e| {
+                Error::as_generic(format!(
+                    "failed to load freeze periods from '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            periods.extend(file_periods);
+        }
+        let freeze = match Self::active_freeze(&periods, Utc::now()) {
+            Some(freeze) => freeze,
+            None => return Ok(false),
+        };
+        match &freeze.reason {
+            Some(reason) => info!("Merges are frozen until {}: {}", freeze.end, reason),
+            None => info!("Merges are frozen until {}", freeze.end),
+        };
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl Step for CheckNotFrozen {
+    async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        match self.frozen()? {
+            true => Ok(StepStatus::Waiting),
+            false => Ok(StepStatus::Passed),
+        }
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckNotFrozen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check not frozen")
+    }
+}
+
+/// The paths github looks up CODEOWNERS files in, in order of precedence
+const CODEOWNERS_PATHS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Checks whether a pull request is approved by however many people its branch protection
+/// rules require and, optionally, by the code owners of every file it changes
+pub struct CheckReviewsStep {
+    github: Arc<dyn GithubClient>,
+    reviews: ReviewsConfig,
+    team_members: HashMap<(String, String), HashSet<String>>,
+    approvals: Option<Approvals>,
+    request_reviews: bool,
+    requested_reviewers: HashSet<String>,
+}
+
+impl CheckReviewsStep {
+    pub fn new(github: Arc<dyn GithubClient>, reviews: ReviewsConfig) -> Self {
+        Self {
+            github,
+            reviews,
+            team_members: HashMap::new(),
+            approvals: None,
+            request_reviews: false,
+            requested_reviewers: HashSet::new(),
+        }
+    }
+
+    /// Requests a fresh review from users whose approvals expired, once per user
+    pub fn with_review_requests(mut self, request_reviews: bool) -> Self {
+        self.request_reviews = request_reviews;
+        self
+    }
+
+    fn compute_approvals(reviews: &[PullRequestReview]) -> usize {
+        Self::approved_users(reviews).len()
+    }
+
+    pub(crate) fn approved_users(reviews: &[PullRequestReview]) -> HashSet<&str> {
+        let mut users_approved = HashSet::new();
+        for review in reviews {
+            match review.state {
+                ReviewState::Approved => users_approved.insert(review.user.login.as_str()),
+                ReviewState::ChangesRequested | ReviewState::Dismissed => {
+                    users_approved.remove(review.user.login.as_str())
+                }
+                _ => continue,
+            };
+        }
+        users_approved
+    }
+
+    /// Drops the reviews that don't count towards approving the pull request, which are the author's
+    /// own if configured and those by excluded reviewers
+    fn counted_reviews(
+        config: &ReviewsConfig,
+        pull_request: &PullRequest,
+        reviews: Vec<PullRequestReview>,
+    ) -> Vec<PullRequestReview> {
+        reviews
+            .into_iter()
+            .filter(|review| {
+                let login = review.user.login.as_str();
+                let reason = if config.exclude_author && login == pull_request.creator.login {
+                    "they're the pull request's author"
+                } else if config.excluded_reviewers.iter().any(|user| user == login) {
+                    "they're an excluded reviewer"
+                } else {
+                    return true;
+                };
+                if review.state == ReviewState::Approved {
+                    info!("Discarding approval from {} as {}", login, reason);
+                }
+                false
+            })
+            .collect()
+    }
+
+    /// Drops approvals submitted longer than the configured max age before `now`, returning the
+    /// remaining reviews and the users whose approvals were dropped, sorted
+    fn unexpired_reviews(
+        config: &ReviewsConfig,
+        reviews: Vec<PullRequestReview>,
+        now: DateTime<Local>,
+    ) -> (Vec<PullRequestReview>, Vec<String>) {
+        let max_age = match config.approval_max_age_seconds {
+            Some(max_age) => chrono::Duration::seconds(max_age as i64),
+            None => return (reviews, Vec::new()),
+        };
+        let (expired, reviews): (Vec<_>, Vec<_>) = reviews.into_iter().partition(|review| {
+            review.state == ReviewState::Approved && now - review.submitted_at > max_age
+        });
+        if !expired.is_empty() {
+            info!(
+                "Discarding {} approvals older than {}s",
+                expired.len(),
+                max_age.num_seconds()
+            );
+        }
+        let mut expired_approvers: Vec<_> = expired
+            .into_iter()
+            .map(|review| review.user.login)
+            .collect();
+        expired_approvers.sort_unstable();
+        expired_approvers.dedup();
+        (reviews, expired_approvers)
+    }
+
+    /// Asks users whose approvals expired to review the pull request again, unless they were
+    /// already asked to
+    async fn request_fresh_reviews(
+        &mut self,
+        pull_request: &PullRequest,
+        users: &[String],
+    ) -> Result<(), Error> {
+        let reviewers: Vec<_> = users
+            .iter()
+            .filter(|user| !self.requested_reviewers.contains(*user))
+            .cloned()
+            .collect();
+        if !self.request_reviews || reviewers.is_empty() {
+            return Ok(());
+        }
+        info!("Requesting fresh reviews from {}", reviewers.join(", "));
+        self.github
+            .request_reviewers(pull_request, &reviewers)
+            .await?;
+        self.requested_reviewers.extend(reviewers);
+        Ok(())
+    }
+
+    /// The users whose latest review requested changes, sorted by login
+    fn users_requesting_changes(reviews: &[PullRequestReview]) -> Vec<&str> {
+        let mut latest_states = HashMap::new();
+        for review in reviews {
+            // Comments don't change whether a reviewer approved or requested changes
+            if !matches!(review.state, ReviewState::Commented | ReviewState::Pending) {
+                latest_states.insert(review.user.login.as_str(), &review.state);
+            }
+        }
+        let mut users: Vec<_> = latest_states
+            .into_iter()
+            .filter(|(_, state)| matches!(state, ReviewState::ChangesRequested))
+            .map(|(user, _)| user)
+            .collect();
+        users.sort_unstable();
+        users
+    }
+
+    async fn check_resolved_threads(&self, pull_request: &PullRequest) -> Result<(), Error> {
+        let threads = self.github.review_threads(pull_request).await?;
+        let unresolved: Vec<_> = threads
+            .iter()
+            .filter(|thread| !thread.is_resolved)
+            .collect();
+        if unresolved.is_empty() {
+            return Ok(());
+        }
+        if let Some(url) = &unresolved[0].url {
+            info!("First unresolved review thread: {}", url);
+        }
+        Err(Error::as_generic(format!(
+            "{} review threads are unresolved",
+            unresolved.len()
+        )))
+    }
+
+    async fn fetch_codeowners(&self, base: &Branch) -> Result<Option<CodeOwners>, Error> {
+        for path in CODEOWNERS_PATHS {
+            match self
+                .github
+                .file_contents(&base.repo, path, &base.name)
+                .await
+            {
+                Ok(contents) => {
+                    let contents = contents
+                        .decode()
+                        .map_err(|e| Error::as_generic(format!("invalid CODEOWNERS: {}", e)))?;
+                    return Ok(Some(CodeOwners::parse(&contents)));
+                }
+                Err(e) if e.not_found() => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    async fn is_owner_approved(
+        &mut self,
+        owner: &CodeOwner,
+        approved_users: &HashSet<&str>,
+    ) -> Result<bool, Error> {
+        match owner {
+            CodeOwner::User(login) => Ok(approved_users.contains(login.as_str())),
+            CodeOwner::Team { org, slug } => {
+                let members = self.team_members(org, slug).await?;
+                Ok(approved_users.iter().any(|user| members.contains(*user)))
+            }
+        }
+    }
+
+    /// The logins of a team's members, which are only fetched once
+    async fn team_members(&mut self, org: &str, slug: &str) -> Result<&HashSet<String>, Error> {
+        let key = (org.to_string(), slug.to_string());
+        if !self.team_members.contains_key(&key) {
+            let members = self.github.team_members(org, slug).await?;
+            let members = members.into_iter().map(|user| user.login).collect();
+            self.team_members.insert(key.clone(), members);
+        }
+        Ok(&self.team_members[&key])
+    }
+
+    /// The authorized users whose latest comment containing the approval or retraction phrase
+    /// approves the pull request. Authors can't approve their own pull requests.
+    async fn comment_approvers(
+        &mut self,
+        pull_request: &PullRequest,
+        config: &CommentApprovalConfig,
+    ) -> Result<HashSet<String>, Error> {
+        let comments = self.github.list_comments(pull_request).await?;
+        let mut approvals = HashMap::new();
+        for comment in &comments {
+            if comment.user.login == pull_request.creator.login {
+                continue;
+            }
+            let body = comment.body.as_deref().unwrap_or_default();
+            for line in body.lines().map(str::trim) {
+                if line == config.phrase {
+                    approvals.insert(comment.user.login.as_str(), true);
+                } else if line == config.retract_phrase {
+                    approvals.insert(comment.user.login.as_str(), false);
+                }
+            }
+        }
+        let mut approvers = HashSet::new();
+        for team in &config.authorized_teams {
+            let (org, slug) = team_org_and_slug(team, &pull_request.base.repo.owner.login);
+            let members = self.team_members(org, slug).await?;
+            let approved = approvals
+                .iter()
+                .filter(|(user, approved)| **approved && members.contains(**user))
+                .map(|(user, _)| user.to_string());
+            approvers.extend(approved);
+        }
+        Ok(approvers)
+    }
+
+    /// Returns the members of each required team that approved the pull request, sorted by login
+    async fn team_approvers(
+        &mut self,
+        pull_request: &PullRequest,
+        reviews: &[PullRequestReview],
+    ) -> Result<Vec<(TeamApproval, Vec<String>)>, Error> {
+        let approved_users = Self::approved_users(reviews);
+        let mut team_approvers = Vec::new();
+        for team in self.reviews.required_teams.clone() {
+            let (org, slug) = team.org_and_slug(&pull_request.base.repo.owner.login);
+            let members = self.team_members(org, slug).await?;
+            let mut approvers: Vec<_> = approved_users
+                .iter()
+                .filter(|user| members.contains(**user))
+                .map(|user| user.to_string())
+                .collect();
+            approvers.sort_unstable();
+            team_approvers.push((team, approvers));
+        }
+        Ok(team_approvers)
+    }
+
+    /// Returns the files changed in this pull request that are missing an approval from any of
+    /// their code owners
+    async fn find_unapproved_files(
+        &mut self,
+        pull_request: &PullRequest,
+        codeowners: &CodeOwners,
+        approved_users: &HashSet<&str>,
+    ) -> Result<Vec<String>, Error> {
+        let files = self.github.pull_request_files(pull_request).await?;
+        let mut unapproved_files = Vec::new();
+        for file in files {
+            let owners = match codeowners.owners(&file.filename) {
+                Some(owners) if !owners.is_empty() => owners,
+                _ => continue,
+            };
+            let mut approved = false;
+            for owner in owners {
+                if self.is_owner_approved(owner, approved_users).await? {
+                    approved = true;
+                    break;
+                }
+            }
+            if !approved {
+                unapproved_files.push(file.filename);
+            }
+        }
+        Ok(unapproved_files)
+    }
+
+    async fn check_codeowners(
+        &mut self,
+        pull_request: &PullRequest,
+        reviews: &[PullRequestReview],
+    ) -> Result<(), Error> {
+        let codeowners = match self.fetch_codeowners(&pull_request.base).await? {
+            Some(codeowners) => codeowners,
+            None => {
+                info!("No CODEOWNERS file found, only checking number of approvals");
+                return Ok(());
+            }
+        };
+        let approved_users = Self::approved_users(reviews);
+        let unapproved_files = self
+            .find_unapproved_files(pull_request, &codeowners, &approved_users)
+            .await?;
+        match unapproved_files.len() {
+            0 => Ok(()),
+            1 => Err(Error::as_generic(format!(
+                "file '{}' is not approved by any of its code owners",
+                unapproved_files[0]
+            ))),
+            count => Err(Error::as_generic(format!(
+                "{} files are not approved by any of their code owners",
+                count
+            ))),
+        }
+    }
+
+    /// Returns the required approvers whose latest review isn't an approval
+    fn missing_required_approvers(&self, reviews: &[PullRequestReview]) -> Vec<&str> {
+        let approved_users = Self::approved_users(reviews);
+        self.reviews
+            .required_approvers
+            .iter()
+            .map(String::as_str)
+            .filter(|approver| !approved_users.contains(approver))
+            .collect()
+    }
+}
+
+async fn fetch_branch_protection(
+    github: &dyn GithubClient,
+    branch: &Branch,
+) -> Result<Option<BranchProtection>, Error> {
+    match github.branch_protection(branch).await {
+        Ok(branch_protection) => Ok(Some(branch_protection)),
+        Err(e) if e.not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The approvals needed, which is the highest of the configured ones and the branch protection's
+fn required_approvals(
+    reviews: &ReviewsConfig,
+    branch_protection: Option<&BranchProtection>,
+) -> u32 {
+    match branch_protection {
+        Some(protection) => protection.reviews.approvals.max(reviews.approvals),
+        None => reviews.approvals,
+    }
+}
+
+#[async_trait]
+impl Step for CheckReviewsStep {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let branch_protection = fetch_branch_protection(&*self.github, &pull_request.base).await?;
+        let approvals_needed =
+            required_approvals(&self.reviews, branch_protection.as_ref()) as usize;
+        let reviews = self.github.pull_request_reviews(pull_request).await?;
+        let reviews = Self::counted_reviews(&self.reviews, pull_request, reviews);
+        self.approvals = None;
+        // An expired approval still replaces the reviewer's earlier request for changes
+        let requesting_changes = Self::users_requesting_changes(&reviews);
+        if !requesting_changes.is_empty() {
+            return Err(Error::as_generic(format!(
+                "changes were requested by {}",
+                requesting_changes.join(", ")
+            )));
+        }
+        let (reviews, expired_approvers) =
+            Self::unexpired_reviews(&self.reviews, reviews, Local::now());
+        let review_approvers = Self::approved_users(&reviews);
+        let mut approvals = Approvals {
+            reviewers: review_approvers
+                .iter()
+                .map(|user| user.to_string())
+                .collect(),
+            required_approvers: self
+                .reviews
+                .required_approvers
+                .iter()
+                .filter(|approver| review_approvers.contains(approver.as_str()))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        approvals.reviewers.sort_unstable();
+        let mut total_users_approved = Self::compute_approvals(&reviews);
+        if let Some(comment_approval) = self.reviews.comment_approval.clone() {
+            let mut comment_approvers: Vec<_> = self
+                .comment_approvers(pull_request, &comment_approval)
+                .await?
+                .into_iter()
+                .filter(|user| !review_approvers.contains(user.as_str()))
+                .collect();
+            if !comment_approvers.is_empty() {
+                comment_approvers.sort_unstable();
+                info!(
+                    "Counting approvals from comments by {}",
+                    comment_approvers.join(", ")
+                );
+                total_users_approved += comment_approvers.len();
+            }
+            approvals.commenters = comment_approvers;
+        }
+        self.approvals = Some(approvals);
+
+        if total_users_approved < approvals_needed {
+            // Users who approved again since their approval expired don't need to be asked to
+            let expired_approvers: Vec<_> = expired_approvers
+                .into_iter()
+                .filter(|user| !review_approvers.contains(user.as_str()))
+                .collect();
+            if !expired_approvers.is_empty() {
+                info!(
+                    "Not enough approvals (need {}, have {}), waiting for {} to review again as their approvals expired",
+                    approvals_needed,
+                    total_users_approved,
+                    expired_approvers.join(", ")
+                );
+                self.request_fresh_reviews(pull_request, &expired_approvers)
+                    .await?;
+                return Ok(StepStatus::Waiting);
+            }
+            let reason = format!(
+                "not enough approvals (need {}, have {})",
+                approvals_needed, total_users_approved
+            );
+            return Err(Error::as_generic(reason));
+        }
+        let missing_approvers = self.missing_required_approvers(&reviews);
+        if !missing_approvers.is_empty() {
+            info!(
+                "Waiting for approval from required approvers: {}",
+                missing_approvers.join(", ")
+            );
+            return Ok(StepStatus::Waiting);
+        }
+        let team_approvers = self.team_approvers(pull_request, &reviews).await?;
+        if let Some(approvals) = &mut self.approvals {
+            approvals.teams = team_approvers
+                .iter()
+                .map(|(team, approvers)| TeamApprovers {
+                    team: team.team.clone(),
+                    approvers: approvers.clone(),
+                })
+                .collect();
+        }
+        let missing_teams: Vec<_> = team_approvers
+            .into_iter()
+            .map(|(team, approvers)| (team, approvers.len()))
+            .filter(|(team, approvals)| *approvals < team.approvals as usize)
+            .collect();
+        if !missing_teams.is_empty() {
+            let missing_teams: Vec<_> = missing_teams
+                .iter()
+                .map(|(team, approvals)| {
+                    format!("{} ({}/{})", team.team, approvals, team.approvals)
+                })
+                .collect();
+            info!(
+                "Waiting for approvals from required teams: {}",
+                missing_teams.join(", ")
+            );
+            return Ok(StepStatus::Waiting);
+        }
+        if self.reviews.require_codeowners {
+            self.check_codeowners(pull_request, &reviews).await?;
+        }
+        if self.reviews.require_resolved_threads {
+            self.check_resolved_threads(pull_request).await?;
+        }
+        if let Some(approvals) = &self.approvals {
+            info!("Reviews passed with approvals: {}", approvals);
+        }
+        Ok(StepStatus::Passed)
+    }
+
+    /// Requesting fresh reviews is the only thing this step does other than reading state
+    fn is_readonly(&self) -> bool {
+        !self.request_reviews || self.reviews.approval_max_age_seconds.is_none()
+    }
+
+    fn approvals(&self) -> Option<Approvals> {
+        self.approvals.clone()
+    }
+}
+
+impl fmt::Display for CheckReviewsStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check reviews")
+    }
+}
+
+/// Checks whether a pull request has enough approvals given after its last push, as older ones
+/// may not reflect the current code
+pub struct CheckApprovedAfterLastPush {
+    github: Arc<dyn GithubClient>,
+    reviews: ReviewsConfig,
+}
+
+impl CheckApprovedAfterLastPush {
+    pub fn new(github: Arc<dyn GithubClient>, reviews: ReviewsConfig) -> Self {
+        Self { github, reviews }
+    }
+}
+
+#[async_trait]
+impl Step for CheckApprovedAfterLastPush {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let branch_protection = fetch_branch_protection(&*self.github, &pull_request.base).await?;
+        if let Some(protection) = &branch_protection {
+            if protection.reviews.dismiss_stale_reviews {
+                // Github already takes care of this
+                return Ok(StepStatus::Passed);
+            }
+        }
+        let approvals_needed = required_approvals(&self.reviews, branch_protection.as_ref());
+        let head = self
+            .github
+            .commit(&pull_request.base.repo, &pull_request.head.sha)
+            .await?;
+        let last_push = head.commit.committer.date;
+        let reviews = self.github.pull_request_reviews(pull_request).await?;
+        let reviews = CheckReviewsStep::counted_reviews(&self.reviews, pull_request, reviews);
+        let (reviews, _) =
+            CheckReviewsStep::unexpired_reviews(&self.reviews, reviews, Local::now());
+        let fresh_reviews: Vec<_> = reviews
+            .iter()
+            .filter(|review| {
+                review.state != ReviewState::Approved || review.submitted_at >= last_push
+            })
+            .cloned()
+            .collect();
+        let total_approvals = CheckReviewsStep::approved_users(&reviews).len();
+        let fresh_approvals = CheckReviewsStep::approved_users(&fresh_reviews).len();
+        if fresh_approvals < total_approvals {
+            info!(
+                "Discarded {} stale approvals given before the last push",
+                total_approvals - fresh_approvals
+            );
+        }
+        if fresh_approvals < approvals_needed as usize {
+            return Err(Error::as_generic(format!(
+                "not enough approvals since the last push (need {}, have {})",
+                approvals_needed, fresh_approvals
+            )));
+        }
+        Ok(StepStatus::Passed)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckApprovedAfterLastPush {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check approved after last push")
+    }
+}
+
+/// Makes a pull request wait if its branch was force pushed after it was approved, as the approved
+/// commits may not even be part of it anymore. Re-review is requested from the affected
+/// approvers once per force push, unless running read only.
+pub struct CheckNoForcePushSinceApproval {
+    github: Arc<dyn GithubClient>,
+    request_reviews: bool,
+    requested_for: Option<DateTime<Local>>,
+}
+
+impl CheckNoForcePushSinceApproval {
+    pub fn new(github: Arc<dyn GithubClient>, request_reviews: bool) -> Self {
+        Self {
+            github,
+            request_reviews,
+            requested_for: None,
+        }
+    }
+
+    /// Returns the users whose current approval was given before `pushed_at`, sorted
+    fn stale_approvers(reviews: &[PullRequestReview], pushed_at: DateTime<Local>) -> Vec<String> {
+        let approved_users = CheckReviewsStep::approved_users(reviews);
+        let mut last_approvals: HashMap<&str, DateTime<Local>> = HashMap::new();
+        for review in reviews {
+            if review.state == ReviewState::Approved {
+                last_approvals.insert(review.user.login.as_str(), review.submitted_at);
+            }
+        }
+        let mut stale: Vec<_> = last_approvals
+            .into_iter()
+            .filter(|(user, approved_at)| approved_users.contains(user) && *approved_at < pushed_at)
+            .map(|(user, _)| user.to_string())
+            .collect();
+        stale.sort();
+        stale
+    }
+}
+
+#[async_trait]
+impl Step for CheckNoForcePushSinceApproval {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let events = self.github.timeline_events(pull_request).await?;
+        let force_push = events
+            .into_iter()
+            .filter(|event| event.event == "head_ref_force_pushed")
+            .filter_map(|event| event.created_at.map(|at| (at, event.actor)))
+            .max_by_key(|(at, _)| *at);
+        let (pushed_at, actor) = match force_push {
+            Some(force_push) => force_push,
+            None => return Ok(StepStatus::Passed),
+        };
+        let reviews = self.github.pull_request_reviews(pull_request).await?;
+        let stale_approvers = Self::stale_approvers(&reviews, pushed_at);
+        if stale_approvers.is_empty() {
+            return Ok(StepStatus::Passed);
+        }
+        let actor = actor.map_or_else(|| "someone".to_string(), |actor| actor.login);
+        warn!(
+            "Branch was force pushed by {} at {} after being approved by {}, waiting for re-review",
+            actor,
+            pushed_at,
+            stale_approvers.join(", ")
+        );
+        if self.request_reviews && self.requested_for != Some(pushed_at) {
+            self.github
+                .request_reviewers(pull_request, &stale_approvers)
+                .await?;
+            self.requested_for = Some(pushed_at);
+        }
+        Ok(StepStatus::Waiting)
+    }
+
+    fn is_readonly(&self) -> bool {
+        !self.request_reviews
+    }
+}
+
+impl fmt::Display for CheckNoForcePushSinceApproval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check no force push since approval")
+    }
+}
+
+/// Checks whether a pull request is behind master, and updates it if configured to do so. After
+/// updating it, it waits for CI to run on the new head before letting the pull request through so
+/// it's never merged based on checks that ran before the update.
+pub struct CheckBehindMaster {
+    github: Arc<dyn GithubClient>,
+    auto_update_branch: bool,
+    compare_branches: bool,
+    branch_update: Option<BranchUpdate>,
+}
+
+/// A branch update that's waiting for CI to run on the new head
+struct BranchUpdate {
+    previous_sha: String,
+
+    /// Whether any checks ran on the previous head, otherwise there's nothing to wait for
+    had_checks: bool,
+}
+
+/// How many CI checks were reported for a commit and how many of those are still running
+struct CheckCounts {
+    total: usize,
+    pending: usize,
+}
+
+impl CheckBehindMaster {
+    pub fn new(github: Arc<dyn GithubClient>, auto_update_branch: bool) -> Self {
+        Self {
+            github,
+            auto_update_branch,
+            compare_branches: false,
+            branch_update: None,
+        }
+    }
+
+    /// Always compares the branches rather than going by github's mergeable state, which is
+    /// relative to the base branch github knows of rather than an overridden one
+    pub fn with_compare_branches(mut self) -> Self {
+        self.compare_branches = true;
+        self
+    }
+
+    /// Counts both statuses and github actions runs on the pull request's current head, only
+    /// looking at the latest one for each status context and workflow
+    async fn count_checks(&self, pull_request: &PullRequest) -> Result<CheckCounts, Error> {
+        let mut statuses = HashMap::new();
+        for status in self.github.pull_request_statuses(pull_request).await? {
+            statuses.entry(status.context).or_insert(status.state);
+        }
+        let action_runs = self
+            .github
+            .action_runs_for_sha(&pull_request.base.repo, &pull_request.head.sha)
+            .await?;
+        let mut runs = HashMap::new();
+        for run in action_runs.workflow_runs {
+            runs.entry(run.workflow_id).or_insert(run.conclusion);
+        }
+        let pending = statuses
+            .values()
+            .filter(|state| **state == StatusState::Pending)
+            .count()
+            + runs
+                .values()
+                .filter(|conclusion| conclusion.is_none())
+                .count();
+        Ok(CheckCounts {
+            total: statuses.len() + runs.len(),
+            pending,
+        })
+    }
+
+    async fn update_branch(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let checks = self.count_checks(pull_request).await?;
+        match self.github.update_branch(pull_request).await {
+            Ok(_) => {
+                self.branch_update = Some(BranchUpdate {
+                    previous_sha: pull_request.head.sha.clone(),
+                    had_checks: checks.total > 0,
+                });
+                Ok(StepStatus::Waiting)
+            }
+            // This means either the head sha has _just_ changed or the branch is already up to
+            // date. Odds are someone just updated it manually which means we're waiting either way
+            Err(e) if e.unprocessable_entity() => Ok(StepStatus::Waiting),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether the pull request is behind its base branch. Github's mergeable state says so
+    /// directly, but while it's still being computed this falls back to comparing both branches.
+    async fn is_behind(&self, pull_request: &PullRequest) -> Result<bool, Error> {
+        match pull_request.mergeable_state {
+            _ if self.compare_branches => (),
+            MergeableState::Behind => return Ok(true),
+            MergeableState::Unknown => (),
+            _ => return Ok(false),
+        }
+        let base = &pull_request.base;
+        let comparison = self
+            .github
+            .compare_commits(&base.repo, &base.name, &pull_request.head.sha)
+            .await?;
+        Ok(comparison.behind_by > 0)
+    }
+
+    /// Waits until the update shows up in the pull request and CI finished running on it
+    async fn wait_for_update(
+        &mut self,
+        pull_request: &PullRequest,
+        update: &BranchUpdate,
+    ) -> Result<StepStatus, Error> {
+        if pull_request.head.sha == update.previous_sha {
+            info!("Waiting for the branch update to show up in the pull request");
+            return Ok(StepStatus::Waiting);
+        }
+        if update.had_checks {
+            let checks = self.count_checks(pull_request).await?;
+            if checks.total == 0 {
+                info!("Waiting for checks to start running on the updated branch");
+                return Ok(StepStatus::Waiting);
+            }
+            if checks.pending > 0 {
+                info!(
+                    "Waiting for {} checks to finish running on the updated branch",
+                    checks.pending
+                );
+                return Ok(StepStatus::Waiting);
+            }
+        }
+        Ok(StepStatus::Passed)
+    }
+}
+
+#[async_trait]
+impl Step for CheckBehindMaster {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if !self.is_behind(pull_request).await? {
+            if let Some(update) = self.branch_update.take() {
+                let status = self.wait_for_update(pull_request, &update).await?;
+                if status == StepStatus::Waiting {
+                    self.branch_update = Some(update);
+                }
+                return Ok(status);
+            }
+            return Ok(StepStatus::Passed);
+        }
+        if !self.auto_update_branch {
+            warn!("Pull request branch is behind master, waiting for it to be updated");
+            return Ok(StepStatus::Waiting);
+        }
+        // Github updates the branch in the fork, which it can only do if the author allows it
+        if pull_request.is_from_fork() && !pull_request.maintainer_can_modify {
+            warn!(
+                "Pull request branch is behind master but fork '{}' doesn't allow maintainers to update it, waiting for the author to update it",
+                pull_request.head.repo.full_name
+            );
+            return Ok(StepStatus::Waiting);
+        }
+        warn!("Pull request branch is behind master, updating it");
+        self.update_branch(pull_request).await
+    }
+
+    /// Updating the branch is the only thing this step does other than reading its state
+    fn is_readonly(&self) -> bool {
+        !self.auto_update_branch
+    }
+}
+
+impl fmt::Display for CheckBehindMaster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check if behind master")
+    }
+}
+
+/// Makes sure CI reported something on a pull request's head, as otherwise there's no failures
+/// and the build looks green. If the base branch requires status checks, every one of them needs
+/// to have been reported.
+pub struct CheckRequiredChecksPresent {
+    github: Arc<dyn GithubClient>,
+    required_contexts: Option<Vec<String>>,
+}
+
+impl CheckRequiredChecksPresent {
+    pub fn new(github: Arc<dyn GithubClient>) -> Self {
+        Self {
+            github,
+            required_contexts: None,
+        }
+    }
+
+    /// The status checks required by the base branch, which are only fetched once
+    async fn required_contexts(&mut self, branch: &Branch) -> Result<&[String], Error> {
+        if self.required_contexts.is_none() {
+            let contexts = match self.github.required_status_checks(branch).await {
+                Ok(checks) => checks.contexts,
+                Err(e) if e.not_found() || e.forbidden() => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            self.required_contexts = Some(contexts);
+        }
+        Ok(self.required_contexts.as_deref().unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl Step for CheckRequiredChecksPresent {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let statuses = self.github.pull_request_statuses(pull_request).await?;
+        let check_runs = self
+            .github
+            .check_runs(&pull_request.base.repo, &pull_request.head.sha)
+            .await?;
+        let mut reported: HashSet<_> = statuses
+            .iter()
+            .map(|status| status.context.as_str())
+            .collect();
+        reported.extend(check_runs.check_runs.iter().map(|run| run.name.as_str()));
+        let required_contexts = self.required_contexts(&pull_request.base).await?;
+        if required_contexts.is_empty() {
+            if reported.is_empty() {
+                info!("Waiting for CI to report any checks on the head commit");
+                return Ok(StepStatus::Waiting);
+            }
+            return Ok(StepStatus::Passed);
+        }
+        let missing: Vec<_> = required_contexts
+            .iter()
+            .map(String::as_str)
+            .filter(|context| !reported.contains(context))
+            .collect();
+        if !missing.is_empty() {
+            info!(
+                "Waiting for required checks to be reported: {}",
+                missing.join(", ")
+            );
+            return Ok(StepStatus::Waiting);
+        }
+        Ok(StepStatus::Passed)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckRequiredChecksPresent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check required checks present")
+    }
+}
+
+/// Rounds a duration up to whole minutes, never showing less than one
+fn remaining_minutes(remaining: std::time::Duration) -> u64 {
+    remaining.as_secs().div_ceil(60).max(1)
+}
+
+/// Checks whether the build for a pull request failed, re-triggering CI runs if needed
+pub struct CheckBuildFailed {
+    github: Arc<dyn GithubClient>,
+    workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
+    ignored_contexts: Vec<Regex>,
+    rerun_failed_actions: bool,
+    last_head_hash: Option<String>,
+    status_failures_config: HashMap<String, StatusFailuresConfig>,
+    status_failures: HashMap<String, u32>,
+    required_contexts: Option<Option<HashSet<String>>>,
+    check_states: Option<CheckStates>,
+    min_green: Option<std::time::Duration>,
+}
+
+impl CheckBuildFailed {
+    pub fn new(
+        github: Arc<dyn GithubClient>,
+        workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
+        status_failures_config: HashMap<String, StatusFailuresConfig>,
+        ignored_contexts: &[String],
+        rerun_failed_actions: bool,
+    ) -> Result<Self, regex::Error> {
+        let ignored_contexts = ignored_contexts
+            .iter()
+            .map(|pattern| build_glob_pattern(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            github,
+            workflow_runners,
+            ignored_contexts,
+            rerun_failed_actions,
+            last_head_hash: None,
+            status_failures_config,
+            status_failures: HashMap::default(),
+            required_contexts: None,
+            check_states: None,
+            min_green: None,
+        })
+    }
+
+    /// Makes the build only count as passed once its checks have all been green for this long
+    pub fn with_min_green(mut self, min_green: std::time::Duration) -> Self {
+        self.min_green = Some(min_green);
+        self
+    }
+
+    /// When the last of the checks that count went green, if any did
+    async fn last_turned_green(
+        &mut self,
+        pull_request: &PullRequest,
+    ) -> Result<Option<DateTime<Local>>, Error> {
+        self.load_required_contexts(&pull_request.base).await?;
+        let statuses = self.github.pull_request_statuses(pull_request).await?;
+        let check_runs = self
+            .github
+            .check_runs(&pull_request.base.repo, &pull_request.head.sha)
+            .await?;
+        let mut latest_statuses = HashMap::new();
+        for status in statuses {
+            latest_statuses
+                .entry(status.context.clone())
+                .or_insert(status);
+        }
+        let green_statuses = latest_statuses
+            .into_values()
+            .filter(|status| status.state == StatusState::Success)
+            .map(|status| (status.context, Some(status.created_at)));
+        let green_check_runs = check_runs
+            .check_runs
+            .into_iter()
+            .filter(|run| run.conclusion == Some(WorkflowRunConclusion::Success))
+            .map(|run| (run.name, run.completed_at));
+        let required_contexts = self.required_contexts.clone().flatten();
+        let last_green = green_statuses
+            .chain(green_check_runs)
+            .filter(|(name, _)| !is_ignored(&self.ignored_contexts, name))
+            .filter(|(name, _)| match &required_contexts {
+                Some(required_contexts) => required_contexts.contains(name),
+                None => true,
+            })
+            .filter_map(|(_, green_at)| green_at)
+            .max();
+        Ok(last_green)
+    }
+
+    /// Waits until the checks have been green for long enough, if configured to
+    async fn check_green_long_enough(
+        &mut self,
+        pull_request: &PullRequest,
+    ) -> Result<StepStatus, Error> {
+        let min_green = match self.min_green {
+            Some(min_green) => min_green,
+            None => return Ok(StepStatus::Passed),
+        };
+        let last_green = match self.last_turned_green(pull_request).await? {
+            Some(last_green) => last_green,
+            None => return Ok(StepStatus::Passed),
+        };
+        let green_for = (Local::now() - last_green).to_std().unwrap_or_default();
+        if green_for >= min_green {
+            return Ok(StepStatus::Passed);
+        }
+        info!(
+            "Checks have been green for {}s, waiting until they've been for {}s",
+            green_for.as_secs(),
+            min_green.as_secs()
+        );
+        Ok(StepStatus::Waiting)
+    }
+
+    /// Fetches the status checks required by the base branch's protection rules. This is only done
+    /// once as these are unlikely to change while we're processing a pull request.
+    async fn load_required_contexts(&mut self, branch: &Branch) -> Result<(), Error> {
+        if self.required_contexts.is_some() {
+            return Ok(());
+        }
+        let required_contexts = match self.github.required_status_checks(branch).await {
+            Ok(checks) => Some(checks.contexts.into_iter().collect()),
+            Err(e) if e.not_found() || e.forbidden() => {
+                warn!("Can't fetch required status checks, considering all of them as required");
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.required_contexts = Some(required_contexts);
+        Ok(())
+    }
+
+    async fn check_actions(
+        &self,
+        pull_request: &PullRequest,
+        states: &mut CheckStates,
+    ) -> Result<StepStatus, Error> {
+        let split_runs = self.fetch_action_runs(pull_request).await?;
+        split_runs.record_states(states);
+        match split_runs.pending.len() {
+            0 => {
+                if split_runs.failed.is_empty() {
+                    return Ok(StepStatus::Passed);
+                }
+                if !self.rerun_failed_actions {
+                    return Err(Error::as_generic(format!(
+                        "actions workflow '{}' failed",
+                        split_runs.failed[0].name
+                    )));
+                }
+                self.process_failed_actions(pull_request, &split_runs.failed)
+                    .await?;
+            }
+            1 => info!(
+                "Waiting for '{}' action workflow to finish",
+                split_runs.pending[0].name,
+            ),
+            count => info!("Waiting for {} actions workflow to finish", count),
+        }
+        Ok(StepStatus::Waiting)
+    }
+
+    async fn check_statuses(
+        &mut self,
+        pull_request: &PullRequest,
+        states: &mut CheckStates,
+    ) -> Result<StepStatus, Error> {
+        let mut summaries = self.fetch_status_summaries(pull_request).await?;
+        self.load_required_contexts(&pull_request.base).await?;
+        if let Some(Some(required_contexts)) = &self.required_contexts {
+            summaries.retain_required(required_contexts);
+        }
+        summaries.remove_ignored(&self.ignored_contexts);
+        summaries.record_states(states);
+        match summaries.pending.len() {
+            0 => {
+                if summaries.failed.is_empty() {
+                    return Ok(StepStatus::Passed);
+                }
+                return self
+                    .process_failed_statuses(pull_request, summaries.failed)
+                    .await;
+            }
+            1 => {
+                let remaining = self
+                    .describe_remaining_time(pull_request, &summaries.pending)
+                    .await;
+                info!(
+                    "Waiting for external job '{}' to finish{}",
+                    summaries.pending[0].name, remaining
+                );
+            }
+            count => {
+                let remaining = self
+                    .describe_remaining_time(pull_request, &summaries.pending)
+                    .await;
+                info!("Waiting for {} external jobs to finish{}", count, remaining);
+            }
+        }
+        Ok(StepStatus::Waiting)
+    }
+
+    /// The time left until the pending jobs are done according to the runners that can estimate
+    /// it, formatted to be appended to a log message
+    async fn describe_remaining_time(
+        &self,
+        pull_request: &PullRequest,
+        pending: &[StatusSummary],
+    ) -> String {
+        let job_urls: Vec<_> = pending.iter().map(|summary| summary.url.clone()).collect();
+        let mut estimate = None;
+        for runner in &self.workflow_runners {
+            let remaining = runner.estimated_completion(pull_request, &job_urls).await;
+            estimate = estimate.max(remaining);
+        }
+        match estimate {
+            Some(remaining) => format!(", ~{} minutes remaining", remaining_minutes(remaining)),
+            None => String::new(),
+        }
+    }
+
+    async fn process_failed_statuses(
+        &mut self,
+        pull_request: &PullRequest,
+        statuses: Vec<StatusSummary>,
+    ) -> Result<StepStatus, Error> {
+        warn!("Processing {} failed external jobs", statuses.len());
+        self.check_max_failures(&statuses)?;
+        let failed_job_urls: Vec<_> = statuses.into_iter().map(|summary| summary.url).collect();
+        let mut total_triggered = 0;
+        let mut total_pending = 0;
+        let mut accepted_job_urls = HashSet::new();
+        for runner in &self.workflow_runners {
+            match runner
+                .process_failed_jobs(pull_request, &failed_job_urls)
+                .await
+                .map_err(|e| Error::WorkflowRunner(Box::new(e)))?
+            {
+                WorkflowStatus::Triggered => total_triggered += 1,
+                WorkflowStatus::Pending => total_pending += 1,
+                WorkflowStatus::Accepted(urls) => accepted_job_urls.extend(urls),
+                WorkflowStatus::Success => (),
+            }
+        }
+        if total_pending > 0 {
+            info!("Some failed jobs are still running, waiting for them to finish");
+            return Ok(StepStatus::Waiting);
+        }
+        if total_triggered > 0 {
+            return Ok(StepStatus::Waiting);
+        }
+        if failed_job_urls
+            .iter()
+            .all(|url| accepted_job_urls.contains(url))
+        {
+            info!("All failed jobs are considered acceptable");
+            return Ok(StepStatus::Passed);
+        }
+        // There's failed jobs but we don't know how to re-trigger them. e.g. we don't support
+        // whatever service they're being ran on.
+        Err(Error::as_generic(
+            "failed jobs belong to unknown external services",
+        ))
+    }
+
+    fn check_max_failures(&mut self, failed_statuses: &[StatusSummary]) -> Result<(), Error> {
+        for status in failed_statuses {
+            if let Some(config) = self.status_failures_config.get(&status.name) {
+                let failures = self.status_failures.entry(status.name.clone()).or_insert(0);
+                *failures += 1;
+                if *failures >= config.max_failures {
+                    return Err(Error::as_generic(format!(
+                        "status check '{}' reached {} failures",
+                        status.name, failures
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch_status_summaries(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<StatusSummaries, Error> {
+        let (repo, sha) = (&pull_request.base.repo, &pull_request.head.sha);
+        // The combined status only holds the latest status of each context
+        let combined = self.github.combined_status(repo, sha).await?;
+        debug!("Combined status of commit {} is {:?}", sha, combined.state);
+        let mut passed = Vec::new();
+        let mut failed = Vec::new();
+        let mut pending = Vec::new();
+        let mut seen_names = HashSet::new();
+        for status in combined.statuses {
+            seen_names.insert(status.context.clone());
+            let url = Self::parse_status_url(&status.target_url)?;
+            let summary = StatusSummary {
+                url,
+                name: status.context,
+            };
+            // Anything else, like the "error" state, doesn't let github merge either
+            match status.state {
+                StatusState::Success => passed.push(summary),
+                StatusState::Pending => pending.push(summary),
+                _ => failed.push(summary),
+            };
+        }
+        // Check runs reported by other apps count too, unless a status has the same name
+        let check_runs = self.github.check_runs(repo, sha).await?;
+        for run in check_runs.check_runs {
+            if run.is_github_actions() || !seen_names.insert(run.name.clone()) {
+                continue;
+            }
+            let url = match &run.html_url {
+                Some(url) => Self::parse_status_url(url)?,
+                None => continue,
+            };
+            let summary = StatusSummary {
+                url,
+                name: run.name,
+            };
+            // Cancelled, timed out and runs that need action count as failed just like github does
+            match run.conclusion {
+                None => pending.push(summary),
+                Some(
+                    WorkflowRunConclusion::Success
+                    | WorkflowRunConclusion::Neutral
+                    | WorkflowRunConclusion::Skipped,
+                ) => passed.push(summary),
+                Some(_) => failed.push(summary),
+            };
+        }
+        // The statuses are paginated, so the failing ones may not all be in there
+        if combined.state == StatusState::Failure && failed.is_empty() {
+            return Err(Error::as_generic(format!(
+                "combined status of commit {} is failing but no failed checks were found",
+                sha
+            )));
+        }
+        Ok(StatusSummaries {
+            passed,
+            pending,
+            failed,
+        })
+    }
+
+    async fn fetch_action_runs(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<SplitActionRuns, Error> {
+        let action_runs = self.github.action_runs(pull_request).await?;
+        let mut last_run_per_workflow = HashMap::new();
+        for run in action_runs.workflow_runs {
+            if run.head_sha != pull_request.head.sha {
+                continue;
+            }
+            last_run_per_workflow.entry(run.workflow_id).or_insert(run);
+        }
+        let mut passed = Vec::new();
+        let mut pending = Vec::new();
+        let mut failed = Vec::new();
+        for run in last_run_per_workflow.into_values() {
+            if is_ignored(&self.ignored_contexts, &run.name) {
+                if matches!(run.conclusion, Some(WorkflowRunConclusion::Failure)) {
+                    warn!("Ignoring failed workflow '{}' as configured", run.name);
+                }
+                continue;
+            }
+            match run.conclusion {
+                None => pending.push(run),
+                Some(WorkflowRunConclusion::Success) => passed.push(run),
+                Some(WorkflowRunConclusion::Failure) => failed.push(run),
+                _ => (),
+            }
+        }
+        Ok(SplitActionRuns {
+            passed,
+            pending,
+            failed,
+        })
+    }
+
+    async fn process_failed_actions(
+        &self,
+        pull_request: &PullRequest,
+        actions: &[WorkflowRun],
+    ) -> Result<(), Error> {
+        for run in actions {
+            warn!("Actions workflow '{}' failed, re-running it", run.name);
+            self.github
+                .rerun_workflow(&pull_request.base.repo, run.id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn check_builds(
+        &mut self,
+        pull_request: &PullRequest,
+        states: &mut CheckStates,
+    ) -> Result<StepStatus, Error> {
+        let statuses_result = self.check_statuses(pull_request, states).await?;
+        let actions_result = self.check_actions(pull_request, states).await?;
+        if (statuses_result, actions_result) == (StepStatus::Passed, StepStatus::Passed)
+            && pull_request.mergeable_state == MergeableState::Blocked
+        {
+            // This means we don't currently support/know whatever led this PR to be unstable
+            Err(Error::as_generic(
+                "pull request is blocked for unknown reasons",
+            ))
+        } else {
+            Ok(StepStatus::Waiting)
+        }
+    }
+
+    fn parse_status_url(url: &str) -> Result<Url, Error> {
+        let url = Url::parse(url)
+            .map_err(|_| Error::as_generic(format!("invalid status target URL: {}", url)))?;
+        Ok(url)
+    }
+}
+
+struct StatusSummary {
+    url: Url,
+    name: String,
+}
+
+struct StatusSummaries {
+    passed: Vec<StatusSummary>,
+    pending: Vec<StatusSummary>,
+    failed: Vec<StatusSummary>,
+}
+
+impl StatusSummaries {
+    fn retain_required(&mut self, required_contexts: &HashSet<String>) {
+        self.passed
+            .retain(|summary| required_contexts.contains(&summary.name));
+        self.pending
+            .retain(|summary| required_contexts.contains(&summary.name));
+        self.failed.retain(|summary| {
+            let required = required_contexts.contains(&summary.name);
+            if !required {
+                info!(
+                    "Ignoring failed status check '{}' as it's not required",
+                    summary.name
+                );
+            }
+            required
+        });
+    }
+
+    fn remove_ignored(&mut self, ignored_contexts: &[Regex]) {
+        self.passed
+            .retain(|summary| !is_ignored(ignored_contexts, &summary.name));
+        self.pending
+            .retain(|summary| !is_ignored(ignored_contexts, &summary.name));
+        self.failed.retain(|summary| {
+            let ignored = is_ignored(ignored_contexts, &summary.name);
+            if ignored {
+                warn!(
+                    "Ignoring failed status check '{}' as configured",
+                    summary.name
+                );
+            }
+            !ignored
+        });
+    }
+
+    fn record_states(&self, states: &mut CheckStates) {
+        let names = |summaries: &[StatusSummary]| {
+            summaries
+                .iter()
+                .map(|summary| summary.name.clone())
+                .collect::<Vec<_>>()
+        };
+        states.passed.extend(names(&self.passed));
+        states.failed.extend(names(&self.failed));
+        states.pending.extend(names(&self.pending));
+    }
+}
+
+/// Builds a regex out of a glob pattern where `*` matches any number of characters
+pub(crate) fn build_glob_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    let parts: Vec<_> = pattern.split('*').map(regex::escape).collect();
+    Regex::new(&format!("^{}$", parts.join(".*")))
+}
+
+fn is_ignored(ignored_contexts: &[Regex], name: &str) -> bool {
+    ignored_contexts
+        .iter()
+        .any(|pattern| pattern.is_match(name))
+}
+
+struct SplitActionRuns {
+    passed: Vec<WorkflowRun>,
+    pending: Vec<WorkflowRun>,
+    failed: Vec<WorkflowRun>,
+}
+
+impl SplitActionRuns {
+    fn record_states(&self, states: &mut CheckStates) {
+        let names =
+            |runs: &[WorkflowRun]| runs.iter().map(|run| run.name.clone()).collect::<Vec<_>>();
+        states.passed.extend(names(&self.passed));
+        states.failed.extend(names(&self.failed));
+        states.pending.extend(names(&self.pending));
+    }
+}
+
+fn log_check_states(states: &CheckStates) {
+    if !states.passed.is_empty() {
+        info!("Checks passed: {}", states.passed.join(", "));
+    }
+    if !states.failed.is_empty() {
+        info!("Checks failed: {}", states.failed.join(", "));
+    }
+    if !states.pending.is_empty() {
+        info!("Checks in progress: {}", states.pending.join(", "));
+    }
+}
+
+#[async_trait]
+impl Step for CheckBuildFailed {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if !matches!(pull_request.mergeable_state, MergeableState::Blocked) {
+            // Don't keep reporting the states of checks that may have ran on a previous head
+            self.check_states = None;
+            return self.check_green_long_enough(pull_request).await;
+        }
+        if self.last_head_hash.as_ref() != Some(&pull_request.head.sha) {
+            if self.last_head_hash.is_some() {
+                info!("Resetting failure counters as the head sha changed");
+                self.status_failures.clear();
+            }
+            self.last_head_hash = Some(pull_request.head.sha.clone());
+        }
+        let mut states = CheckStates::default();
+        let result = self.check_builds(pull_request, &mut states).await;
+        states.sort();
+        log_check_states(&states);
+        self.check_states = Some(states);
+        result
+    }
+
+    fn check_states(&self) -> Option<CheckStates> {
+        self.check_states.clone()
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckBuildFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check if CI builds failed")
+    }
+}
+
+/// Runs a command that decides whether a pull request can be merged, which passes if it exits
+/// successfully. The pull request's details are passed in `MERGEBRO_*` environment variables.
+pub struct CheckExternalScript {
+    check: ExternalCheck,
+}
+
+impl CheckExternalScript {
+    pub fn new(check: ExternalCheck) -> Self {
+        Self { check }
+    }
+
+    fn build_command(&self, pull_request: &PullRequest) -> Command {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.check.command)
+            .env("MERGEBRO_PR_NUMBER", pull_request.number.to_string())
+            .env("MERGEBRO_PR_TITLE", &pull_request.title)
+            .env("MERGEBRO_PR_AUTHOR", &pull_request.creator.login)
+            .env("MERGEBRO_REPO", &pull_request.base.repo.full_name)
+            .env("MERGEBRO_HEAD_SHA", &pull_request.head.sha)
+            .env("MERGEBRO_HEAD_BRANCH", &pull_request.head.name)
+            .env("MERGEBRO_BASE_BRANCH", &pull_request.base.name)
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        command
+    }
+}
+
+#[async_trait]
+impl Step for CheckExternalScript {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let name = &self.check.name;
+        let output = self.build_command(pull_request).output();
+        let timeout = std::time::Duration::from_secs(self.check.timeout_seconds);
+        let output = match tokio::time::timeout(timeout, output).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                return Err(Error::as_generic(format!(
+                    "failed to run external check '{}': {}",
+                    name, e
+                )))
+            }
+            Err(_) => {
+                return Err(Error::as_generic(format!(
+                    "external check '{}' timed out after {} seconds",
+                    name, self.check.timeout_seconds
+                )))
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stdout.trim().is_empty() {
+            info!("External check '{}' stdout:\n{}", name, stdout.trim_end());
+        }
+        if !stderr.trim().is_empty() {
+            info!("External check '{}' stderr:\n{}", name, stderr.trim_end());
+        }
+        if output.status.success() {
+            return Ok(StepStatus::Passed);
+        }
+        warn!("External check '{}' failed with {}", name, output.status);
+        Err(Error::as_generic(format!(
+            "external check '{}' failed with {}",
+            name, output.status
+        )))
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    /// A bit longer than the check's own timeout, so it fails with that rather than waiting
+    fn timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(
+            self.check.timeout_seconds + 1,
+        ))
+    }
+
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for CheckExternalScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "run external check '{}'", self.check.name)
+    }
+}
+
+/// Makes sure the pull request's head didn't change while the other steps ran, so we never merge
+/// code that wasn't checked. This should run last.
+pub struct CheckHeadUnchanged {
+    github: Arc<dyn GithubClient>,
+    identifier: PullRequestIdentifier,
+}
+
+impl CheckHeadUnchanged {
+    pub fn new(github: Arc<dyn GithubClient>, identifier: PullRequestIdentifier) -> Self {
+        Self { github, identifier }
+    }
+}
+
+#[async_trait]
+impl Step for CheckHeadUnchanged {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let current = self.github.pull_request_info(&self.identifier).await?;
+        if current.head.sha != pull_request.head.sha {
+            warn!(
+                "Pull request head changed from {} to {} while running checks, restarting them",
+                pull_request.head.sha, current.head.sha
+            );
+            return Ok(StepStatus::Waiting);
+        }
+        Ok(StepStatus::Passed)
+    }
+}
+
+impl fmt::Display for CheckHeadUnchanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check head unchanged")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MergeWindow;
+    use crate::github::client::MockGithubClient;
+    use crate::github::{
+        ActionRuns, BranchProtectionReviews, CheckRun, CheckRunApp, CheckRuns, CombinedStatus,
+        Commit, CommitComparison, CommitDetails, CommitSignature, Issue, IssueComment, Label,
+        Milestone, NoBody, Repository, RequiredStatusChecks, ReviewThread, Status, TimelineEvent,
+        User, WorfklowRunStatus, WorkflowRun, WorkflowRunConclusion,
+    };
+    use chrono::{NaiveTime, Weekday};
+    use rstest::rstest;
+    use std::collections::VecDeque;
+    use std::future;
+    use std::sync::Mutex;
+
+    struct WorkflowRunFixture {
+        pending: WorkflowRun,
+        failed: WorkflowRun,
+    }
+
+    fn make_workflow_run_fixture() -> WorkflowRunFixture {
+        let pending = WorkflowRun {
+            id: 1,
+            workflow_id: 42,
+            name: "Some workflow".into(),
+            head_sha: "mysha".into(),
+            status: WorfklowRunStatus::InProgress,
+            conclusion: None,
+        };
+        let failed = WorkflowRun {
+            status: WorfklowRunStatus::Completed,
+            conclusion: Some(WorkflowRunConclusion::Failure),
+            ..pending.clone()
+        };
+        WorkflowRunFixture { pending, failed }
+    }
+
+    fn make_request_review(user: &str, state: ReviewState) -> PullRequestReview {
+        PullRequestReview {
+            user: User { login: user.into() },
+            state,
+            submitted_at: chrono::offset::Local::now(),
+        }
+    }
+
+    fn make_merge_window_step() -> CheckMergeWindow {
+        CheckMergeWindow::new(MergeWindowsConfig {
+            timezone: chrono_tz::America::New_York,
+            windows: vec![MergeWindow {
+                days: vec![
+                    Weekday::Mon,
+                    Weekday::Tue,
+                    Weekday::Wed,
+                    Weekday::Thu,
+                    Weekday::Fri,
+                ],
+                start: NaiveTime::from_hms(9, 0, 0),
+                end: NaiveTime::from_hms(17, 0, 0),
+            }],
+        })
+    }
+
+    #[test]
+    fn test_merge_window_open() {
+        let step = make_merge_window_step();
+        // Wednesday 10:00 in New York
+        assert!(step.is_open_at(Utc.ymd(2021, 6, 16).and_hms(14, 0, 0)));
+        // Wednesday 17:00 in New York
+        assert!(!step.is_open_at(Utc.ymd(2021, 6, 16).and_hms(21, 0, 0)));
+        // Saturday 10:00 in New York
+        assert!(!step.is_open_at(Utc.ymd(2021, 6, 19).and_hms(14, 0, 0)));
+    }
+
+    #[test]
+    fn test_merge_window_next_opening() {
+        let step = make_merge_window_step();
+        // Friday 18:00 in New York, the next window opens on Monday
+        let opening = step
+            .next_opening_after(Utc.ymd(2021, 6, 18).and_hms(22, 0, 0))
+            .unwrap();
+        assert_eq!(
+            opening,
+            chrono_tz::America::New_York
+                .ymd(2021, 6, 21)
+                .and_hms(9, 0, 0)
+        );
+    }
+
+    #[rstest]
+    #[case::clean(Some(true), MergeableState::Clean, Some(StepStatus::Passed))]
+    #[case::blocked(Some(true), MergeableState::Blocked, Some(StepStatus::Passed))]
+    #[case::computing(None, MergeableState::Unknown, Some(StepStatus::Waiting))]
+    #[case::unknown(Some(true), MergeableState::Unknown, Some(StepStatus::Waiting))]
+    #[case::conflicts(Some(false), MergeableState::Dirty, None)]
+    #[case::draft(Some(true), MergeableState::Draft, Some(StepStatus::Waiting))]
+    #[tokio::test]
+    async fn test_mergeable(
+        #[case] mergeable: Option<bool>,
+        #[case] mergeable_state: MergeableState,
+        #[case] expected: Option<StepStatus>,
+    ) {
+        let pull_request = PullRequest {
+            mergeable,
+            mergeable_state,
+            ..Default::default()
+        };
+        let result = CheckMergeable.execute(&pull_request).await.ok();
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case::draft(true, StepStatus::Waiting)]
+    #[case::ready(false, StepStatus::Passed)]
+    #[tokio::test]
+    async fn test_not_draft(#[case] draft: bool, #[case] expected: StepStatus) {
+        let pull_request = PullRequest {
+            draft,
+            ..Default::default()
+        };
+        let result = CheckNotDraft.execute(&pull_request).await.unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case::merged(true, StepStatus::AlreadyMerged)]
+    #[case::closed(false, StepStatus::Closed)]
+    #[tokio::test]
+    async fn test_current_state_closed(#[case] merged: bool, #[case] expected: StepStatus) {
+        let pull_request = PullRequest {
+            state: PullRequestState::Closed,
+            merged,
+            ..Default::default()
+        };
+        let result = CheckCurrentStateStep.execute(&pull_request).await.unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_labels() {
+        let mut step = CheckBlockingLabels::new(vec!["do-not-merge".into()]);
+        let mut pull_request = PullRequest {
+            labels: vec![Label {
+                name: "enhancement".into(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+
+        pull_request.labels.push(Label {
+            name: "Do-Not-Merge".into(),
+        });
+        let error = step.execute(&pull_request).await.unwrap_err();
+        assert!(error.to_string().contains("Do-Not-Merge"));
+    }
+
+    #[tokio::test]
+    async fn test_check_build_failed_actions_pending() {
+        let fixture = make_workflow_run_fixture();
+        let action_runs = ActionRuns {
+            workflow_runs: vec![fixture.pending, fixture.failed],
+        };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_action_runs()
+            .returning(move |_| Box::pin(future::ready(Ok(action_runs.clone()))));
+
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], true).unwrap();
+        let mut states = CheckStates::default();
+        let result = step
+            .check_actions(&pull_request, &mut states)
+            .await
+            .unwrap();
+        assert_eq!(result, StepStatus::Waiting);
+        assert_eq!(states.pending, vec!["Some workflow".to_string()]);
+        assert!(states.failed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_build_failed_actions_failure() {
+        let fixture = make_workflow_run_fixture();
+        let failed_run_id = fixture.failed.id;
+        let action_runs = ActionRuns {
+            workflow_runs: vec![fixture.failed],
+        };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_action_runs()
+            .returning(move |_| Box::pin(future::ready(Ok(action_runs.clone()))));
+        github
+            .expect_rerun_workflow()
+            .withf(move |_repo, run_id| *run_id == failed_run_id)
+            .returning(move |_, _| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], true).unwrap();
+        let result = step
+            .check_actions(&pull_request, &mut CheckStates::default())
+            .await
+            .unwrap();
+        assert_eq!(result, StepStatus::Waiting);
+    }
+
+    fn make_status(context: &str, state: StatusState) -> Status {
+        Status {
+            target_url: "https://circleci.com/gh/potato/smasher/1337".into(),
+            state,
+            created_at: chrono::offset::Local::now(),
+            context: context.into(),
+        }
+    }
+
+    fn expect_combined_status(
+        github: &mut MockGithubClient,
+        statuses: Vec<Status>,
+        check_runs: Vec<CheckRun>,
+    ) {
+        github.expect_combined_status().returning(move |_, _| {
+            Box::pin(future::ready(Ok(CombinedStatus {
+                state: StatusState::Pending,
+                statuses: statuses.clone(),
+            })))
+        });
+        github.expect_check_runs().returning(move |_, _| {
+            Box::pin(future::ready(Ok(CheckRuns {
+                check_runs: check_runs.clone(),
+            })))
+        });
+    }
+
+    #[tokio::test]
+    async fn test_check_build_failed_merges_check_runs() {
+        let check_run = |name: &str, app: &str, conclusion| CheckRun {
+            name: name.into(),
+            app: Some(CheckRunApp { slug: app.into() }),
+            html_url: Some(format!("https://github.com/potato/smasher/runs/{}", name)),
+            conclusion,
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        expect_combined_status(
+            &mut github,
+            vec![make_status("ci/build", StatusState::Success)],
+            vec![
+                check_run("ci/build", "checker", Some(WorkflowRunConclusion::Failure)),
+                check_run("lint", "checker", Some(WorkflowRunConclusion::Failure)),
+                check_run("docs", "checker", None),
+                check_run(
+                    "test",
+                    "github-actions",
+                    Some(WorkflowRunConclusion::Failure),
+                ),
+            ],
+        );
+        let step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], true).unwrap();
+        let summaries = step
+            .fetch_status_summaries(&PullRequest::default())
+            .await
+            .unwrap();
+        let names = |summaries: &[StatusSummary]| {
+            summaries
+                .iter()
+                .map(|summary| summary.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&summaries.passed), vec!["ci/build"]);
+        assert_eq!(names(&summaries.failed), vec!["lint"]);
+        assert_eq!(names(&summaries.pending), vec!["docs"]);
+    }
+
+    #[rstest]
+    #[case::success(Some(WorkflowRunConclusion::Success), false)]
+    #[case::neutral(Some(WorkflowRunConclusion::Neutral), false)]
+    #[case::skipped(Some(WorkflowRunConclusion::Skipped), false)]
+    #[case::failure(Some(WorkflowRunConclusion::Failure), true)]
+    #[case::cancelled(Some(WorkflowRunConclusion::Unknown), true)]
+    #[tokio::test]
+    async fn test_check_run_conclusions(
+        #[case] conclusion: Option<WorkflowRunConclusion>,
+        #[case] failed: bool,
+    ) {
+        let check_run = CheckRun {
+            name: "lint".into(),
+            app: Some(CheckRunApp {
+                slug: "checker".into(),
+            }),
+            html_url: Some("https://github.com/potato/smasher/runs/1".into()),
+            conclusion,
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        expect_combined_status(
+            &mut github,
+            vec![make_status("ci/build", StatusState::Unknown)],
+            vec![check_run],
+        );
+        let step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], true).unwrap();
+        let summaries = step
+            .fetch_status_summaries(&PullRequest::default())
+            .await
+            .unwrap();
+        // Statuses in the error state always fail
+        assert_eq!(summaries.failed.len(), if failed { 2 } else { 1 });
+    }
+
+    #[tokio::test]
+    async fn test_failing_combined_status_without_failed_checks() {
+        let mut github = MockGithubClient::default();
+        github.expect_combined_status().returning(|_, _| {
+            Box::pin(future::ready(Ok(CombinedStatus {
+                state: StatusState::Failure,
+                statuses: vec![make_status("ci/build", StatusState::Success)],
+            })))
+        });
+        github
+            .expect_check_runs()
+            .returning(|_, _| Box::pin(future::ready(Ok(CheckRuns::default()))));
+        let step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], true).unwrap();
+        assert!(step
+            .fetch_status_summaries(&PullRequest::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_build_failed_ignores_optional_statuses() {
+        let statuses = vec![
+            make_status("required", StatusState::Success),
+            make_status("optional", StatusState::Failure),
+        ];
+        let mut github = MockGithubClient::default();
+        expect_combined_status(&mut github, statuses, vec![]);
+        github
+            .expect_required_status_checks()
+            .times(1)
+            .returning(|_| {
+                Box::pin(future::ready(Ok(RequiredStatusChecks {
+                    contexts: vec!["required".into()],
+                })))
+            });
+
+        let pull_request = PullRequest::default();
+        let mut step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], true).unwrap();
+        for _ in 0..2 {
+            let result = step
+                .check_statuses(&pull_request, &mut CheckStates::default())
+                .await
+                .unwrap();
+            assert_eq!(result, StepStatus::Passed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_behind_master_waits_for_checks_after_update() {
+        // The statuses for each successive poll, starting with the one that updates the branch
+        let polls = Arc::new(Mutex::new(VecDeque::from(vec![
+            vec![make_status("ci/build", StatusState::Success)],
+            vec![],
+            vec![make_status("ci/build", StatusState::Pending)],
+            vec![
+                make_status("ci/build", StatusState::Success),
+                make_status("ci/build", StatusState::Pending),
+            ],
+        ])));
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_statuses().returning(move |_| {
+            let statuses = polls.lock().unwrap().pop_front().unwrap();
+            Box::pin(future::ready(Ok(statuses)))
+        });
+        github.expect_action_runs_for_sha().returning(|_, _| {
+            Box::pin(future::ready(Ok(ActionRuns {
+                workflow_runs: vec![],
+            })))
+        });
+        github
+            .expect_update_branch()
+            .times(1)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let mut step = CheckBehindMaster::new(Arc::new(github), true);
+        let mut pull_request = PullRequest {
+            mergeable_state: MergeableState::Behind,
+            head: Branch {
+                sha: "oldsha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Waiting
+        );
+
+        // Github hasn't caught up with the update yet
+        pull_request.mergeable_state = MergeableState::Clean;
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Waiting
+        );
+
+        // The update is there but CI hasn't started, then it's running and finally it's done
+        pull_request.head.sha = "newsha".into();
+        for expected in [StepStatus::Waiting, StepStatus::Waiting, StepStatus::Passed] {
+            assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+        }
+
+        // There's nothing left to wait for
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_behind_master_already_updated() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_statuses()
+            .returning(|_| Box::pin(future::ready(Ok(vec![]))));
+        github.expect_action_runs_for_sha().returning(|_, _| {
+            Box::pin(future::ready(Ok(ActionRuns {
+                workflow_runs: vec![],
+            })))
+        });
+        github.expect_update_branch().returning(|_| {
+            Box::pin(future::ready(Err(crate::client::Error::Http(
+                reqwest::StatusCode::UNPROCESSABLE_ENTITY,
+            ))))
+        });
+
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Behind,
+            ..Default::default()
+        };
+
+        let mut step = CheckBehindMaster::new(Arc::new(github), true);
+        let result = step.execute(&pull_request).await.unwrap();
+        assert_eq!(result, StepStatus::Waiting);
+    }
+
+    #[rstest]
+    #[case::maintainer_can_modify(true, 1)]
+    #[case::maintainer_cannot_modify(false, 0)]
+    #[tokio::test]
+    async fn test_check_behind_master_fork(
+        #[case] maintainer_can_modify: bool,
+        #[case] updates: usize,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_statuses()
+            .returning(|_| Box::pin(future::ready(Ok(vec![]))));
+        github
+            .expect_action_runs_for_sha()
+            .withf(|repo, _| repo.full_name == "potato/smasher")
+            .returning(|_, _| {
+                Box::pin(future::ready(Ok(ActionRuns {
+                    workflow_runs: vec![],
+                })))
+            });
+        github
+            .expect_update_branch()
+            .times(updates)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let branch = |repo: &str| Branch {
+            repo: Repository {
+                full_name: repo.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Behind,
+            maintainer_can_modify,
+            head: branch("alice/smasher"),
+            base: branch("potato/smasher"),
+            ..Default::default()
+        };
+
+        let mut step = CheckBehindMaster::new(Arc::new(github), true);
+        let result = step.execute(&pull_request).await.unwrap();
+        assert_eq!(result, StepStatus::Waiting);
+    }
+
+    #[rstest]
+    #[case::behind(2, 1)]
+    #[case::up_to_date(0, 0)]
+    #[tokio::test]
+    async fn test_check_behind_master_unknown_state(
+        #[case] behind_by: u32,
+        #[case] updates: usize,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_compare_commits()
+            .withf(|_, base, head| base == "master" && head == "mysha")
+            .times(1)
+            .returning(move |_, _, _| {
+                Box::pin(future::ready(Ok(CommitComparison {
+                    ahead_by: 1,
+                    behind_by,
+                    ..Default::default()
+                })))
+            });
+        github
+            .expect_pull_request_statuses()
+            .returning(|_| Box::pin(future::ready(Ok(vec![]))));
+        github.expect_action_runs_for_sha().returning(|_, _| {
+            Box::pin(future::ready(Ok(ActionRuns {
+                workflow_runs: vec![],
+            })))
+        });
+        github
+            .expect_update_branch()
+            .times(updates)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Unknown,
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            base: Branch {
+                name: "master".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut step = CheckBehindMaster::new(Arc::new(github), true);
+        step.execute(&pull_request).await.unwrap();
+    }
+
+    #[rstest]
+    #[case::behind(2, StepStatus::Waiting)]
+    #[case::up_to_date(0, StepStatus::Passed)]
+    #[tokio::test]
+    async fn test_check_behind_master_compare_branches(
+        #[case] behind_by: u32,
+        #[case] expected: StepStatus,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_compare_commits()
+            .withf(|_, base, head| base == "stack/base" && head == "mysha")
+            .returning(move |_, _, _| {
+                Box::pin(future::ready(Ok(CommitComparison {
+                    ahead_by: 1,
+                    behind_by,
+                    ..Default::default()
+                })))
+            });
+
+        // Github's mergeable state is about the base branch it knows of
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Clean,
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            base: Branch {
+                name: "stack/base".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut step = CheckBehindMaster::new(Arc::new(github), false).with_compare_branches();
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_check_behind_master_no_auto_update() {
+        let mut github = MockGithubClient::default();
+        github.expect_update_branch().never();
+
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Behind,
+            ..Default::default()
+        };
+
+        let mut step = CheckBehindMaster::new(Arc::new(github), false);
+        let result = step.execute(&pull_request).await.unwrap();
+        assert_eq!(result, StepStatus::Waiting);
+    }
+
+    #[test]
+    fn test_check_reviews_compute_approvals() {
+        assert_eq!(
+            1,
+            CheckReviewsStep::compute_approvals(&[make_request_review(
+                "bob",
+                ReviewState::Approved
+            )])
+        );
+        assert_eq!(
+            2,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review("bob", ReviewState::Approved),
+                make_request_review("mike", ReviewState::Approved)
+            ])
+        );
+        assert_eq!(
+            1,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review("bob", ReviewState::Approved),
+                make_request_review("bob", ReviewState::Commented)
+            ])
+        );
+        assert_eq!(
+            0,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review("bob", ReviewState::Approved),
+                make_request_review("bob", ReviewState::ChangesRequested)
+            ])
+        );
+        assert_eq!(
+            0,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review("bob", ReviewState::Approved),
+                make_request_review("bob", ReviewState::Dismissed)
+            ])
+        );
+    }
+
+    #[rstest]
+    #[case::passes(
+        "test \"$MERGEBRO_PR_NUMBER $MERGEBRO_BASE_BRANCH\" = '1337 main'",
+        true
+    )]
+    #[case::fails("echo 'no potatoes allowed' >&2; exit 1", false)]
+    #[tokio::test]
+    async fn test_check_external_script(#[case] command: &str, #[case] passes: bool) {
+        let pull_request = PullRequest {
+            number: 1337,
+            base: Branch {
+                name: "main".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut step = CheckExternalScript::new(ExternalCheck {
+            name: "potatoes".into(),
+            command: command.into(),
+            timeout_seconds: 10,
+        });
+        let result = step.execute(&pull_request).await;
+        assert_eq!(result.is_ok(), passes);
+    }
+
+    #[test]
+    fn test_check_reviews_users_requesting_changes() {
+        let reviews = [
+            make_request_review("mike", ReviewState::ChangesRequested),
+            make_request_review("bob", ReviewState::ChangesRequested),
+            make_request_review("bob", ReviewState::Commented),
+            make_request_review("alice", ReviewState::ChangesRequested),
+            make_request_review("alice", ReviewState::Approved),
+        ];
+        assert_eq!(
+            vec!["bob", "mike"],
+            CheckReviewsStep::users_requesting_changes(&reviews)
+        );
+        assert!(
+            CheckReviewsStep::users_requesting_changes(&[make_request_review(
+                "bob",
+                ReviewState::Dismissed
+            )])
+            .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_reviews_comment_approvers() {
+        let comment = |login: &str, body: &str| IssueComment {
+            id: 1,
+            user: User {
+                login: login.into(),
+            },
+            body: Some(body.into()),
+        };
+        let mut github = MockGithubClient::default();
+        github.expect_list_comments().returning(move |_| {
+            Box::pin(future::ready(Ok(vec![
+                comment("alice", "Looks good\n/lgtm"),
+                comment("bob", "/lgtm"),
+                comment("bob", "Wait, this breaks mashing\n/hold"),
+                comment("carol", "/hold"),
+                comment("carol", " /lgtm "),
+                comment("mallory", "/lgtm"),
+                comment("mike", "/lgtm"),
+            ])))
+        });
+        github
+            .expect_team_members()
+            .withf(|org, slug| org == "potato" && slug == "smashers")
+            .returning(|_, _| {
+                let members = ["alice", "bob", "carol", "mike"].iter().map(|login| User {
+                    login: login.to_string(),
+                });
+                Box::pin(future::ready(Ok(members.collect())))
+            });
+        let config = CommentApprovalConfig {
+            phrase: "/lgtm".into(),
+            retract_phrase: "/hold".into(),
+            authorized_teams: vec!["smashers".into()],
+        };
+        let mut step = CheckReviewsStep::new(Arc::new(github), ReviewsConfig::default());
+        let mut pull_request = PullRequest {
+            creator: User {
+                login: "mike".into(),
+            },
+            ..Default::default()
+        };
+        pull_request.base.repo.owner.login = "potato".into();
+        let approvers = step
+            .comment_approvers(&pull_request, &config)
+            .await
+            .unwrap();
+        let expected: HashSet<_> = ["alice", "carol"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(approvers, expected);
+    }
+
+    #[rstest]
+    #[case::excluding_author(true, vec!["bob"])]
+    #[case::including_author(false, vec!["alice", "bob"])]
+    fn test_check_reviews_counted_reviews(
+        #[case] exclude_author: bool,
+        #[case] expected: Vec<&str>,
+    ) {
+        let reviews = ReviewsConfig {
+            exclude_author,
+            excluded_reviewers: vec!["approve-bot".into()],
+            ..ReviewsConfig::default()
+        };
+        let pull_request = PullRequest {
+            creator: User {
+                login: "alice".into(),
+            },
+            ..Default::default()
+        };
+        let reviews = CheckReviewsStep::counted_reviews(
+            &reviews,
+            &pull_request,
+            vec![
+                make_request_review("alice", ReviewState::Approved),
+                make_request_review("approve-bot", ReviewState::Approved),
+                make_request_review("bob", ReviewState::Approved),
+            ],
+        );
+        let logins: Vec<_> = reviews
+            .iter()
+            .map(|review| review.user.login.as_str())
+            .collect();
+        assert_eq!(logins, expected);
+    }
+
+    #[test]
+    fn test_check_reviews_unexpired_reviews() {
+        let reviews = ReviewsConfig {
+            approval_max_age_seconds: Some(7 * 24 * 60 * 60),
+            ..ReviewsConfig::default()
+        };
+        let now = Local::now();
+        let review = |user: &str, state: ReviewState, days_ago: i64| PullRequestReview {
+            submitted_at: now - chrono::Duration::days(days_ago),
+            ..make_request_review(user, state)
+        };
+        let (reviews, expired_approvers) = CheckReviewsStep::unexpired_reviews(
+            &reviews,
+            vec![
+                review("alice", ReviewState::Approved, 10),
+                review("bob", ReviewState::Commented, 10),
+                review("alice", ReviewState::Approved, 2),
+                review("carol", ReviewState::Approved, 8),
+            ],
+            now,
+        );
+        assert_eq!(expired_approvers, vec!["alice", "carol"]);
+        assert_eq!(
+            CheckReviewsStep::approved_users(&reviews),
+            ["alice"].into_iter().collect()
+        );
+        assert_eq!(reviews.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_check_reviews_requests_fresh_reviews() {
+        let mut github = MockGithubClient::default();
+        github.expect_branch_protection().returning(|_| {
+            Box::pin(future::ready(Err(crate::client::Error::Http(
+                reqwest::StatusCode::NOT_FOUND,
+            ))))
+        });
+        github.expect_pull_request_reviews().returning(|_| {
+            let expired = |user: &str| PullRequestReview {
+                submitted_at: Local::now() - chrono::Duration::days(10),
+                ..make_request_review(user, ReviewState::Approved)
+            };
+            Box::pin(future::ready(Ok(vec![
+                expired("alice"),
+                expired("bob"),
+                make_request_review("bob", ReviewState::Approved),
+            ])))
+        });
+        github
+            .expect_request_reviewers()
+            .withf(|_, reviewers| reviewers == ["alice".to_string()])
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let reviews = ReviewsConfig {
+            approvals: 2,
+            approval_max_age_seconds: Some(7 * 24 * 60 * 60),
+            ..ReviewsConfig::default()
+        };
+        let mut step = CheckReviewsStep::new(Arc::new(github), reviews).with_review_requests(true);
+        assert!(!step.is_readonly());
+        for _ in 0..2 {
+            assert_eq!(
+                step.execute(&PullRequest::default()).await.unwrap(),
+                StepStatus::Waiting
+            );
         }
     }
-}
 
-impl fmt::Display for CheckBuildFailed {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "check if CI builds failed")
+    #[tokio::test]
+    async fn test_check_reviews_expired_approval_after_changes_requested() {
+        let mut github = MockGithubClient::default();
+        github.expect_branch_protection().returning(|_| {
+            Box::pin(future::ready(Err(crate::client::Error::Http(
+                reqwest::StatusCode::NOT_FOUND,
+            ))))
+        });
+        github.expect_pull_request_reviews().returning(|_| {
+            let review = |state: ReviewState, days_ago: i64| PullRequestReview {
+                submitted_at: Local::now() - chrono::Duration::days(days_ago),
+                ..make_request_review("alice", state)
+            };
+            Box::pin(future::ready(Ok(vec![
+                review(ReviewState::ChangesRequested, 12),
+                review(ReviewState::Approved, 10),
+            ])))
+        });
+        github
+            .expect_request_reviewers()
+            .withf(|_, reviewers| reviewers == ["alice".to_string()])
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let reviews = ReviewsConfig {
+            approvals: 1,
+            approval_max_age_seconds: Some(7 * 24 * 60 * 60),
+            ..ReviewsConfig::default()
+        };
+        let mut step = CheckReviewsStep::new(Arc::new(github), reviews).with_review_requests(true);
+        assert_eq!(
+            step.execute(&PullRequest::default()).await.unwrap(),
+            StepStatus::Waiting
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::github::client::MockGithubClient;
-    use crate::github::{ActionRuns, NoBody, User, WorfklowRunStatus, WorkflowRun};
-    use std::future;
+    #[tokio::test]
+    async fn test_check_reviews_records_approvals() {
+        let mut github = MockGithubClient::default();
+        github.expect_branch_protection().returning(|_| {
+            Box::pin(future::ready(Err(crate::client::Error::Http(
+                reqwest::StatusCode::NOT_FOUND,
+            ))))
+        });
+        github.expect_pull_request_reviews().returning(|_| {
+            Box::pin(future::ready(Ok(vec![
+                make_request_review("bob", ReviewState::Approved),
+                make_request_review("alice", ReviewState::Approved),
+                make_request_review("carol", ReviewState::Commented),
+            ])))
+        });
+        github.expect_team_members().returning(|_, _| {
+            Box::pin(future::ready(Ok(vec![User {
+                login: "bob".into(),
+            }])))
+        });
+        let reviews = ReviewsConfig {
+            approvals: 2,
+            required_approvers: vec!["alice".into()],
+            required_teams: vec![TeamApproval {
+                team: "platform".into(),
+                approvals: 1,
+            }],
+            ..ReviewsConfig::default()
+        };
+        let mut step = CheckReviewsStep::new(Arc::new(github), reviews);
+        assert_eq!(step.approvals(), None);
+        assert_eq!(
+            step.execute(&PullRequest::default()).await.unwrap(),
+            StepStatus::Passed
+        );
+        assert_eq!(
+            step.approvals(),
+            Some(Approvals {
+                reviewers: vec!["alice".into(), "bob".into()],
+                commenters: vec![],
+                required_approvers: vec!["alice".into()],
+                teams: vec![TeamApprovers {
+                    team: "platform".into(),
+                    approvers: vec!["bob".into()],
+                }],
+            })
+        );
+    }
 
-    struct WorkflowRunFixture {
-        pending: WorkflowRun,
-        failed: WorkflowRun,
+    #[test]
+    fn test_check_reviews_missing_required_approvers() {
+        let reviews = ReviewsConfig {
+            required_approvers: vec!["alice".into(), "mike".into()],
+            ..ReviewsConfig::default()
+        };
+        let step = CheckReviewsStep::new(Arc::new(MockGithubClient::default()), reviews);
+        assert_eq!(
+            vec!["mike"],
+            step.missing_required_approvers(&[
+                make_request_review("alice", ReviewState::Approved),
+                make_request_review("bob", ReviewState::Approved),
+            ])
+        );
+        assert_eq!(
+            vec!["alice"],
+            step.missing_required_approvers(&[
+                make_request_review("alice", ReviewState::Approved),
+                make_request_review("mike", ReviewState::Approved),
+                make_request_review("alice", ReviewState::ChangesRequested),
+            ])
+        );
     }
 
-    fn make_workflow_run_fixture() -> WorkflowRunFixture {
-        let pending = WorkflowRun {
-            id: 1,
-            workflow_id: 42,
-            name: "Some workflow".into(),
-            head_sha: "mysha".into(),
-            status: WorfklowRunStatus::InProgress,
-            conclusion: None,
+    #[tokio::test]
+    async fn test_check_reviews_team_approvers() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_team_members()
+            .withf(|org, slug| org == "potato" && slug == "platform")
+            .times(1)
+            .returning(|_, _| {
+                Box::pin(future::ready(Ok(vec![
+                    User {
+                        login: "alice".into(),
+                    },
+                    User {
+                        login: "bob".into(),
+                    },
+                ])))
+            });
+        github
+            .expect_team_members()
+            .withf(|org, slug| org == "security-org" && slug == "security")
+            .times(1)
+            .returning(|_, _| {
+                Box::pin(future::ready(Ok(vec![User {
+                    login: "mike".into(),
+                }])))
+            });
+        let reviews = ReviewsConfig {
+            required_teams: vec![
+                TeamApproval {
+                    team: "platform".into(),
+                    approvals: 2,
+                },
+                TeamApproval {
+                    team: "security-org/security".into(),
+                    approvals: 1,
+                },
+            ],
+            ..ReviewsConfig::default()
         };
-        let failed = WorkflowRun {
-            status: WorfklowRunStatus::Completed,
-            conclusion: Some(WorkflowRunConclusion::Failure),
-            ..pending.clone()
+        let mut step = CheckReviewsStep::new(Arc::new(github), reviews);
+        let mut pull_request = PullRequest::default();
+        pull_request.base.repo.owner.login = "potato".into();
+
+        let approvers = |team_approvers: Vec<(TeamApproval, Vec<String>)>| {
+            team_approvers
+                .into_iter()
+                .map(|(team, approvers)| (team.team, approvers))
+                .collect::<Vec<_>>()
         };
-        WorkflowRunFixture { pending, failed }
+        let team_approvers = step
+            .team_approvers(
+                &pull_request,
+                &[
+                    make_request_review("alice", ReviewState::Approved),
+                    make_request_review("mike", ReviewState::Approved),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            approvers(team_approvers),
+            vec![
+                ("platform".to_string(), vec!["alice".to_string()]),
+                (
+                    "security-org/security".to_string(),
+                    vec!["mike".to_string()]
+                ),
+            ]
+        );
+
+        // Team members are cached so they're not fetched again
+        let team_approvers = step
+            .team_approvers(
+                &pull_request,
+                &[
+                    make_request_review("bob", ReviewState::Approved),
+                    make_request_review("alice", ReviewState::Approved),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            approvers(team_approvers),
+            vec![
+                (
+                    "platform".to_string(),
+                    vec!["alice".to_string(), "bob".to_string()]
+                ),
+                ("security-org/security".to_string(), vec![]),
+            ]
+        );
     }
 
-    fn make_request_review(user: &str, state: ReviewState) -> PullRequestReview {
-        PullRequestReview {
-            user: User {
-                login: user.into(),
+    #[tokio::test]
+    async fn test_check_build_failed_ignores_contexts() {
+        let statuses = vec![
+            make_status("ci/build", StatusState::Success),
+            make_status("coverage/project", StatusState::Failure),
+            make_status("coverage/patch", StatusState::Pending),
+        ];
+        let mut github = MockGithubClient::default();
+        expect_combined_status(&mut github, statuses, vec![]);
+        github
+            .expect_required_status_checks()
+            .returning(|_| Box::pin(future::ready(Ok(RequiredStatusChecks::default()))));
+
+        let ignored_contexts = vec!["coverage/*".to_string()];
+        let mut step = CheckBuildFailed::new(
+            Arc::new(github),
+            vec![],
+            HashMap::new(),
+            &ignored_contexts,
+            true,
+        )
+        .unwrap();
+        let result = step
+            .check_statuses(&PullRequest::default(), &mut CheckStates::default())
+            .await
+            .unwrap();
+        assert_eq!(result, StepStatus::Passed);
+    }
+
+    #[rstest]
+    #[case::exact("main", true)]
+    #[case::glob("release/1.2", true)]
+    #[case::feature("feature/potatoes", false)]
+    #[case::prefix("main-backup", false)]
+    #[tokio::test]
+    async fn test_check_base_branch_allowed(#[case] base: &str, #[case] allowed: bool) {
+        let pull_request = PullRequest {
+            base: Branch {
+                name: base.into(),
                 ..Default::default()
             },
-            state,
-            submitted_at: chrono::offset::Local::now(),
-        }
+            ..Default::default()
+        };
+        let mut step = CheckBaseBranchAllowed::new(&["main".into(), "release/*".into()]).unwrap();
+        assert_eq!(step.execute(&pull_request).await.is_ok(), allowed);
     }
 
+    #[rstest]
+    #[case::current("feature", 0, true)]
+    #[case::stale("feature", 3, false)]
+    #[case::parent_itself("main", 3, true)]
     #[tokio::test]
-    async fn test_check_build_failed_actions_pending() {
-        let fixture = make_workflow_run_fixture();
-        let action_runs = ActionRuns {
-            workflow_runs: vec![fixture.pending, fixture.failed],
-        };
+    async fn test_check_base_current(
+        #[case] base: &str,
+        #[case] behind_by: u32,
+        #[case] passed: bool,
+    ) {
         let mut github = MockGithubClient::default();
         github
-            .expect_action_runs()
-            .returning(move |_| Box::pin(future::ready(Ok(action_runs.clone()))));
+            .expect_compare_commits()
+            .withf(|_, base, head| base == "main" && head == "feature")
+            .returning(move |_, _, _| {
+                Box::pin(future::ready(Ok(CommitComparison {
+                    ahead_by: 1,
+                    behind_by,
+                    files: vec![],
+                })))
+            });
+        let mut step = CheckBaseCurrent::new(Arc::new(github), "main");
+        let pull_request = PullRequest {
+            base: Branch {
+                name: base.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(step.execute(&pull_request).await.is_ok(), passed);
+    }
 
+    #[rstest]
+    #[case::feature("feature/add-potatoes", true)]
+    #[case::fix("fix/smasher", true)]
+    #[case::no_prefix("add-potatoes", false)]
+    #[case::prefix_in_middle("potato/feature/add", false)]
+    #[tokio::test]
+    async fn test_check_branch_naming_convention(#[case] head: &str, #[case] allowed: bool) {
         let pull_request = PullRequest {
             head: Branch {
-                sha: "mysha".into(),
+                name: head.into(),
                 ..Default::default()
             },
             ..Default::default()
         };
+        let mut step =
+            CheckBranchNamingConvention::new(&["feature/*".into(), "fix/*".into()]).unwrap();
+        assert_eq!(step.execute(&pull_request).await.is_ok(), allowed);
+    }
 
-        let step = CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new()).unwrap();
-        let result = step.check_actions(&pull_request).await.unwrap();
-        assert_eq!(result, StepStatus::Waiting);
+    #[test]
+    fn test_build_glob_pattern() {
+        let pattern = build_glob_pattern("coverage/*").unwrap();
+        assert!(pattern.is_match("coverage/"));
+        assert!(pattern.is_match("coverage/project"));
+        assert!(!pattern.is_match("ci/coverage/project"));
+
+        let pattern = build_glob_pattern("ci.build").unwrap();
+        assert!(pattern.is_match("ci.build"));
+        assert!(!pattern.is_match("ci-build"));
     }
 
     #[tokio::test]
-    async fn test_check_build_failed_actions_failure() {
+    async fn test_check_build_failed_actions_failure_without_reruns() {
         let fixture = make_workflow_run_fixture();
-        let failed_run_id = fixture.failed.id;
         let action_runs = ActionRuns {
             workflow_runs: vec![fixture.failed],
         };
@@ -471,10 +3650,6 @@ mod tests {
         github
             .expect_action_runs()
             .returning(move |_| Box::pin(future::ready(Ok(action_runs.clone()))));
-        github
-            .expect_rerun_workflow()
-            .withf(move |_repo, run_id| *run_id == failed_run_id)
-            .returning(move |_, _| Box::pin(future::ready(Ok(NoBody {}))));
 
         let pull_request = PullRequest {
             head: Branch {
@@ -484,47 +3659,515 @@ mod tests {
             ..Default::default()
         };
 
-        let step = CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new()).unwrap();
-        let result = step.check_actions(&pull_request).await.unwrap();
-        assert_eq!(result, StepStatus::Waiting);
+        let step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], false).unwrap();
+        assert!(step
+            .check_actions(&pull_request, &mut CheckStates::default())
+            .await
+            .is_err());
     }
 
-    #[test]
-    fn test_check_reviews_compute_approvals() {
+    #[tokio::test]
+    async fn test_check_reviews_unresolved_threads() {
+        let mut github = MockGithubClient::default();
+        github.expect_review_threads().returning(|_| {
+            Box::pin(future::ready(Ok(vec![
+                ReviewThread {
+                    is_resolved: true,
+                    url: None,
+                },
+                ReviewThread {
+                    is_resolved: false,
+                    url: Some("https://github.com/potato/smasher/pull/1#discussion_r1".into()),
+                },
+            ])))
+        });
+        let reviews = ReviewsConfig {
+            require_resolved_threads: true,
+            ..ReviewsConfig::default()
+        };
+        let step = CheckReviewsStep::new(Arc::new(github), reviews);
+        let error = step
+            .check_resolved_threads(&PullRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "1 review threads are unresolved");
+    }
+
+    #[rstest]
+    #[case::unchanged("mysha", StepStatus::Passed)]
+    #[case::force_pushed("othersha", StepStatus::Waiting)]
+    #[tokio::test]
+    async fn test_check_head_unchanged(
+        #[case] current_sha: &'static str,
+        #[case] expected: StepStatus,
+    ) {
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_info().returning(move |_| {
+            Box::pin(future::ready(Ok(PullRequest {
+                head: Branch {
+                    sha: current_sha.into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })))
+        });
+        let identifier = PullRequestIdentifier {
+            base_url: "https://github.com".into(),
+            owner: "potato".into(),
+            repo: "smasher".into(),
+            pull_number: 1337,
+        };
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut step = CheckHeadUnchanged::new(Arc::new(github), identifier);
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_required_labels() {
+        let mut step = CheckRequiredLabels::new(vec!["reviewed".into(), "qa-passed".into()]);
+        let mut pull_request = PullRequest {
+            labels: vec![Label {
+                name: "Reviewed".into(),
+            }],
+            ..Default::default()
+        };
         assert_eq!(
-            1,
-            CheckReviewsStep::compute_approvals(&[make_request_review(
-                "bob",
-                ReviewState::Approved
-            )])
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Waiting
         );
+
+        pull_request.labels.push(Label {
+            name: "qa-passed".into(),
+        });
         assert_eq!(
-            2,
-            CheckReviewsStep::compute_approvals(&[
-                make_request_review("bob", ReviewState::Approved),
-                make_request_review("mike", ReviewState::Approved)
-            ])
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
         );
+    }
+
+    #[rstest]
+    #[case::within_limits(10, 100, 200, &[], StepStatus::Passed)]
+    #[case::too_many_files(11, 100, 200, &[], StepStatus::Waiting)]
+    #[case::too_many_lines(10, 400, 200, &[], StepStatus::Waiting)]
+    #[case::overridden(50, 400, 200, &["Allow-Large-Merge"], StepStatus::Passed)]
+    #[case::other_label(50, 400, 200, &["enhancement"], StepStatus::Waiting)]
+    #[tokio::test]
+    async fn test_pull_request_size(
+        #[case] changed_files: u32,
+        #[case] additions: u32,
+        #[case] deletions: u32,
+        #[case] labels: &[&str],
+        #[case] expected: StepStatus,
+    ) {
+        let mut step = CheckPullRequestSize::new(Some(10), Some(500), "allow-large-merge".into());
+        let pull_request = PullRequest {
+            changed_files,
+            additions,
+            deletions,
+            labels: labels
+                .iter()
+                .map(|name| Label {
+                    name: name.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::matches("feat(parser): add potatoes", StepStatus::Passed)]
+    #[case::no_scope("fix: smash potatoes", StepStatus::Passed)]
+    #[case::no_type("Add potatoes", StepStatus::Waiting)]
+    #[case::unknown_type("potato: add potatoes", StepStatus::Waiting)]
+    #[tokio::test]
+    async fn test_title_pattern(#[case] title: &str, #[case] expected: StepStatus) {
+        let mut step = CheckTitlePattern::new(r"^(feat|fix|chore)(\([a-z]+\))?: .+").unwrap();
+        let pull_request = PullRequest {
+            title: title.into(),
+            ..Default::default()
+        };
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_invalid_title_pattern() {
+        assert!(CheckTitlePattern::new("(feat").is_err());
+    }
+
+    #[rstest]
+    #[case::colon("WIP: add potatoes", StepStatus::Waiting)]
+    #[case::brackets("[wip] add potatoes", StepStatus::Waiting)]
+    #[case::ready("Add potatoes", StepStatus::Passed)]
+    #[case::wip_in_middle("Add WIP potatoes", StepStatus::Passed)]
+    #[tokio::test]
+    async fn test_title_not_wip(#[case] title: &str, #[case] expected: StepStatus) {
+        let mut step = CheckTitleNotWip::new(r"(?i)^(wip:|\[wip\])").unwrap();
+        let pull_request = PullRequest {
+            title: title.into(),
+            ..Default::default()
+        };
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::before("2026-12-19T23:00:00Z", None)]
+    #[case::during("2026-12-24T12:00:00Z", Some("2027-01-04T00:00:00Z"))]
+    #[case::overlapping("2027-01-02T12:00:00Z", Some("2027-01-04T00:00:00Z"))]
+    #[case::within_short_one("2026-12-31T12:00:00Z", Some("2027-01-04T00:00:00Z"))]
+    #[case::at_end("2027-01-04T00:00:00Z", None)]
+    fn test_active_freeze(#[case] now: &str, #[case] expected_end: Option<&str>) {
+        let period = |start: &str, end: &str| FreezePeriod {
+            start: DateTime::parse_from_rfc3339(start).unwrap(),
+            end: DateTime::parse_from_rfc3339(end).unwrap(),
+            reason: None,
+        };
+        let periods = vec![
+            period("2026-12-20T00:00:00Z", "2027-01-04T00:00:00Z"),
+            period("2026-12-31T00:00:00Z", "2027-01-01T00:00:00Z"),
+        ];
+        let now = DateTime::parse_from_rfc3339(now)
+            .unwrap()
+            .with_timezone(&Utc);
+        let end = CheckNotFrozen::active_freeze(&periods, now).map(|period| period.end);
         assert_eq!(
-            1,
-            CheckReviewsStep::compute_approvals(&[
-                make_request_review("bob", ReviewState::Approved),
-                make_request_review("bob", ReviewState::Commented)
-            ])
+            end,
+            expected_end.map(|end| DateTime::parse_from_rfc3339(end).unwrap())
         );
+    }
+
+    #[tokio::test]
+    async fn test_milestone_assigned() {
+        let mut pull_request = PullRequest::default();
         assert_eq!(
-            0,
-            CheckReviewsStep::compute_approvals(&[
-                make_request_review("bob", ReviewState::Approved),
-                make_request_review("bob", ReviewState::ChangesRequested)
-            ])
+            CheckMilestoneAssigned.execute(&pull_request).await.unwrap(),
+            StepStatus::Waiting
         );
+
+        pull_request.milestone = Some(Milestone {
+            number: 1,
+            title: "v1.0".into(),
+        });
         assert_eq!(
-            0,
-            CheckReviewsStep::compute_approvals(&[
-                make_request_review("bob", ReviewState::Approved),
-                make_request_review("bob", ReviewState::Dismissed)
-            ])
+            CheckMilestoneAssigned.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
         );
     }
+
+    #[rstest]
+    #[case::closes("Closes #42", vec!["#42"])]
+    #[case::lowercase_with_colon("fixes: #42", vec!["#42"])]
+    #[case::other_repo("Resolved potato/smasher#7", vec!["potato/smasher#7"])]
+    #[case::several(
+        "Fix #1, closes #2 and fixes #1 again",
+        vec!["#1", "#2"]
+    )]
+    #[case::mention_only("Related to #42", vec![])]
+    #[case::no_number("Closes the potato issue", vec![])]
+    fn test_linked_issues(#[case] body: &str, #[case] expected: Vec<&str>) {
+        let step = CheckLinkedIssue::new(Arc::new(MockGithubClient::default()), false);
+        let references: Vec<_> = step
+            .linked_issues(body)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(references, expected);
+    }
+
+    #[rstest]
+    #[case::none(None, StepStatus::Waiting)]
+    #[case::open(Some(PullRequestState::Open), StepStatus::Passed)]
+    #[case::closed(Some(PullRequestState::Closed), StepStatus::Waiting)]
+    #[tokio::test]
+    async fn test_check_linked_issue_verified(
+        #[case] state: Option<PullRequestState>,
+        #[case] expected: StepStatus,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_issue()
+            .withf(|repo, number| repo == "potato/smasher" && *number == 42)
+            .returning(move |_, number| {
+                Box::pin(future::ready(match &state {
+                    Some(state) => Ok(Issue {
+                        number,
+                        state: state.clone(),
+                    }),
+                    None => Err(crate::client::Error::Http(reqwest::StatusCode::NOT_FOUND)),
+                }))
+            });
+        let mut step = CheckLinkedIssue::new(Arc::new(github), true);
+        let pull_request = PullRequest {
+            body: Some("Closes #42".into()),
+            base: Branch {
+                repo: Repository {
+                    full_name: "potato/smasher".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::stale_approval_discarded(2, false, false, false)]
+    #[case::enough_fresh_approvals(1, false, false, true)]
+    #[case::github_dismisses_stale_reviews(2, true, false, true)]
+    #[case::author_approval_discarded(1, false, true, false)]
+    #[tokio::test]
+    async fn test_check_approved_after_last_push(
+        #[case] approvals: u32,
+        #[case] dismiss_stale_reviews: bool,
+        #[case] exclude_author: bool,
+        #[case] passes: bool,
+    ) {
+        let now = chrono::offset::Local::now();
+        let last_push = now - chrono::Duration::hours(1);
+        let mut github = MockGithubClient::default();
+        github.expect_branch_protection().returning(move |_| {
+            Box::pin(future::ready(Ok(BranchProtection {
+                reviews: BranchProtectionReviews {
+                    approvals,
+                    dismiss_stale_reviews,
+                },
+                ..Default::default()
+            })))
+        });
+        github
+            .expect_commit()
+            .withf(|_, sha| sha == "mysha")
+            .returning(move |_, sha| {
+                let signature = CommitSignature {
+                    name: "alice".into(),
+                    email: "alice@potato.com".into(),
+                    date: last_push,
+                };
+                Box::pin(future::ready(Ok(Commit {
+                    sha: sha.into(),
+                    commit: CommitDetails {
+                        author: signature.clone(),
+                        committer: signature,
+                        message: "Add potatoes".into(),
+                    },
+                    author: None,
+                })))
+            });
+        github.expect_pull_request_reviews().returning(move |_| {
+            Box::pin(future::ready(Ok(vec![
+                PullRequestReview {
+                    submitted_at: now - chrono::Duration::hours(2),
+                    ..make_request_review("alice", ReviewState::Approved)
+                },
+                make_request_review("bob", ReviewState::Approved),
+            ])))
+        });
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            creator: User {
+                login: "bob".into(),
+            },
+            ..Default::default()
+        };
+        let reviews = ReviewsConfig {
+            exclude_author,
+            ..ReviewsConfig::default()
+        };
+
+        let mut step = CheckApprovedAfterLastPush::new(Arc::new(github), reviews);
+        assert_eq!(step.execute(&pull_request).await.is_ok(), passes);
+    }
+
+    #[rstest]
+    #[case::approved_after_force_push(1, None)]
+    #[case::approved_before_force_push(3, Some(vec!["alice".to_string()]))]
+    #[tokio::test]
+    async fn test_check_no_force_push_since_approval(
+        #[case] approved_hours_ago: i64,
+        #[case] expected_reviewers: Option<Vec<String>>,
+    ) {
+        let now = chrono::offset::Local::now();
+        let mut github = MockGithubClient::new();
+        github.expect_timeline_events().returning(move |_| {
+            Box::pin(future::ready(Ok(vec![
+                TimelineEvent {
+                    event: "reviewed".into(),
+                    created_at: Some(now - chrono::Duration::hours(approved_hours_ago)),
+                    ..Default::default()
+                },
+                TimelineEvent {
+                    event: "head_ref_force_pushed".into(),
+                    actor: Some(User {
+                        login: "mallory".into(),
+                    }),
+                    created_at: Some(now - chrono::Duration::hours(2)),
+                    ..Default::default()
+                },
+            ])))
+        });
+        github.expect_pull_request_reviews().returning(move |_| {
+            Box::pin(future::ready(Ok(vec![
+                PullRequestReview {
+                    submitted_at: now - chrono::Duration::hours(approved_hours_ago),
+                    ..make_request_review("alice", ReviewState::Approved)
+                },
+                PullRequestReview {
+                    submitted_at: now - chrono::Duration::hours(4),
+                    ..make_request_review("bob", ReviewState::Approved)
+                },
+                PullRequestReview {
+                    submitted_at: now - chrono::Duration::hours(3),
+                    ..make_request_review("bob", ReviewState::ChangesRequested)
+                },
+            ])))
+        });
+        let passes = expected_reviewers.is_none();
+        if let Some(expected) = expected_reviewers {
+            github
+                .expect_request_reviewers()
+                .withf(move |_, reviewers| reviewers == expected.as_slice())
+                .times(1)
+                .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        }
+
+        let mut step = CheckNoForcePushSinceApproval::new(Arc::new(github), true);
+        let expected_status = if passes {
+            StepStatus::Passed
+        } else {
+            StepStatus::Waiting
+        };
+        for _ in 0..2 {
+            let status = step.execute(&PullRequest::default()).await.unwrap();
+            assert_eq!(status, expected_status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_no_force_push_since_approval_without_force_push() {
+        let mut github = MockGithubClient::new();
+        github.expect_timeline_events().returning(|_| {
+            Box::pin(future::ready(Ok(vec![TimelineEvent {
+                event: "committed".into(),
+                ..Default::default()
+            }])))
+        });
+        let mut step = CheckNoForcePushSinceApproval::new(Arc::new(github), true);
+        let status = step.execute(&PullRequest::default()).await.unwrap();
+        assert_eq!(status, StepStatus::Passed);
+    }
+
+    #[rstest]
+    #[case::all_reported(vec!["ci/build", "tests"], true, true)]
+    #[case::missing_required(vec!["ci/build", "lint"], true, false)]
+    #[case::no_checks(vec![], false, false)]
+    #[case::not_required(vec![], true, true)]
+    #[tokio::test]
+    async fn test_check_required_checks_present(
+        #[case] required: Vec<&'static str>,
+        #[case] reported: bool,
+        #[case] passes: bool,
+    ) {
+        let mut github = MockGithubClient::new();
+        github
+            .expect_required_status_checks()
+            .times(1)
+            .returning(move |_| {
+                let contexts = required.iter().map(|context| context.to_string()).collect();
+                Box::pin(future::ready(Ok(RequiredStatusChecks { contexts })))
+            });
+        github.expect_pull_request_statuses().returning(move |_| {
+            let statuses = match reported {
+                true => vec![make_status("ci/build", StatusState::Pending)],
+                false => Vec::new(),
+            };
+            Box::pin(future::ready(Ok(statuses)))
+        });
+        github.expect_check_runs().returning(move |_, _| {
+            let check_runs = match reported {
+                true => vec![CheckRun {
+                    name: "tests".into(),
+                    ..Default::default()
+                }],
+                false => Vec::new(),
+            };
+            Box::pin(future::ready(Ok(CheckRuns { check_runs })))
+        });
+
+        let mut step = CheckRequiredChecksPresent::new(Arc::new(github));
+        let expected = match passes {
+            true => StepStatus::Passed,
+            false => StepStatus::Waiting,
+        };
+        for _ in 0..2 {
+            assert_eq!(
+                step.execute(&PullRequest::default()).await.unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::soaking(2, StepStatus::Waiting)]
+    #[case::soaked(20, StepStatus::Passed)]
+    #[tokio::test]
+    async fn test_check_build_failed_min_green(
+        #[case] green_minutes_ago: i64,
+        #[case] expected: StepStatus,
+    ) {
+        let now = chrono::offset::Local::now();
+        let mut github = MockGithubClient::new();
+        github.expect_required_status_checks().returning(|_| {
+            Box::pin(future::ready(Err(crate::client::Error::Http(
+                reqwest::StatusCode::NOT_FOUND,
+            ))))
+        });
+        github.expect_pull_request_statuses().returning(move |_| {
+            Box::pin(future::ready(Ok(vec![Status {
+                created_at: now - chrono::Duration::minutes(30),
+                ..make_status("ci/build", StatusState::Success)
+            }])))
+        });
+        github.expect_check_runs().returning(move |_, _| {
+            Box::pin(future::ready(Ok(CheckRuns {
+                check_runs: vec![CheckRun {
+                    name: "deploy-preview".into(),
+                    conclusion: Some(WorkflowRunConclusion::Success),
+                    completed_at: Some(now - chrono::Duration::minutes(green_minutes_ago)),
+                    ..Default::default()
+                }],
+            })))
+        });
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Clean,
+            ..Default::default()
+        };
+        let mut step = CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), &[], true)
+            .unwrap()
+            .with_min_green(std::time::Duration::from_secs(600));
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::too_new(30, StepStatus::Waiting)]
+    #[case::old_enough(90, StepStatus::Passed)]
+    #[tokio::test]
+    async fn test_check_minimum_age(#[case] minutes_ago: i64, #[case] expected: StepStatus) {
+        let pull_request = PullRequest {
+            created_at: Some(chrono::offset::Local::now() - chrono::Duration::minutes(minutes_ago)),
+            ..Default::default()
+        };
+        let mut step = CheckMinimumAge::new(std::time::Duration::from_secs(3600));
+        assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+    }
 }
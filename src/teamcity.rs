@@ -0,0 +1,279 @@
+use crate::client::{ApiClient, Result};
+use crate::github::PullRequest;
+use crate::processing::Error;
+use crate::processing::{WorkflowRunner, WorkflowStatus};
+use async_trait::async_trait;
+use log::info;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT};
+use reqwest::Url;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Build {
+    pub id: u64,
+
+    /// Unset while the build is queued
+    pub status: Option<BuildStatus>,
+
+    pub state: BuildState,
+    pub web_url: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BuildStatus {
+    Success,
+    Failure,
+    Error,
+
+    /// Used for canceled builds, among others
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildState {
+    Queued,
+    Running,
+    Finished,
+
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Builds {
+    #[serde(default)]
+    pub build: Vec<Build>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NoBody {}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct QueueBuildRequest<'a> {
+    build_type: BuildType<'a>,
+    branch_name: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+struct BuildType<'a> {
+    id: &'a str,
+}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait TeamCityClient {
+    /// The most recent build of the configured build type on a branch, including queued ones
+    async fn latest_build(&self, branch: &str) -> Result<Option<Build>>;
+    async fn queue_build(&self, branch: &str) -> Result<NoBody>;
+}
+
+pub struct DefaultTeamCityClient {
+    client: ApiClient,
+    api_base: String,
+    build_type_id: String,
+}
+
+impl DefaultTeamCityClient {
+    pub fn new<S: Into<String>>(base_url: &Url, build_type_id: S, token: String) -> Self {
+        // The API replies with XML unless told otherwise
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        Self {
+            client: ApiClient::from_bearer_token(token).with_headers(headers),
+            api_base: format!("{}/app/rest", base_url.as_str().trim_end_matches('/')),
+            build_type_id: build_type_id.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl TeamCityClient for DefaultTeamCityClient {
+    async fn latest_build(&self, branch: &str) -> Result<Option<Build>> {
+        let url = format!(
+            "{}/builds?locator=buildType:(id:{}),branch:(name:{}),defaultFilter:false,state:any,count:1",
+            self.api_base, self.build_type_id, branch
+        );
+        let builds: Builds = self.client.get(&url).await?;
+        Ok(builds.build.into_iter().next())
+    }
+
+    async fn queue_build(&self, branch: &str) -> Result<NoBody> {
+        let url = format!("{}/buildQueue", self.api_base);
+        let body = QueueBuildRequest {
+            build_type: BuildType {
+                id: &self.build_type_id,
+            },
+            branch_name: branch,
+        };
+        self.client.post(&url, &body).await
+    }
+}
+
+/// Re-runs failed TeamCity builds of a build type for pull requests. These are built on the
+/// `pull/{number}` branch, which is what TeamCity's pull requests build feature uses.
+pub struct TeamCityWorkflowRunner<C> {
+    client: Arc<C>,
+    base_url: Url,
+}
+
+impl<C: TeamCityClient> TeamCityWorkflowRunner<C> {
+    pub fn new(client: Arc<C>, base_url: Url) -> Self {
+        Self { client, base_url }
+    }
+
+    fn is_build_url(&self, url: &Url) -> bool {
+        url.host() == self.base_url.host()
+            && url.port() == self.base_url.port()
+            && url.path().starts_with(self.base_url.path())
+    }
+}
+
+#[async_trait]
+impl<C: TeamCityClient + Send + Sync> WorkflowRunner for TeamCityWorkflowRunner<C> {
+    async fn process_failed_jobs(
+        &self,
+        pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> std::result::Result<WorkflowStatus, Error> {
+        let build_urls: Vec<_> = job_urls
+            .iter()
+            .filter(|url| self.is_build_url(url))
+            .cloned()
+            .collect();
+        if build_urls.is_empty() {
+            return Ok(WorkflowStatus::Success);
+        }
+        let branch = format!("pull/{}", pull_request.number);
+        let build = match self.client.latest_build(&branch).await? {
+            Some(build) => build,
+            None => return Ok(WorkflowStatus::Success),
+        };
+        match (&build.state, &build.status) {
+            (BuildState::Queued, _) | (BuildState::Running, _) => {
+                info!("TeamCity build {} is still running", build.id);
+                Ok(WorkflowStatus::Pending)
+            }
+            // The status reported on github is outdated, e.g. the build was re-ran in TeamCity
+            (BuildState::Finished, Some(BuildStatus::Success)) => {
+                Ok(WorkflowStatus::Accepted(build_urls))
+            }
+            (BuildState::Finished, _) => {
+                info!(
+                    "Re-running failed TeamCity build {} at {}",
+                    build.id, build.web_url
+                );
+                self.client.queue_build(&branch).await?;
+                Ok(WorkflowStatus::Triggered)
+            }
+            (BuildState::Unknown, _) => Ok(WorkflowStatus::Success),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::future;
+
+    const BUILD_URL: &str = "https://ci.potato.com/teamcity/buildConfiguration/Smasher_Build/42";
+
+    fn make_runner(client: MockTeamCityClient) -> TeamCityWorkflowRunner<MockTeamCityClient> {
+        TeamCityWorkflowRunner::new(
+            Arc::new(client),
+            Url::parse("https://ci.potato.com/teamcity").unwrap(),
+        )
+    }
+
+    #[rstest]
+    #[case::queued(BuildState::Queued, None, WorkflowStatus::Pending)]
+    #[case::running(
+        BuildState::Running,
+        Some(BuildStatus::Failure),
+        WorkflowStatus::Pending
+    )]
+    #[case::succeeded(
+        BuildState::Finished,
+        Some(BuildStatus::Success),
+        WorkflowStatus::Accepted(vec![Url::parse(BUILD_URL).unwrap()])
+    )]
+    #[case::failed(
+        BuildState::Finished,
+        Some(BuildStatus::Failure),
+        WorkflowStatus::Triggered
+    )]
+    #[case::errored(
+        BuildState::Finished,
+        Some(BuildStatus::Error),
+        WorkflowStatus::Triggered
+    )]
+    #[case::canceled(
+        BuildState::Finished,
+        Some(BuildStatus::Unknown),
+        WorkflowStatus::Triggered
+    )]
+    #[tokio::test]
+    async fn test_process_failed_jobs(
+        #[case] state: BuildState,
+        #[case] status: Option<BuildStatus>,
+        #[case] expected: WorkflowStatus,
+    ) {
+        let mut client = MockTeamCityClient::default();
+        client
+            .expect_latest_build()
+            .withf(|branch| branch == "pull/1337")
+            .returning(move |_| {
+                Box::pin(future::ready(Ok(Some(Build {
+                    id: 42,
+                    status: status.clone(),
+                    state: state.clone(),
+                    web_url: BUILD_URL.into(),
+                }))))
+            });
+        client
+            .expect_queue_build()
+            .withf(|branch| branch == "pull/1337")
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+        let runner = make_runner(client);
+        let pull_request = PullRequest {
+            number: 1337,
+            ..Default::default()
+        };
+        let job_urls = vec![
+            Url::parse(BUILD_URL).unwrap(),
+            Url::parse("https://circleci.com/gh/potato/smasher/1").unwrap(),
+        ];
+        let status = runner
+            .process_failed_jobs(&pull_request, &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, expected);
+    }
+
+    #[tokio::test]
+    async fn test_ignores_other_urls() {
+        let runner = make_runner(MockTeamCityClient::default());
+        let job_urls = vec![Url::parse("https://ci.potato.com/jenkins/job/smasher/1").unwrap()];
+        let status = runner
+            .process_failed_jobs(&PullRequest::default(), &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, WorkflowStatus::Success);
+    }
+
+    #[test]
+    fn test_parse_builds() {
+        let builds: Builds = serde_json::from_str(
+            r#"{"count": 1, "build": [{"id": 42, "buildTypeId": "Smasher_Build", "state": "queued", "webUrl": "https://ci.potato.com/teamcity/viewQueued.html?itemId=42"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(builds.build[0].state, BuildState::Queued);
+        assert_eq!(builds.build[0].status, None);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::client::{ApiClient, Result};
+use crate::github::PullRequest;
+use crate::processing::Error;
+use crate::processing::{WorkflowRunner, WorkflowStatus};
+use async_trait::async_trait;
+use log::{debug, info};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Url;
+use serde_derive::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Build {
+    pub id: u64,
+    pub state: BuildState,
+    pub commit: Commit,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub sha: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum BuildState {
+    #[serde(rename = "created")]
+    Created,
+
+    #[serde(rename = "received")]
+    Received,
+
+    #[serde(rename = "started")]
+    Started,
+
+    #[serde(rename = "passed")]
+    Passed,
+
+    #[serde(rename = "failed")]
+    Failed,
+
+    /// The build couldn't run, e.g. because its configuration is broken
+    #[serde(rename = "errored")]
+    Errored,
+
+    #[serde(rename = "canceled")]
+    Canceled,
+
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NoBody {}
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait TravisClient {
+    async fn build(&self, build_id: u64) -> Result<Build>;
+    async fn restart_build(&self, build_id: u64) -> Result<NoBody>;
+}
+
+pub struct DefaultTravisClient {
+    client: ApiClient,
+}
+
+impl DefaultTravisClient {
+    const API_BASE: &'static str = "https://api.travis-ci.com";
+
+    pub fn new<S: Into<String>>(token: S) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert("Travis-API-Version", HeaderValue::from_static("3"));
+        Self {
+            client: ApiClient::from_token(token).with_headers(headers),
+        }
+    }
+}
+
+#[async_trait]
+impl TravisClient for DefaultTravisClient {
+    async fn build(&self, build_id: u64) -> Result<Build> {
+        let url = format!("{}/build/{}", Self::API_BASE, build_id);
+        self.client.get(&url).await
+    }
+
+    async fn restart_build(&self, build_id: u64) -> Result<NoBody> {
+        let url = format!("{}/build/{}/restart", Self::API_BASE, build_id);
+        self.client.post(&url, &()).await
+    }
+}
+
+/// Restarts failed Travis CI builds that reported a failed status on a pull request
+pub struct TravisWorkflowRunner<C> {
+    client: Arc<C>,
+}
+
+impl<C: TravisClient> TravisWorkflowRunner<C> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self { client }
+    }
+
+    fn parse_build_url(url: &Url) -> Option<u64> {
+        let domain = url.domain()?;
+        if domain != "travis-ci.com" && !domain.ends_with(".travis-ci.com") {
+            return None;
+        }
+        // URLs look like `https://app.travis-ci.com/github/{owner}/{repo}/builds/{id}`
+        let mut segments = url.path_segments()?;
+        segments.find(|segment| *segment == "builds")?;
+        segments.next()?.parse().ok()
+    }
+}
+
+#[async_trait]
+impl<C: TravisClient + Send + Sync> WorkflowRunner for TravisWorkflowRunner<C> {
+    async fn process_failed_jobs(
+        &self,
+        pull_request: &PullRequest,
+        job_urls: &[Url],
+    ) -> std::result::Result<WorkflowStatus, Error> {
+        let mut pending = HashSet::new();
+        let mut failed = HashSet::new();
+        for job_url in job_urls {
+            let build_id = match Self::parse_build_url(job_url) {
+                Some(build_id) => build_id,
+                None => continue,
+            };
+            let build = self.client.build(build_id).await?;
+            if build.commit.sha != pull_request.head.sha {
+                debug!("Ignoring travis build {} for an older commit", build.id);
+                continue;
+            }
+            match build.state {
+                BuildState::Created | BuildState::Received | BuildState::Started => {
+                    pending.insert(build.id);
+                }
+                BuildState::Failed | BuildState::Errored | BuildState::Canceled => {
+                    failed.insert(build.id);
+                }
+                BuildState::Passed | BuildState::Unknown => (),
+            };
+        }
+        if !pending.is_empty() {
+            info!("{} failed travis builds are still running", pending.len());
+            return Ok(WorkflowStatus::Pending);
+        }
+        if failed.is_empty() {
+            return Ok(WorkflowStatus::Success);
+        }
+        info!("Restarting {} failed travis builds", failed.len());
+        for build_id in failed {
+            self.client.restart_build(build_id).await?;
+        }
+        Ok(WorkflowStatus::Triggered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Branch;
+    use rstest::rstest;
+    use std::future;
+
+    #[test]
+    fn test_parse_build_url() {
+        let parse = |url| {
+            TravisWorkflowRunner::<MockTravisClient>::parse_build_url(&Url::parse(url).unwrap())
+        };
+        assert_eq!(
+            parse("https://app.travis-ci.com/github/potato/smasher/builds/1337?utm_source=github"),
+            Some(1337)
+        );
+        assert_eq!(
+            parse("https://travis-ci.com/potato/smasher/builds/42"),
+            Some(42)
+        );
+        assert_eq!(
+            parse("https://app.travis-ci.com/github/potato/smasher"),
+            None
+        );
+        assert_eq!(
+            parse("https://circleci.com/gh/potato/smasher/builds/42"),
+            None
+        );
+    }
+
+    #[rstest]
+    #[case::started(BuildState::Started, WorkflowStatus::Pending)]
+    #[case::passed(BuildState::Passed, WorkflowStatus::Success)]
+    #[case::failed(BuildState::Failed, WorkflowStatus::Triggered)]
+    #[case::errored(BuildState::Errored, WorkflowStatus::Triggered)]
+    #[tokio::test]
+    async fn test_process_failed_jobs(#[case] state: BuildState, #[case] expected: WorkflowStatus) {
+        let mut client = MockTravisClient::default();
+        client.expect_build().returning(move |build_id| {
+            Box::pin(future::ready(Ok(Build {
+                id: build_id,
+                state: state.clone(),
+                commit: Commit {
+                    sha: "mysha".into(),
+                },
+            })))
+        });
+        client
+            .expect_restart_build()
+            .withf(|build_id| *build_id == 1337)
+            .returning(|_| Box::pin(future::ready(Ok(NoBody {}))));
+        let runner = TravisWorkflowRunner::new(Arc::new(client));
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job_urls =
+            vec![
+                Url::parse("https://app.travis-ci.com/github/potato/smasher/builds/1337").unwrap(),
+            ];
+        let status = runner
+            .process_failed_jobs(&pull_request, &job_urls)
+            .await
+            .unwrap();
+        assert_eq!(status, expected);
+    }
+}
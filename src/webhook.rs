@@ -0,0 +1,368 @@
+use crate::github::{Repository, StatusState};
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_LENGTH;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, error, info, warn};
+use openssl::{hash::MessageDigest, memcmp, pkey::PKey, sign::Signer};
+use serde_derive::Deserialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+const EVENT_HEADER: &str = "x-github-event";
+const SIGNATURE_HEADER: &str = "x-hub-signature-256";
+
+/// The largest payload github sends, anything bigger is rejected before it's read, let alone
+/// verified
+const MAX_BODY_BYTES: usize = 25 * 1024 * 1024;
+
+/// Something that happened in github that may let pull requests make progress
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookTrigger {
+    PullRequests {
+        repo: Repository,
+        numbers: Vec<u32>,
+    },
+
+    /// A commit changed, github doesn't say which pull requests it belongs to
+    Commit {
+        repo: Repository,
+        sha: String,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+struct PullRequestNumber {
+    number: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct CheckSuite {
+    pull_requests: Vec<PullRequestNumber>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CheckSuiteEvent {
+    action: String,
+    check_suite: CheckSuite,
+    repository: Repository,
+}
+
+#[derive(Deserialize, Debug)]
+struct PullRequestReviewEvent {
+    action: String,
+    pull_request: PullRequestNumber,
+    repository: Repository,
+}
+
+#[derive(Deserialize, Debug)]
+struct PullRequestEvent {
+    action: String,
+    number: u32,
+    repository: Repository,
+}
+
+#[derive(Deserialize, Debug)]
+struct StatusEvent {
+    sha: String,
+    state: StatusState,
+    repository: Repository,
+}
+
+/// Checks a `X-Hub-Signature-256` header, which is the HMAC-SHA256 of the body using the secret
+pub fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let provided = match signature.strip_prefix("sha256=").and_then(decode_hex) {
+        Some(provided) => provided,
+        None => return false,
+    };
+    let expected = match hmac_sha256(secret.as_bytes(), body) {
+        Ok(expected) => expected,
+        Err(e) => {
+            error!("Failed to compute webhook signature: {}", e);
+            return false;
+        }
+    };
+    provided.len() == expected.len() && memcmp::eq(&provided, &expected)
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+    signer.sign_to_vec()
+}
+
+fn decode_hex(input: &str) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..input.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(input.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses a webhook event, returning `None` if it can't affect whether a pull request is merged
+pub fn parse_event(event: &str, body: &[u8]) -> Result<Option<WebhookTrigger>, serde_json::Error> {
+    let trigger = match event {
+        "check_suite" => {
+            let event: CheckSuiteEvent = serde_json::from_slice(body)?;
+            if event.action != "completed" || event.check_suite.pull_requests.is_empty() {
+                return Ok(None);
+            }
+            WebhookTrigger::PullRequests {
+                repo: event.repository,
+                numbers: event
+                    .check_suite
+                    .pull_requests
+                    .into_iter()
+                    .map(|pull_request| pull_request.number)
+                    .collect(),
+            }
+        }
+        "pull_request_review" => {
+            let event: PullRequestReviewEvent = serde_json::from_slice(body)?;
+            if event.action != "submitted" {
+                return Ok(None);
+            }
+            WebhookTrigger::PullRequests {
+                repo: event.repository,
+                numbers: vec![event.pull_request.number],
+            }
+        }
+        "pull_request" => {
+            let event: PullRequestEvent = serde_json::from_slice(body)?;
+            if event.action != "labeled" {
+                return Ok(None);
+            }
+            WebhookTrigger::PullRequests {
+                repo: event.repository,
+                numbers: vec![event.number],
+            }
+        }
+        "status" => {
+            let event: StatusEvent = serde_json::from_slice(body)?;
+            if event.state == StatusState::Pending {
+                return Ok(None);
+            }
+            WebhookTrigger::Commit {
+                repo: event.repository,
+                sha: event.sha,
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(trigger))
+}
+
+/// Reads a body, giving up with `None` as soon as it's longer than `limit` bytes
+async fn read_limited_body(mut body: Body, limit: usize) -> Result<Option<Vec<u8>>, hyper::Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+fn respond(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    secret: Arc<String>,
+    triggers: mpsc::UnboundedSender<WebhookTrigger>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST {
+        return Ok(respond(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let event = header(EVENT_HEADER).unwrap_or_default();
+    let signature = header(SIGNATURE_HEADER).unwrap_or_default();
+    let content_length = header(CONTENT_LENGTH.as_str()).and_then(|value| value.parse().ok());
+    if content_length.is_some_and(|length: usize| length > MAX_BODY_BYTES) {
+        warn!("Rejecting '{}' webhook with a body that's too large", event);
+        return Ok(respond(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let body = match read_limited_body(request.into_body(), MAX_BODY_BYTES).await {
+        Ok(Some(body)) => body,
+        Ok(None) => {
+            warn!("Rejecting '{}' webhook with a body that's too large", event);
+            return Ok(respond(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+        Err(e) => {
+            warn!("Failed to read webhook body: {}", e);
+            return Ok(respond(StatusCode::BAD_REQUEST));
+        }
+    };
+    if !verify_signature(&secret, &body, &signature) {
+        warn!("Rejecting '{}' webhook with an invalid signature", event);
+        return Ok(respond(StatusCode::UNAUTHORIZED));
+    }
+    match parse_event(&event, &body) {
+        Ok(Some(trigger)) => {
+            debug!("Received '{}' webhook", event);
+            // The receiver only goes away when we're shutting down
+            let _ = triggers.send(trigger);
+            Ok(respond(StatusCode::ACCEPTED))
+        }
+        Ok(None) => Ok(respond(StatusCode::NO_CONTENT)),
+        Err(e) => {
+            warn!("Invalid '{}' webhook payload: {}", event, e);
+            Ok(respond(StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+/// An HTTP server receiving github webhooks, running on its own task. Relevant events are sent
+/// through a channel so they can be processed elsewhere.
+pub struct WebhookServer {
+    shutdown: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl WebhookServer {
+    pub fn start(
+        port: u16,
+        secret: String,
+        triggers: mpsc::UnboundedSender<WebhookTrigger>,
+    ) -> Result<Self, hyper::Error> {
+        let address = SocketAddr::from(([0, 0, 0, 0], port));
+        let secret = Arc::new(secret);
+        let make_service = make_service_fn(move |_| {
+            let secret = secret.clone();
+            let triggers = triggers.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle_request(request, secret.clone(), triggers.clone())
+                }))
+            }
+        });
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let server = Server::try_bind(&address)?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                shutdown_receiver.await.ok();
+            });
+        info!("Listening for webhooks on {}", address);
+        let handle = tokio::spawn(async {
+            if let Err(e) = server.await {
+                error!("Webhook server failed: {}", e);
+            }
+        });
+        Ok(Self { shutdown, handle })
+    }
+
+    /// Stops accepting connections and waits for in flight requests to finish
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.handle.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::User;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::valid(
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+        true
+    )]
+    #[case::wrong(
+        "sha256=000007ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+        false
+    )]
+    #[case::truncated("sha256=757107ea", false)]
+    #[case::not_hex("sha256=potato", false)]
+    #[case::missing_prefix(
+        "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+        false
+    )]
+    fn test_verify_signature(#[case] signature: &str, #[case] expected: bool) {
+        // The example from github's docs
+        let valid = verify_signature("It's a Secret to Everybody", b"Hello, World!", signature);
+        assert_eq!(valid, expected);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_large_bodies() {
+        let body = read_limited_body(Body::from("potato"), 6).await.unwrap();
+        assert_eq!(body.as_deref(), Some(&b"potato"[..]));
+        assert_eq!(
+            read_limited_body(Body::from("potatoes"), 6).await.unwrap(),
+            None
+        );
+
+        let request = Request::post("/")
+            .header(CONTENT_LENGTH, MAX_BODY_BYTES + 1)
+            .body(Body::empty())
+            .unwrap();
+        let (triggers, _receiver) = mpsc::unbounded_channel();
+        let response = handle_request(request, Arc::new("secret".into()), triggers)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let repo = Repository {
+            name: "smasher".into(),
+            owner: User {
+                login: "potato".into(),
+            },
+            full_name: "potato/smasher".into(),
+        };
+        let repository = r#""repository": {"name": "smasher", "full_name": "potato/smasher", "owner": {"login": "potato"}}"#;
+
+        let body = format!(
+            r#"{{"action": "completed", "check_suite": {{"pull_requests": [{{"number": 1337}}]}}, {}}}"#,
+            repository
+        );
+        assert_eq!(
+            parse_event("check_suite", body.as_bytes()).unwrap(),
+            Some(WebhookTrigger::PullRequests {
+                repo: repo.clone(),
+                numbers: vec![1337]
+            })
+        );
+
+        let body = format!(
+            r#"{{"action": "dismissed", "pull_request": {{"number": 1337}}, {}}}"#,
+            repository
+        );
+        assert_eq!(
+            parse_event("pull_request_review", body.as_bytes()).unwrap(),
+            None
+        );
+
+        let body = format!(r#"{{"sha": "mysha", "state": "success", {}}}"#, repository);
+        assert_eq!(
+            parse_event("status", body.as_bytes()).unwrap(),
+            Some(WebhookTrigger::Commit {
+                repo,
+                sha: "mysha".into()
+            })
+        );
+
+        assert_eq!(parse_event("ping", b"{}").unwrap(), None);
+    }
+}